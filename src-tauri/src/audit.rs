@@ -0,0 +1,329 @@
+//! Audit Log Module
+//!
+//! Append-only record of login and PDF/XML signing attempts for compliance and
+//! support. Entries are newline-delimited JSON; document paths are stored as a
+//! SHA-256 hash of the path so the log does not reveal file names.
+
+use crate::error::ESignError;
+use crate::pkcs11::CertificateInfo;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Log location under the user's home directory
+const AUDIT_DIR: &str = ".esign";
+const AUDIT_FILE: &str = "audit.log";
+
+/// Operation recorded by an audit entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOperation {
+    Login,
+    SignPdf,
+    SignXml,
+}
+
+/// One line of the audit log
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEntry {
+    /// ISO 8601 (RFC 3339) UTC time of the attempt
+    pub timestamp: String,
+    pub operation: AuditOperation,
+    /// SHA-256 of the signed document's path (hex), never the path itself
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pdf_path_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slot_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_serial: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cert_thumbprint: Option<String>,
+    pub success: bool,
+    /// ESignError variant, as reported to the frontend as `type`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<String>,
+    /// VNPT-CA error code, when the error carries one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+}
+
+impl AuditEntry {
+    fn new(operation: AuditOperation, success: bool) -> Self {
+        Self {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            operation,
+            pdf_path_hash: None,
+            slot_id: None,
+            cert_serial: None,
+            cert_thumbprint: None,
+            success,
+            error_type: None,
+            error_code: None,
+        }
+    }
+
+    fn sign(
+        operation: AuditOperation,
+        path: &str,
+        cert_info: Option<&CertificateInfo>,
+        success: bool,
+    ) -> Self {
+        Self {
+            pdf_path_hash: Some(hash_path(path)),
+            cert_serial: cert_info.map(|cert| cert.serial.clone()),
+            cert_thumbprint: cert_info.map(|cert| cert.thumbprint.clone()),
+            ..Self::new(operation, success)
+        }
+    }
+}
+
+/// Appends audit entries to a file; a disabled logger records nothing
+/// Write failures are reported on stderr and never fail the audited operation
+pub struct AuditLogger {
+    path: Option<PathBuf>,
+    /// Keeps concurrent entries from interleaving
+    write_lock: Mutex<()>,
+}
+
+impl AuditLogger {
+    /// Log to `path`, created (with its directory) on the first entry
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: Some(path.into()),
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Logger that drops every entry
+    pub fn disabled() -> Self {
+        Self {
+            path: None,
+            write_lock: Mutex::new(()),
+        }
+    }
+
+    /// Log file path, None when disabled
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Record a completed signing operation on the document at `path`
+    pub fn log_sign_attempt(
+        &self,
+        operation: AuditOperation,
+        path: &str,
+        cert_info: &CertificateInfo,
+        success: bool,
+    ) {
+        self.append(&AuditEntry::sign(operation, path, Some(cert_info), success));
+    }
+
+    /// Record a signing operation that failed; `cert_info` is None if the certificate was not read
+    pub fn log_sign_failure(
+        &self,
+        operation: AuditOperation,
+        path: &str,
+        cert_info: Option<&CertificateInfo>,
+        error: &ESignError,
+    ) {
+        self.append(&AuditEntry {
+            error_type: Some(error.kind().to_string()),
+            error_code: error.code(),
+            ..AuditEntry::sign(operation, path, cert_info, false)
+        });
+    }
+
+    /// Record a PIN login on `slot_id`
+    pub fn log_login_attempt(&self, slot_id: u64, success: bool) {
+        self.append(&AuditEntry {
+            slot_id: Some(slot_id),
+            ..AuditEntry::new(AuditOperation::Login, success)
+        });
+    }
+
+    fn append(&self, entry: &AuditEntry) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        // Nothing is left half-written by a panicking writer, so a poisoned lock is safe
+        let _guard = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = append_line(path, entry) {
+            eprintln!("Failed to write audit log {}: {}", path.display(), e);
+        }
+    }
+}
+
+/// `~/.esign/audit.log` (USERPROFILE on Windows), or the temp directory without a home
+pub fn default_log_path() -> PathBuf {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(AUDIT_DIR)
+        .join(AUDIT_FILE)
+}
+
+/// SHA-256 of the path string (hex)
+fn hash_path(path: &str) -> String {
+    hex::encode(Sha256::digest(path.as_bytes()))
+}
+
+fn append_line(path: &Path, entry: &AuditEntry) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::SigningErrorCode;
+
+    fn cert_info() -> CertificateInfo {
+        serde_json::from_value(serde_json::json!({
+            "serial": "01AB",
+            "subject": "CN=Test Signer",
+            "issuer": "CN=Test CA",
+            "valid_from": "2024-01-01",
+            "valid_to": "2027-01-01",
+            "thumbprint": "DEADBEEF",
+            "der_base64": "",
+        }))
+        .unwrap()
+    }
+
+    fn read_entries(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir()
+            .join(format!("esign_audit_{}", name))
+            .join(AUDIT_FILE);
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    // ============ Audit Log Tests ============
+
+    #[test]
+    fn test_log_sign_attempt_writes_json_line() {
+        let path = temp_log("sign");
+        let logger = AuditLogger::new(&path);
+        logger.log_sign_attempt(
+            AuditOperation::SignPdf,
+            "/home/user/contract.pdf",
+            &cert_info(),
+            true,
+        );
+
+        let entries = read_entries(&path);
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry["operation"], "sign_pdf");
+        assert_eq!(entry["success"], true);
+        assert_eq!(entry["cert_serial"], "01AB");
+        assert_eq!(entry["cert_thumbprint"], "DEADBEEF");
+        assert_eq!(entry["pdf_path_hash"], hash_path("/home/user/contract.pdf"));
+        assert!(entry.get("error_code").is_none());
+        assert!(chrono::DateTime::parse_from_rfc3339(entry["timestamp"].as_str().unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_log_does_not_contain_pdf_path() {
+        let path = temp_log("privacy");
+        let logger = AuditLogger::new(&path);
+        logger.log_sign_attempt(
+            AuditOperation::SignPdf,
+            "/home/user/secret-contract.pdf",
+            &cert_info(),
+            true,
+        );
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("secret-contract"));
+        assert_eq!(hash_path("a.pdf").len(), 64);
+        assert_ne!(hash_path("a.pdf"), hash_path("b.pdf"));
+    }
+
+    #[test]
+    fn test_log_sign_failure_records_error_code() {
+        let path = temp_log("failure");
+        let logger = AuditLogger::new(&path);
+        let error = ESignError::Signing {
+            code: SigningErrorCode::TokenNotFound,
+            message: "Not logged in".to_string(),
+        };
+        logger.log_sign_failure(AuditOperation::SignPdf, "/tmp/in.pdf", None, &error);
+        logger.log_sign_failure(
+            AuditOperation::SignPdf,
+            "/tmp/in.pdf",
+            Some(&cert_info()),
+            &ESignError::Pdf("bad".into()),
+        );
+
+        let entries = read_entries(&path);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["success"], false);
+        assert_eq!(entries[0]["error_type"], "Signing");
+        assert_eq!(entries[0]["error_code"], 8);
+        assert!(entries[0].get("cert_serial").is_none());
+        assert_eq!(entries[1]["error_type"], "Pdf");
+        assert!(entries[1].get("error_code").is_none());
+        assert_eq!(entries[1]["cert_serial"], "01AB");
+    }
+
+    #[test]
+    fn test_log_sign_xml_operation() {
+        let path = temp_log("xml");
+        let logger = AuditLogger::new(&path);
+        logger.log_sign_attempt(AuditOperation::SignXml, "/tmp/tax.xml", &cert_info(), true);
+
+        let entries = read_entries(&path);
+        assert_eq!(entries[0]["operation"], "sign_xml");
+        assert_eq!(entries[0]["pdf_path_hash"], hash_path("/tmp/tax.xml"));
+    }
+
+    #[test]
+    fn test_log_login_attempt_appends() {
+        let path = temp_log("login");
+        let logger = AuditLogger::new(&path);
+        logger.log_login_attempt(3, false);
+        logger.log_login_attempt(3, true);
+
+        let entries = read_entries(&path);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["operation"], "login");
+        assert_eq!(entries[0]["slot_id"], 3);
+        assert_eq!(entries[0]["success"], false);
+        assert_eq!(entries[1]["success"], true);
+        assert!(entries[1].get("pdf_path_hash").is_none());
+    }
+
+    #[test]
+    fn test_disabled_logger_writes_nothing() {
+        let logger = AuditLogger::disabled();
+        assert!(logger.path().is_none());
+        logger.log_login_attempt(0, true);
+    }
+
+    #[test]
+    fn test_default_log_path() {
+        let path = default_log_path();
+        assert!(path.ends_with(Path::new(AUDIT_DIR).join(AUDIT_FILE)));
+    }
+}
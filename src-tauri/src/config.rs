@@ -0,0 +1,163 @@
+//! Config Module
+//!
+//! Settings kept between sessions (last PKCS#11 library, idle timeout, TSA
+//! servers) in `~/.esign/config.json`, or `%APPDATA%\esign\config.json` on Windows.
+
+use crate::error::ESignError;
+use crate::tsa::TsaConfig;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Config location under the user's home directory (APPDATA on Windows)
+const CONFIG_DIR_UNIX: &str = ".esign";
+const CONFIG_DIR_WINDOWS: &str = "esign";
+const CONFIG_FILE: &str = "config.json";
+
+/// Settings persisted between sessions; missing fields take their defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppConfig {
+    /// PKCS#11 library initialized automatically at startup if it still exists
+    pub last_library_path: Option<String>,
+    pub preferred_slot_id: Option<u64>,
+    /// Sessions are logged out after this many idle seconds; 0 means never
+    pub idle_timeout_secs: u64,
+    pub tsa_config: TsaConfig,
+    /// Timestamp batch signatures with `tsa_config` (off: Vietnamese TSA servers are unreliable)
+    pub tsa_enabled: bool,
+    /// Seconds a batch job may reuse its cached timestamp token
+    pub tsa_cache_ttl_secs: u64,
+    /// Audit log location; `~/.esign/audit.log` when unset
+    pub audit_log_path: Option<String>,
+    /// Mark token certificates non-modifiable after each login (CA policy)
+    pub lock_certificates_after_login: bool,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            last_library_path: None,
+            preferred_slot_id: None,
+            idle_timeout_secs: crate::DEFAULT_IDLE_TIMEOUT.as_secs(),
+            tsa_config: TsaConfig::default(),
+            tsa_enabled: false,
+            tsa_cache_ttl_secs: crate::tsa::DEFAULT_CACHE_TTL.as_secs(),
+            audit_log_path: None,
+            lock_certificates_after_login: false,
+        }
+    }
+}
+
+/// `%APPDATA%\esign\config.json` on Windows, otherwise `~/.esign/config.json`
+/// Falls back to the temp directory without a home directory
+pub fn default_config_path() -> PathBuf {
+    let app_data = cfg!(windows)
+        .then(|| std::env::var_os("APPDATA"))
+        .flatten()
+        .map(|dir| PathBuf::from(dir).join(CONFIG_DIR_WINDOWS));
+    app_data
+        .unwrap_or_else(|| {
+            std::env::var_os("HOME")
+                .or_else(|| std::env::var_os("USERPROFILE"))
+                .map(PathBuf::from)
+                .unwrap_or_else(std::env::temp_dir)
+                .join(CONFIG_DIR_UNIX)
+        })
+        .join(CONFIG_FILE)
+}
+
+/// Read the config file; a missing or unreadable file gives the defaults
+pub fn load_config(path: &Path) -> AppConfig {
+    let data = match std::fs::read(path) {
+        Ok(data) => data,
+        Err(_) => return AppConfig::default(),
+    };
+    serde_json::from_slice(&data).unwrap_or_else(|e| {
+        eprintln!("Ignoring invalid config {}: {}", path.display(), e);
+        AppConfig::default()
+    })
+}
+
+/// Write the config as pretty JSON, creating its directory if needed
+pub fn save_config(path: &Path, config: &AppConfig) -> Result<(), ESignError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let json = serde_json::to_vec_pretty(config)
+        .map_err(|e| ESignError::Internal(format!("Failed to serialize config: {}", e)))?;
+
+    // Write to a temp file first so a crash never leaves a truncated config
+    let temp_path = path.with_extension("json.tmp");
+    std::fs::write(&temp_path, json)?;
+    std::fs::rename(&temp_path, path)?;
+    Ok(())
+}
+
+/// Delete the config file (absent is fine)
+pub fn remove_config(path: &Path) -> Result<(), ESignError> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("esign_config_{}_{}", name, std::process::id()))
+            .join(CONFIG_FILE)
+    }
+
+    // ============ Load/Save Tests ============
+
+    #[test]
+    fn test_load_missing_config_gives_defaults() {
+        let config = load_config(&temp_config_path("missing"));
+        assert_eq!(config.last_library_path, None);
+        assert_eq!(config.idle_timeout_secs, 300);
+        assert_eq!(config.tsa_cache_ttl_secs, 300);
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = temp_config_path("round_trip");
+        let config = AppConfig {
+            last_library_path: Some("/usr/lib/libvnpt.so".to_string()),
+            preferred_slot_id: Some(2),
+            idle_timeout_secs: 0,
+            audit_log_path: Some("/tmp/audit.log".to_string()),
+            ..Default::default()
+        };
+
+        // The directory does not exist yet
+        save_config(&path, &config).unwrap();
+        let loaded = load_config(&path);
+        assert_eq!(loaded.last_library_path, config.last_library_path);
+        assert_eq!(loaded.preferred_slot_id, Some(2));
+        assert_eq!(loaded.idle_timeout_secs, 0);
+        assert_eq!(loaded.audit_log_path, config.audit_log_path);
+
+        remove_config(&path).unwrap();
+        assert!(!path.exists());
+        // Removing again is not an error
+        remove_config(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_partial_and_invalid_config() {
+        let path = temp_config_path("partial");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+
+        std::fs::write(&path, r#"{"preferred_slot_id": 1}"#).unwrap();
+        let config = load_config(&path);
+        assert_eq!(config.preferred_slot_id, Some(1));
+        assert_eq!(config.idle_timeout_secs, 300);
+
+        std::fs::write(&path, b"not json").unwrap();
+        assert_eq!(load_config(&path).preferred_slot_id, None);
+        let _ = std::fs::remove_file(&path);
+    }
+}
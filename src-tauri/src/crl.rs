@@ -0,0 +1,414 @@
+//! CRL Module
+//!
+//! Certificate revocation checks against CRLs (RFC 5280), for CAs whose OCSP
+//! responders are unreliable. The CRL is downloaded from the first HTTP URL in the
+//! certificate's CRL Distribution Points and cached until its nextUpdate.
+//! The CRL issuer name must match the certificate issuer; the CRL signature is not verified.
+
+use crate::error::ESignError;
+use crate::ocsp::revocation_error;
+use der::{Decode, Encode};
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use x509_cert::crl::CertificateList;
+use x509_parser::prelude::*;
+
+/// CRL download timeout
+const CRL_TIMEOUT: Duration = Duration::from_secs(30);
+/// Largest CRL accepted (large CAs publish CRLs of several MB)
+const MAX_CRL_SIZE: usize = 20 * 1024 * 1024;
+/// Cache lifetime for CRLs without nextUpdate
+const CRL_DEFAULT_TTL: Duration = Duration::from_secs(60 * 60);
+/// id-ce-cRLReasons (2.5.29.21)
+const OID_CRL_REASON: &str = "2.5.29.21";
+
+/// Retry guidance shown when a CRL cannot be downloaded
+const CRL_RETRY_SUGGESTION: &str = "Check the network connection or try the OCSP check instead";
+
+/// Revocation status of a certificate according to its CRL
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum RevocationStatus {
+    Good,
+    /// `reason` is the RFC 5280 CRLReason name ("unspecified" without a reason code)
+    Revoked {
+        reason: String,
+    },
+    /// The certificate has no HTTP CRL distribution point
+    Unknown,
+}
+
+/// Revoked serials of one CRL
+#[derive(Debug, Clone)]
+pub struct ParsedCrl {
+    /// Raw serial INTEGER content → CRLReason name
+    revoked: HashMap<Vec<u8>, String>,
+    /// DER issuer Name, compared with the certificate issuer
+    issuer: Vec<u8>,
+    /// nextUpdate as Unix seconds
+    next_update: Option<u64>,
+}
+
+impl ParsedCrl {
+    /// Parse a DER CertificateList
+    pub fn from_der(der: &[u8]) -> Result<Self, ESignError> {
+        let crl = CertificateList::from_der(der)
+            .map_err(|e| revocation_error(format!("Invalid CRL: {}", e)))?;
+        let tbs = crl.tbs_cert_list;
+
+        let issuer = tbs
+            .issuer
+            .to_der()
+            .map_err(|e| revocation_error(format!("Invalid CRL issuer: {}", e)))?;
+        let revoked = tbs
+            .revoked_certificates
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| {
+                let reason = entry
+                    .crl_entry_extensions
+                    .unwrap_or_default()
+                    .iter()
+                    .find(|ext| ext.extn_id.to_string() == OID_CRL_REASON)
+                    .and_then(|ext| match ext.extn_value.as_bytes() {
+                        // ENUMERATED, one content byte
+                        [0x0A, 0x01, code] => Some(*code),
+                        _ => None,
+                    })
+                    .map_or("unspecified", crl_reason_name);
+                (entry.serial_number.as_bytes().to_vec(), reason.to_string())
+            })
+            .collect();
+
+        Ok(Self {
+            revoked,
+            issuer,
+            next_update: tbs
+                .next_update
+                .map(|time| time.to_unix_duration().as_secs()),
+        })
+    }
+
+    /// Status of the certificate with this raw serial
+    pub fn status_of(&self, serial: &[u8]) -> RevocationStatus {
+        match self.revoked.get(serial) {
+            Some(reason) => RevocationStatus::Revoked {
+                reason: reason.clone(),
+            },
+            None => RevocationStatus::Good,
+        }
+    }
+
+    /// Still usable: before nextUpdate, or within the default TTL when there is none
+    fn is_fresh(&self, fetched_at: Instant) -> bool {
+        match self.next_update {
+            Some(next_update) => chrono::Utc::now().timestamp() < next_update as i64,
+            None => fetched_at.elapsed() < CRL_DEFAULT_TTL,
+        }
+    }
+}
+
+/// Downloaded CRLs keyed by distribution point URL
+#[derive(Default)]
+pub struct CrlCache {
+    entries: Mutex<HashMap<String, (ParsedCrl, Instant)>>,
+}
+
+impl CrlCache {
+    /// Create empty CRL cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check `cert_der` against the CRL from its first HTTP distribution point
+    pub fn check_revocation(&self, cert_der: &[u8]) -> Result<RevocationStatus, ESignError> {
+        let (_, cert) = X509Certificate::from_der(cert_der)
+            .map_err(|e| revocation_error(format!("Failed to parse certificate: {}", e)))?;
+
+        let Some(url) = crl_distribution_url(&cert) else {
+            return Ok(RevocationStatus::Unknown);
+        };
+        let crl = self.get_or_fetch(&url)?;
+
+        if crl.issuer != cert.issuer().as_raw() {
+            return Err(revocation_error(format!(
+                "CRL from {} was not issued by the certificate's CA",
+                url
+            )));
+        }
+        Ok(crl.status_of(cert.tbs_certificate.raw_serial()))
+    }
+
+    /// Cached CRL for `url`, downloaded again once its nextUpdate has passed
+    fn get_or_fetch(&self, url: &str) -> Result<ParsedCrl, ESignError> {
+        let cached = self
+            .entries
+            .lock()
+            .ok()
+            .and_then(|entries| entries.get(url).cloned());
+        if let Some((crl, fetched_at)) = cached {
+            if crl.is_fresh(fetched_at) {
+                return Ok(crl);
+            }
+        }
+
+        // Download without holding the lock (CRLs can take a while)
+        let crl = ParsedCrl::from_der(&download_crl(url)?)?;
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(url.to_string(), (crl.clone(), Instant::now()));
+        }
+        Ok(crl)
+    }
+}
+
+/// First HTTP(S) fullName URI of the CRL Distribution Points extension
+fn crl_distribution_url(cert: &X509Certificate) -> Option<String> {
+    cert.extensions()
+        .iter()
+        .find_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::CRLDistributionPoints(points) => Some(points),
+            _ => None,
+        })?
+        .iter()
+        .filter_map(|point| match &point.distribution_point {
+            Some(DistributionPointName::FullName(names)) => Some(names),
+            _ => None,
+        })
+        .flatten()
+        .find_map(|name| match name {
+            GeneralName::URI(uri) if uri.starts_with("http://") || uri.starts_with("https://") => {
+                Some(uri.to_string())
+            }
+            _ => None,
+        })
+}
+
+fn download_crl(url: &str) -> Result<Vec<u8>, ESignError> {
+    let client = Client::builder()
+        .timeout(CRL_TIMEOUT)
+        .build()
+        .map_err(|e| revocation_error(format!("Failed to create HTTP client: {}", e)))?;
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|e| ESignError::network_error(url, &e.to_string(), CRL_RETRY_SUGGESTION))?;
+
+    if !response.status().is_success() {
+        return Err(revocation_error(format!(
+            "CRL download from {} returned HTTP {}",
+            url,
+            response.status()
+        )));
+    }
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_CRL_SIZE as u64)
+    {
+        return Err(revocation_error(format!("CRL from {} is too large", url)));
+    }
+
+    let body = response
+        .bytes()
+        .map_err(|e| revocation_error(format!("Failed to read CRL: {}", e)))?;
+    if body.len() > MAX_CRL_SIZE {
+        return Err(revocation_error(format!("CRL from {} is too large", url)));
+    }
+    Ok(body.to_vec())
+}
+
+/// CRLReason name (RFC 5280 §5.3.1)
+fn crl_reason_name(code: u8) -> &'static str {
+    match code {
+        1 => "keyCompromise",
+        2 => "cACompromise",
+        3 => "affiliationChanged",
+        4 => "superseded",
+        5 => "cessationOfOperation",
+        6 => "certificateHold",
+        8 => "removeFromCRL",
+        9 => "privilegeWithdrawn",
+        10 => "aACompromise",
+        _ => "unspecified",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CertValidationCode;
+    use crate::test_utils::{
+        build_certificate_with_extensions, certificate_list, crl_distribution_points_extension,
+        test_identity,
+    };
+
+    /// Serial used by the test certificate builder
+    const TEST_SERIAL: &[u8] = &[0x01, 0x23, 0x45];
+
+    fn cert_with_cdp(url: &str) -> Vec<u8> {
+        build_certificate_with_extensions(
+            &test_identity().key,
+            "CRL Signer",
+            "250101000000Z",
+            "491231235959Z",
+            &[crl_distribution_points_extension(url)],
+        )
+    }
+
+    // ============ CRL Parsing Tests ============
+
+    #[test]
+    fn test_parse_crl_revoked_with_reason() {
+        let der = certificate_list(
+            "CRL Signer",
+            &[(TEST_SERIAL, Some(1)), (&[0x05], None)],
+            Some("491231000000Z"),
+        );
+        let crl = ParsedCrl::from_der(&der).unwrap();
+
+        assert_eq!(
+            crl.status_of(TEST_SERIAL),
+            RevocationStatus::Revoked {
+                reason: "keyCompromise".to_string()
+            }
+        );
+        assert_eq!(
+            crl.status_of(&[0x05]),
+            RevocationStatus::Revoked {
+                reason: "unspecified".to_string()
+            }
+        );
+        assert_eq!(crl.status_of(&[0x06]), RevocationStatus::Good);
+        assert!(crl.is_fresh(Instant::now()));
+    }
+
+    #[test]
+    fn test_parse_crl_past_next_update_is_stale() {
+        let der = certificate_list("CRL Signer", &[], Some("250102000000Z"));
+        let crl = ParsedCrl::from_der(&der).unwrap();
+        assert!(crl.revoked.is_empty());
+        assert!(!crl.is_fresh(Instant::now()));
+
+        // Without nextUpdate the default TTL applies
+        let crl = ParsedCrl::from_der(&certificate_list("CRL Signer", &[], None)).unwrap();
+        assert!(crl.is_fresh(Instant::now()));
+    }
+
+    #[test]
+    fn test_parse_crl_garbage() {
+        assert!(ParsedCrl::from_der(b"not a crl").is_err());
+    }
+
+    #[test]
+    fn test_crl_distribution_url() {
+        let der = cert_with_cdp("http://crl.example.vn/ca.crl");
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert_eq!(
+            crl_distribution_url(&cert).as_deref(),
+            Some("http://crl.example.vn/ca.crl")
+        );
+
+        let (_, plain) = X509Certificate::from_der(&test_identity().cert_der).unwrap();
+        assert_eq!(crl_distribution_url(&plain), None);
+    }
+
+    #[test]
+    fn test_check_revocation_without_cdp_is_unknown() {
+        let status = CrlCache::new()
+            .check_revocation(&test_identity().cert_der)
+            .unwrap();
+        assert_eq!(status, RevocationStatus::Unknown);
+    }
+
+    // ============ HTTP Tests ============
+
+    #[test]
+    fn test_check_revocation_caches_until_next_update() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/ca.crl"))
+                .times(1)
+                .respond_with(status_code(200).body(certificate_list(
+                    "CRL Signer",
+                    &[(TEST_SERIAL, Some(4))],
+                    Some("491231000000Z"),
+                ))),
+        );
+
+        let cert = cert_with_cdp(&server.url("/ca.crl").to_string());
+        let cache = CrlCache::new();
+        let expected = RevocationStatus::Revoked {
+            reason: "superseded".to_string(),
+        };
+        assert_eq!(cache.check_revocation(&cert).unwrap(), expected);
+        // Served from the cache: the server expects a single request
+        assert_eq!(cache.check_revocation(&cert).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_check_revocation_refreshes_stale_crl() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/ca.crl"))
+                .times(2)
+                .respond_with(status_code(200).body(certificate_list(
+                    "CRL Signer",
+                    &[],
+                    Some("250102000000Z"),
+                ))),
+        );
+
+        let cert = cert_with_cdp(&server.url("/ca.crl").to_string());
+        let cache = CrlCache::new();
+        assert_eq!(
+            cache.check_revocation(&cert).unwrap(),
+            RevocationStatus::Good
+        );
+        assert_eq!(
+            cache.check_revocation(&cert).unwrap(),
+            RevocationStatus::Good
+        );
+    }
+
+    #[test]
+    fn test_check_revocation_rejects_other_issuer() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/ca.crl"))
+                .respond_with(status_code(200).body(certificate_list("Other CA", &[], None))),
+        );
+
+        let cert = cert_with_cdp(&server.url("/ca.crl").to_string());
+        let err = CrlCache::new().check_revocation(&cert).unwrap_err();
+        assert!(matches!(
+            err,
+            ESignError::CertValidation {
+                code: CertValidationCode::RevocationCheckFailed,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_check_revocation_http_error() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/ca.crl"))
+                .respond_with(status_code(404)),
+        );
+
+        let cert = cert_with_cdp(&server.url("/ca.crl").to_string());
+        let err = CrlCache::new().check_revocation(&cert).unwrap_err();
+        assert!(err.to_string().contains("404"));
+    }
+}
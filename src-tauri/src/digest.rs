@@ -0,0 +1,153 @@
+//! Digest Module
+//!
+//! Interchangeable SHA-256 implementations for the document digest.
+//! `ring` is faster than `sha2` on large scanned PDFs; both give identical output.
+
+use sha2::{Digest, Sha256};
+
+/// Incremental hash computation
+pub trait DigestCalculator {
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self: Box<Self>) -> Vec<u8>;
+}
+
+/// SHA-256 from the RustCrypto `sha2` crate
+pub struct Sha2Digest256(Sha256);
+
+impl Sha2Digest256 {
+    pub fn new() -> Self {
+        Self(Sha256::new())
+    }
+}
+
+impl DigestCalculator for Sha2Digest256 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finalize().to_vec()
+    }
+}
+
+/// SHA-256 from `ring` (assembly-optimized)
+pub struct RingDigest256(ring::digest::Context);
+
+impl RingDigest256 {
+    pub fn new() -> Self {
+        Self(ring::digest::Context::new(&ring::digest::SHA256))
+    }
+}
+
+impl DigestCalculator for RingDigest256 {
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> Vec<u8> {
+        self.0.finish().as_ref().to_vec()
+    }
+}
+
+/// SHA-256 implementation used for document digests
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DigestBackend {
+    #[default]
+    Sha2,
+    #[allow(dead_code)] // Selected via PdfSigningEngine::with_digest_backend
+    Ring,
+}
+
+impl DigestBackend {
+    /// Fresh hasher for this backend
+    pub fn calculator(self) -> Box<dyn DigestCalculator> {
+        match self {
+            Self::Sha2 => Box::new(Sha2Digest256::new()),
+            Self::Ring => Box::new(RingDigest256::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const BACKENDS: [DigestBackend; 2] = [DigestBackend::Sha2, DigestBackend::Ring];
+
+    fn digest(backend: DigestBackend, chunks: &[&[u8]]) -> Vec<u8> {
+        let mut calculator = backend.calculator();
+        for chunk in chunks {
+            calculator.update(chunk);
+        }
+        calculator.finalize()
+    }
+
+    // ============ Backend Equivalence Tests ============
+
+    #[test]
+    fn test_sha256_known_vector() {
+        let expected =
+            hex::decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .unwrap();
+        for backend in BACKENDS {
+            assert_eq!(digest(backend, &[b"abc"]), expected, "{:?}", backend);
+        }
+    }
+
+    #[test]
+    fn test_backends_identical_output() {
+        let data: Vec<u8> = (0..1_000_000u32).map(|i| (i % 251) as u8).collect();
+        assert_eq!(
+            digest(DigestBackend::Sha2, &[&data]),
+            digest(DigestBackend::Ring, &[&data])
+        );
+        assert_eq!(
+            digest(DigestBackend::Sha2, &[]),
+            digest(DigestBackend::Ring, &[])
+        );
+    }
+
+    #[test]
+    fn test_chunked_updates_match_single_update() {
+        let data = b"%PDF-1.7 chunked digest input";
+        for backend in BACKENDS {
+            assert_eq!(
+                digest(backend, &[&data[..9], &data[9..20], &data[20..]]),
+                digest(backend, &[data])
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_backend_is_sha2() {
+        assert_eq!(DigestBackend::default(), DigestBackend::Sha2);
+    }
+
+    // ============ Throughput Benchmark ============
+
+    /// Each backend must hash 10 MB at a usable rate and agree with the others
+    /// Run with: cargo test --release bench_digest_backends -- --ignored
+    #[test]
+    #[ignore]
+    fn bench_digest_backends_10mb() {
+        const ROUNDS: u32 = 10;
+        const MIN_MB_PER_SEC: f64 = 5.0;
+
+        let data = vec![0x5Au8; 10 * 1024 * 1024];
+        let expected = digest(DigestBackend::Sha2, &[&data]);
+        for backend in BACKENDS {
+            let started = Instant::now();
+            for _ in 0..ROUNDS {
+                assert_eq!(digest(backend, &[&data]), expected);
+            }
+            let mb_per_sec = f64::from(ROUNDS * 10) / started.elapsed().as_secs_f64();
+            assert!(
+                mb_per_sec >= MIN_MB_PER_SEC,
+                "{:?} hashed {:.1} MB/s",
+                backend,
+                mb_per_sec
+            );
+        }
+    }
+}
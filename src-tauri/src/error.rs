@@ -2,7 +2,8 @@
 //!
 //! Implements VNPT-CA compatible error codes (0-11)
 
-use serde::{Deserialize, Serialize};
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
 /// Signing error codes compatible with VNPT-CA Plugin
@@ -94,11 +95,97 @@ pub enum ESignError {
     },
 
     #[error("Certificate validation error (code {code:?}): {message}")]
-    #[allow(dead_code)]
     CertValidation {
         code: CertValidationCode,
         message: String,
     },
+
+    /// Failures outside token, PDF and TSA handling (poisoned locks, panicked tasks)
+    #[error("Internal error: {0}")]
+    Internal(String),
+}
+
+impl ESignError {
+    /// Create a network error with the unreachable URL and retry guidance
+    /// Used for TSA/OCSP calls so users see what failed and what to do next
+    pub fn network_error(url: &str, cause: &str, retry_suggestion: &str) -> Self {
+        ESignError::Tsa(format!(
+            "Network error reaching {}: {}. {}",
+            url, cause, retry_suggestion
+        ))
+    }
+
+    /// Invalid command argument (VNPT-CA code 1)
+    pub fn invalid_input(message: impl Into<String>) -> Self {
+        ESignError::Signing {
+            code: SigningErrorCode::InvalidInput,
+            message: message.into(),
+        }
+    }
+
+    /// A mutex guarding shared state was poisoned by a panicking thread
+    pub fn lock_poisoned(what: &str) -> Self {
+        ESignError::Internal(format!("{} mutex poisoned", what))
+    }
+
+    /// Variant name reported to the frontend as `type`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ESignError::Pkcs11(_) => "Pkcs11",
+            ESignError::LibraryArchitectureMismatch { .. } => "LibraryArchitectureMismatch",
+            ESignError::Pdf(_) => "Pdf",
+            ESignError::Tsa(_) => "Tsa",
+            ESignError::Io(_) => "Io",
+            ESignError::Signing { .. } => "Signing",
+            ESignError::CertValidation { .. } => "CertValidation",
+            ESignError::Internal(_) => "Internal",
+        }
+    }
+
+    /// VNPT-CA error code, for Signing and CertValidation errors
+    pub fn code(&self) -> Option<i32> {
+        match self {
+            ESignError::Signing { code, .. } => Some(*code as i32),
+            ESignError::CertValidation { code, .. } => Some(*code as i32),
+            _ => None,
+        }
+    }
+}
+
+/// Structured error for Tauri IPC, so the frontend can switch on `type` and `code`
+/// `{ "type": "Signing", "code": 8, "message": "...", "guidance": null }`;
+/// LibraryArchitectureMismatch adds `library_arch`, `host_arch` and `library_path`
+impl Serialize for ESignError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let code = self.code();
+
+        match self {
+            ESignError::LibraryArchitectureMismatch {
+                library_arch,
+                host_arch,
+                library_path,
+                guidance,
+            } => {
+                let mut s = serializer.serialize_struct("ESignError", 7)?;
+                s.serialize_field("type", self.kind())?;
+                s.serialize_field("code", &code)?;
+                s.serialize_field("message", &self.to_string())?;
+                s.serialize_field("guidance", guidance)?;
+                s.serialize_field("library_arch", library_arch)?;
+                s.serialize_field("host_arch", host_arch)?;
+                s.serialize_field("library_path", library_path)?;
+                s.end()
+            }
+            _ => {
+                let mut s = serializer.serialize_struct("ESignError", 4)?;
+                s.serialize_field("type", self.kind())?;
+                s.serialize_field("code", &code)?;
+                s.serialize_field("message", &self.to_string())?;
+                s.serialize_field("guidance", &None::<String>)?;
+                s.end()
+            }
+        }
+    }
 }
 
 /// Result type for signing operations, compatible with VNPT-CA response format
@@ -235,6 +322,90 @@ mod tests {
         assert!(msg.contains("TokenNotFound"));
     }
 
+    #[test]
+    fn test_esign_error_network_error() {
+        let err = ESignError::network_error(
+            "https://ca.vnpt.vn/tsa",
+            "connection refused",
+            "Check internet connectivity",
+        );
+        let msg = format!("{}", err);
+        assert!(msg.contains("https://ca.vnpt.vn/tsa"));
+        assert!(msg.contains("connection refused"));
+        assert!(msg.contains("Check internet connectivity"));
+    }
+
+    #[test]
+    fn test_esign_error_network_error_is_tsa() {
+        let err = ESignError::network_error("http://tsa.fpt.vn", "timeout", "Retry later");
+        assert!(matches!(err, ESignError::Tsa(_)));
+    }
+
+    #[test]
+    fn test_esign_error_invalid_input() {
+        let err = ESignError::invalid_input("Paths cannot be empty");
+        assert!(matches!(
+            err,
+            ESignError::Signing {
+                code: SigningErrorCode::InvalidInput,
+                ..
+            }
+        ));
+        assert!(ESignError::lock_poisoned("Token manager")
+            .to_string()
+            .contains("Token manager mutex poisoned"));
+    }
+
+    // ============ IPC Serialization Tests ============
+
+    #[test]
+    fn test_esign_error_serialize_signing() {
+        let err = ESignError::Signing {
+            code: SigningErrorCode::TokenNotFound,
+            message: "USB Token not connected".to_string(),
+        };
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["type"], "Signing");
+        assert_eq!(json["code"], 8);
+        assert!(json["message"]
+            .as_str()
+            .unwrap()
+            .contains("USB Token not connected"));
+        assert!(json["guidance"].is_null());
+    }
+
+    #[test]
+    fn test_esign_error_serialize_without_code() {
+        let json = serde_json::to_value(ESignError::Pdf("Invalid PDF".to_string())).unwrap();
+        assert_eq!(json["type"], "Pdf");
+        assert!(json["code"].is_null());
+        assert_eq!(json["message"], "PDF error: Invalid PDF");
+
+        let json = serde_json::to_value(ESignError::CertValidation {
+            code: CertValidationCode::Revoked,
+            message: "revoked".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json["type"], "CertValidation");
+        assert_eq!(json["code"], 4);
+    }
+
+    #[test]
+    fn test_esign_error_serialize_architecture_mismatch() {
+        let err = ESignError::LibraryArchitectureMismatch {
+            library_arch: "x86_64".to_string(),
+            host_arch: "aarch64".to_string(),
+            library_path: "/usr/lib/libvnpt.so".to_string(),
+            guidance: "Install the ARM64 driver".to_string(),
+        };
+        let json = serde_json::to_value(&err).unwrap();
+        assert_eq!(json["type"], "LibraryArchitectureMismatch");
+        assert_eq!(json["library_arch"], "x86_64");
+        assert_eq!(json["host_arch"], "aarch64");
+        assert_eq!(json["library_path"], "/usr/lib/libvnpt.so");
+        assert_eq!(json["guidance"], "Install the ARM64 driver");
+    }
+
     #[test]
     fn test_esign_error_debug() {
         let err = ESignError::Pkcs11("Test error".to_string());
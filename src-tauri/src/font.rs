@@ -6,70 +6,117 @@
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use lopdf::{Dictionary, Object, ObjectId, Stream};
+use rand::Rng;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
-use ttf_parser::Face;
+use ttf_parser::{Face, GlyphId};
 
 /// Embedded Be Vietnam Pro Regular font (supports Vietnamese)
-const BE_VIETNAM_PRO_REGULAR: &[u8] = include_bytes!("../fonts/BeVietnamPro-Regular.ttf");
+pub(crate) const BE_VIETNAM_PRO_REGULAR: &[u8] =
+    include_bytes!("../fonts/BeVietnamPro-Regular.ttf");
 
 /// Embedded Be Vietnam Pro SemiBold font (supports Vietnamese)
-const BE_VIETNAM_PRO_SEMIBOLD: &[u8] = include_bytes!("../fonts/BeVietnamPro-SemiBold.ttf");
+pub(crate) const BE_VIETNAM_PRO_SEMIBOLD: &[u8] =
+    include_bytes!("../fonts/BeVietnamPro-SemiBold.ttf");
 
 /// Font name used in PDF
 const FONT_NAME: &str = "BeVietnamPro";
 const FONT_NAME_BOLD: &str = "BeVietnamPro-SemiBold";
 
+/// TrueType tables kept in a subset (required for CIDFontType2 with Identity CIDToGIDMap)
+/// Sorted by tag, as the table directory requires
+const SUBSET_TABLES: [&[u8; 4]; 9] = [
+    b"cvt ", b"fpgm", b"glyf", b"head", b"hhea", b"hmtx", b"loca", b"maxp", b"prep",
+];
+
+/// Composite glyph component flags (OpenType glyf table)
+const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+const WE_HAVE_A_SCALE: u16 = 0x0008;
+const MORE_COMPONENTS: u16 = 0x0020;
+const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
 /// Result of embedding a font in a PDF document
 pub struct EmbeddedFont {
     /// Object ID of the Type 0 font dictionary
     pub font_id: ObjectId,
 }
 
-/// Embed Vietnamese-capable font into PDF document
+/// Embed Vietnamese-capable font into PDF document, subset to the glyphs of `text`
 /// Returns the font object ID for use in content streams
 pub fn embed_vietnamese_font(
     doc: &mut lopdf::Document,
     _resource_name: &str,
+    text: &str,
 ) -> Result<EmbeddedFont, String> {
-    embed_font_data(doc, BE_VIETNAM_PRO_REGULAR, FONT_NAME)
+    embed_font_data(doc, BE_VIETNAM_PRO_REGULAR, FONT_NAME, Some(text))
 }
 
-/// Embed Vietnamese-capable bold font into PDF document
+/// Embed Vietnamese-capable bold font into PDF document, subset to the glyphs of `text`
 /// Returns the font object ID for use in content streams
 pub fn embed_vietnamese_font_bold(
     doc: &mut lopdf::Document,
     _resource_name: &str,
+    text: &str,
+) -> Result<EmbeddedFont, String> {
+    embed_font_data(doc, BE_VIETNAM_PRO_SEMIBOLD, FONT_NAME_BOLD, Some(text))
+}
+
+/// Embed the complete regular font, for text the viewer renders later (e.g. /DA)
+pub fn embed_vietnamese_font_full(
+    doc: &mut lopdf::Document,
+    _resource_name: &str,
 ) -> Result<EmbeddedFont, String> {
-    embed_font_data(doc, BE_VIETNAM_PRO_SEMIBOLD, FONT_NAME_BOLD)
+    embed_font_data(doc, BE_VIETNAM_PRO_REGULAR, FONT_NAME, None)
 }
 
 /// Internal function to embed font data
+/// With `text`, only the glyphs it uses are embedded (tagged subset font)
 fn embed_font_data(
     doc: &mut lopdf::Document,
     font_data: &[u8],
     font_name: &str,
+    text: Option<&str>,
 ) -> Result<EmbeddedFont, String> {
-    // 1. Compress TTF data
-    let compressed_ttf = compress_data(font_data)?;
+    let face = Face::parse(font_data, 0).map_err(|e| format!("Failed to parse font: {}", e))?;
+
+    // 1. Subset (or keep) the TTF data; glyph IDs are preserved for Identity-H
+    let used = text.map(|text| used_glyphs(&face, text));
+    let (embedded_data, base_font) = match used {
+        Some(ref used) => (
+            subset_font(font_data, &used.keys().copied().collect())?,
+            format!("{}+{}", random_subset_tag(), font_name),
+        ),
+        None => (font_data.to_vec(), font_name.to_string()),
+    };
+    let compressed_ttf = compress_data(&embedded_data)?;
 
     // 2. Create FontFile2 stream (embedded TTF)
     let mut fontfile_dict = Dictionary::new();
-    fontfile_dict.set("Length1", Object::Integer(font_data.len() as i64));
+    fontfile_dict.set("Length1", Object::Integer(embedded_data.len() as i64));
     fontfile_dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
 
     let fontfile_stream = Stream::new(fontfile_dict, compressed_ttf);
     let fontfile_id = doc.add_object(Object::Stream(fontfile_stream));
 
     // 3. Create FontDescriptor
-    let font_descriptor = create_font_descriptor(fontfile_id, font_name);
+    let glyphs: Vec<u16> = match used {
+        Some(ref used) => used.keys().copied().collect(),
+        None => (0..face.number_of_glyphs()).collect(),
+    };
+    let metrics = FontMetrics::from_glyphs(&face, &glyphs);
+    let font_descriptor = create_font_descriptor(fontfile_id, &base_font, &metrics);
     let font_descriptor_id = doc.add_object(Object::Dictionary(font_descriptor));
 
     // 4. Create CIDFont dictionary
-    let cid_font = create_cid_font(font_descriptor_id, font_name, font_data);
+    let cid_font = create_cid_font(font_descriptor_id, &base_font, &face, &glyphs);
     let cid_font_id = doc.add_object(Object::Dictionary(cid_font));
 
     // 5. Create ToUnicode CMap stream
-    let to_unicode_cmap = create_to_unicode_cmap();
+    let to_unicode_cmap = match used {
+        Some(ref used) => create_subset_to_unicode_cmap(used),
+        None => create_to_unicode_cmap(),
+    };
     let mut cmap_dict = Dictionary::new();
     cmap_dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
     let compressed_cmap = compress_data(to_unicode_cmap.as_bytes())?;
@@ -77,48 +124,87 @@ fn embed_font_data(
     let cmap_id = doc.add_object(Object::Stream(cmap_stream));
 
     // 6. Create Type 0 font dictionary
-    let type0_font = create_type0_font(cid_font_id, cmap_id, font_name);
+    let type0_font = create_type0_font(cid_font_id, cmap_id, &base_font);
     let font_id = doc.add_object(Object::Dictionary(type0_font));
 
     Ok(EmbeddedFont { font_id })
 }
 
+/// FontDescriptor metrics in PDF glyph space (1000 units per em)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FontMetrics {
+    bbox: [i64; 4],
+    ascent: i64,
+    descent: i64,
+    cap_height: i64,
+}
+
+impl FontMetrics {
+    /// Union of the glyph bounding boxes; Ascent/Descent are the highest and lowest points
+    fn from_glyphs(face: &Face, glyphs: &[u16]) -> Self {
+        let scale = 1000.0 / face.units_per_em() as f64;
+        let to_pdf = |value: i16| (value as f64 * scale).round() as i64;
+
+        let (x_min, y_min, x_max, y_max) = glyphs
+            .iter()
+            .filter_map(|&gid| face.glyph_bounding_box(GlyphId(gid)))
+            .fold(None, |acc: Option<(i16, i16, i16, i16)>, rect| {
+                Some(match acc {
+                    None => (rect.x_min, rect.y_min, rect.x_max, rect.y_max),
+                    Some((x0, y0, x1, y1)) => (
+                        x0.min(rect.x_min),
+                        y0.min(rect.y_min),
+                        x1.max(rect.x_max),
+                        y1.max(rect.y_max),
+                    ),
+                })
+            })
+            .unwrap_or((0, face.descender(), 0, face.ascender()));
+
+        Self {
+            bbox: [to_pdf(x_min), to_pdf(y_min), to_pdf(x_max), to_pdf(y_max)],
+            ascent: to_pdf(y_max.max(0)),
+            descent: to_pdf(y_min.min(0)),
+            cap_height: to_pdf(face.capital_height().unwrap_or(face.ascender())),
+        }
+    }
+}
+
 /// Create FontDescriptor dictionary
-fn create_font_descriptor(fontfile_id: ObjectId, font_name: &str) -> Dictionary {
+fn create_font_descriptor(
+    fontfile_id: ObjectId,
+    base_font: &str,
+    metrics: &FontMetrics,
+) -> Dictionary {
     let mut fd = Dictionary::new();
     fd.set("Type", Object::Name(b"FontDescriptor".to_vec()));
-    fd.set(
-        "FontName",
-        Object::Name(format!("{}+{}", "AAAAAA", font_name).into_bytes()),
-    );
+    fd.set("FontName", Object::Name(base_font.as_bytes().to_vec()));
     fd.set("Flags", Object::Integer(32)); // Symbolic
     fd.set(
         "FontBBox",
-        Object::Array(vec![
-            Object::Integer(-620),
-            Object::Integer(-400),
-            Object::Integer(2800),
-            Object::Integer(1200),
-        ]),
+        Object::Array(metrics.bbox.iter().map(|&v| Object::Integer(v)).collect()),
     );
     fd.set("ItalicAngle", Object::Integer(0));
-    fd.set("Ascent", Object::Integer(1069));
-    fd.set("Descent", Object::Integer(-293));
-    fd.set("CapHeight", Object::Integer(714));
+    fd.set("Ascent", Object::Integer(metrics.ascent));
+    fd.set("Descent", Object::Integer(metrics.descent));
+    fd.set("CapHeight", Object::Integer(metrics.cap_height));
     fd.set("StemV", Object::Integer(88));
     fd.set("FontFile2", Object::Reference(fontfile_id));
     fd
 }
 
 /// Create CIDFont dictionary (CIDFontType2 for TrueType)
-fn create_cid_font(font_descriptor_id: ObjectId, font_name: &str, font_data: &[u8]) -> Dictionary {
+/// The W array covers only `glyphs`
+fn create_cid_font(
+    font_descriptor_id: ObjectId,
+    base_font: &str,
+    face: &Face,
+    glyphs: &[u16],
+) -> Dictionary {
     let mut cidfont = Dictionary::new();
     cidfont.set("Type", Object::Name(b"Font".to_vec()));
     cidfont.set("Subtype", Object::Name(b"CIDFontType2".to_vec()));
-    cidfont.set(
-        "BaseFont",
-        Object::Name(format!("{}+{}", "AAAAAA", font_name).into_bytes()),
-    );
+    cidfont.set("BaseFont", Object::Name(base_font.as_bytes().to_vec()));
 
     // CIDSystemInfo
     let mut cid_system_info = Dictionary::new();
@@ -139,29 +225,19 @@ fn create_cid_font(font_descriptor_id: ObjectId, font_name: &str, font_data: &[u
     cidfont.set("DW", Object::Integer(600));
 
     // Build W array with actual glyph widths from the font
-    if let Ok(face) = Face::parse(font_data, 0) {
-        let units_per_em = face.units_per_em() as f64;
-        let scale = 1000.0 / units_per_em;
-
-        // Build width array for common glyphs (0-500)
-        let mut w_array: Vec<Object> = Vec::new();
-        let mut i = 0u16;
-        while i < 500 {
-            if let Some(glyph_id) = ttf_parser::GlyphId(i).into() {
-                if let Some(advance) = face.glyph_hor_advance(glyph_id) {
-                    let width = (advance as f64 * scale).round() as i64;
-                    // Format: [gid [width]]
-                    w_array.push(Object::Integer(i as i64));
-                    w_array.push(Object::Array(vec![Object::Integer(width)]));
-                }
-            }
-            i += 1;
-        }
-
-        if !w_array.is_empty() {
-            cidfont.set("W", Object::Array(w_array));
+    let scale = 1000.0 / face.units_per_em() as f64;
+    let mut w_array: Vec<Object> = Vec::new();
+    for &gid in glyphs {
+        if let Some(advance) = face.glyph_hor_advance(GlyphId(gid)) {
+            let width = (advance as f64 * scale).round() as i64;
+            // Format: [gid [width]]
+            w_array.push(Object::Integer(gid as i64));
+            w_array.push(Object::Array(vec![Object::Integer(width)]));
         }
     }
+    if !w_array.is_empty() {
+        cidfont.set("W", Object::Array(w_array));
+    }
 
     // CIDToGIDMap - Identity mapping for TrueType
     cidfont.set("CIDToGIDMap", Object::Name(b"Identity".to_vec()));
@@ -173,15 +249,12 @@ fn create_cid_font(font_descriptor_id: ObjectId, font_name: &str, font_data: &[u
 fn create_type0_font(
     cid_font_id: ObjectId,
     to_unicode_id: ObjectId,
-    font_name: &str,
+    base_font: &str,
 ) -> Dictionary {
     let mut font = Dictionary::new();
     font.set("Type", Object::Name(b"Font".to_vec()));
     font.set("Subtype", Object::Name(b"Type0".to_vec()));
-    font.set(
-        "BaseFont",
-        Object::Name(format!("{}+{}", "AAAAAA", font_name).into_bytes()),
-    );
+    font.set("BaseFont", Object::Name(base_font.as_bytes().to_vec()));
     font.set("Encoding", Object::Name(b"Identity-H".to_vec()));
     font.set(
         "DescendantFonts",
@@ -218,6 +291,249 @@ end"#
         .to_string()
 }
 
+/// Create ToUnicode CMap mapping each subset glyph ID back to its character
+fn create_subset_to_unicode_cmap(glyphs: &BTreeMap<u16, char>) -> String {
+    let mut cmap = String::from(
+        "/CIDInit /ProcSet findresource begin\n\
+         12 dict begin\n\
+         begincmap\n\
+         /CIDSystemInfo\n\
+         << /Registry (Adobe)\n\
+         /Ordering (UCS)\n\
+         /Supplement 0\n\
+         >> def\n\
+         /CMapName /Adobe-Identity-UCS def\n\
+         /CMapType 2 def\n\
+         1 begincodespacerange\n\
+         <0000> <FFFF>\n\
+         endcodespacerange\n",
+    );
+
+    // bfchar blocks hold at most 100 entries
+    let entries: Vec<(&u16, &char)> = glyphs.iter().collect();
+    for chunk in entries.chunks(100) {
+        cmap.push_str(&format!("{} beginbfchar\n", chunk.len()));
+        for (gid, ch) in chunk {
+            let utf16: String = ch
+                .encode_utf16(&mut [0u16; 2])
+                .iter()
+                .map(|unit| format!("{:04X}", unit))
+                .collect();
+            cmap.push_str(&format!("<{:04X}> <{}>\n", gid, utf16));
+        }
+        cmap.push_str("endbfchar\n");
+    }
+
+    cmap.push_str(
+        "endcmap\n\
+         CMapName currentdict /CMap defineresource pop\n\
+         end\n\
+         end",
+    );
+    cmap
+}
+
+/// Random six-letter subset tag (PDF 32000-1 §9.6.4), e.g. "QXKZRB"
+fn random_subset_tag() -> String {
+    let mut rng = rand::thread_rng();
+    (0..6).map(|_| rng.gen_range(b'A'..=b'Z') as char).collect()
+}
+
+/// Glyph IDs used by `text`, each with the first character mapped to it
+/// .notdef (glyph 0) is always included
+fn used_glyphs(face: &Face, text: &str) -> BTreeMap<u16, char> {
+    let mut glyphs = BTreeMap::new();
+    for ch in text.chars() {
+        if let Some(gid) = face.glyph_index(ch) {
+            glyphs.entry(gid.0).or_insert(ch);
+        }
+    }
+    glyphs.entry(0).or_insert('\u{FFFD}');
+    glyphs
+}
+
+/// Build a TrueType subset that keeps glyph IDs stable (required by Identity CIDToGIDMap)
+/// Unused glyphs become empty outlines; composite components are kept automatically
+/// Fonts without glyf/loca (CFF outlines) are returned unchanged
+fn subset_font(font_data: &[u8], glyphs: &BTreeSet<u16>) -> Result<Vec<u8>, String> {
+    let tables = read_table_directory(font_data)?;
+    let table = |tag: &[u8; 4]| tables.get(tag).copied();
+    let (Some(head), Some(maxp), Some(loca), Some(glyf)) = (
+        table(b"head"),
+        table(b"maxp"),
+        table(b"loca"),
+        table(b"glyf"),
+    ) else {
+        return Ok(font_data.to_vec());
+    };
+    if head.len() < 54 || maxp.len() < 6 {
+        return Err("Font head/maxp table truncated".to_string());
+    }
+
+    let num_glyphs = u16::from_be_bytes([maxp[4], maxp[5]]);
+    let long_loca = i16::from_be_bytes([head[50], head[51]]) == 1;
+    let glyph_data = |gid: u16| -> &[u8] {
+        let offset = |index: usize| -> Option<usize> {
+            if long_loca {
+                let bytes = loca.get(index * 4..index * 4 + 4)?;
+                Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+            } else {
+                let bytes = loca.get(index * 2..index * 2 + 2)?;
+                Some(u16::from_be_bytes([bytes[0], bytes[1]]) as usize * 2)
+            }
+        };
+        match (offset(gid as usize), offset(gid as usize + 1)) {
+            (Some(start), Some(end)) if start <= end => glyf.get(start..end).unwrap_or(&[]),
+            _ => &[],
+        }
+    };
+
+    // Close the glyph set over composite glyph components
+    let mut keep: BTreeSet<u16> = glyphs.iter().copied().filter(|&g| g < num_glyphs).collect();
+    keep.insert(0);
+    let mut pending: Vec<u16> = keep.iter().copied().collect();
+    while let Some(gid) = pending.pop() {
+        for component in composite_components(glyph_data(gid)) {
+            if component < num_glyphs && keep.insert(component) {
+                pending.push(component);
+            }
+        }
+    }
+
+    // Rebuild glyf with empty entries for dropped glyphs, using long loca offsets
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::with_capacity((num_glyphs as usize + 1) * 4);
+    for gid in 0..num_glyphs {
+        new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+        if keep.contains(&gid) {
+            new_glyf.extend_from_slice(glyph_data(gid));
+            new_glyf.resize(new_glyf.len().next_multiple_of(4), 0);
+        }
+    }
+    new_loca.extend_from_slice(&(new_glyf.len() as u32).to_be_bytes());
+
+    let mut new_head = head.to_vec();
+    new_head[8..12].fill(0); // checkSumAdjustment, recomputed below
+    new_head[50..52].copy_from_slice(&1i16.to_be_bytes()); // indexToLocFormat: long
+
+    let subset_tables: Vec<(&[u8; 4], Vec<u8>)> = SUBSET_TABLES
+        .iter()
+        .filter_map(|&tag| {
+            let data = match tag {
+                b"glyf" => new_glyf.clone(),
+                b"loca" => new_loca.clone(),
+                b"head" => new_head.clone(),
+                _ => table(tag)?.to_vec(),
+            };
+            Some((tag, data))
+        })
+        .collect();
+    Ok(write_font(&subset_tables))
+}
+
+/// Table tag -> table bytes from the sfnt table directory
+fn read_table_directory(font_data: &[u8]) -> Result<BTreeMap<[u8; 4], &[u8]>, String> {
+    let truncated = || "Font table directory truncated".to_string();
+    let header = font_data.get(..12).ok_or_else(truncated)?;
+    let num_tables = u16::from_be_bytes([header[4], header[5]]) as usize;
+
+    let mut tables = BTreeMap::new();
+    for i in 0..num_tables {
+        let record = font_data
+            .get(12 + i * 16..28 + i * 16)
+            .ok_or_else(truncated)?;
+        let tag = [record[0], record[1], record[2], record[3]];
+        let offset = u32::from_be_bytes([record[8], record[9], record[10], record[11]]) as usize;
+        let length = u32::from_be_bytes([record[12], record[13], record[14], record[15]]) as usize;
+        let data = font_data
+            .get(offset..offset.saturating_add(length))
+            .ok_or_else(|| format!("Font table {} out of bounds", String::from_utf8_lossy(&tag)))?;
+        tables.insert(tag, data);
+    }
+    Ok(tables)
+}
+
+/// Glyph IDs referenced by a composite glyph (numberOfContours < 0)
+fn composite_components(glyph: &[u8]) -> Vec<u16> {
+    let mut components = Vec::new();
+    if glyph.len() < 10 || i16::from_be_bytes([glyph[0], glyph[1]]) >= 0 {
+        return components;
+    }
+
+    let mut pos = 10;
+    while let Some(header) = glyph.get(pos..pos + 4) {
+        let flags = u16::from_be_bytes([header[0], header[1]]);
+        components.push(u16::from_be_bytes([header[2], header[3]]));
+
+        pos += 4;
+        pos += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+        pos += if flags & WE_HAVE_A_SCALE != 0 {
+            2
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            4
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            8
+        } else {
+            0
+        };
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+    components
+}
+
+/// Serialize an sfnt font from (tag, data) pairs sorted by tag
+/// Sets head.checkSumAdjustment so the whole-font checksum is 0xB1B0AFBA
+fn write_font(tables: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let num_tables = tables.len() as u16;
+    let entry_selector = (u16::BITS - 1 - num_tables.max(1).leading_zeros()) as u16;
+    let search_range = (1u16 << entry_selector) * 16;
+    let range_shift = num_tables * 16 - search_range;
+
+    let mut font = Vec::new();
+    font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+    for value in [num_tables, search_range, entry_selector, range_shift] {
+        font.extend_from_slice(&value.to_be_bytes());
+    }
+
+    let mut offset = 12 + tables.len() * 16;
+    let mut head_offset = None;
+    for (tag, data) in tables {
+        if *tag == b"head" {
+            head_offset = Some(offset);
+        }
+        font.extend_from_slice(*tag);
+        font.extend_from_slice(&table_checksum(data).to_be_bytes());
+        font.extend_from_slice(&(offset as u32).to_be_bytes());
+        font.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        offset += data.len().next_multiple_of(4);
+    }
+    for (_, data) in tables {
+        font.extend_from_slice(data);
+        font.resize(font.len().next_multiple_of(4), 0);
+    }
+
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0_AFBAu32.wrapping_sub(table_checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+    font
+}
+
+/// Sum of big-endian u32 words, zero-padded to a multiple of 4 bytes
+fn table_checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut word = [0u8; 4];
+        word[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(word))
+    })
+}
+
 /// Compress data using zlib/deflate
 fn compress_data(data: &[u8]) -> Result<Vec<u8>, String> {
     let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
@@ -260,6 +576,35 @@ fn utf8_to_pdf_hex_with_font(text: &str, font_data: &[u8]) -> String {
     hex
 }
 
+/// Measure text width in PDF points using glyph advances from the regular font
+pub fn text_width(text: &str, font_size: f64) -> f64 {
+    text_width_with_font(text, font_size, BE_VIETNAM_PRO_REGULAR)
+}
+
+/// Measure text width in PDF points using glyph advances from the bold font
+pub fn text_width_bold(text: &str, font_size: f64) -> f64 {
+    text_width_with_font(text, font_size, BE_VIETNAM_PRO_SEMIBOLD)
+}
+
+/// Internal function to measure text width using specified font
+fn text_width_with_font(text: &str, font_size: f64, font_data: &[u8]) -> f64 {
+    let face = match Face::parse(font_data, 0) {
+        Ok(f) => f,
+        // Fallback: approximate average glyph width
+        Err(_) => return text.chars().count() as f64 * font_size * 0.6,
+    };
+
+    let scale = font_size / face.units_per_em() as f64;
+    text.chars()
+        .map(|ch| {
+            face.glyph_index(ch)
+                .and_then(|g| face.glyph_hor_advance(g))
+                .unwrap_or(0) as f64
+                * scale
+        })
+        .sum()
+}
+
 /// Parse hex color string (#RRGGBB) to RGB values (0.0-1.0)
 pub fn parse_color_rgb(color: &str) -> (f64, f64, f64) {
     let color = color.trim_start_matches('#');
@@ -303,6 +648,14 @@ mod tests {
         println!("Hello bold glyph hex: {}", hex);
     }
 
+    #[test]
+    fn test_text_width_bold() {
+        let short = text_width_bold("A", 10.0);
+        let long = text_width_bold("AAAA", 10.0);
+        assert!(short > 0.0);
+        assert!((long - short * 4.0).abs() < 0.01);
+    }
+
     #[test]
     fn test_parse_color_rgb() {
         let (r, g, b) = parse_color_rgb("#FF0000");
@@ -329,6 +682,136 @@ mod tests {
         assert_eq!(&BE_VIETNAM_PRO_SEMIBOLD[0..4], &[0x00, 0x01, 0x00, 0x00]);
     }
 
+    // ============ Font Subsetting Tests ============
+
+    const SIGNER_TEXT: &str = "Được ký bởi: Nguyễn Văn An";
+
+    /// Dictionary behind a reference in `doc`
+    fn dict(doc: &lopdf::Document, object: &Object) -> Dictionary {
+        doc.get_dictionary(object.as_reference().unwrap())
+            .unwrap()
+            .clone()
+    }
+
+    #[test]
+    fn test_subset_font_keeps_used_glyphs_only() {
+        let face = Face::parse(BE_VIETNAM_PRO_REGULAR, 0).unwrap();
+        let used = used_glyphs(&face, SIGNER_TEXT);
+        let subset = subset_font(BE_VIETNAM_PRO_REGULAR, &used.keys().copied().collect()).unwrap();
+
+        let subset_face = Face::parse(&subset, 0).expect("Subset must be a valid font");
+        // Glyph IDs are preserved for the Identity CIDToGIDMap
+        assert_eq!(subset_face.number_of_glyphs(), face.number_of_glyphs());
+        for ch in ['Đ', 'ễ', 'ă', 'A'] {
+            let gid = face.glyph_index(ch).unwrap();
+            assert_eq!(
+                subset_face.glyph_bounding_box(gid),
+                face.glyph_bounding_box(gid),
+                "{}",
+                ch
+            );
+        }
+        let unused = face.glyph_index('Z').unwrap();
+        assert!(face.glyph_bounding_box(unused).is_some());
+        assert!(subset_face.glyph_bounding_box(unused).is_none());
+    }
+
+    #[test]
+    fn test_subset_font_size() {
+        let face = Face::parse(BE_VIETNAM_PRO_SEMIBOLD, 0).unwrap();
+        let used = used_glyphs(&face, "CÔNG TY TNHH GIẢI PHÁP CÔNG NGHỆ VIỆT NAM");
+        let subset = subset_font(BE_VIETNAM_PRO_SEMIBOLD, &used.keys().copied().collect()).unwrap();
+        assert!(subset.len() < 30 * 1024, "subset is {} bytes", subset.len());
+        assert!(subset.len() < BE_VIETNAM_PRO_SEMIBOLD.len() / 4);
+    }
+
+    #[test]
+    fn test_subset_font_checksum_adjustment() {
+        let subset = subset_font(BE_VIETNAM_PRO_REGULAR, &BTreeSet::from([0, 1, 2])).unwrap();
+        assert_eq!(table_checksum(&subset), 0xB1B0_AFBA);
+        let tables = read_table_directory(&subset).unwrap();
+        let tags: Vec<&[u8; 4]> = tables.keys().collect();
+        assert_eq!(tags, SUBSET_TABLES.to_vec());
+    }
+
+    #[test]
+    fn test_composite_components() {
+        // numberOfContours = -1, bbox, then two components (word args, then byte args + scale)
+        let mut glyph = vec![0xFF, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0];
+        glyph.extend_from_slice(&(ARG_1_AND_2_ARE_WORDS | MORE_COMPONENTS).to_be_bytes());
+        glyph.extend_from_slice(&7u16.to_be_bytes());
+        glyph.extend_from_slice(&[0, 0, 0, 0]);
+        glyph.extend_from_slice(&WE_HAVE_A_SCALE.to_be_bytes());
+        glyph.extend_from_slice(&42u16.to_be_bytes());
+        glyph.extend_from_slice(&[0, 0, 0x40, 0x00]);
+        assert_eq!(composite_components(&glyph), vec![7, 42]);
+
+        // Simple glyph (numberOfContours >= 0) has no components
+        assert!(composite_components(&[0, 1, 0, 0, 0, 0, 0, 0, 0, 0]).is_empty());
+        assert!(composite_components(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_embed_font_subset_dictionaries() {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let embedded = embed_vietnamese_font(&mut doc, "F1", SIGNER_TEXT).unwrap();
+
+        let type0 = doc.get_dictionary(embedded.font_id).unwrap().clone();
+        let base_font =
+            String::from_utf8(type0.get(b"BaseFont").unwrap().as_name().unwrap().to_vec()).unwrap();
+        let (tag, name) = base_font.split_once('+').unwrap();
+        assert_eq!(name, FONT_NAME);
+        assert_eq!(tag.len(), 6);
+        assert!(tag.chars().all(|c| c.is_ascii_uppercase()));
+
+        // W array: one [gid [width]] pair per distinct glyph
+        let face = Face::parse(BE_VIETNAM_PRO_REGULAR, 0).unwrap();
+        let used = used_glyphs(&face, SIGNER_TEXT);
+        let descendants = type0.get(b"DescendantFonts").unwrap().as_array().unwrap();
+        let cid_font = dict(&doc, &descendants[0]);
+        let widths = cid_font.get(b"W").unwrap().as_array().unwrap();
+        assert_eq!(widths.len(), used.len() * 2);
+
+        // Metrics come from the subset glyphs, not the whole font
+        let descriptor = dict(&doc, cid_font.get(b"FontDescriptor").unwrap());
+        let metrics = FontMetrics::from_glyphs(&face, &used.keys().copied().collect::<Vec<_>>());
+        assert_eq!(
+            descriptor.get(b"Ascent").unwrap().as_i64().unwrap(),
+            metrics.ascent
+        );
+        assert_ne!(
+            metrics,
+            FontMetrics::from_glyphs(&face, &(0..face.number_of_glyphs()).collect::<Vec<_>>())
+        );
+
+        // ToUnicode maps subset glyph IDs back to characters
+        let cmap_id = type0.get(b"ToUnicode").unwrap().as_reference().unwrap();
+        let cmap_stream = doc.get_object(cmap_id).unwrap().as_stream().unwrap();
+        let cmap = String::from_utf8(cmap_stream.decompressed_content().unwrap()).unwrap();
+        let gid = face.glyph_index('ễ').unwrap().0;
+        assert!(cmap.contains(&format!("<{:04X}> <1EC5>", gid)));
+        assert!(cmap.contains(&format!("{} beginbfchar", used.len())));
+        assert!(!cmap.contains("beginbfrange"));
+    }
+
+    #[test]
+    fn test_embed_full_font_has_no_subset_tag() {
+        let mut doc = lopdf::Document::with_version("1.7");
+        let embedded = embed_vietnamese_font_full(&mut doc, "F1").unwrap();
+        let type0 = doc.get_dictionary(embedded.font_id).unwrap();
+        assert_eq!(
+            type0.get(b"BaseFont").unwrap().as_name().unwrap(),
+            FONT_NAME.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_random_subset_tag() {
+        let tag = random_subset_tag();
+        assert_eq!(tag.len(), 6);
+        assert!(tag.chars().all(|c| c.is_ascii_uppercase()));
+    }
+
     #[test]
     fn test_font_parsing() {
         let face = Face::parse(BE_VIETNAM_PRO_REGULAR, 0).expect("Failed to parse font");
@@ -0,0 +1,741 @@
+//! Signature Image Module
+//!
+//! Fetches official seal images by URL and converts PNG/JPEG data into
+//! PDF image XObjects for signature appearances.
+
+use crate::error::ESignError;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use lopdf::{Dictionary, Document, Object, ObjectId, Stream};
+use reqwest::blocking::Client;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Maximum accepted seal image size (512 KB)
+pub const MAX_SEAL_IMAGE_BYTES: usize = 512 * 1024;
+
+/// Largest accepted width or height of an uploaded signature image (pixels)
+pub const MAX_SIGNATURE_IMAGE_DIMENSION: u32 = 4096;
+
+/// Timeout for fetching a seal image
+const FETCH_TIMEOUT_SECS: u64 = 5;
+
+/// Supported signature image formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    /// Parse HTTP Content-Type (parameters like charset are ignored)
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next()?.trim().to_ascii_lowercase();
+        match mime.as_str() {
+            "image/png" => Some(Self::Png),
+            "image/jpeg" => Some(Self::Jpeg),
+            _ => None,
+        }
+    }
+
+    /// Detect format from file signature bytes
+    pub fn from_magic(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Self::Jpeg)
+        } else {
+            None
+        }
+    }
+}
+
+/// Encoded image data ready to embed
+#[derive(Debug, Clone)]
+pub struct SignatureImage {
+    pub format: ImageFormat,
+    pub data: Vec<u8>,
+}
+
+impl SignatureImage {
+    /// Wrap raw image bytes, detecting format from the file signature
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self, ESignError> {
+        if data.len() > MAX_SEAL_IMAGE_BYTES {
+            return Err(ESignError::Pdf(format!(
+                "Image too large: {} KB (max {} KB)",
+                data.len() / 1024,
+                MAX_SEAL_IMAGE_BYTES / 1024
+            )));
+        }
+        let format = ImageFormat::from_magic(&data)
+            .ok_or_else(|| ESignError::Pdf("Image must be PNG or JPEG".to_string()))?;
+        Ok(Self { format, data })
+    }
+}
+
+/// Format and size of an uploaded signature image
+#[derive(Debug, Clone, Serialize)]
+pub struct ImageInfo {
+    /// "png" or "jpeg"
+    pub format: String,
+    pub width: u32,
+    pub height: u32,
+    pub size_bytes: usize,
+    /// PNG with an alpha channel (embedded as /SMask)
+    pub has_alpha: bool,
+}
+
+/// Check a base64 signature image before signing: format, byte size and dimensions
+/// PNGs are fully decoded, so anything accepted here can be embedded
+pub fn signature_image_info(image_base64: &str) -> Result<ImageInfo, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let data = STANDARD
+        .decode(image_base64.trim())
+        .map_err(|e| ESignError::Pdf(format!("Invalid image base64: {}", e)))?;
+    let image = SignatureImage::from_bytes(data)?;
+
+    let (format, width, height, has_alpha) = match image.format {
+        ImageFormat::Jpeg => {
+            let info = parse_jpeg_info(&image.data)?;
+            ("jpeg", info.width, info.height, false)
+        }
+        ImageFormat::Png => {
+            let png = decode_png(&image.data)?;
+            ("png", png.width, png.height, png.alpha.is_some())
+        }
+    };
+    if width == 0 || height == 0 {
+        return Err(ESignError::Pdf("Image has no pixels".to_string()));
+    }
+    if width > MAX_SIGNATURE_IMAGE_DIMENSION || height > MAX_SIGNATURE_IMAGE_DIMENSION {
+        return Err(ESignError::Pdf(format!(
+            "Image too large: {}x{} pixels (max {} per side)",
+            width, height, MAX_SIGNATURE_IMAGE_DIMENSION
+        )));
+    }
+
+    Ok(ImageInfo {
+        format: format.to_string(),
+        width,
+        height,
+        size_bytes: image.data.len(),
+        has_alpha,
+    })
+}
+
+/// Fetch a seal image (PNG/JPEG, max 512 KB, 5 second timeout)
+pub fn fetch_seal_image(url: &str) -> Result<SignatureImage, ESignError> {
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(ESignError::Pdf(format!(
+            "Seal image URL must use http or https: {}",
+            url
+        )));
+    }
+
+    let client = Client::builder()
+        .timeout(Duration::from_secs(FETCH_TIMEOUT_SECS))
+        .build()
+        .map_err(|e| ESignError::Pdf(format!("Failed to create HTTP client: {}", e)))?;
+
+    let response = client
+        .get(url)
+        .send()
+        .map_err(|e| ESignError::Pdf(format!("Failed to fetch seal image {}: {}", url, e)))?;
+
+    if !response.status().is_success() {
+        return Err(ESignError::Pdf(format!(
+            "Seal image request failed with status: {}",
+            response.status()
+        )));
+    }
+
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let format = ImageFormat::from_content_type(&content_type).ok_or_else(|| {
+        ESignError::Pdf(format!(
+            "Seal image must be image/png or image/jpeg, got '{}'",
+            content_type
+        ))
+    })?;
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_SEAL_IMAGE_BYTES as u64)
+    {
+        return Err(ESignError::Pdf(format!(
+            "Seal image exceeds {} KB",
+            MAX_SEAL_IMAGE_BYTES / 1024
+        )));
+    }
+
+    // Content-Length may be absent or wrong, so cap the read as well
+    let mut data = Vec::new();
+    response
+        .take(MAX_SEAL_IMAGE_BYTES as u64 + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| ESignError::Pdf(format!("Failed to read seal image: {}", e)))?;
+    if data.len() > MAX_SEAL_IMAGE_BYTES {
+        return Err(ESignError::Pdf(format!(
+            "Seal image exceeds {} KB",
+            MAX_SEAL_IMAGE_BYTES / 1024
+        )));
+    }
+
+    Ok(SignatureImage { format, data })
+}
+
+/// Fetched seal images keyed by SHA-256 of the URL
+#[derive(Default)]
+pub struct ImageCache {
+    entries: Mutex<HashMap<String, SignatureImage>>,
+}
+
+impl ImageCache {
+    /// Create empty image cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Return cached image for URL, fetching it on first use
+    pub fn get_or_fetch(&self, url: &str) -> Result<SignatureImage, ESignError> {
+        let key = hex::encode(Sha256::digest(url.as_bytes()));
+
+        if let Some(image) = self.entries.lock().ok().and_then(|e| e.get(&key).cloned()) {
+            return Ok(image);
+        }
+
+        // Fetch without holding the lock (network may take up to 5 seconds)
+        let image = fetch_seal_image(url)?;
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(key, image.clone());
+        }
+        Ok(image)
+    }
+}
+
+/// Embedded image XObject with its pixel dimensions
+pub struct ImageXObject {
+    pub id: ObjectId,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Add an image XObject to the document
+/// JPEG is embedded as-is (DCTDecode); PNG is decoded and re-encoded with
+/// FlateDecode, with any alpha channel split into an /SMask
+pub fn create_image_xobject(
+    doc: &mut Document,
+    image: &SignatureImage,
+) -> Result<ImageXObject, ESignError> {
+    match image.format {
+        ImageFormat::Jpeg => {
+            let info = parse_jpeg_info(&image.data)?;
+            let mut dict = image_dict(info.width, info.height, 8);
+            dict.set("ColorSpace", Object::Name(info.color_space.to_vec()));
+            dict.set("Filter", Object::Name(b"DCTDecode".to_vec()));
+
+            let mut stream = Stream::new(dict, image.data.clone());
+            stream.allows_compression = false;
+            Ok(ImageXObject {
+                id: doc.add_object(Object::Stream(stream)),
+                width: info.width,
+                height: info.height,
+            })
+        }
+        ImageFormat::Png => {
+            let png = decode_png(&image.data)?;
+            let mut dict = image_dict(png.width, png.height, png.bit_depth);
+            dict.set("ColorSpace", png.color_space);
+            dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
+
+            if let Some(alpha) = png.alpha {
+                let mut mask_dict = image_dict(png.width, png.height, 8);
+                mask_dict.set("ColorSpace", Object::Name(b"DeviceGray".to_vec()));
+                mask_dict.set("Filter", Object::Name(b"FlateDecode".to_vec()));
+                let mask_id = doc.add_object(Object::Stream(Stream::new(
+                    mask_dict,
+                    zlib_compress(&alpha)?,
+                )));
+                dict.set("SMask", Object::Reference(mask_id));
+            }
+
+            let stream = Stream::new(dict, zlib_compress(&png.pixels)?);
+            Ok(ImageXObject {
+                id: doc.add_object(Object::Stream(stream)),
+                width: png.width,
+                height: png.height,
+            })
+        }
+    }
+}
+
+/// Common image XObject dictionary entries
+fn image_dict(width: u32, height: u32, bits_per_component: u8) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("Type", Object::Name(b"XObject".to_vec()));
+    dict.set("Subtype", Object::Name(b"Image".to_vec()));
+    dict.set("Width", Object::Integer(width as i64));
+    dict.set("Height", Object::Integer(height as i64));
+    dict.set(
+        "BitsPerComponent",
+        Object::Integer(bits_per_component as i64),
+    );
+    dict
+}
+
+/// JPEG frame information needed for the image dictionary
+#[derive(Debug)]
+struct JpegInfo {
+    width: u32,
+    height: u32,
+    color_space: &'static [u8],
+}
+
+/// Read dimensions and component count from the JPEG SOF segment
+fn parse_jpeg_info(data: &[u8]) -> Result<JpegInfo, ESignError> {
+    let invalid = || ESignError::Pdf("Invalid JPEG image".to_string());
+
+    let mut pos = 2; // Skip SOI
+    while pos + 4 <= data.len() {
+        if data[pos] != 0xFF {
+            return Err(invalid());
+        }
+        let marker = data[pos + 1];
+        if marker == 0xFF {
+            pos += 1; // Fill byte
+            continue;
+        }
+        let segment_len = u16::from_be_bytes([data[pos + 2], data[pos + 3]]) as usize;
+
+        // SOF0-SOF15, excluding DHT (C4), JPG (C8) and DAC (CC)
+        if (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker) {
+            let sof = data.get(pos + 4..pos + 10).ok_or_else(invalid)?;
+            let height = u16::from_be_bytes([sof[1], sof[2]]) as u32;
+            let width = u16::from_be_bytes([sof[3], sof[4]]) as u32;
+            let color_space: &'static [u8] = match sof[5] {
+                1 => b"DeviceGray",
+                3 => b"DeviceRGB",
+                4 => b"DeviceCMYK",
+                n => {
+                    return Err(ESignError::Pdf(format!(
+                        "Unsupported JPEG component count: {}",
+                        n
+                    )))
+                }
+            };
+            return Ok(JpegInfo {
+                width,
+                height,
+                color_space,
+            });
+        }
+
+        pos += 2 + segment_len;
+    }
+
+    Err(invalid())
+}
+
+/// Decoded PNG ready for embedding
+struct DecodedPng {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_space: Object,
+    /// Unfiltered color samples (packed rows for palette images)
+    pixels: Vec<u8>,
+    /// 8-bit alpha samples for color types 4 and 6
+    alpha: Option<Vec<u8>>,
+}
+
+/// Decode a non-interlaced PNG (8-bit gray/RGB/GA/RGBA, or palette)
+fn decode_png(data: &[u8]) -> Result<DecodedPng, ESignError> {
+    let invalid = |msg: &str| ESignError::Pdf(format!("Invalid PNG image: {}", msg));
+
+    if ImageFormat::from_magic(data) != Some(ImageFormat::Png) {
+        return Err(invalid("bad signature"));
+    }
+
+    let mut header = None;
+    let mut palette = None;
+    let mut idat = Vec::new();
+    let mut pos = 8;
+    while pos + 8 <= data.len() {
+        let len =
+            u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize;
+        let chunk_type = &data[pos + 4..pos + 8];
+        let body = data
+            .get(pos + 8..pos + 8 + len)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        match chunk_type {
+            b"IHDR" if len == 13 => header = Some(body.to_vec()),
+            b"PLTE" => palette = Some(body.to_vec()),
+            b"IDAT" => idat.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos += 12 + len; // length + type + body + CRC
+    }
+
+    let header = header.ok_or_else(|| invalid("missing IHDR"))?;
+    let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+    let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+    let bit_depth = header[8];
+    let color_type = header[9];
+    if header[12] != 0 {
+        return Err(ESignError::Pdf(
+            "Interlaced PNG images are not supported".to_string(),
+        ));
+    }
+
+    let channels: usize = match (color_type, bit_depth) {
+        (0, 8) => 1,
+        (2, 8) => 3,
+        (3, 1 | 2 | 4 | 8) => 1,
+        (4, 8) => 2,
+        (6, 8) => 4,
+        _ => {
+            return Err(ESignError::Pdf(format!(
+                "Unsupported PNG format (color type {}, bit depth {})",
+                color_type, bit_depth
+            )))
+        }
+    };
+
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(idat.as_slice())
+        .read_to_end(&mut filtered)
+        .map_err(|e| invalid(&e.to_string()))?;
+
+    let bits_per_pixel = channels * bit_depth as usize;
+    let row_bytes = (width as usize * bits_per_pixel).div_ceil(8);
+    let bytes_per_pixel = bits_per_pixel.div_ceil(8).max(1);
+    let raw = unfilter_png_rows(&filtered, row_bytes, height as usize, bytes_per_pixel)
+        .ok_or_else(|| invalid("corrupt image data"))?;
+
+    let (pixels, alpha, color_space) = match color_type {
+        0 => (raw, None, Object::Name(b"DeviceGray".to_vec())),
+        2 => (raw, None, Object::Name(b"DeviceRGB".to_vec())),
+        3 => {
+            let palette = palette.ok_or_else(|| invalid("missing PLTE"))?;
+            let entries = (palette.len() / 3).max(1) as i64;
+            let indexed = Object::Array(vec![
+                Object::Name(b"Indexed".to_vec()),
+                Object::Name(b"DeviceRGB".to_vec()),
+                Object::Integer(entries - 1),
+                Object::String(palette, lopdf::StringFormat::Hexadecimal),
+            ]);
+            (raw, None, indexed)
+        }
+        4 => {
+            let (gray, alpha) = split_alpha(&raw, 1);
+            (gray, Some(alpha), Object::Name(b"DeviceGray".to_vec()))
+        }
+        _ => {
+            let (rgb, alpha) = split_alpha(&raw, 3);
+            (rgb, Some(alpha), Object::Name(b"DeviceRGB".to_vec()))
+        }
+    };
+
+    Ok(DecodedPng {
+        width,
+        height,
+        bit_depth,
+        color_space,
+        pixels,
+        alpha,
+    })
+}
+
+/// Reverse PNG row filters (None, Sub, Up, Average, Paeth)
+fn unfilter_png_rows(
+    data: &[u8],
+    row_bytes: usize,
+    rows: usize,
+    bytes_per_pixel: usize,
+) -> Option<Vec<u8>> {
+    if data.len() < rows * (row_bytes + 1) {
+        return None;
+    }
+
+    let mut output = vec![0u8; rows * row_bytes];
+    for row in 0..rows {
+        let filter = data[row * (row_bytes + 1)];
+        let src = &data[row * (row_bytes + 1) + 1..(row + 1) * (row_bytes + 1)];
+        let (done, current) = output.split_at_mut(row * row_bytes);
+        let prev = if row > 0 {
+            &done[(row - 1) * row_bytes..]
+        } else {
+            &[][..]
+        };
+        let current = &mut current[..row_bytes];
+
+        for i in 0..row_bytes {
+            let left = if i >= bytes_per_pixel {
+                current[i - bytes_per_pixel]
+            } else {
+                0
+            };
+            let up = prev.get(i).copied().unwrap_or(0);
+            let up_left = if i >= bytes_per_pixel {
+                prev.get(i - bytes_per_pixel).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            let predictor = match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return None,
+            };
+            current[i] = src[i].wrapping_add(predictor);
+        }
+    }
+
+    Some(output)
+}
+
+/// PNG Paeth predictor
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Split interleaved color+alpha samples into separate planes
+fn split_alpha(raw: &[u8], color_channels: usize) -> (Vec<u8>, Vec<u8>) {
+    let pixel_size = color_channels + 1;
+    let mut color = Vec::with_capacity(raw.len() / pixel_size * color_channels);
+    let mut alpha = Vec::with_capacity(raw.len() / pixel_size);
+    for pixel in raw.chunks_exact(pixel_size) {
+        color.extend_from_slice(&pixel[..color_channels]);
+        alpha.push(pixel[color_channels]);
+    }
+    (color, alpha)
+}
+
+/// Compress data using zlib/deflate
+fn zlib_compress(data: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(data)
+        .map_err(|e| ESignError::Pdf(format!("Image compression failed: {}", e)))?;
+    encoder
+        .finish()
+        .map_err(|e| ESignError::Pdf(format!("Image compression failed: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::sample_png;
+    use httptest::{matchers::*, responders::*, Expectation, Server};
+
+    // ============ Format Detection Tests ============
+
+    #[test]
+    fn test_format_from_content_type() {
+        assert_eq!(
+            ImageFormat::from_content_type("image/png"),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::from_content_type("image/jpeg; charset=binary"),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(ImageFormat::from_content_type("text/html"), None);
+    }
+
+    #[test]
+    fn test_format_from_magic() {
+        assert_eq!(
+            ImageFormat::from_magic(&sample_png(1, 1, false)),
+            Some(ImageFormat::Png)
+        );
+        assert_eq!(
+            ImageFormat::from_magic(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(ImageFormat::Jpeg)
+        );
+        assert_eq!(ImageFormat::from_magic(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn test_signature_image_rejects_oversized() {
+        let mut data = sample_png(1, 1, false);
+        data.resize(MAX_SEAL_IMAGE_BYTES + 1, 0);
+        assert!(SignatureImage::from_bytes(data).is_err());
+    }
+
+    #[test]
+    fn test_signature_image_info_png() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        let info = signature_image_info(&STANDARD.encode(sample_png(3, 2, true))).unwrap();
+        assert_eq!(info.format, "png");
+        assert_eq!((info.width, info.height), (3, 2));
+        assert!(info.has_alpha);
+    }
+
+    #[test]
+    fn test_signature_image_info_rejects_invalid() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        assert!(signature_image_info("not base64!").is_err());
+        assert!(signature_image_info(&STANDARD.encode(b"GIF89a")).is_err());
+        // Truncated PNG: valid signature, no image data
+        assert!(signature_image_info(&STANDARD.encode(b"\x89PNG\r\n\x1a\n")).is_err());
+    }
+
+    #[test]
+    fn test_signature_image_info_rejects_large_dimensions() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+        // SOF0 declaring 5000x80
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x50, 0x13, 0x88, 0x03,
+        ];
+        let err = signature_image_info(&STANDARD.encode(jpeg)).unwrap_err();
+        assert!(err.to_string().contains("5000x80"));
+    }
+
+    // ============ Decoding Tests ============
+
+    #[test]
+    fn test_parse_jpeg_info() {
+        // SOI, APP0 (empty payload), SOF0 120x80 with 3 components
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x02, 0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0x50, 0x00,
+            0x78, 0x03,
+        ];
+        let info = parse_jpeg_info(&jpeg).unwrap();
+        assert_eq!(info.width, 120);
+        assert_eq!(info.height, 80);
+        assert_eq!(info.color_space, b"DeviceRGB");
+    }
+
+    #[test]
+    fn test_decode_png_rgb() {
+        let png = decode_png(&sample_png(2, 3, false)).unwrap();
+        assert_eq!((png.width, png.height), (2, 3));
+        assert_eq!(png.pixels.len(), 2 * 3 * 3);
+        assert!(png.alpha.is_none());
+    }
+
+    #[test]
+    fn test_decode_png_rgba_splits_alpha() {
+        let png = decode_png(&sample_png(2, 2, true)).unwrap();
+        assert_eq!(png.pixels.len(), 2 * 2 * 3);
+        assert_eq!(png.alpha.unwrap().len(), 2 * 2);
+    }
+
+    #[test]
+    fn test_unfilter_png_rows_sub_and_up() {
+        // Row 0: Sub filter, Row 1: Up filter (1 byte per pixel)
+        let data = [1, 10, 5, 2, 1, 1];
+        let raw = unfilter_png_rows(&data, 2, 2, 1).unwrap();
+        assert_eq!(raw, vec![10, 15, 11, 16]);
+    }
+
+    #[test]
+    fn test_create_image_xobject_png_with_smask() {
+        let mut doc = Document::with_version("1.5");
+        let image = SignatureImage::from_bytes(sample_png(4, 4, true)).unwrap();
+
+        let xobject = create_image_xobject(&mut doc, &image).unwrap();
+        assert_eq!((xobject.width, xobject.height), (4, 4));
+
+        let stream = doc.get_object(xobject.id).unwrap().as_stream().unwrap();
+        assert!(stream.dict.has(b"SMask"));
+    }
+
+    // ============ Fetch and Cache Tests ============
+
+    #[test]
+    fn test_fetch_seal_image_png() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/seal.png")).respond_with(
+                status_code(200)
+                    .insert_header("Content-Type", "image/png")
+                    .body(sample_png(1, 1, false)),
+            ),
+        );
+
+        let image = fetch_seal_image(&server.url("/seal.png").to_string()).unwrap();
+        assert_eq!(image.format, ImageFormat::Png);
+        assert_eq!(image.data, sample_png(1, 1, false));
+    }
+
+    #[test]
+    fn test_fetch_seal_image_rejects_content_type() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/seal.gif")).respond_with(
+                status_code(200)
+                    .insert_header("Content-Type", "image/gif")
+                    .body("GIF89a"),
+            ),
+        );
+
+        assert!(fetch_seal_image(&server.url("/seal.gif").to_string()).is_err());
+    }
+
+    #[test]
+    fn test_fetch_seal_image_rejects_oversized() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/big.png")).respond_with(
+                status_code(200)
+                    .insert_header("Content-Type", "image/png")
+                    .body(vec![0u8; MAX_SEAL_IMAGE_BYTES + 1]),
+            ),
+        );
+
+        assert!(fetch_seal_image(&server.url("/big.png").to_string()).is_err());
+    }
+
+    #[test]
+    fn test_fetch_seal_image_rejects_non_http_url() {
+        assert!(fetch_seal_image("file:///etc/passwd").is_err());
+    }
+
+    #[test]
+    fn test_image_cache_fetches_once() {
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("GET", "/seal.png"))
+                .times(1)
+                .respond_with(
+                    status_code(200)
+                        .insert_header("Content-Type", "image/png")
+                        .body(sample_png(1, 1, false)),
+                ),
+        );
+
+        let cache = ImageCache::new();
+        let url = server.url("/seal.png").to_string();
+        let first = cache.get_or_fetch(&url).unwrap();
+        let second = cache.get_or_fetch(&url).unwrap();
+        assert_eq!(first.data, second.data);
+    }
+}
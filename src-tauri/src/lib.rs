@@ -3,221 +3,1886 @@
 //! This library provides the backend functionality for the eSign Desktop application,
 //! including PKCS#11 token communication, PDF signing, and TSA integration.
 
+mod audit;
+mod config;
+mod crl;
+mod digest;
 mod error;
 mod font;
+mod image;
+mod ocsp;
+mod oid;
 mod pdf;
 mod pkcs11;
+mod pkcs12;
+mod preview;
+mod signing_lock;
+mod slot_registry;
+mod token_monitor;
 mod tsa;
+pub mod verify;
+mod xml_sign;
 
-use pdf::{PdfSigner, PdfSigningEngine, SignResult};
-use pkcs11::{CertificateInfo, DetectedLibrary, TokenInfo, TokenManager};
-use std::sync::Mutex;
-use tauri::State;
+#[cfg(test)]
+mod test_utils;
+
+// Library API, for signing without the Tauri commands
+pub use error::{CertValidationCode, ESignError, SigningErrorCode};
+pub use pdf::{PdfSigner, PdfSignerBuilder, PdfSigningEngine, SignResult};
+pub use tsa::{TsaClient, TsaConfig};
+
+use audit::{AuditLogger, AuditOperation};
+use config::AppConfig;
+use crl::{CrlCache, RevocationStatus};
+use image::{ImageCache, ImageInfo};
+use ocsp::{OcspClient, OcspResponse};
+use pdf::{
+    BatchSignJob, BatchSignProgress, BatchSignResult, CertifyResult, CertifyStatus, PageInfo,
+    PdfValidationReport, SignatureFieldInfo, SignatureTemplate,
+};
+use pkcs11::helpers::{
+    allowed_library_prefixes, certificate_to_pem, certificate_validity_report, validate_pin,
+};
+use pkcs11::{
+    detect_duplicate_library_path, CertExportFormat, CertPolicyInfo, CertValidityReport,
+    CertificateInfo, CertificateInfoExtended, DetectedLibrary, KeyInfo, LibraryManager,
+    LibraryVersionInfo, MechanismInfo, SignMechanism, SigningAlgorithm, TokenInfo, TokenManager,
+    TokenManagerConfig, VendorInfo,
+};
+use pkcs12::P12ExportResult;
+use serde::Deserialize;
+use signing_lock::SigningLockGuard;
+use slot_registry::SlotRegistry;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Manager, RunEvent, State};
+use token_monitor::{TokenChange, TokenMonitor, POLL_INTERVAL};
+use tsa::{TsaClientBuilder, TsaHealthResult};
+use verify::{PdfDiffReport, SignatureVerificationResult};
+use xml_sign::XmlSignResult;
+use zeroize::Zeroize;
+
+/// Registry key behind the single-token commands (init_token_manager, login_token, sign_pdf)
+const DEFAULT_SLOT: u64 = 0;
+
+/// Idle time after which token sessions are logged out (5 minutes)
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Delay between two idle timeout checks
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Status polling commands that do not count as user activity
+const PASSIVE_COMMANDS: [&str; 3] = [
+    "get_session_remaining_seconds",
+    "check_token_status",
+    "check_session_alive",
+];
 
 /// Application state shared across commands
-/// Uses Mutex for thread-safe access to TokenManager
+/// Each slot's TokenManager has its own Mutex, so tokens can be used concurrently
 pub struct AppState {
-    token_manager: Mutex<Option<TokenManager>>,
+    /// Token managers keyed by slot id; DEFAULT_SLOT backs the single-token commands
+    token_managers: SlotRegistry<TokenManager>,
+    /// PKCS#11 libraries preloaded at startup
+    library_manager: LibraryManager,
+    /// Seal images fetched by URL, shared across signing operations
+    image_cache: Arc<ImageCache>,
+    /// Set per slot while a PDF signing operation holds that token
+    signing_in_progress: SlotRegistry<bool>,
+    /// Last successful (or in-flight) initialization per library path
+    library_init_timestamps: Mutex<HashMap<String, Instant>>,
+    /// Background slot poller, running between start/stop_monitoring
+    token_monitor: Mutex<Option<TokenMonitor>>,
+    /// CKA_ID of the certificate chosen with select_certificate, used at login
+    selected_cert_id: Mutex<Option<Vec<u8>>>,
+    /// Sessions are logged out after this long without activity; zero means never
+    idle_timeout: Mutex<Duration>,
+    /// Time of the last command call (status polling excluded)
+    last_activity: Mutex<Instant>,
+    /// Login and signing attempts; disabled unless set up in run()
+    audit_logger: AuditLogger,
+    /// Downloaded CRLs, reused until their nextUpdate
+    crl_cache: CrlCache,
+    /// Settings persisted between sessions
+    config: Mutex<AppConfig>,
+    /// Where `config` is saved; None disables persistence (tests)
+    config_path: Option<PathBuf>,
 }
 
 impl Default for AppState {
     fn default() -> Self {
         Self {
-            token_manager: Mutex::new(None),
+            token_managers: SlotRegistry::new(),
+            library_manager: LibraryManager::new(),
+            image_cache: Arc::new(ImageCache::new()),
+            signing_in_progress: SlotRegistry::new(),
+            library_init_timestamps: Mutex::new(HashMap::new()),
+            token_monitor: Mutex::new(None),
+            selected_cert_id: Mutex::new(None),
+            idle_timeout: Mutex::new(DEFAULT_IDLE_TIMEOUT),
+            last_activity: Mutex::new(Instant::now()),
+            audit_logger: AuditLogger::disabled(),
+            crl_cache: CrlCache::new(),
+            config: Mutex::new(AppConfig::default()),
+            config_path: None,
+        }
+    }
+}
+
+impl AppState {
+    /// Settings saved by the previous session (defaults if there are none)
+    pub fn load_config() -> AppConfig {
+        config::load_config(&config::default_config_path())
+    }
+
+    /// State for the app run: audit log and idle timeout taken from the saved config
+    fn from_config(config: AppConfig, config_path: PathBuf) -> Self {
+        let audit_log_path = config
+            .audit_log_path
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(audit::default_log_path);
+        Self {
+            audit_logger: AuditLogger::new(audit_log_path),
+            idle_timeout: Mutex::new(Duration::from_secs(config.idle_timeout_secs)),
+            config: Mutex::new(config),
+            config_path: Some(config_path),
+            ..Self::default()
+        }
+    }
+
+    /// Copy the current library and idle timeout into the config and write it
+    fn persist_config(&self) -> Result<(), ESignError> {
+        let last_library_path = match self.token_managers.get(DEFAULT_SLOT) {
+            Some(entry) => Some(
+                entry
+                    .lock()
+                    .map_err(|_| ESignError::lock_poisoned("Token manager"))?
+                    .library_path()
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let idle_timeout = *self
+            .idle_timeout
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Idle timeout"))?;
+
+        let mut config = self
+            .config
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Config"))?;
+        if last_library_path.is_some() {
+            config.last_library_path = last_library_path;
+        }
+        config.idle_timeout_secs = idle_timeout.as_secs();
+
+        match self.config_path {
+            Some(ref path) => config::save_config(path, &config),
+            None => Ok(()),
+        }
+    }
+
+    /// Initialize the library from the config (if still installed) and select the preferred slot
+    fn restore_saved_library(&self) -> Result<(), ESignError> {
+        let (library_path, preferred_slot_id) = {
+            let config = self
+                .config
+                .lock()
+                .map_err(|_| ESignError::lock_poisoned("Config"))?;
+            (config.last_library_path.clone(), config.preferred_slot_id)
+        };
+        let Some(library_path) = library_path.filter(|path| Path::new(path).exists()) else {
+            return Ok(());
+        };
+
+        init_token_manager_inner(self, &library_path, DEFAULT_SLOT)?;
+        if let Some(slot_id) = preferred_slot_id {
+            self.token_managers
+                .with_slot(DEFAULT_SLOT, |manager| manager.select_slot(slot_id))
+                .map_err(ESignError::Pkcs11)??;
+        }
+        Ok(())
+    }
+
+    /// Manager registered for `slot_id`; the registry lock is released on return
+    fn manager_for_slot(&self, slot_id: u64) -> Result<Arc<Mutex<TokenManager>>, ESignError> {
+        self.token_managers.get(slot_id).ok_or_else(|| {
+            ESignError::Pkcs11(if slot_id == DEFAULT_SLOT {
+                "Token manager not initialized. Call init_token_manager first.".to_string()
+            } else {
+                format!(
+                    "Token manager for slot {} not initialized. Call init_token_for_slot first.",
+                    slot_id
+                )
+            })
+        })
+    }
+
+    /// Manager used by the single-token commands
+    fn default_manager(&self) -> Result<Arc<Mutex<TokenManager>>, ESignError> {
+        self.manager_for_slot(DEFAULT_SLOT)
+    }
+
+    /// Manager with a logged-in session on `slot_id`, whichever command logged it in
+    fn logged_in_manager(&self, slot_id: u64) -> Result<Arc<Mutex<TokenManager>>, ESignError> {
+        for (_, entry) in self.token_managers.entries() {
+            let logged_in_slot = entry
+                .lock()
+                .map_err(|_| ESignError::lock_poisoned("Token manager"))?
+                .logged_in_slot();
+            if logged_in_slot == Some(slot_id) {
+                return Ok(entry);
+            }
+        }
+        Err(ESignError::Signing {
+            code: SigningErrorCode::TokenNotFound,
+            message: format!("No logged-in session on slot {}", slot_id),
+        })
+    }
+
+    /// Certificate id chosen by the user, if any
+    fn selected_cert_id(&self) -> Result<Option<Vec<u8>>, ESignError> {
+        Ok(self
+            .selected_cert_id
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Certificate selection"))?
+            .clone())
+    }
+
+    /// Start polling token slots in the background
+    /// Emits "token-inserted" / "token-removed" with the TokenInfo payload, and
+    /// "token-session-invalidated" (after logging out) when the logged-in token is removed
+    pub fn start_monitoring(&self, app_handle: AppHandle) {
+        let Ok(mut monitor) = self.token_monitor.lock() else {
+            return;
+        };
+        if monitor.is_some() {
+            return;
+        }
+        let poll_handle = app_handle.clone();
+        *monitor = Some(TokenMonitor::start(
+            POLL_INTERVAL,
+            move || {
+                let state = poll_handle.state::<AppState>();
+                let entries = state.token_managers.entries();
+                if entries.is_empty() {
+                    return None;
+                }
+                let mut tokens = Vec::new();
+                for (_, entry) in entries {
+                    // Skip the round while a command (e.g. signing) holds a manager
+                    let manager = entry.try_lock().ok()?;
+                    push_unique_tokens(&mut tokens, manager.list_slots().ok()?);
+                }
+                Some(tokens)
+            },
+            move |change| match change {
+                TokenChange::Inserted(info) => {
+                    let _ = app_handle.emit("token-inserted", &info);
+                }
+                TokenChange::Removed(info) => {
+                    let _ = app_handle.emit("token-removed", &info);
+                    if invalidate_session_for_slot(&app_handle.state::<AppState>(), info.slot_id) {
+                        let _ = app_handle.emit("token-session-invalidated", &info);
+                    }
+                }
+            },
+        ));
+    }
+
+    /// Record user activity, restarting the idle timeout
+    fn touch_activity(&self) {
+        if let Ok(mut last_activity) = self.last_activity.lock() {
+            *last_activity = Instant::now();
+        }
+    }
+
+    /// Idle time left at `now`; None when the timeout is disabled
+    fn idle_remaining(&self, now: Instant) -> Option<Duration> {
+        let idle_timeout = *self.idle_timeout.lock().ok()?;
+        let last_activity = *self.last_activity.lock().ok()?;
+        idle_remaining(last_activity, idle_timeout, now)
+    }
+
+    /// Log out every session if the idle timeout elapsed at `now`
+    /// Returns true if a logged-in session was cleared
+    fn expire_idle_sessions(&self, now: Instant) -> bool {
+        if self.idle_remaining(now) != Some(Duration::ZERO) {
+            return false;
+        }
+        let mut expired = false;
+        for (_, entry) in self.token_managers.entries() {
+            let Ok(manager) = entry.lock() else {
+                continue;
+            };
+            if manager.is_logged_in() {
+                eprintln!("Session idle for too long, logging out");
+                manager.logout();
+                expired = true;
+            }
+        }
+        expired
+    }
+
+    /// Check the idle timeout every IDLE_CHECK_INTERVAL on a background thread
+    /// Emits "session-expired" after logging out an idle session
+    pub fn start_idle_timer(&self, app_handle: AppHandle) {
+        std::thread::spawn(move || loop {
+            std::thread::sleep(IDLE_CHECK_INTERVAL);
+            let state = app_handle.state::<AppState>();
+            if state.expire_idle_sessions(Instant::now()) {
+                let _ = app_handle.emit("session-expired", ());
+            }
+        });
+    }
+
+    /// Stop the slot poller started by start_monitoring
+    pub fn stop_monitoring(&self) {
+        if let Some(monitor) = self.token_monitor.lock().ok().and_then(|mut m| m.take()) {
+            monitor.stop();
+        }
+    }
+}
+
+/// Log out every manager whose session is on `slot_id`; true if a session was cleared
+fn invalidate_session_for_slot(state: &AppState, slot_id: u64) -> bool {
+    let mut invalidated = false;
+    for (_, entry) in state.token_managers.entries() {
+        let Ok(manager) = entry.lock() else {
+            continue;
+        };
+        if manager.logged_in_slot() == Some(slot_id) {
+            eprintln!("Logged-in token in slot {} was removed", slot_id);
+            manager.logout();
+            invalidated = true;
+        }
+    }
+    invalidated
+}
+
+/// Idle time left before `idle_timeout` elapses; None when the timeout is zero (never)
+fn idle_remaining(
+    last_activity: Instant,
+    idle_timeout: Duration,
+    now: Instant,
+) -> Option<Duration> {
+    if idle_timeout.is_zero() {
+        return None;
+    }
+    Some(idle_timeout.saturating_sub(now.saturating_duration_since(last_activity)))
+}
+
+/// Wrap the command handler so every call except status polling counts as activity
+fn track_activity<R: tauri::Runtime>(
+    handler: impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static,
+) -> impl Fn(tauri::ipc::Invoke<R>) -> bool + Send + Sync + 'static {
+    move |invoke| {
+        if !PASSIVE_COMMANDS.contains(&invoke.message.command()) {
+            invoke
+                .message
+                .webview()
+                .state::<AppState>()
+                .touch_activity();
+        }
+        handler(invoke)
+    }
+}
+
+/// Signing command called before login_token / login_slot
+fn not_logged_in() -> ESignError {
+    ESignError::Signing {
+        code: SigningErrorCode::TokenNotFound,
+        message: "Not logged in. Call login_token first.".to_string(),
+    }
+}
+
+/// Failed export write, naming what was being written
+fn write_error(what: &str, e: std::io::Error) -> ESignError {
+    ESignError::Io(std::io::Error::new(
+        e.kind(),
+        format!("Failed to write {}: {}", what, e),
+    ))
+}
+
+/// Failed launch of the system default application
+fn open_error(what: &str, e: impl std::fmt::Display) -> ESignError {
+    ESignError::Io(std::io::Error::other(format!(
+        "Failed to open {}: {}",
+        what, e
+    )))
+}
+
+/// Append tokens not already listed
+/// Managers sharing a library report the same slots
+fn push_unique_tokens(tokens: &mut Vec<TokenInfo>, more: Vec<TokenInfo>) {
+    for token in more {
+        if !tokens
+            .iter()
+            .any(|t| t.slot_id == token.slot_id && t.serial == token.serial)
+        {
+            tokens.push(token);
+        }
+    }
+}
+
+/// Tauri command: Get application info
+#[tauri::command]
+fn get_app_info() -> serde_json::Value {
+    serde_json::json!({
+        "name": "eSign Desktop",
+        "version": "1.0.0",
+        "description": "Cross-platform PDF signing with Vietnamese USB tokens"
+    })
+}
+
+/// Tauri command: Detect available PKCS#11 libraries
+/// Returns list of detected CA libraries (VNPT, Viettel, FPT) with versions
+/// and a compatibility warning for versions with known issues
+#[tauri::command]
+fn detect_libraries(state: State<AppState>) -> Result<Vec<DetectedLibrary>, ESignError> {
+    // Libraries in use report their cached info instead of being reloaded
+    let mut active = HashMap::new();
+    for (_, entry) in state.token_managers.entries() {
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        if let Ok(info) = manager.library_info() {
+            active.insert(manager.library_path().to_string(), info);
+        }
+    }
+
+    Ok(TokenManager::auto_detect_with_versions(&active))
+}
+
+/// Tauri command: Folders PKCS#11 libraries may be loaded from on this platform
+/// Shown to users whose CA software is installed elsewhere
+#[tauri::command]
+fn get_allowed_library_paths() -> Vec<String> {
+    allowed_library_prefixes()
+        .iter()
+        .map(|prefix| prefix.to_string())
+        .collect()
+}
+
+/// Tauri command: Detect PKCS#11 libraries without blocking on slow filesystems
+/// Path checks run concurrently; each library gets a 100ms load probe
+#[tauri::command]
+async fn detect_libraries_async(
+    state: State<'_, AppState>,
+) -> Result<Vec<DetectedLibrary>, ESignError> {
+    // Don't probe libraries currently in use (probe finalizes them on drop)
+    let mut active_paths = Vec::new();
+    for (_, entry) in state.token_managers.entries() {
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        active_paths.push(manager.library_path().to_string());
+    }
+
+    Ok(TokenManager::auto_detect_async(active_paths).await)
+}
+
+/// Tauri command: Preload detected PKCS#11 libraries
+/// Returns paths that loaded successfully; failures are logged
+#[tauri::command]
+fn warmup_libraries(state: State<AppState>) -> Result<Vec<String>, ESignError> {
+    Ok(warmup_detected_libraries(&state.library_manager))
+}
+
+/// Preload detected libraries, logging any that fail to load
+fn warmup_detected_libraries(library_manager: &LibraryManager) -> Vec<String> {
+    library_manager
+        .warmup_all()
+        .into_iter()
+        .filter_map(|(path, result)| match result {
+            Ok(()) => Some(path),
+            Err(e) => {
+                eprintln!("Library warmup failed for {}: {}", path, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Tauri command: Initialize token manager with specified library
+/// Must be called before other token operations
+#[tauri::command]
+fn init_token_manager(state: State<AppState>, library_path: String) -> Result<(), ESignError> {
+    // Debounce rapid duplicate calls; recorded up front so a concurrent call
+    // cannot start a second init while the first is still finalizing
+    {
+        let mut timestamps = state
+            .library_init_timestamps
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Library init timestamps"))?;
+        let now = Instant::now();
+        if detect_duplicate_library_path(&timestamps, &library_path, now) {
+            eprintln!(
+                "Skipping duplicate init of {} (initialized less than 500ms ago)",
+                library_path
+            );
+            return Ok(());
+        }
+        timestamps.insert(library_path.clone(), now);
+    }
+
+    let result = init_token_manager_inner(&state, &library_path, DEFAULT_SLOT);
+    match result {
+        // Remember the library so the next launch initializes it automatically
+        Ok(()) => {
+            if let Err(e) = state.persist_config() {
+                eprintln!("Failed to save config: {}", e);
+            }
+        }
+        Err(_) => {
+            if let Ok(mut timestamps) = state.library_init_timestamps.lock() {
+                timestamps.remove(&library_path);
+            }
+        }
+    }
+    result
+}
+
+/// Tauri command: Save the current library and idle timeout for the next launch
+#[tauri::command]
+fn save_config(state: State<AppState>) -> Result<(), ESignError> {
+    state.persist_config()
+}
+
+/// Tauri command: Delete the saved config and go back to the defaults (for testing)
+#[tauri::command]
+fn reset_config(state: State<AppState>) -> Result<(), ESignError> {
+    *state
+        .config
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Config"))? = AppConfig::default();
+    match state.config_path {
+        Some(ref path) => config::remove_config(path),
+        None => Ok(()),
+    }
+}
+
+/// Create the manager registered under `slot_id` (kept if already on `library_path`)
+/// A library already initialized for another slot is shared, not initialized twice
+fn init_token_manager_inner(
+    state: &AppState,
+    library_path: &str,
+    slot_id: u64,
+) -> Result<(), ESignError> {
+    // Drop old manager first to ensure C_Finalize is called
+    if let Some(old_entry) = state.token_managers.get(slot_id) {
+        // Check if re-initializing with same library (skip if identical)
+        let same_library = old_entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?
+            .library_path()
+            == library_path;
+        if same_library {
+            return Ok(());
+        }
+        // Dropping the last handle triggers C_Finalize via Drop impl
+        state.token_managers.remove(slot_id);
+        drop(old_entry);
+    }
+
+    let mut shared = None;
+    for (_, entry) in state.token_managers.entries() {
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        if manager.library_path() == library_path {
+            shared = Some(manager.share_library());
+            break;
+        }
+    }
+
+    let manager = match shared {
+        Some(manager) => manager,
+        None => {
+            // Delay to ensure PKCS#11 library fully finalized
+            // cryptoki v0.7.0's finalize() consumes self, so we rely on Drop cleanup + delay
+            std::thread::sleep(std::time::Duration::from_millis(200));
+
+            // Create new manager, reusing the library handle preloaded at startup if any
+            let preloaded = state.library_manager.take(library_path);
+            TokenManager::with_preloaded(library_path, preloaded)?
+        }
+    };
+    let lock_after_login = state
+        .config
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Config"))?
+        .lock_certificates_after_login;
+    let manager = manager.with_config(TokenManagerConfig { lock_after_login });
+    state.token_managers.insert(slot_id, manager);
+
+    Ok(())
+}
+
+/// Tauri command: Initialize a token manager bound to one slot
+/// Several slots can be initialized and logged in at the same time
+#[tauri::command]
+fn init_token_for_slot(
+    state: State<AppState>,
+    library_path: String,
+    slot_id: u64,
+) -> Result<(), ESignError> {
+    init_token_manager_inner(&state, &library_path, slot_id)?;
+    state
+        .token_managers
+        .with_slot(slot_id, |manager| {
+            if manager.selected_slot() == Some(slot_id) {
+                return Ok(());
+            }
+            manager.select_slot(slot_id)
+        })
+        .map_err(ESignError::Pkcs11)?
+}
+
+/// Tauri command: List available tokens/slots across all initialized managers
+#[tauri::command]
+fn list_tokens(state: State<AppState>) -> Result<Vec<TokenInfo>, ESignError> {
+    let entries = state.token_managers.entries();
+    if entries.is_empty() {
+        return Err(ESignError::Pkcs11(
+            "Token manager not initialized. Call init_token_manager first.".to_string(),
+        ));
+    }
+
+    let mut tokens = Vec::new();
+    for (_, entry) in entries {
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        push_unique_tokens(&mut tokens, manager.list_slots()?);
+    }
+    Ok(tokens)
+}
+
+/// Tauri command: Slot IDs with a logged-in session, across all managers
+#[tauri::command]
+fn get_active_slots(state: State<AppState>) -> Vec<u64> {
+    let mut slots: Vec<u64> = state
+        .token_managers
+        .entries()
+        .into_iter()
+        .filter_map(|(_, entry)| entry.lock().ok()?.logged_in_slot())
+        .collect();
+    slots.sort_unstable();
+    slots.dedup();
+    slots
+}
+
+/// Tauri command: PIN attempts left before the token locks
+/// None when the token does not report a retry counter
+#[tauri::command]
+fn get_pin_retry_count(state: State<AppState>, slot_id: u64) -> Result<Option<u64>, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_pin_retry_count(slot_id)
+}
+
+/// Tauri command: Login to token with PIN
+#[tauri::command]
+fn login_token(state: State<AppState>, slot_id: u64, pin: String) -> Result<(), ESignError> {
+    // 4-16 alphanumeric characters
+    validate_pin(&pin).map_err(ESignError::invalid_input)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    // Logging in again (e.g. another token) replaces the current session
+    if manager.is_logged_in() {
+        manager.logout();
+    }
+    manager.select_slot(slot_id)?;
+    let cert_id = state.selected_cert_id()?;
+    let result = manager.login_with_certificate(&pin, cert_id.as_deref());
+    state
+        .audit_logger
+        .log_login_attempt(slot_id, result.is_ok());
+    result
+}
+
+/// Tauri command: Login to the token initialized with init_token_for_slot
+/// Sessions on other slots are kept
+#[tauri::command]
+fn login_slot(state: State<AppState>, slot_id: u64, pin: String) -> Result<(), ESignError> {
+    // 4-16 alphanumeric characters
+    validate_pin(&pin).map_err(ESignError::invalid_input)?;
+
+    let entry = state.manager_for_slot(slot_id)?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    if manager.is_logged_in() {
+        manager.logout();
+    }
+    manager.select_slot(slot_id)?;
+    let cert_id = state.selected_cert_id()?;
+    let result = manager.login_with_certificate(&pin, cert_id.as_deref());
+    state
+        .audit_logger
+        .log_login_attempt(slot_id, result.is_ok());
+    result
+}
+
+/// Tauri command: Logout from the token initialized with init_token_for_slot
+#[tauri::command]
+fn logout_slot(state: State<AppState>, slot_id: u64) -> Result<(), ESignError> {
+    if let Some(entry) = state.token_managers.get(slot_id) {
+        entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?
+            .logout();
+    }
+    Ok(())
+}
+
+/// Tauri command: Change the token user PIN (C_SetPIN)
+/// An existing login on the same slot is kept, so signing continues with the new PIN
+#[tauri::command]
+fn change_token_pin(
+    state: State<AppState>,
+    slot_id: u64,
+    mut old_pin: String,
+    mut new_pin: String,
+) -> Result<(), ESignError> {
+    let result = (|| -> Result<(), ESignError> {
+        validate_pin(&old_pin).map_err(ESignError::invalid_input)?;
+        validate_pin(&new_pin).map_err(ESignError::invalid_input)?;
+
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+        // Changing the PIN of another token ends the current session, as in login
+        if manager.selected_slot() != Some(slot_id) {
+            if manager.is_logged_in() {
+                manager.logout();
+            }
+            manager.select_slot(slot_id)?;
+        }
+        manager.change_pin(&old_pin, &new_pin)
+    })();
+
+    old_pin.zeroize();
+    new_pin.zeroize();
+    result
+}
+
+/// Tauri command: Get certificate information from logged-in token
+#[tauri::command]
+fn get_certificate(state: State<AppState>) -> Result<CertificateInfo, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_certificate_info()
+}
+
+/// Tauri command: Check the logged-in certificate's validity period
+/// Warns when it has expired, is not yet valid or expires within 30 days
+#[tauri::command]
+fn check_certificate_validity(state: State<AppState>) -> Result<CertValidityReport, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    let cert_der = manager.get_certificate_der()?;
+    certificate_validity_report(&cert_der, chrono::Utc::now().timestamp())
+}
+
+/// Tauri command: All certificates on the logged-in token, so the user can pick one
+/// Tokens often carry a signing and an encryption certificate
+#[tauri::command]
+fn list_certificates(state: State<AppState>) -> Result<Vec<CertificateInfo>, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.list_certificates()
+}
+
+/// Tauri command: All private keys on the logged-in token, so the user can pick one
+#[tauri::command]
+fn list_signing_keys(state: State<AppState>) -> Result<Vec<KeyInfo>, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
+
+    manager.list_signing_keys()
+}
+
+/// Tauri command: Sign with the private key `key_id_hex` (hex CKA_ID from list_signing_keys)
+/// The key's certificate is selected with it; the choice is remembered for later logins
+#[tauri::command]
+fn select_signing_key(state: State<AppState>, key_id_hex: String) -> Result<(), ESignError> {
+    let key_id = hex::decode(&key_id_hex)
+        .map_err(|_| ESignError::invalid_input(format!("Invalid key id: {}", key_id_hex)))?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    if manager.is_logged_in() {
+        manager.select_signing_key(&key_id)?;
+    }
+
+    // Login prefers the key with this CKA_ID, as for select_certificate
+    *state
+        .selected_cert_id
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Certificate selection"))? = Some(key_id);
+    Ok(())
+}
+
+/// Tauri command: Sign with the certificate `cert_id` (hex CKA_ID from list_certificates)
+/// Applies to the current login and is remembered for later logins
+#[tauri::command]
+fn select_certificate(state: State<AppState>, cert_id: String) -> Result<(), ESignError> {
+    let id = hex::decode(&cert_id)
+        .map_err(|_| ESignError::invalid_input(format!("Invalid certificate id: {}", cert_id)))?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    if manager.is_logged_in() {
+        manager.select_certificate(&id)?;
+    }
+
+    *state
+        .selected_cert_id
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Certificate selection"))? = Some(id);
+    Ok(())
+}
+
+/// Tauri command: Get certificate info with key algorithm/size, SANs and key usages
+#[tauri::command]
+fn get_certificate_extended(state: State<AppState>) -> Result<CertificateInfoExtended, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_certificate_info_extended()
+}
+
+/// Tauri command: Get certificate policies (assurance level) of the token certificate
+#[tauri::command]
+fn get_certificate_policies(state: State<AppState>) -> Result<Vec<CertPolicyInfo>, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_certificate_policies()
+}
+
+/// Tauri command: Save the token certificate as PEM or DER (.pem or .cer)
+#[tauri::command]
+fn export_certificate(
+    state: State<AppState>,
+    output_path: String,
+    format: CertExportFormat,
+) -> Result<(), ESignError> {
+    let path = pdf::validate_output_path(&output_path, &["pem", "cer"])?;
+    let cert_der = {
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        manager.get_certificate_der()?
+    };
+
+    let data = match format {
+        CertExportFormat::Pem => certificate_to_pem(&cert_der).into_bytes(),
+        CertExportFormat::Der => cert_der,
+    };
+    std::fs::write(&path, data).map_err(|e| write_error("certificate", e))
+}
+
+/// Tauri command: Save the token certificate chain as concatenated PEM blocks
+#[tauri::command]
+fn export_certificate_chain(state: State<AppState>, output_path: String) -> Result<(), ESignError> {
+    let path = pdf::validate_output_path(&output_path, &["pem", "cer"])?;
+    let chain = {
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        manager.get_certificate_chain()?
+    };
+
+    let pem: String = chain.iter().map(|cert| certificate_to_pem(cert)).collect();
+    std::fs::write(&path, pem).map_err(|e| write_error("certificate chain", e))
+}
+
+/// Tauri command: Save the certificate chain as a password-protected PKCS#12 file
+/// Certificates only; the generated password is returned for the user to note down
+#[tauri::command]
+fn export_certificate_p12(
+    state: State<AppState>,
+    output_path: String,
+    friendly_name: String,
+) -> Result<P12ExportResult, ESignError> {
+    let path = pdf::validate_output_path(&output_path, &["p12", "pfx"])?;
+    let chain = {
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        manager.get_certificate_chain()?
+    };
+
+    let (der, password) =
+        pkcs12::export_certificate_chain_p12(&chain, &friendly_name).map_err(|e| {
+            ESignError::Internal(format!(
+                "PKCS#12 export failed: {} (only the certificate chain is exported; \
+                 the private key cannot leave the token)",
+                e
+            ))
+        })?;
+    std::fs::write(&path, der).map_err(|e| write_error("PKCS#12 file", e))?;
+
+    Ok(P12ExportResult {
+        output_path: path.to_string_lossy().into_owned(),
+        password,
+    })
+}
+
+/// Tauri command: Check the token certificate's revocation status via OCSP
+#[tauri::command]
+fn check_certificate_revocation(state: State<AppState>) -> Result<OcspResponse, ESignError> {
+    // Copy the chain out so the token manager is not locked during the network call
+    let chain = {
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        manager.get_certificate_chain()?
+    };
+    let cert_der = chain.first().ok_or_else(|| ESignError::Signing {
+        code: SigningErrorCode::CertificateNotFound,
+        message: "Certificate chain is empty".to_string(),
+    })?;
+
+    OcspClient::new()
+        .and_then(|client| client.check_certificate(cert_der, chain.get(1).map(Vec::as_slice)))
+}
+
+/// Tauri command: Check the token certificate against its CA's CRL
+/// Alternative to the OCSP check for CAs with unreliable responders
+#[tauri::command]
+fn check_crl_revocation(state: State<AppState>) -> Result<RevocationStatus, ESignError> {
+    // Copy the certificate out so the token manager is not locked during the download
+    let cert_der = {
+        let entry = state.default_manager()?;
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        manager.get_certificate_der()?
+    };
+
+    state.crl_cache.check_revocation(&cert_der)
+}
+
+/// Tauri command: Probe one TSA server (availability, latency, transport warning)
+#[tauri::command]
+fn check_tsa_server(url: String) -> Result<TsaHealthResult, ESignError> {
+    let client = TsaClient::new()?;
+    Ok(client.health_check(&url))
+}
+
+/// Tauri command: Probe all configured TSA servers in parallel
+/// Available servers first, fastest first
+#[tauri::command]
+fn check_all_tsa_servers() -> Result<Vec<TsaHealthResult>, ESignError> {
+    let client = TsaClient::new()?;
+    Ok(client.check_all_tsa_servers())
+}
+
+/// Tauri command: URL of the fastest responding TSA server
+/// Every server is pinged concurrently with `timeout_ms` per request
+#[tauri::command]
+fn select_best_tsa_server(timeout_ms: u64) -> Result<String, ESignError> {
+    Ok(TsaClient::from_fastest_server(timeout_ms)?
+        .primary_url()
+        .to_string())
+}
+
+/// Tauri command: Get vendor-specific token attributes (firmware version etc.)
+#[tauri::command]
+fn get_vendor_attributes(state: State<AppState>, slot_id: u64) -> Result<VendorInfo, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_vendor_attributes(slot_id)
+}
+
+/// Tauri command: Mechanisms supported by a token slot, for diagnosing signing failures
+#[tauri::command]
+fn get_slot_mechanisms(
+    state: State<AppState>,
+    slot_id: u64,
+) -> Result<Vec<MechanismInfo>, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.get_slot_mechanisms(slot_id)
+}
+
+/// Tauri command: Get PKCS#11 library manufacturer and versions (C_GetInfo)
+#[tauri::command]
+fn get_library_version_info(state: State<AppState>) -> Result<LibraryVersionInfo, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    manager.library_info()
+}
+
+/// Tauri command: Logout from token
+#[tauri::command]
+fn logout_token(state: State<AppState>) -> Result<(), ESignError> {
+    logout_slot(state, DEFAULT_SLOT)
+}
+
+/// Tauri command: Set the idle timeout after which sessions are logged out
+/// 0 disables the timeout
+#[tauri::command]
+fn set_idle_timeout(state: State<AppState>, timeout_secs: u64) -> Result<(), ESignError> {
+    *state
+        .idle_timeout
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Idle timeout"))? =
+        Duration::from_secs(timeout_secs);
+    Ok(())
+}
+
+/// Tauri command: Seconds until an idle session is logged out
+/// None when the timeout is disabled or no session is logged in
+#[tauri::command]
+fn get_session_remaining_seconds(state: State<AppState>) -> Result<Option<u64>, ESignError> {
+    let mut logged_in = false;
+    for (_, entry) in state.token_managers.entries() {
+        let manager = entry
+            .lock()
+            .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+        logged_in |= manager.is_logged_in();
+    }
+    if !logged_in {
+        return Ok(None);
+    }
+    Ok(state
+        .idle_remaining(Instant::now())
+        .map(|remaining| remaining.as_secs()))
+}
+
+/// Tauri command: Check token status
+/// Returns connection status and certificate info if logged in
+#[tauri::command]
+fn check_token_status(state: State<AppState>) -> Result<serde_json::Value, ESignError> {
+    match state.token_managers.get(DEFAULT_SLOT) {
+        Some(entry) => {
+            let manager = entry
+                .lock()
+                .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+            let logged_in = manager.is_logged_in();
+            let cert_info = if logged_in {
+                manager.get_certificate_info().ok()
+            } else {
+                None
+            };
+
+            Ok(serde_json::json!({
+                "initialized": true,
+                "logged_in": logged_in,
+                "library_path": manager.library_path(),
+                "certificate": cert_info
+            }))
+        }
+        None => {
+            // Check for available libraries
+            let detected = TokenManager::auto_detect();
+            Ok(serde_json::json!({
+                "initialized": false,
+                "logged_in": false,
+                "detected_libraries": detected
+            }))
         }
     }
 }
 
-/// Tauri command: Get application info
+/// Tauri command: Merge several PDF files and sign the result as one document
+/// Limits: 20 files, 200 MB total input size
+#[tauri::command]
+fn merge_and_sign_pdf(
+    state: State<AppState>,
+    pdf_paths: Vec<String>,
+    output_path: String,
+    signer_params: PdfSigner,
+) -> Result<SignResult, ESignError> {
+    if pdf_paths.is_empty() || output_path.is_empty() {
+        return Err(ESignError::invalid_input("Paths cannot be empty"));
+    }
+
+    // The merged output is the signed document, so it is what the audit entry names
+    audited_sign(
+        &state,
+        AuditOperation::SignPdf,
+        &output_path,
+        |signer_cert| {
+            merge_and_sign_pdf_with_manager(
+                &state,
+                &pdf_paths,
+                &output_path,
+                signer_params,
+                signer_cert,
+            )
+        },
+        |signed| signed.success,
+    )
+}
+
+/// Merge `pdf_paths` into `output_path` and sign it with the default token
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn merge_and_sign_pdf_with_manager(
+    state: &AppState,
+    pdf_paths: &[String],
+    output_path: &str,
+    mut signer_params: PdfSigner,
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<SignResult, ESignError> {
+    let signing_flag = state.signing_in_progress.get_or_default(DEFAULT_SLOT);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    if !manager.is_logged_in() {
+        return Err(not_logged_in());
+    }
+    manager.ensure_session_alive()?;
+
+    let cert_der = manager.get_certificate_der()?;
+    let cert_info = manager.get_certificate_info()?;
+    if signer_params.certificate_serial.is_none() {
+        signer_params.certificate_serial = Some(cert_info.serial.clone());
+    }
+    *signer_cert = Some(cert_info);
+
+    let engine = PdfSigningEngine::new()
+        .with_image_cache(Arc::clone(&state.image_cache))
+        .with_output_integrity_check();
+    let sign_fn = |data: &[u8]| manager.sign(data);
+
+    engine.merge_and_sign_pdf(pdf_paths, output_path, &signer_params, sign_fn, &cert_der)
+}
+
+/// Tauri command: Sign a PDF with an uploaded image (PNG/JPEG) drawn in the signature box
+/// set_image_background fills the box with the image; otherwise it is a thumbnail left of the text
+#[tauri::command]
+fn sign_pdf_with_image_signature(
+    state: State<AppState>,
+    pdf_path: String,
+    output_path: String,
+    signer_params: PdfSigner,
+    image_base64: String,
+    set_image_background: Option<bool>,
+) -> Result<SignResult, ESignError> {
+    if pdf_path.is_empty() || output_path.is_empty() {
+        return Err(ESignError::invalid_input("Paths cannot be empty"));
+    }
+    // Reject unusable images before touching the token
+    image::signature_image_info(&image_base64)?;
+
+    let signer_params = PdfSigner {
+        visible: true,
+        image_base64: Some(image_base64),
+        set_image_background,
+        // The uploaded image replaces any seal URL
+        seal_image_url: None,
+        ..signer_params
+    };
+    audited_sign(
+        &state,
+        AuditOperation::SignPdf,
+        &pdf_path,
+        |signer_cert| {
+            sign_pdf_file_with_manager(
+                &state,
+                &pdf_path,
+                &output_path,
+                signer_params,
+                PdfSigningEngine::new().with_output_integrity_check(),
+                signer_cert,
+            )
+        },
+        |signed| signed.success,
+    )
+}
+
+/// Sign `pdf_path` to `output_path` with `engine` and the default token
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn sign_pdf_file_with_manager(
+    state: &AppState,
+    pdf_path: &str,
+    output_path: &str,
+    mut signer_params: PdfSigner,
+    engine: PdfSigningEngine,
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<SignResult, ESignError> {
+    let signing_flag = state.signing_in_progress.get_or_default(DEFAULT_SLOT);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    if !manager.is_logged_in() {
+        return Err(not_logged_in());
+    }
+    manager.ensure_session_alive()?;
+
+    let cert_der = manager.get_certificate_der()?;
+    let cert_info = manager.get_certificate_info()?;
+    if signer_params.certificate_serial.is_none() {
+        signer_params.certificate_serial = Some(cert_info.serial.clone());
+    }
+    *signer_cert = Some(cert_info);
+
+    let sign_fn = |data: &[u8]| manager.sign(data);
+    engine.sign_pdf(pdf_path, output_path, &signer_params, sign_fn, &cert_der)
+}
+
+/// Tauri command: Check an uploaded signature image (format, size, dimensions) before signing
+#[tauri::command]
+fn validate_signature_image(image_base64: String) -> Result<ImageInfo, ESignError> {
+    image::signature_image_info(&image_base64)
+}
+
+/// Tauri command: Sign a PDF and return it base64-encoded without writing to disk
+/// For streaming the signed document back to a browser or web backend
+#[tauri::command]
+fn sign_pdf_return_base64(
+    state: State<AppState>,
+    pdf_path: String,
+    signer_params: PdfSigner,
+) -> Result<String, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    if pdf_path.is_empty() {
+        return Err(ESignError::invalid_input("Path cannot be empty"));
+    }
+
+    let signed = audited_sign(
+        &state,
+        AuditOperation::SignPdf,
+        &pdf_path,
+        |signer_cert| sign_pdf_to_bytes_with_manager(&state, &pdf_path, signer_params, signer_cert),
+        |_| true,
+    )?;
+    Ok(STANDARD.encode(signed))
+}
+
+/// Sign `pdf_path` in memory with the default token
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn sign_pdf_to_bytes_with_manager(
+    state: &AppState,
+    pdf_path: &str,
+    mut signer_params: PdfSigner,
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<Vec<u8>, ESignError> {
+    let signing_flag = state.signing_in_progress.get_or_default(DEFAULT_SLOT);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    if !manager.is_logged_in() {
+        return Err(not_logged_in());
+    }
+    manager.ensure_session_alive()?;
+
+    let cert_der = manager.get_certificate_der()?;
+    let cert_info = manager.get_certificate_info()?;
+    if signer_params.certificate_serial.is_none() {
+        signer_params.certificate_serial = Some(cert_info.serial.clone());
+    }
+    *signer_cert = Some(cert_info);
+
+    let engine = PdfSigningEngine::new()
+        .with_image_cache(Arc::clone(&state.image_cache))
+        .with_output_integrity_check();
+    let sign_fn = |data: &[u8]| manager.sign(data);
+
+    engine.sign_pdf_to_bytes(pdf_path, &signer_params, sign_fn, &cert_der)
+}
+
+/// Tauri command: Certify a PDF (DocMDP signature) so later changes are detectable
+/// The PDF must not have any signature field yet; doc_mdp_level 1-3, default 1 (no changes)
+#[tauri::command]
+fn certify_pdf(
+    state: State<AppState>,
+    pdf_path: String,
+    output_path: String,
+    signer_params: PdfSigner,
+    doc_mdp_level: Option<u8>,
+) -> Result<CertifyResult, ESignError> {
+    certify_pdf_audited(
+        &state,
+        &pdf_path,
+        &output_path,
+        signer_params,
+        doc_mdp_level,
+    )
+}
+
+/// Validate, certify with the default token and record the attempt in the audit log
+fn certify_pdf_audited(
+    state: &AppState,
+    pdf_path: &str,
+    output_path: &str,
+    signer_params: PdfSigner,
+    doc_mdp_level: Option<u8>,
+) -> Result<CertifyResult, ESignError> {
+    if pdf_path.is_empty() || output_path.is_empty() {
+        return Err(ESignError::invalid_input("Paths cannot be empty"));
+    }
+    let doc_mdp_level = doc_mdp_level.unwrap_or(1);
+    if !(1..=3).contains(&doc_mdp_level) {
+        return Err(ESignError::invalid_input(
+            "Invalid DocMDP level (must be 1-3)",
+        ));
+    }
+
+    let engine = PdfSigningEngine::new()
+        .with_image_cache(Arc::clone(&state.image_cache))
+        .with_output_integrity_check()
+        .with_certification(doc_mdp_level);
+    let result = audited_sign(
+        state,
+        AuditOperation::SignPdf,
+        pdf_path,
+        |signer_cert| {
+            sign_pdf_file_with_manager(
+                state,
+                pdf_path,
+                output_path,
+                signer_params,
+                engine,
+                signer_cert,
+            )
+        },
+        |signed| signed.success,
+    )?;
+    Ok(CertifyResult {
+        result,
+        doc_mdp_level,
+    })
+}
+
+/// Tauri command: DocMDP permission level of a certified PDF; None if not certified
+#[tauri::command]
+fn get_pdf_certify_status(pdf_path: String) -> Result<Option<CertifyStatus>, ESignError> {
+    pdf::get_certify_status(&pdf_path)
+}
+
+/// Tauri command: Signature fields already in a PDF, signed or empty, with their placement
+#[tauri::command]
+fn get_signature_field_list(pdf_path: String) -> Result<Vec<SignatureFieldInfo>, ESignError> {
+    pdf::get_signature_field_list(&pdf_path)
+}
+
+/// Tauri command: Names of the empty signature fields a PDF can be signed into
+#[tauri::command]
+fn find_existing_signature_fields(pdf_path: String) -> Result<Vec<String>, ESignError> {
+    pdf::find_existing_signature_fields(&pdf_path)
+}
+
+/// Tauri command: Sign an XML document (e.g. tax declaration) with an enveloped XMLDSig signature
+#[tauri::command]
+fn sign_xml(
+    state: State<AppState>,
+    xml_path: String,
+    output_path: String,
+) -> Result<XmlSignResult, ESignError> {
+    sign_xml_audited(&state, &xml_path, &output_path)
+}
+
+/// Validate, sign with the default token and record the attempt in the audit log
+fn sign_xml_audited(
+    state: &AppState,
+    xml_path: &str,
+    output_path: &str,
+) -> Result<XmlSignResult, ESignError> {
+    if xml_path.is_empty() || output_path.is_empty() {
+        return Err(ESignError::invalid_input("Paths cannot be empty"));
+    }
+
+    audited_sign(
+        state,
+        AuditOperation::SignXml,
+        xml_path,
+        |signer_cert| sign_xml_with_manager(state, xml_path, output_path, signer_cert),
+        |signed| signed.success,
+    )
+}
+
+/// Sign `xml_path` to `output_path` with the default token
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn sign_xml_with_manager(
+    state: &AppState,
+    xml_path: &str,
+    output_path: &str,
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<XmlSignResult, ESignError> {
+    let signing_flag = state.signing_in_progress.get_or_default(DEFAULT_SLOT);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    if !manager.is_logged_in() {
+        return Err(not_logged_in());
+    }
+    manager.ensure_session_alive()?;
+
+    let cert_der = manager.get_certificate_der()?;
+    *signer_cert = Some(manager.get_certificate_info()?);
+    xml_sign::sign_xml_file(xml_path, output_path, |data| manager.sign(data), &cert_der)
+}
+
+/// Tauri command: Check a PDF for encryption, certification and parse errors before signing
 #[tauri::command]
-fn get_app_info() -> serde_json::Value {
-    serde_json::json!({
-        "name": "eSign Desktop",
-        "version": "1.0.0",
-        "description": "Cross-platform PDF signing with Vietnamese USB tokens"
-    })
+fn validate_pdf_before_sign(pdf_path: String) -> Result<PdfValidationReport, ESignError> {
+    pdf::validate_pdf_file(&pdf_path)
 }
 
-/// Tauri command: Detect available PKCS#11 libraries
-/// Returns list of detected CA libraries (VNPT, Viettel, FPT)
+/// Tauri command: Classify a PDF before signing
+/// One of digitally_created, scanned_image, portfolio, password_protected, unknown
 #[tauri::command]
-fn detect_libraries() -> Vec<DetectedLibrary> {
-    TokenManager::auto_detect()
+fn get_pdf_type(pdf_path: String) -> Result<String, ESignError> {
+    pdf::detect_pdf_type_file(&pdf_path).map(|pdf_type| pdf_type.to_string())
 }
 
-/// Tauri command: Initialize token manager with specified library
-/// Must be called before other token operations
+/// Tauri command: Sign several PDFs with the same parameters, one after another
+/// Uses the current login (PIN entered once); emits `sign-batch-progress` after each file
+/// and keeps going when a single file fails
 #[tauri::command]
-fn init_token_manager(state: State<AppState>, library_path: String) -> Result<(), String> {
-    // Drop old manager first to ensure C_Finalize is called
-    {
-        let mut guard = state
-            .token_manager
-            .lock()
-            .map_err(|_| "Token manager mutex poisoned")?;
+fn sign_pdfs_batch(
+    app: AppHandle,
+    state: State<AppState>,
+    jobs: Vec<BatchSignJob>,
+    common_params: PdfSigner,
+) -> Result<Vec<BatchSignResult>, ESignError> {
+    sign_pdfs_batch_audited(&state, &jobs, common_params, |progress| {
+        if let Err(e) = app.emit("sign-batch-progress", progress) {
+            eprintln!("Failed to emit batch progress: {}", e);
+        }
+    })
+}
 
-        if let Some(old_manager) = guard.take() {
-            // Check if re-initializing with same library (skip if identical)
-            if old_manager.library_path() == library_path {
-                *guard = Some(old_manager);
-                return Ok(());
-            }
-            // Explicit drop triggers C_Finalize via Drop impl
-            drop(old_manager);
+/// Validate and sign `jobs` with the default token, one audit entry per input file
+/// If the batch cannot start (e.g. not logged in), every file is recorded with that error
+fn sign_pdfs_batch_audited(
+    state: &AppState,
+    jobs: &[BatchSignJob],
+    common_params: PdfSigner,
+    on_progress: impl FnMut(&BatchSignProgress),
+) -> Result<Vec<BatchSignResult>, ESignError> {
+    if jobs.is_empty() {
+        return Err(ESignError::invalid_input("No files to sign"));
+    }
+
+    let mut signer_cert = None;
+    let result =
+        sign_pdfs_batch_with_manager(state, jobs, common_params, on_progress, &mut signer_cert);
+    if let Err(e) = &result {
+        for job in jobs {
+            state.audit_logger.log_sign_failure(
+                AuditOperation::SignPdf,
+                &job.input_path,
+                signer_cert.as_ref(),
+                e,
+            );
         }
-    } // guard released here
+    }
+    result
+}
 
-    // Delay to ensure PKCS#11 library fully finalized
-    // cryptoki v0.7.0's finalize() consumes self, so we rely on Drop cleanup + delay
-    std::thread::sleep(std::time::Duration::from_millis(200));
+/// Sign `jobs` with the default token, logging each job's outcome as it finishes
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn sign_pdfs_batch_with_manager(
+    state: &AppState,
+    jobs: &[BatchSignJob],
+    mut signer_params: PdfSigner,
+    on_progress: impl FnMut(&BatchSignProgress),
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<Vec<BatchSignResult>, ESignError> {
+    let signing_flag = state.signing_in_progress.get_or_default(DEFAULT_SLOT);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    if !manager.is_logged_in() {
+        return Err(not_logged_in());
+    }
+    manager.ensure_session_alive()?;
 
-    // Create new manager
-    let manager = TokenManager::new(&library_path).map_err(|e| e.to_string())?;
+    let cert_der = manager.get_certificate_der()?;
+    let cert_info = manager.get_certificate_info()?;
+    if signer_params.certificate_serial.is_none() {
+        signer_params.certificate_serial = Some(cert_info.serial.clone());
+    }
+    *signer_cert = Some(cert_info.clone());
 
-    let mut guard = state
-        .token_manager
+    let mut engine = PdfSigningEngine::new()
+        .with_image_cache(Arc::clone(&state.image_cache))
+        .with_output_integrity_check();
+    let config = state
+        .config
         .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    *guard = Some(manager);
+        .map_err(|_| ESignError::lock_poisoned("Config"))?
+        .clone();
+    if config.tsa_enabled {
+        // Cached tokens are scoped to each job (BatchSignJob::job_id)
+        let tsa_client = TsaClientBuilder::new()
+            .config(config.tsa_config)
+            .caching(true)
+            .cache_ttl(Duration::from_secs(config.tsa_cache_ttl_secs))
+            .build()?;
+        engine = engine.with_tsa_client(tsa_client);
+    }
+    let sign_fn = |data: &[u8]| manager.sign(data);
 
-    Ok(())
+    Ok(engine.sign_pdfs_batch_with_outcomes(
+        jobs,
+        &signer_params,
+        sign_fn,
+        &cert_der,
+        on_progress,
+        |job, outcome| match outcome {
+            Ok(signed) => state.audit_logger.log_sign_attempt(
+                AuditOperation::SignPdf,
+                &job.input_path,
+                &cert_info,
+                signed.success,
+            ),
+            Err(e) => state.audit_logger.log_sign_failure(
+                AuditOperation::SignPdf,
+                &job.input_path,
+                Some(&cert_info),
+                e,
+            ),
+        },
+    ))
 }
 
-/// Tauri command: List available tokens/slots
+/// Tauri command: Open file with system default application
 #[tauri::command]
-fn list_tokens(state: State<AppState>) -> Result<Vec<TokenInfo>, String> {
-    let guard = state
-        .token_manager
-        .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    let manager = guard
-        .as_ref()
-        .ok_or("Token manager not initialized. Call init_token_manager first.")?;
+fn open_file(path: String) -> Result<(), ESignError> {
+    opener::open(&path).map_err(|e| open_error("file", e))
+}
 
-    manager.list_slots().map_err(|e| e.to_string())
+/// Tauri command: Build signer coordinates from page percentages
+/// Reads the page size from the PDF so callers need not know PDF points
+#[tauri::command]
+fn from_percentage(
+    pdf_path: String,
+    page: u32,
+    x_pct: f64,
+    y_pct: f64,
+    width_pct: f64,
+    height_pct: f64,
+) -> Result<PdfSigner, ESignError> {
+    let page_info = pdf::get_page_info(&pdf_path, page)?;
+    PdfSigner::from_percentage(&page_info, x_pct, y_pct, width_pct, height_pct)
 }
 
-/// Tauri command: Login to token with PIN
+/// Tauri command: Size and rotation of every page, for the signature placement UI
 #[tauri::command]
-fn login_token(state: State<AppState>, slot_id: u64, pin: String) -> Result<(), String> {
-    // Validate PIN length (4-16 characters)
-    if pin.len() < 4 || pin.len() > 16 {
-        return Err("PIN must be 4-16 characters".into());
-    }
+fn get_page_info(pdf_path: String) -> Result<Vec<PageInfo>, ESignError> {
+    pdf::get_all_page_info(&pdf_path)
+}
 
-    // Validate PIN contains only alphanumeric characters
-    if !pin.chars().all(|c| c.is_ascii_alphanumeric()) {
-        return Err("PIN contains invalid characters".into());
-    }
+/// Tauri command: Number of pages in a PDF
+#[tauri::command]
+fn get_page_count(pdf_path: String) -> Result<u32, ESignError> {
+    get_page_info(pdf_path).map(|pages| pages.len() as u32)
+}
 
-    let guard = state
-        .token_manager
-        .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    let manager = guard.as_ref().ok_or("Token manager not initialized")?;
+/// Tauri command: PNG preview (base64) of the signature appearance at 300 DPI
+#[tauri::command]
+fn preview_signature_appearance(
+    params: PdfSigner,
+    cert_info: CertificateInfo,
+) -> Result<String, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
 
-    manager.login(slot_id, &pin).map_err(|e| e.to_string())
+    preview::render_signature_preview(&params, &cert_info).map(|png| STANDARD.encode(png))
 }
 
-/// Tauri command: Get certificate information from logged-in token
+/// Tauri command: Preset signature text templates (VNPT-CA style, minimal, full)
 #[tauri::command]
-fn get_certificate(state: State<AppState>) -> Result<CertificateInfo, String> {
-    let guard = state
-        .token_manager
-        .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    let manager = guard.as_ref().ok_or("Token manager not initialized")?;
+fn get_default_signature_templates() -> Vec<SignatureTemplate> {
+    pdf::default_signature_templates()
+}
 
-    manager.get_certificate_info().map_err(|e| e.to_string())
+/// Tauri command: Flatten form fields into page content without signing
+/// Signature fields are kept; signed PDFs are rejected
+#[tauri::command]
+fn flatten_pdf_forms(input_path: String, output_path: String) -> Result<(), ESignError> {
+    pdf::flatten_pdf_file(&input_path, &output_path)
 }
 
-/// Tauri command: Logout from token
+/// Tauri command: Stamp a "draft - not signed" watermark on every page
+/// Defaults to "BẢN NHÁP – CHƯA KÝ"; the watermark is removed when the PDF is signed
 #[tauri::command]
-fn logout_token(state: State<AppState>) -> Result<(), String> {
-    let guard = state
-        .token_manager
-        .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    if let Some(manager) = guard.as_ref() {
-        manager.logout();
-    }
-    Ok(())
+fn watermark_unsigned_pdf(
+    pdf_path: String,
+    output_path: String,
+    watermark_text: Option<String>,
+) -> Result<(), ESignError> {
+    pdf::watermark_pdf_file(&pdf_path, &output_path, watermark_text.as_deref())
 }
 
-/// Tauri command: Check token status
-/// Returns connection status and certificate info if logged in
+/// Tauri command: Merge PDF files (in order) into one PDF without signing
+/// Limits: 20 files, 200 MB total input size
 #[tauri::command]
-fn check_token_status(state: State<AppState>) -> Result<serde_json::Value, String> {
-    let guard = state
-        .token_manager
-        .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
+fn merge_pdfs(input_paths: Vec<String>, output_path: String) -> Result<(), ESignError> {
+    pdf::merge_pdf_files(&input_paths, &output_path)
+}
 
-    match guard.as_ref() {
-        Some(manager) => {
-            let logged_in = manager.is_logged_in();
-            let cert_info = if logged_in {
-                manager.get_certificate_info().ok()
-            } else {
-                None
-            };
+/// Tauri command: Text drawn inside a signature rectangle [llx, lly, urx, ury]
+/// Lets users confirm the signature covers the intended label (best effort)
+#[tauri::command]
+fn extract_text_near_signature(
+    pdf_path: String,
+    page: u32,
+    rect: [f64; 4],
+) -> Result<String, ESignError> {
+    pdf::extract_text_near_signature(&pdf_path, page, rect)
+}
 
-            Ok(serde_json::json!({
-                "initialized": true,
-                "logged_in": logged_in,
-                "library_path": manager.library_path(),
-                "certificate": cert_info
-            }))
-        }
-        None => {
-            // Check for available libraries
-            let detected = TokenManager::auto_detect();
-            Ok(serde_json::json!({
-                "initialized": false,
-                "logged_in": false,
-                "detected_libraries": detected
-            }))
-        }
-    }
+/// Tauri command: Report bytes a signed copy changed compared to the original PDF
+/// Heuristic audit of edits outside the signatures; use verify_pdf_signatures for validity
+#[tauri::command]
+fn diff_pdf_signatures(
+    original_path: String,
+    signed_path: String,
+) -> Result<PdfDiffReport, ESignError> {
+    verify::diff_pdf_signatures(&original_path, &signed_path)
 }
 
-/// Tauri command: Open file with system default application
+/// Tauri command: Verify all signatures embedded in a PDF (no token needed)
+#[tauri::command]
+fn verify_pdf_signatures(pdf_path: String) -> Result<Vec<SignatureVerificationResult>, ESignError> {
+    verify::verify_pdf_signatures(&pdf_path)
+}
+
+/// Tauri command: Check the token session is still usable
+/// Returns false if not logged in or the token was unplugged since login
 #[tauri::command]
-fn open_file(path: String) -> Result<(), String> {
-    opener::open(&path).map_err(|e| format!("Failed to open file: {}", e))
+fn check_session_alive(state: State<AppState>) -> Result<bool, ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+
+    Ok(manager.is_session_alive())
 }
 
 /// Tauri command: Sign data using token
 /// Input: base64-encoded data to sign
 /// Output: base64-encoded signature
 #[tauri::command]
-fn sign_data(state: State<AppState>, data_base64: String) -> Result<String, String> {
+fn sign_data(state: State<AppState>, data_base64: String) -> Result<String, ESignError> {
     use base64::{engine::general_purpose::STANDARD, Engine as _};
 
-    let guard = state
-        .token_manager
+    let entry = state.default_manager()?;
+    let manager = entry
         .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    let manager = guard.as_ref().ok_or("Token manager not initialized")?;
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
 
     // Decode input data
     let data = STANDARD
         .decode(&data_base64)
-        .map_err(|e| format!("Invalid base64 input: {}", e))?;
+        .map_err(|e| ESignError::invalid_input(format!("Invalid base64 input: {}", e)))?;
 
     // Sign the data
-    let signature = manager.sign(&data).map_err(|e| e.to_string())?;
+    let signature = manager.sign(&data)?;
 
     // Encode signature as base64
     Ok(STANDARD.encode(&signature))
 }
 
+/// Tauri command: Random bytes from the token's hardware generator, base64-encoded
+/// For nonces and document identifiers; length 1-1024
+#[tauri::command]
+fn get_random_bytes(state: State<AppState>, length: usize) -> Result<String, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
+
+    Ok(STANDARD.encode(manager.get_random_bytes(length)?))
+}
+
+/// Tauri command: Mark the logged-in certificate chain non-modifiable on the token
+#[tauri::command]
+fn lock_token_objects(state: State<AppState>) -> Result<(), ESignError> {
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
+    manager.lock_certificate_objects()
+}
+
+/// Tauri command: Sign data with the token logged in on `slot_id` (external integrations)
+/// `RsaPkcs` expects the data to be a DER DigestInfo; input/output are base64 like `sign_data`
+#[tauri::command]
+fn sign_data_with_slot(
+    state: State<AppState>,
+    slot_id: u64,
+    data_base64: String,
+    mechanism: SignMechanism,
+) -> Result<String, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let entry = state.logged_in_manager(slot_id)?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
+
+    let data = STANDARD
+        .decode(&data_base64)
+        .map_err(|e| ESignError::invalid_input(format!("Invalid base64 input: {}", e)))?;
+
+    let signature = manager.sign_with_mechanism(&data, mechanism)?;
+
+    Ok(STANDARD.encode(&signature))
+}
+
+/// Tauri command: Sign data using token with an explicit algorithm
+/// Algorithm: SHA256withRSA, SHA384withRSA, SHA512withRSA or SHA1withRSA (deprecated)
+/// Input/output are base64-encoded like `sign_data`
+#[tauri::command]
+fn sign_data_with_algorithm(
+    state: State<AppState>,
+    data_base64: String,
+    algorithm: String,
+) -> Result<String, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let algorithm = SigningAlgorithm::from_name(&algorithm)?;
+
+    let entry = state.default_manager()?;
+    let manager = entry
+        .lock()
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
+    manager.ensure_session_alive()?;
+
+    let data = STANDARD
+        .decode(&data_base64)
+        .map_err(|e| ESignError::invalid_input(format!("Invalid base64 input: {}", e)))?;
+
+    let signature = manager.sign_with_algorithm(&data, algorithm)?;
+
+    Ok(STANDARD.encode(&signature))
+}
+
 /// Tauri command: Sign a PDF file
 /// Requires token to be logged in first
 #[tauri::command]
-#[allow(clippy::too_many_arguments)]
-fn sign_pdf(
+fn sign_pdf(state: State<AppState>, options: SignPdfOptions) -> Result<SignResult, ESignError> {
+    sign_pdf_blocking(&state, DEFAULT_SLOT, options)
+}
+
+/// Tauri command: Sign a PDF file with the token logged in via login_slot
+/// Same options as `sign_pdf`; only `slot_id` is locked, other slots can sign concurrently
+#[tauri::command]
+fn sign_pdf_with_slot(
     state: State<AppState>,
+    slot_id: u64,
+    options: SignPdfOptions,
+) -> Result<SignResult, ESignError> {
+    sign_pdf_blocking(&state, slot_id, options)
+}
+
+/// Tauri command: Sign PDF off the main thread
+/// Same options as `sign_pdf`; emits "signing-started" (input path) and
+/// "signing-complete" (SignResult) in addition to resolving the IPC promise
+#[tauri::command]
+async fn sign_pdf_async(app: AppHandle, options: SignPdfOptions) -> Result<SignResult, ESignError> {
+    let _ = app.emit("signing-started", &options.pdf_path);
+
+    // AppHandle is a cheap Arc clone and Send, unlike State
+    let handle = app.clone();
+    let result =
+        run_blocking(move || sign_pdf_blocking(&handle.state::<AppState>(), DEFAULT_SLOT, options))
+            .await;
+
+    if let Ok(ref sign_result) = result {
+        let _ = app.emit("signing-complete", sign_result);
+    }
+    result
+}
+
+/// Run blocking work (token signing, PDF I/O) on the blocking thread pool
+async fn run_blocking<T, F>(job: F) -> Result<T, ESignError>
+where
+    F: FnOnce() -> Result<T, ESignError> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| ESignError::Internal(format!("Signing task failed: {}", e)))?
+}
+
+/// Parameters shared by `sign_pdf`, `sign_pdf_with_slot` and `sign_pdf_async`
+/// Sent from the frontend as one `options` object with camelCase keys
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SignPdfOptions {
     pdf_path: String,
     output_path: String,
     visible: bool,
@@ -235,55 +1900,157 @@ fn sign_pdf(
     show_name: Option<bool>,
     show_timestamp: Option<bool>,
     show_reason: Option<bool>,
-) -> Result<SignResult, String> {
+    // Open the signed PDF in the default viewer when done
+    auto_open_after_sign: Option<bool>,
+    // Flate-compress unfiltered streams before signing
+    compress: bool,
+    // Official seal image URL drawn in the signature box
+    seal_image_url: Option<String>,
+    // Invisible signatures: skip the widget annotation entirely
+    invisible_no_widget: Option<bool>,
+}
+
+/// Parse "#RRGGBB" into (r, g, b)
+fn parse_hex_color(color: &str) -> Option<(u8, u8, u8)> {
+    let hex = color.strip_prefix('#').filter(|hex| hex.len() == 6)?;
+    let channel = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Validate, sign with the token logged in on `slot_id` and optionally open the result
+fn sign_pdf_blocking(
+    state: &AppState,
+    slot_id: u64,
+    options: SignPdfOptions,
+) -> Result<SignResult, ESignError> {
+    let pdf_path = options.pdf_path.clone();
+    audited_sign(
+        state,
+        AuditOperation::SignPdf,
+        &pdf_path,
+        |signer_cert| sign_pdf_with_manager(state, slot_id, options, signer_cert),
+        |signed| signed.success,
+    )
+}
+
+/// Run a signing operation on the document at `path` and record its outcome in the audit log
+/// `sign` sets its certificate argument once the certificate has been read;
+/// `succeeded` tells whether a returned value is a successful signature
+fn audited_sign<T>(
+    state: &AppState,
+    operation: AuditOperation,
+    path: &str,
+    sign: impl FnOnce(&mut Option<CertificateInfo>) -> Result<T, ESignError>,
+    succeeded: impl FnOnce(&T) -> bool,
+) -> Result<T, ESignError> {
+    let mut signer_cert = None;
+    let result = sign(&mut signer_cert);
+    match (&result, &signer_cert) {
+        (Ok(signed), Some(cert_info)) => {
+            state
+                .audit_logger
+                .log_sign_attempt(operation, path, cert_info, succeeded(signed))
+        }
+        (Ok(_), None) => {}
+        (Err(e), _) => {
+            state
+                .audit_logger
+                .log_sign_failure(operation, path, signer_cert.as_ref(), e)
+        }
+    }
+    result
+}
+
+/// Sign with the manager logged in on `slot_id`
+/// `signer_cert` is set once the certificate has been read, for the audit log
+fn sign_pdf_with_manager(
+    state: &AppState,
+    slot_id: u64,
+    options: SignPdfOptions,
+    signer_cert: &mut Option<CertificateInfo>,
+) -> Result<SignResult, ESignError> {
+    let SignPdfOptions {
+        pdf_path,
+        output_path,
+        visible,
+        reason,
+        signer_name,
+        page,
+        llx,
+        lly,
+        urx,
+        ury,
+        font_size,
+        color_rgb,
+        show_name,
+        show_timestamp,
+        show_reason,
+        auto_open_after_sign,
+        compress,
+        seal_image_url,
+        invisible_no_widget,
+    } = options;
+
     // Validate paths are not empty
     if pdf_path.is_empty() || output_path.is_empty() {
-        return Err("Paths cannot be empty".into());
+        return Err(ESignError::invalid_input("Paths cannot be empty"));
     }
 
     // Validate page number (1-1000 range)
     if let Some(p) = page {
         if p == 0 || p > 1000 {
-            return Err("Invalid page number (must be 1-1000)".into());
+            return Err(ESignError::invalid_input(
+                "Invalid page number (must be 1-1000)",
+            ));
         }
     }
 
     // Validate reason length
     if let Some(ref r) = reason {
         if r.len() > 500 {
-            return Err("Reason too long (max 500 characters)".into());
+            return Err(ESignError::invalid_input(
+                "Reason too long (max 500 characters)",
+            ));
         }
     }
 
     // Validate signer name length
     if let Some(ref s) = signer_name {
         if s.len() > 200 {
-            return Err("Signer name too long (max 200 characters)".into());
+            return Err(ESignError::invalid_input(
+                "Signer name too long (max 200 characters)",
+            ));
         }
     }
 
     // Validate color format (#RRGGBB)
-    if let Some(ref c) = color_rgb {
-        if !c.starts_with('#') || c.len() != 7 {
-            return Err("Invalid color format (must be #RRGGBB)".into());
-        }
-    }
+    let text_color =
+        match color_rgb {
+            Some(ref c) => Some(parse_hex_color(c).ok_or_else(|| {
+                ESignError::invalid_input("Invalid color format (must be #RRGGBB)")
+            })?),
+            None => None,
+        };
 
-    let guard = state
-        .token_manager
+    // Released on return or panic
+    let signing_flag = state.signing_in_progress.get_or_default(slot_id);
+    let _signing_lock = SigningLockGuard::acquire(&signing_flag)?;
+
+    let entry = state.manager_for_slot(slot_id)?;
+    let manager = entry
         .lock()
-        .map_err(|_| "Token manager mutex poisoned")?;
-    let manager = guard
-        .as_ref()
-        .ok_or("Token manager not initialized. Call init_token_manager first.")?;
+        .map_err(|_| ESignError::lock_poisoned("Token manager"))?;
 
     if !manager.is_logged_in() {
-        return Err("Not logged in. Call login_token first.".to_string());
+        return Err(not_logged_in());
     }
+    manager.ensure_session_alive()?;
 
     // Get certificate from token
-    let cert_der = manager.get_certificate_der().map_err(|e| e.to_string())?;
-    let cert_info = manager.get_certificate_info().map_err(|e| e.to_string())?;
+    let cert_der = manager.get_certificate_der()?;
+    let cert_info = manager.get_certificate_info()?;
+    *signer_cert = Some(cert_info.clone());
+    let validity = certificate_validity_report(&cert_der, chrono::Utc::now().timestamp())?;
 
     // Build signer name based on show_name setting
     let final_signer = if show_name.unwrap_or(true) {
@@ -300,42 +2067,92 @@ fn sign_pdf(
     };
 
     // Use custom position if provided, otherwise use defaults
-    let sig_llx = llx.unwrap_or(50.0);
-    let sig_lly = lly.unwrap_or(50.0);
-    let sig_urx = urx.unwrap_or(250.0);
-    let sig_ury = ury.unwrap_or(100.0);
-
-    let signer_params = PdfSigner {
-        page: page.unwrap_or(1),
-        llx: sig_llx,
-        lly: sig_lly,
-        urx: sig_urx,
-        ury: sig_ury,
-        visible,
-        description: final_description,
-        signer: final_signer,
-        signing_time: if show_timestamp.unwrap_or(true) {
-            Some(pdf::get_current_signing_time())
-        } else {
-            None
-        },
-        certificate_serial: Some(cert_info.serial.clone()),
-        sig_text_size: font_size,
-        sig_color_rgb: color_rgb,
-        ..Default::default()
-    };
+    let mut builder = PdfSignerBuilder::new()
+        .page(page.unwrap_or(1))
+        .position(
+            llx.unwrap_or(50.0),
+            lly.unwrap_or(50.0),
+            urx.unwrap_or(250.0),
+            ury.unwrap_or(100.0),
+        )
+        .visible(visible)
+        .certificate_serial(cert_info.serial.clone())
+        .invisible_no_widget(invisible_no_widget.unwrap_or(false));
+    if let Some(description) = final_description {
+        builder = builder.reason(description);
+    }
+    if let Some(signer) = final_signer {
+        builder = builder.signer(signer);
+    }
+    if show_timestamp.unwrap_or(true) {
+        builder = builder.signing_time(pdf::get_current_signing_time());
+    }
+    if let Some(size) = font_size {
+        builder = builder.font_size(size);
+    }
+    if let Some((r, g, b)) = text_color {
+        builder = builder.text_color(r, g, b);
+    }
+    if let Some(url) = seal_image_url {
+        builder = builder.seal_image_url(url);
+    }
+    let signer_params = builder.build()?;
 
     // Create signing engine without TSA (Vietnamese TSA servers are unreliable)
     // Signatures will be valid but won't have trusted timestamps
-    let engine = PdfSigningEngine::new();
+    let mut engine = PdfSigningEngine::new()
+        .with_image_cache(Arc::clone(&state.image_cache))
+        .with_output_integrity_check();
+    if compress {
+        engine = engine.with_compression(6);
+    }
 
     // Sign the PDF
     // Create a closure that captures manager for signing
     let sign_fn = |data: &[u8]| manager.sign(data);
 
-    engine
-        .sign_pdf(&pdf_path, &output_path, &signer_params, sign_fn, &cert_der)
-        .map_err(|e| e.to_string())
+    let mut result =
+        engine.sign_pdf(&pdf_path, &output_path, &signer_params, sign_fn, &cert_der)?;
+    result.cert_warning = validity.warning;
+
+    if auto_open_after_sign.unwrap_or(false) {
+        let signed_path = result.output_path.clone();
+        std::thread::spawn(move || {
+            // Give the OS time to flush the signed file before the viewer opens it
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            if let Err(e) = open_signed_pdf(signed_path) {
+                eprintln!("Auto-open after signing failed: {}", e);
+            }
+        });
+    }
+
+    Ok(result)
+}
+
+/// Tauri command: Open a signed PDF in the system default PDF viewer
+/// Validates the file exists and has a .pdf extension before launching
+#[tauri::command]
+fn open_signed_pdf(pdf_path: String) -> Result<(), ESignError> {
+    let path = signed_pdf_to_open(&pdf_path)?;
+    opener::open(&path).map_err(|e| open_error("signed PDF", e))
+}
+
+/// Canonical path of an existing regular .pdf file, safe to hand to the system opener
+fn signed_pdf_to_open(pdf_path: &str) -> Result<PathBuf, ESignError> {
+    let path = pdf::validate_pdf_input_path(pdf_path)?;
+    if !path.is_file() {
+        return Err(ESignError::Pdf(format!("Not a file: {}", path.display())));
+    }
+    Ok(path)
+}
+
+/// Tauri command: Location of the signing/login audit log (None when disabled)
+#[tauri::command]
+fn get_audit_log_path(state: State<AppState>) -> Option<String> {
+    state
+        .audit_logger
+        .path()
+        .map(|path| path.display().to_string())
 }
 
 /// Initialize and run the Tauri application
@@ -345,29 +2162,397 @@ pub fn run() {
         .plugin(tauri_plugin_shell::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
-        .manage(AppState::default())
-        .setup(|_app| {
+        .manage(AppState::from_config(
+            AppState::load_config(),
+            config::default_config_path(),
+        ))
+        .setup(|app| {
+            // Preload PKCS#11 libraries in background so the first init is fast,
+            // then initialize the library used last time (reusing the preloaded handle)
+            let handle = app.handle().clone();
+            std::thread::spawn(move || {
+                let state = handle.state::<AppState>();
+                warmup_detected_libraries(&state.library_manager);
+                if let Err(e) = state.restore_saved_library() {
+                    eprintln!("Auto-init of the saved PKCS#11 library failed: {}", e);
+                }
+            });
+
+            // Notify the frontend when tokens are plugged in or pulled out
+            app.state::<AppState>()
+                .start_monitoring(app.handle().clone());
+
+            // Log out sessions left idle and tell the frontend to prompt for re-login
+            app.state::<AppState>()
+                .start_idle_timer(app.handle().clone());
+
             // DevTools: Uncomment to auto-open in debug mode
             // #[cfg(debug_assertions)]
             // {
-            //     let window = _app.get_webview_window("main").unwrap();
+            //     let window = app.get_webview_window("main").unwrap();
             //     window.open_devtools();
             // }
             Ok(())
         })
-        .invoke_handler(tauri::generate_handler![
+        .invoke_handler(track_activity(tauri::generate_handler![
             get_app_info,
             detect_libraries,
+            detect_libraries_async,
+            get_allowed_library_paths,
+            warmup_libraries,
             init_token_manager,
+            init_token_for_slot,
             list_tokens,
+            get_pin_retry_count,
             login_token,
+            login_slot,
+            logout_slot,
+            get_active_slots,
+            change_token_pin,
             get_certificate,
+            list_certificates,
+            list_signing_keys,
+            select_signing_key,
+            select_certificate,
+            get_certificate_extended,
+            get_certificate_policies,
+            check_certificate_validity,
+            export_certificate,
+            export_certificate_chain,
+            export_certificate_p12,
+            check_tsa_server,
+            check_all_tsa_servers,
+            select_best_tsa_server,
+            check_certificate_revocation,
+            check_crl_revocation,
+            get_vendor_attributes,
+            get_slot_mechanisms,
+            get_library_version_info,
             logout_token,
+            set_idle_timeout,
+            get_session_remaining_seconds,
             check_token_status,
+            check_session_alive,
+            from_percentage,
+            get_page_info,
+            get_page_count,
+            preview_signature_appearance,
+            flatten_pdf_forms,
+            watermark_unsigned_pdf,
+            get_default_signature_templates,
+            extract_text_near_signature,
+            verify_pdf_signatures,
+            diff_pdf_signatures,
             sign_data,
+            get_random_bytes,
+            lock_token_objects,
+            sign_data_with_algorithm,
+            sign_data_with_slot,
             sign_pdf,
+            sign_pdf_with_slot,
+            sign_pdf_async,
+            sign_pdf_return_base64,
+            sign_pdf_with_image_signature,
+            validate_signature_image,
+            merge_and_sign_pdf,
+            merge_pdfs,
+            certify_pdf,
+            get_pdf_certify_status,
+            validate_pdf_before_sign,
+            get_pdf_type,
+            get_signature_field_list,
+            find_existing_signature_fields,
+            sign_xml,
+            sign_pdfs_batch,
             open_file,
-        ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+            open_signed_pdf,
+            get_audit_log_path,
+            save_config,
+            reset_config,
+        ]))
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app, event| {
+            if let RunEvent::Exit = event {
+                app.state::<AppState>().stop_monitoring();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ============ Async Signing Tests ============
+
+    #[tokio::test]
+    async fn test_run_blocking_uses_blocking_pool() {
+        let caller = std::thread::current().id();
+        // Stand-in for sign_pdf_blocking: reports where it ran
+        let worker = run_blocking(|| Ok(std::thread::current().id()))
+            .await
+            .unwrap();
+        assert_ne!(worker, caller);
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_returns_job_error() {
+        let result: Result<(), ESignError> = run_blocking(|| Err(not_logged_in())).await;
+        assert!(matches!(
+            result.unwrap_err(),
+            ESignError::Signing {
+                code: SigningErrorCode::TokenNotFound,
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_run_blocking_reports_panic() {
+        let result: Result<(), ESignError> = run_blocking(|| panic!("token unplugged")).await;
+        let err = result.unwrap_err();
+        assert!(matches!(err, ESignError::Internal(_)));
+        assert!(err.to_string().contains("Signing task failed"));
+    }
+
+    // ============ Idle Timeout Tests ============
+
+    #[test]
+    fn test_idle_remaining_counts_down() {
+        let start = Instant::now();
+        let timeout = Duration::from_secs(300);
+        assert_eq!(idle_remaining(start, timeout, start), Some(timeout));
+        assert_eq!(
+            idle_remaining(start, timeout, start + Duration::from_secs(120)),
+            Some(Duration::from_secs(180))
+        );
+        assert_eq!(
+            idle_remaining(start, timeout, start + Duration::from_secs(301)),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_idle_remaining_zero_timeout_never_expires() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(86_400);
+        assert_eq!(idle_remaining(start, Duration::ZERO, later), None);
+    }
+
+    #[test]
+    fn test_idle_remaining_activity_after_check_time() {
+        // Activity recorded after `now` (clock race) leaves the full timeout
+        let now = Instant::now();
+        let timeout = Duration::from_secs(60);
+        assert_eq!(
+            idle_remaining(now + Duration::from_secs(1), timeout, now),
+            Some(timeout)
+        );
+    }
+
+    #[test]
+    fn test_app_state_default_idle_timeout() {
+        let state = AppState::default();
+        let remaining = state.idle_remaining(Instant::now()).unwrap();
+        assert!(remaining <= DEFAULT_IDLE_TIMEOUT);
+        assert!(remaining > DEFAULT_IDLE_TIMEOUT - IDLE_CHECK_INTERVAL);
+    }
+
+    #[test]
+    fn test_app_state_from_config() {
+        let path = std::env::temp_dir().join("esign_state_config.json");
+        let state = AppState::from_config(
+            AppConfig {
+                idle_timeout_secs: 60,
+                audit_log_path: Some("/tmp/esign_state_audit.log".to_string()),
+                ..Default::default()
+            },
+            path.clone(),
+        );
+        assert_eq!(*state.idle_timeout.lock().unwrap(), Duration::from_secs(60));
+        assert_eq!(
+            state.audit_logger.path(),
+            Some(Path::new("/tmp/esign_state_audit.log"))
+        );
+
+        // Saving picks up the current idle timeout
+        *state.idle_timeout.lock().unwrap() = Duration::ZERO;
+        state.persist_config().unwrap();
+        assert_eq!(config::load_config(&path).idle_timeout_secs, 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_restore_saved_library_skips_missing_file() {
+        let state = AppState::default();
+        state.config.lock().unwrap().last_library_path =
+            Some("/nonexistent/libpkcs11.so".to_string());
+        state.restore_saved_library().unwrap();
+        assert!(state.token_managers.get(DEFAULT_SLOT).is_none());
+
+        // No config path: persisting only updates the in-memory config
+        state.persist_config().unwrap();
+        assert_eq!(state.config.lock().unwrap().idle_timeout_secs, 300);
+    }
+
+    #[test]
+    fn test_touch_activity_restarts_timeout() {
+        let state = AppState::default();
+        let later = Instant::now() + Duration::from_secs(200);
+        assert!(state.idle_remaining(later).unwrap() <= Duration::from_secs(100));
+
+        state.touch_activity();
+        assert!(state.idle_remaining(later).unwrap() > Duration::from_secs(100));
+    }
+
+    #[test]
+    fn test_expire_idle_sessions_without_sessions() {
+        let state = AppState::default();
+        let now = Instant::now();
+        // Not yet idle
+        assert!(!state.expire_idle_sessions(now));
+        // Idle, but nobody is logged in: nothing to report
+        assert!(!state.expire_idle_sessions(now + DEFAULT_IDLE_TIMEOUT));
+
+        *state.idle_timeout.lock().unwrap() = Duration::ZERO;
+        assert_eq!(state.idle_remaining(now + DEFAULT_IDLE_TIMEOUT), None);
+    }
+
+    // ============ Audit Log Tests ============
+
+    /// State whose audit log is written to a fresh temp file
+    fn audited_state(name: &str) -> (AppState, PathBuf) {
+        let path = std::env::temp_dir()
+            .join(format!("esign_lib_audit_{}", name))
+            .join("audit.log");
+        let _ = std::fs::remove_file(&path);
+        let state = AppState {
+            audit_logger: AuditLogger::new(&path),
+            ..AppState::default()
+        };
+        (state, path)
+    }
+
+    fn audit_entries(path: &Path) -> Vec<serde_json::Value> {
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_failed_certify_is_audited() {
+        let (state, path) = audited_state("certify");
+        let result = certify_pdf_audited(
+            &state,
+            "/tmp/contract.pdf",
+            "/tmp/contract_certified.pdf",
+            PdfSigner::default(),
+            Some(2),
+        );
+        assert!(matches!(result, Err(ESignError::Pkcs11(_))));
+
+        let entries = audit_entries(&path);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["operation"], "sign_pdf");
+        assert_eq!(entries[0]["success"], false);
+        assert_eq!(entries[0]["error_type"], "Pkcs11");
+    }
+
+    #[test]
+    fn test_failed_sign_xml_is_audited() {
+        let (state, path) = audited_state("xml");
+        let result = sign_xml_audited(&state, "/tmp/tax.xml", "/tmp/tax_signed.xml");
+        assert!(result.is_err());
+
+        let entries = audit_entries(&path);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["operation"], "sign_xml");
+        assert_eq!(entries[0]["success"], false);
+    }
+
+    #[test]
+    fn test_failed_batch_audits_each_file() {
+        let (state, path) = audited_state("batch");
+        let jobs: Vec<BatchSignJob> = ["/tmp/a.pdf", "/tmp/b.pdf"]
+            .iter()
+            .map(|input| BatchSignJob {
+                input_path: input.to_string(),
+                output_path: format!("{}.signed.pdf", input),
+                job_id: None,
+            })
+            .collect();
+        let result = sign_pdfs_batch_audited(&state, &jobs, PdfSigner::default(), |_| {});
+        assert!(result.is_err());
+
+        let entries = audit_entries(&path);
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|entry| entry["success"] == false));
+        assert_ne!(entries[0]["pdf_path_hash"], entries[1]["pdf_path_hash"]);
+    }
+
+    // ============ Open Signed PDF Tests ============
+
+    #[test]
+    fn test_signed_pdf_to_open_rejects_non_pdf() {
+        let path = std::env::temp_dir().join("esign_open_not_pdf.txt");
+        std::fs::write(&path, b"%PDF-1.7").unwrap();
+        assert!(signed_pdf_to_open(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_signed_pdf_to_open_rejects_missing_file() {
+        let path = std::env::temp_dir().join("esign_open_missing.pdf");
+        let _ = std::fs::remove_file(&path);
+        assert!(signed_pdf_to_open(path.to_str().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_signed_pdf_to_open_rejects_directory() {
+        // Named like a PDF so only the file-type check can reject it
+        let dir = std::env::temp_dir().join("esign_open_dir.pdf");
+        std::fs::create_dir_all(&dir).unwrap();
+        let err = signed_pdf_to_open(dir.to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("Not a file"));
+        std::fs::remove_dir(&dir).ok();
+    }
+
+    #[test]
+    fn test_signed_pdf_to_open_accepts_signed_output() {
+        use crate::test_utils::{sample_pdf, sign_with_test_key, test_identity};
+
+        let dir = std::env::temp_dir();
+        let input = dir.join("esign_open_input.pdf");
+        let output = dir.join("esign_open_output.pdf");
+        std::fs::write(&input, sample_pdf(1)).unwrap();
+        let result = PdfSigningEngine::new()
+            .sign_pdf(
+                input.to_str().unwrap(),
+                output.to_str().unwrap(),
+                &PdfSigner::default(),
+                sign_with_test_key,
+                &test_identity().cert_der,
+            )
+            .unwrap();
+
+        let path = signed_pdf_to_open(&result.output_path).unwrap();
+        assert_eq!(path, output.canonicalize().unwrap());
+
+        std::fs::remove_file(&input).ok();
+        std::fs::remove_file(&output).ok();
+    }
+
+    // ============ Color Parsing Tests ============
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#dc2626"), Some((0xDC, 0x26, 0x26)));
+        assert_eq!(parse_hex_color("#FFFFFF"), Some((255, 255, 255)));
+        assert_eq!(parse_hex_color("dc2626"), None);
+        assert_eq!(parse_hex_color("#dc262"), None);
+        assert_eq!(parse_hex_color("#gg0000"), None);
+        assert_eq!(parse_hex_color("#éé00"), None);
+    }
 }
@@ -0,0 +1,577 @@
+//! OCSP Module
+//!
+//! Implements RFC 6960 certificate status requests for PAdES-LT signatures.
+//! The responder URL is read from the certificate's Authority Information Access extension.
+
+use crate::error::{CertValidationCode, ESignError};
+use crate::pdf::{build_sequence, der_children, encode_children, read_tlv};
+use reqwest::blocking::Client;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use x509_parser::prelude::*;
+
+/// id-ad-ocsp access method (1.3.6.1.5.5.7.48.1)
+pub(crate) const OID_AD_OCSP: &str = "1.3.6.1.5.5.7.48.1";
+/// id-ad-caIssuers access method (1.3.6.1.5.5.7.48.2)
+pub(crate) const OID_AD_CA_ISSUERS: &str = "1.3.6.1.5.5.7.48.2";
+/// id-sha1 (1.3.14.3.2.26), DER content bytes
+const OID_SHA1: &[u8] = &[0x2B, 0x0E, 0x03, 0x02, 0x1A];
+/// id-pkix-ocsp-basic (1.3.6.1.5.5.7.48.1.1), DER content bytes
+const OID_OCSP_BASIC: &[u8] = &[0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01];
+
+/// Retry guidance shown when an OCSP responder cannot be reached
+const OCSP_RETRY_SUGGESTION: &str =
+    "Check the network connection; the signature will be created without revocation info";
+
+/// OCSP client configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcspConfig {
+    /// Request timeout in seconds
+    pub timeout_secs: u64,
+    /// Responder URL used instead of the certificate's AIA entry
+    #[serde(default)]
+    pub responder_url: Option<String>,
+}
+
+impl Default for OcspConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: 15,
+            responder_url: None,
+        }
+    }
+}
+
+/// Certificate status reported by the responder
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OcspStatus {
+    Good,
+    Revoked,
+    Unknown,
+}
+
+/// Parsed OCSP response for a single certificate
+#[derive(Debug, Clone, Serialize)]
+pub struct OcspResponse {
+    pub status: OcspStatus,
+    /// Time the responder signed the response (RFC 3339)
+    pub produced_at: String,
+    pub this_update: String,
+    pub next_update: Option<String>,
+    /// Set only for revoked certificates
+    pub revocation_time: Option<String>,
+    /// URL the response was obtained from
+    pub responder_url: String,
+    /// DER-encoded OCSPResponse, embedded as-is in the CMS
+    #[serde(skip)]
+    pub der: Vec<u8>,
+}
+
+/// OCSP client for RFC 6960 status requests
+pub struct OcspClient {
+    config: OcspConfig,
+    http_client: Client,
+}
+
+impl OcspClient {
+    /// Create OCSP client with default timeout
+    pub fn new() -> Result<Self, ESignError> {
+        Self::with_config(OcspConfig::default())
+    }
+
+    /// Create OCSP client with custom configuration
+    pub fn with_config(config: OcspConfig) -> Result<Self, ESignError> {
+        let http_client = Client::builder()
+            .timeout(Duration::from_secs(config.timeout_secs))
+            .build()
+            .map_err(|e| revocation_error(format!("Failed to create HTTP client: {}", e)))?;
+
+        Ok(Self {
+            config,
+            http_client,
+        })
+    }
+
+    /// Query the certificate's OCSP responder
+    /// Without `issuer_der` the issuer is taken from the certificate itself if
+    /// self-issued, otherwise downloaded from the AIA caIssuers URL
+    pub fn check_certificate(
+        &self,
+        cert_der: &[u8],
+        issuer_der: Option<&[u8]>,
+    ) -> Result<OcspResponse, ESignError> {
+        let (_, cert) = X509Certificate::from_der(cert_der)
+            .map_err(|e| revocation_error(format!("Failed to parse certificate: {}", e)))?;
+
+        let responder_url = match &self.config.responder_url {
+            Some(url) => url.clone(),
+            None => aia_url(&cert, OID_AD_OCSP).ok_or_else(|| ESignError::CertValidation {
+                code: CertValidationCode::OCSPUrlNotFound,
+                message: "Certificate has no OCSP responder URL (AIA extension)".to_string(),
+            })?,
+        };
+
+        let fetched_issuer;
+        let issuer_der = match issuer_der {
+            Some(der) => der,
+            None if cert.subject().as_raw() == cert.issuer().as_raw() => cert_der,
+            None => {
+                fetched_issuer = self.fetch_issuer(&cert)?;
+                &fetched_issuer
+            }
+        };
+        let (_, issuer) = X509Certificate::from_der(issuer_der)
+            .map_err(|e| revocation_error(format!("Failed to parse issuer certificate: {}", e)))?;
+
+        let cert_id = build_cert_id(&cert, &issuer)?;
+        let request = build_ocsp_request(&cert_id)?;
+        let der = self.send_request(&responder_url, request)?;
+
+        parse_ocsp_response(&der, cert.tbs_certificate.raw_serial(), &responder_url)
+    }
+
+    /// Revocation status of `cert_der` issued by `issuer_der`
+    #[allow(dead_code)] // Status-only shorthand for check_certificate
+    pub fn check(&self, cert_der: &[u8], issuer_der: &[u8]) -> Result<OcspStatus, ESignError> {
+        self.check_certificate(cert_der, Some(issuer_der))
+            .map(|response| response.status)
+    }
+
+    /// POST an OCSPRequest and return the raw response body
+    fn send_request(&self, url: &str, request: Vec<u8>) -> Result<Vec<u8>, ESignError> {
+        let response = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/ocsp-request")
+            .body(request)
+            .send()
+            .map_err(|e| ESignError::network_error(url, &e.to_string(), OCSP_RETRY_SUGGESTION))?;
+
+        if !response.status().is_success() {
+            return Err(revocation_error(format!(
+                "OCSP responder {} returned HTTP {}",
+                url,
+                response.status()
+            )));
+        }
+
+        response
+            .bytes()
+            .map(|b| b.to_vec())
+            .map_err(|e| revocation_error(format!("Failed to read OCSP response: {}", e)))
+    }
+
+    /// Download the issuer certificate from the AIA caIssuers URL (DER only)
+    fn fetch_issuer(&self, cert: &X509Certificate) -> Result<Vec<u8>, ESignError> {
+        let url = aia_url(cert, OID_AD_CA_ISSUERS).ok_or_else(|| {
+            revocation_error("Issuer certificate not available (no AIA caIssuers URL)".to_string())
+        })?;
+
+        let response =
+            self.http_client.get(&url).send().map_err(|e| {
+                ESignError::network_error(&url, &e.to_string(), OCSP_RETRY_SUGGESTION)
+            })?;
+
+        if !response.status().is_success() {
+            return Err(revocation_error(format!(
+                "Issuer download from {} returned HTTP {}",
+                url,
+                response.status()
+            )));
+        }
+
+        response
+            .bytes()
+            .map(|b| b.to_vec())
+            .map_err(|e| revocation_error(format!("Failed to read issuer certificate: {}", e)))
+    }
+}
+
+/// First AIA URI with the given access method
+pub(crate) fn aia_url(cert: &X509Certificate, access_method: &str) -> Option<String> {
+    cert.extensions()
+        .iter()
+        .find_map(|ext| match ext.parsed_extension() {
+            ParsedExtension::AuthorityInfoAccess(aia) => Some(aia),
+            _ => None,
+        })?
+        .accessdescs
+        .iter()
+        .filter(|desc| desc.access_method.to_id_string() == access_method)
+        .find_map(|desc| match desc.access_location {
+            GeneralName::URI(uri) => Some(uri.to_string()),
+            _ => None,
+        })
+}
+
+/// Build CertID with SHA-1 hashes of the issuer name and public key (RFC 6960 §4.1.1)
+fn build_cert_id(cert: &X509Certificate, issuer: &X509Certificate) -> Result<Vec<u8>, ESignError> {
+    let sha1 = |data: &[u8]| {
+        ring::digest::digest(&ring::digest::SHA1_FOR_LEGACY_USE_ONLY, data)
+            .as_ref()
+            .to_vec()
+    };
+    let issuer_key: &[u8] = issuer.public_key().subject_public_key.data.as_ref();
+    let hash_algorithm = encode_children(&[(0x06, OID_SHA1.to_vec()), (0x05, Vec::new())])?;
+
+    build_sequence(&encode_children(&[
+        (0x30, hash_algorithm),
+        (0x04, sha1(cert.issuer().as_raw())),
+        (0x04, sha1(issuer_key)),
+        (0x02, cert.tbs_certificate.raw_serial().to_vec()),
+    ])?)
+}
+
+/// Build an unsigned OCSPRequest for a single CertID
+fn build_ocsp_request(cert_id: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let request = build_sequence(cert_id)?;
+    let request_list = build_sequence(&request)?;
+    let tbs_request = build_sequence(&request_list)?;
+    build_sequence(&tbs_request)
+}
+
+/// Parse an OCSPResponse and extract the status of the certificate with `serial`
+/// The responder signature is not checked here; validators verify the embedded response
+fn parse_ocsp_response(
+    der: &[u8],
+    serial: &[u8],
+    responder_url: &str,
+) -> Result<OcspResponse, ESignError> {
+    let invalid = |what: &str| revocation_error(format!("Invalid OCSP response: {}", what));
+
+    let (_, ocsp_response, _) = read_tlv(der).ok_or_else(|| invalid("not DER"))?;
+    let fields = der_children(ocsp_response).ok_or_else(|| invalid("malformed OCSPResponse"))?;
+    match fields.first() {
+        Some((0x0A, status)) if status.as_slice() == [0x00] => {}
+        Some((0x0A, status)) => {
+            return Err(revocation_error(format!(
+                "OCSP responder {} refused the request: {}",
+                responder_url,
+                response_status_name(status.first().copied().unwrap_or(0xFF))
+            )))
+        }
+        _ => return Err(invalid("missing responseStatus")),
+    }
+
+    // responseBytes [0] EXPLICIT SEQUENCE { responseType, response OCTET STRING }
+    let response_bytes = fields
+        .iter()
+        .find(|(tag, _)| *tag == 0xA0)
+        .and_then(|(_, content)| read_tlv(content))
+        .and_then(|(_, content, _)| der_children(content))
+        .ok_or_else(|| invalid("missing responseBytes"))?;
+    match response_bytes.as_slice() {
+        [(0x06, oid), (0x04, _)] if oid.as_slice() == OID_OCSP_BASIC => {}
+        _ => return Err(invalid("unsupported response type")),
+    }
+
+    // BasicOCSPResponse -> tbsResponseData
+    let basic = read_tlv(&response_bytes[1].1)
+        .and_then(|(_, content, _)| der_children(content))
+        .ok_or_else(|| invalid("malformed BasicOCSPResponse"))?;
+    let response_data = basic
+        .first()
+        .and_then(|(_, content)| der_children(content))
+        .ok_or_else(|| invalid("malformed ResponseData"))?;
+
+    // Skip optional version [0] and responderID [1]/[2]
+    let mut rest = response_data
+        .iter()
+        .skip_while(|(tag, _)| matches!(tag, 0xA0 | 0xA1 | 0xA2));
+    let produced_at = match rest.next() {
+        Some((0x18, time)) => format_generalized_time(time),
+        _ => return Err(invalid("missing producedAt")),
+    };
+    let responses = match rest.next() {
+        Some((0x30, content)) => der_children(content).ok_or_else(|| invalid("bad responses"))?,
+        _ => return Err(invalid("missing responses")),
+    };
+
+    for (_, single) in &responses {
+        let single = der_children(single).ok_or_else(|| invalid("bad SingleResponse"))?;
+        let matches_serial = single
+            .first()
+            .and_then(|(_, cert_id)| der_children(cert_id))
+            .and_then(|cert_id| cert_id.get(3).map(|(_, s)| s.as_slice() == serial))
+            .unwrap_or(false);
+        if !matches_serial {
+            continue;
+        }
+
+        let (status, revocation_time) = match single.get(1) {
+            Some((0x80, _)) => (OcspStatus::Good, None),
+            Some((0xA1, revoked_info)) => {
+                let time = read_tlv(revoked_info)
+                    .filter(|(tag, _, _)| *tag == 0x18)
+                    .map(|(_, time, _)| format_generalized_time(time));
+                (OcspStatus::Revoked, time)
+            }
+            Some((0x82, _)) => (OcspStatus::Unknown, None),
+            _ => return Err(invalid("bad certStatus")),
+        };
+        let this_update = match single.get(2) {
+            Some((0x18, time)) => format_generalized_time(time),
+            _ => return Err(invalid("missing thisUpdate")),
+        };
+        let next_update = single
+            .get(3)
+            .filter(|(tag, _)| *tag == 0xA0)
+            .and_then(|(_, content)| read_tlv(content))
+            .map(|(_, time, _)| format_generalized_time(time));
+
+        return Ok(OcspResponse {
+            status,
+            produced_at,
+            this_update,
+            next_update,
+            revocation_time,
+            responder_url: responder_url.to_string(),
+            der: der.to_vec(),
+        });
+    }
+
+    Err(invalid("no status for this certificate"))
+}
+
+/// OCSPResponseStatus name (RFC 6960 §4.2.1)
+fn response_status_name(status: u8) -> &'static str {
+    match status {
+        1 => "malformedRequest",
+        2 => "internalError",
+        3 => "tryLater",
+        5 => "sigRequired",
+        6 => "unauthorized",
+        _ => "unknown status",
+    }
+}
+
+/// GeneralizedTime (YYYYMMDDHHMMSSZ) as RFC 3339, or the raw string if unparseable
+fn format_generalized_time(bytes: &[u8]) -> String {
+    let raw = String::from_utf8_lossy(bytes);
+    chrono::NaiveDateTime::parse_from_str(&raw, "%Y%m%d%H%M%SZ")
+        .map(|t| t.and_utc().to_rfc3339())
+        .unwrap_or_else(|_| raw.into_owned())
+}
+
+pub(crate) fn revocation_error(message: String) -> ESignError {
+    ESignError::CertValidation {
+        code: CertValidationCode::RevocationCheckFailed,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{authority_info_access_extension, ocsp_response, test_identity, tlv};
+
+    fn test_cert() -> X509Certificate<'static> {
+        X509Certificate::from_der(&test_identity().cert_der)
+            .unwrap()
+            .1
+    }
+
+    // ============ Request Building Tests ============
+
+    #[test]
+    fn test_cert_id_structure() {
+        let cert = test_cert();
+        let cert_id = build_cert_id(&cert, &cert).unwrap();
+
+        let (tag, content, rest) = read_tlv(&cert_id).unwrap();
+        assert_eq!(tag, 0x30);
+        assert!(rest.is_empty());
+        let fields = der_children(content).unwrap();
+        let tags: Vec<u8> = fields.iter().map(|(tag, _)| *tag).collect();
+        assert_eq!(tags, vec![0x30, 0x04, 0x04, 0x02]);
+        assert_eq!(fields[1].1.len(), 20);
+        assert_eq!(fields[2].1.len(), 20);
+        assert_eq!(fields[3].1, cert.tbs_certificate.raw_serial());
+    }
+
+    #[test]
+    fn test_ocsp_request_wraps_cert_id() {
+        let cert = test_cert();
+        let cert_id = build_cert_id(&cert, &cert).unwrap();
+        let request = build_ocsp_request(&cert_id).unwrap();
+
+        // OCSPRequest -> TBSRequest -> requestList -> Request -> CertID
+        let mut content = request.as_slice();
+        for _ in 0..4 {
+            let (tag, inner, _) = read_tlv(content).unwrap();
+            assert_eq!(tag, 0x30);
+            content = inner;
+        }
+        assert_eq!(content, cert_id.as_slice());
+    }
+
+    #[test]
+    fn test_aia_url_missing() {
+        assert_eq!(aia_url(&test_cert(), OID_AD_OCSP), None);
+    }
+
+    #[test]
+    fn test_aia_url_from_extension() {
+        use crate::test_utils::build_certificate_with_extensions;
+
+        let der = build_certificate_with_extensions(
+            &test_identity().key,
+            "AIA Signer",
+            "250101000000Z",
+            "491231235959Z",
+            &[authority_info_access_extension("http://ocsp.example.vn")],
+        );
+        let (_, cert) = X509Certificate::from_der(&der).unwrap();
+        assert_eq!(
+            aia_url(&cert, OID_AD_OCSP).as_deref(),
+            Some("http://ocsp.example.vn")
+        );
+        assert_eq!(aia_url(&cert, OID_AD_CA_ISSUERS), None);
+    }
+
+    #[test]
+    fn test_check_certificate_without_responder_url() {
+        let client = OcspClient::new().unwrap();
+        let result = client.check_certificate(&test_identity().cert_der, None);
+        assert!(matches!(
+            result,
+            Err(ESignError::CertValidation {
+                code: CertValidationCode::OCSPUrlNotFound,
+                ..
+            })
+        ));
+    }
+
+    // ============ Response Parsing Tests ============
+
+    #[test]
+    fn test_parse_good_response() {
+        let der = ocsp_response(&[0x01, 0x23], vec![0x80, 0x00], true);
+        let response = parse_ocsp_response(&der, &[0x01, 0x23], "http://ocsp.test").unwrap();
+
+        assert_eq!(response.status, OcspStatus::Good);
+        assert_eq!(response.produced_at, "2025-03-01T12:00:00+00:00");
+        assert_eq!(response.this_update, "2025-03-01T00:00:00+00:00");
+        assert_eq!(
+            response.next_update.as_deref(),
+            Some("2025-03-08T00:00:00+00:00")
+        );
+        assert_eq!(response.revocation_time, None);
+        assert_eq!(response.responder_url, "http://ocsp.test");
+        assert_eq!(response.der, der);
+    }
+
+    #[test]
+    fn test_parse_revoked_response() {
+        let revoked = tlv(0xA1, &tlv(0x18, b"20250215080000Z"));
+        let der = ocsp_response(&[0x05], revoked, false);
+        let response = parse_ocsp_response(&der, &[0x05], "http://ocsp.test").unwrap();
+
+        assert_eq!(response.status, OcspStatus::Revoked);
+        assert_eq!(
+            response.revocation_time.as_deref(),
+            Some("2025-02-15T08:00:00+00:00")
+        );
+        assert_eq!(response.next_update, None);
+    }
+
+    #[test]
+    fn test_parse_unknown_response() {
+        let der = ocsp_response(&[0x05], vec![0x82, 0x00], false);
+        let response = parse_ocsp_response(&der, &[0x05], "http://ocsp.test").unwrap();
+        assert_eq!(response.status, OcspStatus::Unknown);
+    }
+
+    #[test]
+    fn test_parse_response_other_serial() {
+        let der = ocsp_response(&[0x05], vec![0x80, 0x00], false);
+        let err = parse_ocsp_response(&der, &[0x06], "http://ocsp.test").unwrap_err();
+        assert!(err.to_string().contains("no status for this certificate"));
+    }
+
+    #[test]
+    fn test_parse_unsuccessful_status() {
+        let der = tlv(0x30, &tlv(0x0A, &[0x03]));
+        let err = parse_ocsp_response(&der, &[0x05], "http://ocsp.test").unwrap_err();
+        assert!(err.to_string().contains("tryLater"));
+    }
+
+    #[test]
+    fn test_parse_garbage() {
+        assert!(parse_ocsp_response(b"not an ocsp response", &[0x05], "http://ocsp.test").is_err());
+    }
+
+    // ============ HTTP Tests ============
+
+    #[test]
+    fn test_check_certificate_over_http() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let identity = test_identity();
+        let serial = test_cert().tbs_certificate.raw_serial().to_vec();
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ocsp")).respond_with(
+                status_code(200)
+                    .insert_header("Content-Type", "application/ocsp-response")
+                    .body(ocsp_response(&serial, vec![0x80, 0x00], true)),
+            ),
+        );
+
+        let client = OcspClient::with_config(OcspConfig {
+            responder_url: Some(server.url("/ocsp").to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        // Self-signed test certificate is its own issuer
+        let response = client.check_certificate(&identity.cert_der, None).unwrap();
+        assert_eq!(response.status, OcspStatus::Good);
+        assert_eq!(response.responder_url, server.url("/ocsp").to_string());
+    }
+
+    #[test]
+    fn test_check_returns_status_with_explicit_issuer() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let identity = test_identity();
+        let serial = test_cert().tbs_certificate.raw_serial().to_vec();
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ocsp")).respond_with(
+                status_code(200).body(ocsp_response(&serial, vec![0x82, 0x00], false)),
+            ),
+        );
+
+        let client = OcspClient::with_config(OcspConfig {
+            responder_url: Some(server.url("/ocsp").to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let status = client
+            .check(&identity.cert_der, &identity.cert_der)
+            .unwrap();
+        assert_eq!(status, OcspStatus::Unknown);
+    }
+
+    #[test]
+    fn test_check_certificate_http_error() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ocsp"))
+                .respond_with(status_code(503)),
+        );
+
+        let client = OcspClient::with_config(OcspConfig {
+            responder_url: Some(server.url("/ocsp").to_string()),
+            ..Default::default()
+        })
+        .unwrap();
+        let err = client
+            .check_certificate(&test_identity().cert_der, None)
+            .unwrap_err();
+        assert!(err.to_string().contains("503"));
+    }
+}
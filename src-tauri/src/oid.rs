@@ -0,0 +1,125 @@
+//! OID Registry Module
+//!
+//! Maps OID dot-notation strings to human-readable names for display,
+//! covering X.500 attributes, CMS/PKCS#9 attributes and algorithms used
+//! by the signer, and Vietnamese CA certificate policies.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Known OIDs: (dotted, display name, DN short name)
+const KNOWN_OIDS: &[(&str, &str, Option<&str>)] = &[
+    // X.500 attribute types (subject/issuer DN)
+    ("2.5.4.3", "Common Name", Some("CN")),
+    ("2.5.4.4", "Surname", Some("SN")),
+    ("2.5.4.5", "Serial Number", Some("SERIALNUMBER")),
+    ("2.5.4.6", "Country", Some("C")),
+    ("2.5.4.7", "Locality", Some("L")),
+    ("2.5.4.8", "State or Province", Some("ST")),
+    ("2.5.4.9", "Street Address", Some("STREET")),
+    ("2.5.4.10", "Organization", Some("O")),
+    ("2.5.4.11", "Organizational Unit", Some("OU")),
+    ("2.5.4.12", "Title", Some("T")),
+    ("2.5.4.42", "Given Name", Some("GN")),
+    // Vietnamese CAs put MST/CCCD into UID
+    ("0.9.2342.19200300.100.1.1", "User ID", Some("UID")),
+    ("1.2.840.113549.1.9.1", "Email", Some("E")),
+    // Algorithms
+    ("1.2.840.113549.1.1.1", "RSA Encryption", None),
+    ("1.2.840.113549.1.1.5", "SHA-1 with RSA", None),
+    ("1.2.840.113549.1.1.11", "SHA-256 with RSA", None),
+    ("1.2.840.113549.1.1.12", "SHA-384 with RSA", None),
+    ("1.2.840.113549.1.1.13", "SHA-512 with RSA", None),
+    ("1.2.840.10045.2.1", "EC Public Key", None),
+    ("1.2.840.10045.4.3.2", "ECDSA with SHA-256", None),
+    ("1.3.14.3.2.26", "SHA-1", None),
+    ("2.16.840.1.101.3.4.2.1", "SHA-256", None),
+    ("2.16.840.1.101.3.4.2.2", "SHA-384", None),
+    ("2.16.840.1.101.3.4.2.3", "SHA-512", None),
+    // CMS content types and PKCS#9 attributes
+    ("1.2.840.113549.1.7.1", "Data", None),
+    ("1.2.840.113549.1.7.2", "Signed Data", None),
+    ("1.2.840.113549.1.9.3", "Content Type", None),
+    ("1.2.840.113549.1.9.4", "Message Digest", None),
+    ("1.2.840.113549.1.9.5", "Signing Time", None),
+    (
+        "1.2.840.113549.1.9.16.2.14",
+        "Signature Timestamp Token",
+        None,
+    ),
+    ("1.2.840.113549.1.9.16.2.47", "Signing Certificate V2", None),
+    ("1.3.6.1.5.5.7.16.2", "OCSP Response", None),
+    // Certificate extensions and policy qualifiers
+    ("2.5.29.32", "Certificate Policies", None),
+    ("1.3.6.1.5.5.7.2.1", "CPS URI", None),
+    // Vietnamese CA certificate policies
+    ("2.16.704.1.2.2.1.1.1", "VNPT-CA Class 1", None),
+];
+
+/// Lookup table for OID display names
+pub struct OidRegistry;
+
+impl OidRegistry {
+    fn names() -> &'static HashMap<&'static str, &'static str> {
+        static NAMES: OnceLock<HashMap<&'static str, &'static str>> = OnceLock::new();
+        NAMES.get_or_init(|| {
+            KNOWN_OIDS
+                .iter()
+                .map(|(oid, name, _)| (*oid, *name))
+                .collect()
+        })
+    }
+
+    /// Human-readable name for a dotted OID, e.g. "2.5.4.3" → "Common Name"
+    pub fn lookup(oid: &str) -> Option<&'static str> {
+        Self::names().get(oid).copied()
+    }
+
+    /// DN short name for an attribute type OID, e.g. "2.5.4.3" → "CN"
+    pub fn short_name(oid: &str) -> Option<&'static str> {
+        KNOWN_OIDS
+            .iter()
+            .find(|(known, _, _)| *known == oid)
+            .and_then(|(_, _, short)| *short)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ============ OidRegistry Tests ============
+
+    #[test]
+    fn test_lookup_known_oids() {
+        assert_eq!(OidRegistry::lookup("2.5.4.3"), Some("Common Name"));
+        assert_eq!(OidRegistry::lookup("1.2.840.113549.1.9.1"), Some("Email"));
+        assert_eq!(
+            OidRegistry::lookup("2.16.704.1.2.2.1.1.1"),
+            Some("VNPT-CA Class 1")
+        );
+    }
+
+    #[test]
+    fn test_lookup_unknown_oid() {
+        assert_eq!(OidRegistry::lookup("1.2.3.4"), None);
+        assert_eq!(OidRegistry::short_name("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn test_short_name() {
+        assert_eq!(OidRegistry::short_name("2.5.4.3"), Some("CN"));
+        assert_eq!(
+            OidRegistry::short_name("0.9.2342.19200300.100.1.1"),
+            Some("UID")
+        );
+        assert_eq!(OidRegistry::short_name("1.2.840.113549.1.9.1"), Some("E"));
+        // Algorithms have no DN short name
+        assert_eq!(OidRegistry::short_name("2.16.840.1.101.3.4.2.1"), None);
+    }
+
+    #[test]
+    fn test_known_oids_unique() {
+        assert_eq!(OidRegistry::names().len(), KNOWN_OIDS.len());
+    }
+}
@@ -2,29 +2,88 @@
 //!
 //! Contains certificate parsing helpers, path validation, and architecture detection.
 
-use crate::error::ESignError;
+use crate::error::{CertValidationCode, ESignError, SigningErrorCode};
+use crate::ocsp::{aia_url, OID_AD_CA_ISSUERS, OID_AD_OCSP};
+use crate::oid::OidRegistry;
 use x509_parser::prelude::*;
 
-/// Format X.509 Distinguished Name with proper UTF-8 support
+use super::types::{
+    format_datetime, CertPolicyInfo, CertValidityReport, CertificateInfo, CertificateInfoExtended,
+    KeyUsageFlags,
+};
+
+/// Vietnam country arc; CA policy OIDs are registered under it
+const VIETNAM_OID_ARC: &str = "2.16.704.";
+
+/// id-qt-cps policy qualifier (1.3.6.1.5.5.7.2.1)
+const CPS_QUALIFIER_OID: &str = "1.3.6.1.5.5.7.2.1";
+
+/// rsaEncryption (1.2.840.113549.1.1.1)
+const RSA_ENCRYPTION_OID: &str = "1.2.840.113549.1.1.1";
+
+/// id-ecPublicKey (1.2.840.10045.2.1)
+const EC_PUBLIC_KEY_OID: &str = "1.2.840.10045.2.1";
+
+/// Named curves and their key sizes in bits
+const EC_CURVE_SIZES: &[(&str, u32)] = &[
+    ("1.2.840.10045.3.1.7", 256), // P-256
+    ("1.3.132.0.34", 384),        // P-384
+    ("1.3.132.0.35", 521),        // P-521
+    ("1.3.132.0.10", 256),        // secp256k1
+];
+
+/// DN attribute types in VNPT-CA Plugin display order: CN, UID, OU, O, L, ST, C
+const VNPT_DN_ORDER: [&str; 7] = [
+    "2.5.4.3",                   // CN
+    "0.9.2342.19200300.100.1.1", // UID
+    "2.5.4.11",                  // OU
+    "2.5.4.10",                  // O
+    "2.5.4.7",                   // L
+    "2.5.4.8",                   // ST
+    "2.5.4.6",                   // C
+];
+
+/// Format X.509 Distinguished Name with proper UTF-8 support, in certificate order
 /// Handles Vietnamese characters that x509_parser's default to_string() corrupts
 pub fn format_dn_utf8(name: &x509_parser::x509::X509Name) -> String {
+    join_dn_attributes(dn_attributes_utf8(name))
+}
+
+/// Format a Distinguished Name like the VNPT-CA Plugin: CN, UID, OU, O, L, ST, C
+/// Missing fields are skipped; other attribute types follow in certificate order
+pub fn format_dn_utf8_vnpt_order(name: &x509_parser::x509::X509Name) -> String {
+    let mut attributes = dn_attributes_utf8(name);
+    // Stable sort keeps repeated types (e.g. several OUs) in certificate order
+    attributes.sort_by_key(|(oid, _)| {
+        VNPT_DN_ORDER
+            .iter()
+            .position(|known| known == oid)
+            .unwrap_or(VNPT_DN_ORDER.len())
+    });
+    join_dn_attributes(attributes)
+}
+
+/// "TYPE=value" pairs joined with ", ", using short names where known
+fn join_dn_attributes(attributes: Vec<(String, String)>) -> String {
+    attributes
+        .iter()
+        .map(|(oid, value)| {
+            let attr_type = OidRegistry::short_name(oid).unwrap_or(oid.as_str());
+            format!("{}={}", attr_type, value)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// (attribute type OID, decoded value) of every DN attribute, in certificate order
+fn dn_attributes_utf8(name: &x509_parser::x509::X509Name) -> Vec<(String, String)> {
     use x509_parser::der_parser::asn1_rs::Any;
 
     let mut parts = Vec::new();
 
     for rdn in name.iter() {
         for attr in rdn.iter() {
-            // Get attribute type (CN, L, O, etc.)
             let oid_string = attr.attr_type().to_id_string();
-            let attr_type = match oid_string.as_str() {
-                "2.5.4.3" => "CN",
-                "2.5.4.6" => "C",
-                "2.5.4.7" => "L",
-                "2.5.4.8" => "ST",
-                "2.5.4.10" => "O",
-                "2.5.4.11" => "OU",
-                _ => &oid_string,
-            };
 
             // Try to decode value as UTF-8 string
             let value = if let Ok((_rest, any)) = Any::from_der(attr.attr_value().as_bytes()) {
@@ -51,32 +110,502 @@ pub fn format_dn_utf8(name: &x509_parser::x509::X509Name) -> String {
                 attr.as_str().unwrap_or("?").to_string()
             };
 
-            parts.push(format!("{}={}", attr_type, value));
+            parts.push((oid_string, value));
         }
     }
 
-    parts.join(", ")
+    parts
+}
+
+/// CK_MECHANISM_INFO flags reported to the frontend
+pub const CKF_ENCRYPT: u64 = 0x0100;
+pub const CKF_DECRYPT: u64 = 0x0200;
+pub const CKF_SIGN: u64 = 0x0800;
+pub const CKF_SIGN_RECOVER: u64 = 0x1000;
+pub const CKF_VERIFY: u64 = 0x2000;
+
+/// Relevant mechanism flags, in display order
+const MECHANISM_FLAG_NAMES: &[(u64, &str)] = &[
+    (CKF_SIGN, "Sign"),
+    (CKF_SIGN_RECOVER, "SignRecover"),
+    (CKF_VERIFY, "Verify"),
+    (CKF_DECRYPT, "Decrypt"),
+    (CKF_ENCRYPT, "Encrypt"),
+];
+
+/// Human-readable names of common CKM_ mechanism types
+const MECHANISM_NAMES: &[(u64, &str)] = &[
+    (0x0000, "RSA key pair generation"),
+    (0x0001, "RSA PKCS#1 v1.5"),
+    (0x0003, "RSA raw (X.509)"),
+    (0x0006, "SHA-1 with RSA PKCS#1 v1.5"),
+    (0x0009, "RSA OAEP"),
+    (0x000D, "RSA PSS"),
+    (0x000E, "SHA-1 with RSA PSS"),
+    (0x0040, "SHA-256 with RSA PKCS#1 v1.5"),
+    (0x0041, "SHA-384 with RSA PKCS#1 v1.5"),
+    (0x0042, "SHA-512 with RSA PKCS#1 v1.5"),
+    (0x0043, "SHA-256 with RSA PSS"),
+    (0x0044, "SHA-384 with RSA PSS"),
+    (0x0045, "SHA-512 with RSA PSS"),
+    (0x0132, "Triple DES ECB"),
+    (0x0133, "Triple DES CBC"),
+    (0x0220, "SHA-1"),
+    (0x0250, "SHA-256"),
+    (0x0260, "SHA-384"),
+    (0x0270, "SHA-512"),
+    (0x1040, "EC key pair generation"),
+    (0x1041, "ECDSA"),
+    (0x1042, "ECDSA with SHA-1"),
+    (0x1044, "ECDSA with SHA-256"),
+    (0x1045, "ECDSA with SHA-384"),
+    (0x1046, "ECDSA with SHA-512"),
+    (0x1050, "ECDH key derivation"),
+    (0x1080, "AES key generation"),
+    (0x1081, "AES ECB"),
+    (0x1082, "AES CBC"),
+    (0x1085, "AES CBC with padding"),
+    (0x1087, "AES GCM"),
+];
+
+/// Human-readable name for a CKM_ mechanism type
+/// Unknown types are shown as hex; CKM_VENDOR_DEFINED and above are marked as vendor
+pub fn mechanism_name(mechanism_type: u64) -> String {
+    if let Some((_, name)) = MECHANISM_NAMES
+        .iter()
+        .find(|(value, _)| *value == mechanism_type)
+    {
+        return name.to_string();
+    }
+    if mechanism_type >= 0x8000_0000 {
+        format!("Vendor mechanism 0x{:08X}", mechanism_type)
+    } else {
+        format!("Mechanism 0x{:08X}", mechanism_type)
+    }
+}
+
+/// Names of the relevant flags set in a CK_MECHANISM_INFO flags bitmask
+pub fn mechanism_flag_names(flags: u64) -> Vec<String> {
+    MECHANISM_FLAG_NAMES
+        .iter()
+        .filter(|(bit, _)| flags & bit != 0)
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// Look up a human-readable name for a Vietnamese CA policy OID
+pub fn policy_name_for_oid(oid: &str) -> Option<&'static str> {
+    OidRegistry::lookup(oid).filter(|_| oid.starts_with(VIETNAM_OID_ARC))
+}
+
+/// Parse the CertificatePolicies extension of a DER certificate
+/// Returns an empty list if the extension is absent
+pub fn parse_certificate_policies(cert_der: &[u8]) -> Result<Vec<CertPolicyInfo>, ESignError> {
+    use x509_parser::der_parser::asn1_rs::Any;
+
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .map_err(|e| ESignError::Pkcs11(format!("Failed to parse certificate: {}", e)))?;
+
+    let policies = cert
+        .certificate_policies()
+        .map_err(|e| ESignError::Pkcs11(format!("Invalid CertificatePolicies extension: {}", e)))?;
+    let Some(policies) = policies else {
+        return Ok(Vec::new());
+    };
+
+    Ok(policies
+        .value
+        .iter()
+        .map(|policy| {
+            let policy_oid = policy.policy_id.to_id_string();
+            let cps_uri = policy
+                .policy_qualifiers
+                .iter()
+                .flatten()
+                .find(|q| q.policy_qualifier_id.to_id_string() == CPS_QUALIFIER_OID)
+                .map(|q| match Any::from_der(q.qualifier) {
+                    // IA5String TLV
+                    Ok((_, any)) => String::from_utf8_lossy(any.data).to_string(),
+                    Err(_) => String::from_utf8_lossy(q.qualifier).to_string(),
+                });
+
+            CertPolicyInfo {
+                policy_name: policy_name_for_oid(&policy_oid).map(str::to_string),
+                policy_oid,
+                cps_uri,
+            }
+        })
+        .collect())
+}
+
+/// Parse subject, issuer, validity, thumbprint and AIA URLs of a DER certificate
+pub fn certificate_info_from_der(cert_der: &[u8]) -> Result<CertificateInfo, ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+    use sha2::{Digest, Sha256};
+
+    // Parse certificate with x509-parser
+    let (_, cert) = X509Certificate::from_der(cert_der).map_err(|e| ESignError::Signing {
+        code: SigningErrorCode::CertificateNotFound,
+        message: format!("Failed to parse certificate: {}", e),
+    })?;
+
+    // Extract certificate fields
+    let serial = cert.serial.to_string();
+    let subject = format_dn_utf8_vnpt_order(cert.subject());
+    let issuer = format_dn_utf8_vnpt_order(cert.issuer());
+
+    // Format dates as Vietnamese standard
+    let valid_from = format_datetime(cert.validity().not_before.timestamp());
+    let valid_to = format_datetime(cert.validity().not_after.timestamp());
+
+    // Calculate SHA-256 thumbprint
+    let thumbprint = hex::encode(Sha256::digest(cert_der));
+
+    // Base64 encode the DER certificate
+    let der_base64 = STANDARD.encode(cert_der);
+
+    let (ocsp_url, ca_issuers_url) = parse_authority_info_access(&cert);
+
+    let mut info = CertificateInfo {
+        serial,
+        subject,
+        issuer,
+        valid_from,
+        valid_to,
+        thumbprint,
+        der_base64,
+        valid_from_timestamp: cert.validity().not_before.timestamp(),
+        valid_to_timestamp: cert.validity().not_after.timestamp(),
+        validity_fraction: 0.0,
+        validity_class: String::new(),
+        expiring_soon: false,
+        ocsp_url,
+        ca_issuers_url,
+        cert_id: None,
+    };
+    info.refresh_validity();
+
+    Ok(info)
+}
+
+/// Validity of a DER certificate at the given Unix timestamp
+pub fn certificate_validity_report(
+    cert_der: &[u8],
+    now: i64,
+) -> Result<CertValidityReport, ESignError> {
+    let (_, cert) = X509Certificate::from_der(cert_der).map_err(|e| ESignError::Signing {
+        code: SigningErrorCode::CertificateNotFound,
+        message: format!("Failed to parse certificate: {}", e),
+    })?;
+    let validity = cert.validity();
+    Ok(CertValidityReport::at(
+        validity.not_before.timestamp(),
+        validity.not_after.timestamp(),
+        now,
+    ))
+}
+
+/// KeyUsage extension (2.5.29.15) bits of a DER certificate
+/// Without the extension the key is unrestricted, so every flag is set
+pub fn extract_key_usage(cert_der: &[u8]) -> Result<KeyUsageFlags, ESignError> {
+    let (_, cert) = X509Certificate::from_der(cert_der).map_err(|e| ESignError::Signing {
+        code: SigningErrorCode::CertificateNotFound,
+        message: format!("Failed to parse certificate: {}", e),
+    })?;
+    let key_usage = cert
+        .key_usage()
+        .map_err(|e| ESignError::Pkcs11(format!("Invalid KeyUsage extension: {}", e)))?;
+
+    Ok(match key_usage {
+        Some(ext) => KeyUsageFlags {
+            digital_signature: ext.value.digital_signature(),
+            non_repudiation: ext.value.non_repudiation(),
+            key_encipherment: ext.value.key_encipherment(),
+            data_encipherment: ext.value.data_encipherment(),
+        },
+        None => KeyUsageFlags {
+            digital_signature: true,
+            non_repudiation: true,
+            key_encipherment: true,
+            data_encipherment: true,
+        },
+    })
+}
+
+/// Reject certificates whose KeyUsage allows neither digitalSignature nor nonRepudiation
+/// (e.g. encryption-only certificates on the same token)
+pub fn validate_signing_certificate(cert_der: &[u8]) -> Result<(), ESignError> {
+    let usage = extract_key_usage(cert_der)?;
+    if !usage.digital_signature && !usage.non_repudiation {
+        return Err(ESignError::CertValidation {
+            code: CertValidationCode::CannotSign,
+            message: "Certificate key usage does not allow signing (digitalSignature or nonRepudiation required)".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// OCSP and caIssuers URLs from the AuthorityInfoAccess extension
+/// The first URI of each access method is used; both are None without the extension
+pub fn parse_authority_info_access(cert: &X509Certificate) -> (Option<String>, Option<String>) {
+    (aia_url(cert, OID_AD_OCSP), aia_url(cert, OID_AD_CA_ISSUERS))
+}
+
+/// Add key algorithm/size, SANs, key usages and self-signed status to `info`
+pub fn parse_certificate_extended(
+    info: CertificateInfo,
+    cert_der: &[u8],
+) -> Result<CertificateInfoExtended, ESignError> {
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .map_err(|e| ESignError::Pkcs11(format!("Failed to parse certificate: {}", e)))?;
+
+    let spki = cert.public_key();
+    let algorithm_oid = spki.algorithm.algorithm.to_id_string();
+    let (key_algorithm, key_size_bits) = match algorithm_oid.as_str() {
+        RSA_ENCRYPTION_OID => ("RSA".to_string(), rsa_key_size(spki)),
+        EC_PUBLIC_KEY_OID => ("EC".to_string(), ec_key_size(spki)),
+        other => (
+            OidRegistry::lookup(other).unwrap_or(other).to_string(),
+            None,
+        ),
+    };
+
+    let subject_alt_names = cert
+        .subject_alternative_name()
+        .map_err(|e| ESignError::Pkcs11(format!("Invalid SubjectAltName extension: {}", e)))?
+        .map(|ext| {
+            ext.value
+                .general_names
+                .iter()
+                .filter_map(format_general_name)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let key_usages = cert
+        .key_usage()
+        .map_err(|e| ESignError::Pkcs11(format!("Invalid KeyUsage extension: {}", e)))?
+        .map(|ext| {
+            let usage = ext.value;
+            [
+                (usage.digital_signature(), "digitalSignature"),
+                (usage.non_repudiation(), "nonRepudiation"),
+                (usage.key_encipherment(), "keyEncipherment"),
+                (usage.data_encipherment(), "dataEncipherment"),
+                (usage.key_agreement(), "keyAgreement"),
+                (usage.key_cert_sign(), "keyCertSign"),
+                (usage.crl_sign(), "cRLSign"),
+                (usage.encipher_only(), "encipherOnly"),
+                (usage.decipher_only(), "decipherOnly"),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name.to_string())
+            .collect()
+        })
+        .unwrap_or_default();
+
+    let extended_key_usages = cert
+        .extended_key_usage()
+        .map_err(|e| ESignError::Pkcs11(format!("Invalid ExtendedKeyUsage extension: {}", e)))?
+        .map(|ext| {
+            let usage = ext.value;
+            [
+                (usage.any, "anyExtendedKeyUsage"),
+                (usage.server_auth, "serverAuth"),
+                (usage.client_auth, "clientAuth"),
+                (usage.code_signing, "codeSigning"),
+                (usage.email_protection, "emailProtection"),
+                (usage.time_stamping, "timeStamping"),
+                (usage.ocsp_signing, "OCSPSigning"),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| name.to_string())
+            .chain(usage.other.iter().map(|oid| oid.to_id_string()))
+            .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(CertificateInfoExtended {
+        info,
+        key_algorithm,
+        key_size_bits,
+        subject_alt_names,
+        key_usages,
+        extended_key_usages,
+        is_self_signed: is_self_signed(&cert),
+    })
+}
+
+/// RSA key size from the modulus in the SubjectPublicKeyInfo bit string
+fn rsa_key_size(spki: &SubjectPublicKeyInfo) -> Option<u32> {
+    let Ok(x509_parser::public_key::PublicKey::RSA(rsa)) = spki.parsed() else {
+        return None;
+    };
+    let modulus: &[u8] = rsa.modulus;
+    let start = modulus.iter().position(|&b| b != 0)?;
+    Some(((modulus.len() - start) * 8) as u32 - modulus[start].leading_zeros())
+}
+
+/// EC key size from the named curve in the algorithm parameters
+fn ec_key_size(spki: &SubjectPublicKeyInfo) -> Option<u32> {
+    let curve = spki.algorithm.parameters.as_ref()?.as_oid().ok()?;
+    let curve = curve.to_id_string();
+    EC_CURVE_SIZES
+        .iter()
+        .find(|(oid, _)| *oid == curve)
+        .map(|(_, bits)| *bits)
+}
+
+/// SubjectAltName entry with an OpenSSL-style type prefix
+fn format_general_name(name: &GeneralName) -> Option<String> {
+    match name {
+        GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+        GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+        GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+        GeneralName::IPAddress(ip) => match ip.len() {
+            4 => <[u8; 4]>::try_from(*ip)
+                .ok()
+                .map(|ip| format!("IP:{}", std::net::Ipv4Addr::from(ip))),
+            16 => <[u8; 16]>::try_from(*ip)
+                .ok()
+                .map(|ip| format!("IP:{}", std::net::Ipv6Addr::from(ip))),
+            _ => None,
+        },
+        GeneralName::DirectoryName(dn) => Some(format!("DirName:{}", format_dn_utf8(dn))),
+        _ => None,
+    }
+}
+
+/// Subject equals issuer, and the key identifiers agree when both are present
+fn is_self_signed(cert: &X509Certificate) -> bool {
+    let mut subject_key_id = None;
+    let mut authority_key_id = None;
+    for ext in cert.extensions() {
+        match ext.parsed_extension() {
+            ParsedExtension::SubjectKeyIdentifier(id) => subject_key_id = Some(id.0),
+            ParsedExtension::AuthorityKeyIdentifier(aki) => {
+                authority_key_id = aki.key_identifier.as_ref().map(|id| id.0)
+            }
+            _ => {}
+        }
+    }
+
+    let key_ids_match = match (subject_key_id, authority_key_id) {
+        (Some(subject), Some(authority)) => subject == authority,
+        _ => true,
+    };
+    cert.subject().as_raw() == cert.issuer().as_raw() && key_ids_match
+}
+
+/// Encode a DER certificate as PEM (64-character base64 lines)
+pub fn certificate_to_pem(cert_der: &[u8]) -> String {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let encoded = STANDARD.encode(cert_der);
+    let mut pem = String::from("-----BEGIN CERTIFICATE-----\n");
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(std::str::from_utf8(line).unwrap_or_default());
+        pem.push('\n');
+    }
+    pem.push_str("-----END CERTIFICATE-----\n");
+    pem
+}
+
+/// Validate a token PIN: 4-16 ASCII alphanumeric characters
+/// Messages are matched by the frontend error map
+pub fn validate_pin(pin: &str) -> Result<(), &'static str> {
+    if pin.len() < 4 || pin.len() > 16 {
+        return Err("PIN must be 4-16 characters");
+    }
+    if !pin.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err("PIN contains invalid characters");
+    }
+    Ok(())
+}
+
+/// Curve size in bits from CKA_EC_PARAMS (DER namedCurve OID); 0 for unknown curves
+pub fn ec_key_size(ec_params: &[u8]) -> u32 {
+    match ec_params {
+        // prime256v1 (1.2.840.10045.3.1.7)
+        [0x06, 0x08, 0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07] => 256,
+        // secp384r1 (1.3.132.0.34)
+        [0x06, 0x05, 0x2B, 0x81, 0x04, 0x00, 0x22] => 384,
+        // secp521r1 (1.3.132.0.35)
+        [0x06, 0x05, 0x2B, 0x81, 0x04, 0x00, 0x23] => 521,
+        _ => 0,
+    }
+}
+
+/// Largest C_GenerateRandom request accepted from the frontend
+pub const MAX_RANDOM_BYTES: usize = 1024;
+
+/// Validate a random byte count: 1 to MAX_RANDOM_BYTES
+pub fn validate_random_length(len: usize) -> Result<(), ESignError> {
+    if len == 0 || len > MAX_RANDOM_BYTES {
+        return Err(ESignError::invalid_input(format!(
+            "Random length must be 1-{} bytes",
+            MAX_RANDOM_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Allowed PKCS#11 library locations on macOS
+pub const MACOS_LIBRARY_PREFIXES: &[&str] = &[
+    "/Library/",
+    "/usr/local/lib/",
+    // Homebrew on Apple Silicon, MacPorts (OpenSC)
+    "/opt/homebrew/lib/",
+    "/opt/local/lib/",
+];
+
+/// Allowed PKCS#11 library locations on Windows
+pub const WINDOWS_LIBRARY_PREFIXES: &[&str] = &[
+    "C:\\Program Files\\",
+    "C:\\Program Files (x86)\\",
+    // Vietnamese CA standard installation paths (see library_paths)
+    "C:\\vnpt-ca\\",
+    "C:\\Viettel-CA\\",
+    "C:\\FPT-CA\\",
+];
+
+/// Allowed PKCS#11 library locations on Linux
+pub const LINUX_LIBRARY_PREFIXES: &[&str] =
+    &["/usr/lib/", "/usr/lib64/", "/usr/local/lib/", "/opt/"];
+
+/// Allowed PKCS#11 library locations for the current platform (hardcoded for security)
+pub fn allowed_library_prefixes() -> &'static [&'static str] {
+    if cfg!(target_os = "macos") {
+        MACOS_LIBRARY_PREFIXES
+    } else if cfg!(target_os = "windows") {
+        WINDOWS_LIBRARY_PREFIXES
+    } else if cfg!(target_os = "linux") {
+        LINUX_LIBRARY_PREFIXES
+    } else {
+        &["/usr/lib/"]
+    }
+}
+
+/// Whether a canonical path lies under one of `prefixes`
+/// The `\\?\` verbatim prefix that `canonicalize` adds on Windows is ignored;
+/// `case_insensitive` is for Windows, where `C:\VNPT-CA\` and `C:\vnpt-ca\` are the same
+pub fn is_allowed_library_location(path: &str, prefixes: &[&str], case_insensitive: bool) -> bool {
+    let path = path.strip_prefix("\\\\?\\").unwrap_or(path);
+    prefixes.iter().any(|prefix| {
+        if case_insensitive {
+            path.to_lowercase().starts_with(&prefix.to_lowercase())
+        } else {
+            path.starts_with(prefix)
+        }
+    })
 }
 
 /// Validate library path is in allowed locations (security measure)
 /// Prevents arbitrary code injection via malicious PKCS#11 libraries
 pub fn validate_library_path(path: &str) -> Result<(), ESignError> {
-    // Define allowed prefixes per platform (hardcoded for security)
-    #[cfg(target_os = "macos")]
-    let allowed_prefixes: &[&str] = &["/Library/", "/usr/local/lib/"];
-    #[cfg(target_os = "windows")]
-    let allowed_prefixes: &[&str] = &[
-        "C:\\Program Files\\",
-        "C:\\Program Files (x86)\\",
-        // Vietnamese CA standard installation paths
-        "C:\\vnpt-ca\\",
-        "C:\\Viettel-CA\\",
-        "C:\\FPT-CA\\",
-    ];
-    #[cfg(target_os = "linux")]
-    let allowed_prefixes: &[&str] = &["/usr/lib/", "/usr/local/lib/", "/opt/"];
-    #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
-    let allowed_prefixes: &[&str] = &["/usr/lib/"];
+    let allowed_prefixes = allowed_library_prefixes();
 
     // Resolve to canonical path to prevent path traversal
     let path_canonical = std::fs::canonicalize(path)
@@ -84,7 +613,7 @@ pub fn validate_library_path(path: &str) -> Result<(), ESignError> {
     let path_str = path_canonical.to_string_lossy();
 
     // Check if path starts with any allowed prefix
-    if !allowed_prefixes.iter().any(|p| path_str.starts_with(p)) {
+    if !is_allowed_library_location(&path_str, allowed_prefixes, cfg!(target_os = "windows")) {
         return Err(ESignError::Pkcs11(format!(
             "Library path '{}' not in allowed location. Allowed: {:?}",
             path_str, allowed_prefixes
@@ -0,0 +1,102 @@
+//! LibraryManager - PKCS#11 library preloading
+//!
+//! Loads detected PKCS#11 shared objects ahead of time so the first
+//! `init_token_manager` call does not pay the 200-500ms library load cost.
+
+use crate::error::ESignError;
+use cryptoki::context::Pkcs11;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::helpers::validate_library_path;
+use super::manager::{load_pkcs11_library, TokenManager};
+
+/// Repeated init of the same library within this window is treated as a duplicate
+pub const DUPLICATE_INIT_WINDOW: Duration = Duration::from_millis(500);
+
+/// Check whether `path` was initialized less than DUPLICATE_INIT_WINDOW before `now`
+pub fn detect_duplicate_library_path(
+    init_timestamps: &HashMap<String, Instant>,
+    path: &str,
+    now: Instant,
+) -> bool {
+    init_timestamps
+        .get(path)
+        .is_some_and(|last| now.saturating_duration_since(*last) < DUPLICATE_INIT_WINDOW)
+}
+
+/// Cache of loaded (but not yet initialized) PKCS#11 libraries keyed by path
+#[derive(Default)]
+pub struct LibraryManager {
+    loaded: Mutex<HashMap<String, Pkcs11>>,
+}
+
+impl LibraryManager {
+    /// Create empty library cache
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preload every auto-detected library
+    /// Returns one entry per detected path with its load result
+    pub fn warmup_all(&self) -> Vec<(String, Result<(), ESignError>)> {
+        let paths: Vec<String> = TokenManager::auto_detect()
+            .into_iter()
+            .map(|lib| lib.path)
+            .collect();
+        self.warmup_paths(&paths)
+    }
+
+    /// Preload the given libraries in parallel (one thread per library)
+    /// Successful loads are cached; results are returned in input order
+    pub fn warmup_paths(&self, paths: &[String]) -> Vec<(String, Result<(), ESignError>)> {
+        let loaded: Vec<(String, Result<Pkcs11, ESignError>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = paths
+                .iter()
+                .map(|path| scope.spawn(move || Self::load(path)))
+                .collect();
+
+            handles
+                .into_iter()
+                .zip(paths)
+                .map(|(handle, path)| {
+                    let result = handle.join().unwrap_or_else(|_| {
+                        Err(ESignError::Pkcs11(format!(
+                            "Loading library '{}' panicked",
+                            path
+                        )))
+                    });
+                    (path.clone(), result)
+                })
+                .collect()
+        });
+
+        let mut results = Vec::with_capacity(loaded.len());
+        let mut cache = self.loaded.lock().ok();
+        for (path, result) in loaded {
+            match result {
+                Ok(ctx) => {
+                    if let Some(ref mut cache) = cache {
+                        cache.insert(path.clone(), ctx);
+                    }
+                    results.push((path, Ok(())));
+                }
+                Err(e) => results.push((path, Err(e))),
+            }
+        }
+
+        results
+    }
+
+    /// Take the preloaded handle for a library path, if warmup loaded it
+    pub fn take(&self, path: &str) -> Option<Pkcs11> {
+        self.loaded.lock().ok()?.remove(path)
+    }
+
+    /// Validate and load a single library (no C_Initialize)
+    fn load(path: &str) -> Result<Pkcs11, ESignError> {
+        validate_library_path(path)?;
+        load_pkcs11_library(path)
+    }
+}
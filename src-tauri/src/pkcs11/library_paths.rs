@@ -2,16 +2,75 @@
 //!
 //! Defines platform-specific paths for VNPT, Viettel, FPT, and OpenSC libraries.
 
-/// All known library paths for auto-detection
-pub fn all_paths() -> Vec<(&'static str, &'static str)> {
-    vec![
-        ("VNPT-CA", vnpt::PATH),
-        ("Viettel-CA", viettel::PATH),
-        ("FPT-CA", fpt::PATH),
-        ("OpenSC (Generic PKCS#11)", opensc::PATH),
+/// Known candidate paths per CA, in preference order
+fn static_candidates() -> [(&'static str, &'static [&'static str]); 4] {
+    [
+        ("VNPT-CA", vnpt::candidate_paths()),
+        ("Viettel-CA", viettel::candidate_paths()),
+        ("FPT-CA", fpt::candidate_paths()),
+        ("OpenSC (Generic PKCS#11)", opensc::candidate_paths()),
     ]
 }
 
+/// All known library paths (every candidate of every CA)
+pub fn all_paths() -> Vec<(&'static str, &'static str)> {
+    static_candidates()
+        .into_iter()
+        .flat_map(|(name, paths)| paths.iter().map(move |path| (name, *path)))
+        .collect()
+}
+
+/// Candidate paths per CA for auto-detection, which uses the first one that exists
+/// Includes the OpenSC install folder from the Windows registry, if allowed
+pub fn all_candidates() -> Vec<(&'static str, Vec<String>)> {
+    static_candidates()
+        .into_iter()
+        .map(|(name, paths)| {
+            let mut paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+            if name.starts_with("OpenSC") {
+                paths.extend(opensc::registry_path().filter(|path| {
+                    !paths.contains(path)
+                        && super::helpers::is_allowed_library_location(
+                            path,
+                            super::helpers::allowed_library_prefixes(),
+                            cfg!(windows),
+                        )
+                }));
+            }
+            (name, paths)
+        })
+        .collect()
+}
+
+/// Library version with a known defect for one CA
+pub struct KnownIssue {
+    pub ca_name: &'static str,
+    /// First library version without the defect (major, minor)
+    pub fixed_in: (u8, u8),
+    /// Warning shown to the user (Vietnamese)
+    pub warning: &'static str,
+}
+
+/// Known problematic library versions, by CA
+/// - VNPT-CA < 2.0: CKM_SHA256_RSA_PKCS signatures fail verification
+pub const KNOWN_ISSUES: &[KnownIssue] = &[KnownIssue {
+    ca_name: "VNPT-CA",
+    fixed_in: (2, 0),
+    warning: "Phiên bản quá cũ, cần nâng cấp thư viện VNPT-CA lên 2.0 trở lên (lỗi ký SHA-256)",
+}];
+
+/// Known issue affecting a CA's library at the given "major.minor" version
+pub fn known_issue(ca_name: &str, library_version: &str) -> Option<&'static KnownIssue> {
+    let (major, minor) = library_version.split_once('.')?;
+    let version = (
+        major.trim().parse::<u8>().ok()?,
+        minor.trim().parse::<u8>().ok()?,
+    );
+    KNOWN_ISSUES
+        .iter()
+        .find(|issue| issue.ca_name == ca_name && version < issue.fixed_in)
+}
+
 /// VNPT-CA PKCS#11 library paths
 pub mod vnpt {
     #[cfg(target_os = "macos")]
@@ -20,6 +79,10 @@ pub mod vnpt {
     pub const PATH: &str = "C:\\vnpt-ca\\cryptoki.dll";
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
     pub const PATH: &str = "/usr/lib/vnpt-ca/libcryptoki.so";
+
+    pub fn candidate_paths() -> &'static [&'static str] {
+        &[PATH]
+    }
 }
 
 /// Viettel-CA PKCS#11 library paths
@@ -30,6 +93,10 @@ pub mod viettel {
     pub const PATH: &str = "C:\\Viettel-CA\\pkcs11.dll";
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
     pub const PATH: &str = "/usr/lib/viettel-ca/libpkcs11.so";
+
+    pub fn candidate_paths() -> &'static [&'static str] {
+        &[PATH]
+    }
 }
 
 /// FPT-CA PKCS#11 library paths
@@ -40,14 +107,62 @@ pub mod fpt {
     pub const PATH: &str = "C:\\FPT-CA\\pkcs11.dll";
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
     pub const PATH: &str = "/usr/lib/fpt-ca/libpkcs11.so";
+
+    pub fn candidate_paths() -> &'static [&'static str] {
+        &[PATH]
+    }
 }
 
 /// OpenSC PKCS#11 library paths (supports ePass2003, Feitian, and other generic tokens)
+/// Installed by many package managers, so several locations are checked
 pub mod opensc {
+    /// Homebrew (Intel), Homebrew (Apple Silicon), MacPorts, official installer
     #[cfg(target_os = "macos")]
-    pub const PATH: &str = "/usr/local/lib/opensc-pkcs11.so";
+    const CANDIDATES: &[&str] = &[
+        "/usr/local/lib/opensc-pkcs11.so",
+        "/opt/homebrew/lib/opensc-pkcs11.so",
+        "/opt/local/lib/opensc-pkcs11.so",
+        "/Library/OpenSC/lib/opensc-pkcs11.so",
+    ];
+    /// 64-bit and 32-bit installers
     #[cfg(target_os = "windows")]
-    pub const PATH: &str = "C:\\Program Files\\OpenSC Project\\OpenSC\\pkcs11\\opensc-pkcs11.dll";
+    const CANDIDATES: &[&str] = &[
+        "C:\\Program Files\\OpenSC Project\\OpenSC\\pkcs11\\opensc-pkcs11.dll",
+        "C:\\Program Files (x86)\\OpenSC Project\\OpenSC\\pkcs11\\opensc-pkcs11.dll",
+    ];
+    /// Debian/Ubuntu (amd64, arm64), Fedora/RHEL, Arch
     #[cfg(not(any(target_os = "macos", target_os = "windows")))]
-    pub const PATH: &str = "/usr/lib/x86_64-linux-gnu/opensc-pkcs11.so";
+    const CANDIDATES: &[&str] = &[
+        "/usr/lib/x86_64-linux-gnu/opensc-pkcs11.so",
+        "/usr/lib/aarch64-linux-gnu/opensc-pkcs11.so",
+        "/usr/lib64/opensc-pkcs11.so",
+        "/usr/lib/opensc-pkcs11.so",
+    ];
+
+    /// Known OpenSC locations, in preference order
+    pub fn candidate_paths() -> &'static [&'static str] {
+        CANDIDATES
+    }
+
+    /// Module path under the install folder recorded by the OpenSC installer
+    /// (HKLM\\SOFTWARE\\OpenSC Project\\OpenSC, value InstallDir)
+    #[cfg(target_os = "windows")]
+    pub fn registry_path() -> Option<String> {
+        use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+        let key = RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey("SOFTWARE\\OpenSC Project\\OpenSC")
+            .ok()?;
+        let install_dir: String = key.get_value("InstallDir").ok()?;
+        Some(format!(
+            "{}\\pkcs11\\opensc-pkcs11.dll",
+            install_dir.trim_end_matches('\\')
+        ))
+    }
+
+    /// Registry discovery is Windows-only
+    #[cfg(not(target_os = "windows"))]
+    pub fn registry_path() -> Option<String> {
+        None
+    }
 }
@@ -3,56 +3,75 @@
 //! Thread-safe wrapper around cryptoki session for USB token communication.
 
 use crate::error::{ESignError, SigningErrorCode};
+use crate::pdf::{build_sequence, encode_children};
 use cryptoki::{
     context::{CInitializeArgs, Pkcs11},
-    mechanism::Mechanism,
-    object::{Attribute, AttributeType, ObjectClass, ObjectHandle},
+    error::{Error as CryptokiError, RvError},
+    mechanism::{Mechanism, MechanismType},
+    object::{Attribute, AttributeType, CertificateType, ObjectClass},
     session::{Session, UserType},
     slot::Slot,
     types::AuthPin,
 };
 use sha2::{Digest, Sha256};
-use std::sync::Mutex;
-use x509_parser::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
 use zeroize::Zeroize;
 
-use super::helpers::{create_arch_mismatch_error, format_dn_utf8, validate_library_path};
+use super::helpers::{
+    certificate_info_from_der, create_arch_mismatch_error, ec_key_size, mechanism_flag_names,
+    mechanism_name, parse_certificate_extended, parse_certificate_policies, validate_library_path,
+    validate_random_length, validate_signing_certificate, CKF_DECRYPT, CKF_ENCRYPT, CKF_SIGN,
+    CKF_SIGN_RECOVER, CKF_VERIFY,
+};
 use super::library_paths;
-use super::types::{format_datetime, CertificateInfo, DetectedLibrary, TokenInfo};
+#[cfg(target_os = "windows")]
+use super::registry;
+use super::state::{KeyType, SigningKey, TokenOperation, TokenState};
+use super::types::{
+    format_version, retries_from_pin_flags, CertPolicyInfo, CertificateInfo,
+    CertificateInfoExtended, DetectedLibrary, KeyInfo, LibraryVersionInfo, MechanismInfo,
+    SignMechanism, SigningAlgorithm, TokenInfo, TokenManagerConfig, VendorInfo,
+    VENDOR_ATTRIBUTE_IDS,
+};
 
 /// Token manager - handles PKCS#11 operations
 /// Thread-safe wrapper around cryptoki session
 pub struct TokenManager {
     ctx: Pkcs11,
-    session: Mutex<Option<Session>>,
-    signing_key: Mutex<Option<ObjectHandle>>,
-    certificate_der: Mutex<Option<Vec<u8>>>,
-    /// Full certificate chain (end-entity + issuers)
-    certificate_chain: Mutex<Vec<Vec<u8>>>,
+    /// Slot selection and login session; see `TokenOperation::transition`
+    /// Certificate reads share the read lock; login/logout take the write lock
+    state: RwLock<TokenState>,
+    /// Serializes C_Sign on the shared session, which PKCS#11 does not make thread-safe
+    session_calls: Mutex<()>,
     library_path: String,
+    /// C_GetInfo result, read once per loaded library
+    library_info: OnceLock<LibraryVersionInfo>,
+    config: TokenManagerConfig,
 }
 
 impl TokenManager {
     /// Create new TokenManager with specified PKCS#11 library path
     /// Validates library path against allowed locations before loading
     pub fn new(library_path: &str) -> Result<Self, ESignError> {
+        Self::with_preloaded(library_path, None)
+    }
+
+    /// Create TokenManager, reusing a library handle preloaded by `LibraryManager`
+    /// Falls back to loading the library when no preloaded handle is given
+    pub fn with_preloaded(
+        library_path: &str,
+        preloaded: Option<Pkcs11>,
+    ) -> Result<Self, ESignError> {
         // Validate library path is in allowed location (security check)
         validate_library_path(library_path)?;
 
-        // Load PKCS#11 library
-        let ctx = Pkcs11::new(library_path).map_err(|e| {
-            let error_str = e.to_string();
-
-            // Detect architecture mismatch on macOS
-            if error_str.contains("incompatible architecture") {
-                return create_arch_mismatch_error(&error_str, library_path);
-            }
-
-            ESignError::Pkcs11(format!(
-                "Failed to load PKCS#11 library '{}': {}",
-                library_path, e
-            ))
-        })?;
+        // Load PKCS#11 library (unless already loaded during warmup)
+        let ctx = match preloaded {
+            Some(ctx) => ctx,
+            None => load_pkcs11_library(library_path)?,
+        };
 
         // Initialize the library
         ctx.initialize(CInitializeArgs::OsThreads)
@@ -60,32 +79,163 @@ impl TokenManager {
 
         Ok(Self {
             ctx,
-            session: Mutex::new(None),
-            signing_key: Mutex::new(None),
-            certificate_der: Mutex::new(None),
-            certificate_chain: Mutex::new(Vec::new()),
+            state: RwLock::new(TokenState::Uninitialized),
+            session_calls: Mutex::new(()),
             library_path: library_path.to_string(),
+            library_info: OnceLock::new(),
+            config: TokenManagerConfig::default(),
         })
     }
 
+    /// Replace the behaviour options (e.g. locking certificates after login)
+    pub fn with_config(mut self, config: TokenManagerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
     /// Auto-detect available PKCS#11 libraries
     /// Returns list of detected libraries with CA names
+    /// Each CA is reported at its first candidate path that exists; on Windows,
+    /// libraries registered under Cryptography\Callers are appended
     pub fn auto_detect() -> Vec<DetectedLibrary> {
-        library_paths::all_paths()
+        #[allow(unused_mut)] // Only extended on Windows
+        let mut detected: Vec<DetectedLibrary> = library_paths::all_candidates()
             .into_iter()
-            .filter(|(_, path)| std::path::Path::new(path).exists())
-            .map(|(name, path)| DetectedLibrary {
-                ca_name: name.to_string(),
-                path: path.to_string(),
+            .filter_map(|(name, paths)| {
+                detect_first_candidate(name, &paths, |path| std::path::Path::new(path).exists())
+            })
+            .collect();
+        #[cfg(target_os = "windows")]
+        registry::merge_detected(&mut detected, registry::detect_via_registry());
+        detected
+    }
+
+    /// Auto-detect libraries and read their versions (C_GetInfo)
+    /// `active`: version info of libraries already initialized in-process, by path;
+    /// these are not reloaded (dropping a second handle would finalize them)
+    pub fn auto_detect_with_versions(
+        active: &HashMap<String, LibraryVersionInfo>,
+    ) -> Vec<DetectedLibrary> {
+        Self::auto_detect()
+            .into_iter()
+            .map(|lib| {
+                let info = match active.get(&lib.path) {
+                    Some(info) => Some(info.clone()),
+                    None => read_library_info(&lib.path)
+                        .map_err(|e| eprintln!("[PKCS11] {}: {}", lib.path, e))
+                        .ok(),
+                };
+                match info {
+                    Some(ref info) => lib.with_version_info(info),
+                    None => lib,
+                }
             })
             .collect()
     }
 
+    /// Async variant of `auto_detect` - path checks run concurrently on the
+    /// blocking pool, then each found library gets a bounded load probe
+    /// `active_paths`: libraries already initialized in-process; they are reported as
+    /// loadable without probing (a probe would finalize them on drop)
+    pub async fn auto_detect_async(active_paths: Vec<String>) -> Vec<DetectedLibrary> {
+        #[allow(unused_mut)] // Only extended on Windows
+        let mut detected = detect_paths_concurrently(library_paths::all_candidates(), |path| {
+            std::path::Path::new(path).exists()
+        })
+        .await;
+        #[cfg(target_os = "windows")]
+        if let Ok(registered) = tokio::task::spawn_blocking(registry::detect_via_registry).await {
+            registry::merge_detected(&mut detected, registered);
+        }
+
+        let probes: Vec<_> = detected
+            .iter()
+            .map(|lib| {
+                let path = lib.path.clone();
+                let is_active = active_paths.contains(&path);
+                tokio::spawn(async move {
+                    if is_active {
+                        Some(true)
+                    } else {
+                        probe_library_load(path).await
+                    }
+                })
+            })
+            .collect();
+
+        let mut libraries = Vec::with_capacity(detected.len());
+        for (mut lib, probe) in detected.into_iter().zip(probes) {
+            lib.loadable = probe.await.unwrap_or(None);
+            libraries.push(lib);
+        }
+        libraries
+    }
+
+    /// New manager on the same initialized library, with its own slot/session state
+    /// Lets a second slot be used without a second C_Initialize
+    pub fn share_library(&self) -> Self {
+        Self {
+            ctx: self.ctx.clone(),
+            state: RwLock::new(TokenState::Uninitialized),
+            session_calls: Mutex::new(()),
+            library_path: self.library_path.clone(),
+            library_info: OnceLock::new(),
+            config: self.config,
+        }
+    }
+
     /// Get library path
     pub fn library_path(&self) -> &str {
         &self.library_path
     }
 
+    fn read_state(&self) -> Result<RwLockReadGuard<'_, TokenState>, ESignError> {
+        self.state
+            .read()
+            .map_err(|_| ESignError::Pkcs11("Token state lock poisoned".to_string()))
+    }
+
+    fn write_state(&self) -> Result<RwLockWriteGuard<'_, TokenState>, ESignError> {
+        self.state
+            .write()
+            .map_err(|_| ESignError::Pkcs11("Token state lock poisoned".to_string()))
+    }
+
+    /// Exclusive access to the session for calls that change its operation state
+    fn lock_session_calls(&self) -> Result<MutexGuard<'_, ()>, ESignError> {
+        self.session_calls
+            .lock()
+            .map_err(|_| ESignError::Pkcs11("Session call lock poisoned".to_string()))
+    }
+
+    /// Find a slot with a token present by ID
+    fn find_slot(&self, slot_id: u64) -> Result<Slot, ESignError> {
+        let slots = self
+            .ctx
+            .get_slots_with_token()
+            .map_err(|e| ESignError::Signing {
+                code: SigningErrorCode::TokenNotFound,
+                message: format!("Failed to get slots: {}", e),
+            })?;
+
+        slots
+            .into_iter()
+            .find(|s| s.id() == slot_id)
+            .ok_or_else(|| ESignError::Signing {
+                code: SigningErrorCode::TokenNotFound,
+                message: format!("Slot {} not found", slot_id),
+            })
+    }
+
+    /// Choose the slot to log in to (Uninitialized/SlotSelected -> SlotSelected)
+    pub fn select_slot(&self, slot_id: u64) -> Result<(), ESignError> {
+        let mut state = self.write_state()?;
+        TokenOperation::SelectSlot.transition(state.kind())?;
+        self.find_slot(slot_id)?;
+        *state = TokenState::SlotSelected { slot_id };
+        Ok(())
+    }
+
     /// List available token slots
     pub fn list_slots(&self) -> Result<Vec<TokenInfo>, ESignError> {
         let slots = self
@@ -142,22 +292,75 @@ impl TokenManager {
             model,
             serial,
             has_token: true,
+            retries_remaining: retries_from_pin_flags(
+                token_info.user_pin_locked(),
+                token_info.user_pin_final_try(),
+            ),
         })
     }
 
-    /// Login to token with PIN
-    /// Opens a session and authenticates with user PIN
-    /// PIN is securely zeroized after authentication attempt
-    pub fn login(&self, slot_id: u64, pin: &str) -> Result<(), ESignError> {
-        // Find the slot
+    /// PIN attempts left on a slot's token, None if not reported
+    pub fn get_pin_retry_count(&self, slot_id: u64) -> Result<Option<u64>, ESignError> {
+        let slot = self.find_slot(slot_id)?;
+        Ok(self.get_token_info(slot)?.retries_remaining)
+    }
+
+    /// Get PKCS#11 library information (C_GetInfo), cached after the first call
+    pub fn library_info(&self) -> Result<LibraryVersionInfo, ESignError> {
+        if let Some(info) = self.library_info.get() {
+            return Ok(info.clone());
+        }
+
+        let info = self
+            .ctx
+            .get_library_info()
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to get library info: {}", e)))?;
+        let info = version_info(&info);
+
+        Ok(self.library_info.get_or_init(|| info).clone())
+    }
+
+    /// Mechanisms supported by a slot's token, with key sizes and relevant flags
+    /// Mechanisms whose info cannot be read are skipped
+    pub fn get_slot_mechanisms(&self, slot_id: u64) -> Result<Vec<MechanismInfo>, ESignError> {
+        let slot = self.find_slot(slot_id)?;
+        let mechanisms = self
+            .ctx
+            .get_mechanism_list(slot)
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to read mechanism list: {}", e)))?;
+
+        Ok(mechanisms
+            .into_iter()
+            .filter_map(|mechanism| {
+                let info = self.ctx.get_mechanism_info(slot, mechanism).ok()?;
+                let flags = [
+                    (info.sign(), CKF_SIGN),
+                    (info.sign_recover(), CKF_SIGN_RECOVER),
+                    (info.verify(), CKF_VERIFY),
+                    (info.decrypt(), CKF_DECRYPT),
+                    (info.encrypt(), CKF_ENCRYPT),
+                ]
+                .into_iter()
+                .filter(|(set, _)| *set)
+                .fold(0, |flags, (_, bit)| flags | bit);
+                Some(MechanismInfo {
+                    name: mechanism_name(*mechanism as u64),
+                    key_size_min: info.min_key_size() as u64,
+                    key_size_max: info.max_key_size() as u64,
+                    flags: mechanism_flag_names(flags),
+                })
+            })
+            .collect())
+    }
+
+    /// Read vendor-specific attributes (firmware version etc.) for a slot
+    /// Probes CKA_VENDOR_DEFINED + 1..=5 on the first public certificate object;
+    /// attributes the token does not support are skipped
+    pub fn get_vendor_attributes(&self, slot_id: u64) -> Result<VendorInfo, ESignError> {
         let slots = self
             .ctx
             .get_slots_with_token()
-            .map_err(|e| ESignError::Signing {
-                code: SigningErrorCode::TokenNotFound,
-                message: format!("Failed to get slots: {}", e),
-            })?;
-
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to get slots: {}", e)))?;
         let slot = slots
             .into_iter()
             .find(|s| s.id() == slot_id)
@@ -166,6 +369,64 @@ impl TokenManager {
                 message: format!("Slot {} not found", slot_id),
             })?;
 
+        let manufacturer = self.get_token_info(slot)?.manufacturer;
+
+        let session = self
+            .ctx
+            .open_ro_session(slot)
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to open session: {}", e)))?;
+
+        let objects = session
+            .find_objects(&[Attribute::Class(ObjectClass::CERTIFICATE)])
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to find objects: {}", e)))?;
+
+        let mut raw = HashMap::new();
+        if let Some(object) = objects.first() {
+            for id in VENDOR_ATTRIBUTE_IDS {
+                let attr_type = AttributeType::VendorDefined(id as _);
+                match session.get_attributes(*object, &[attr_type]) {
+                    Ok(attrs) => {
+                        for attr in attrs {
+                            if let Attribute::VendorDefined((_, bytes)) = attr {
+                                raw.insert(id, bytes);
+                            }
+                        }
+                    }
+                    Err(CryptokiError::Pkcs11(RvError::AttributeTypeInvalid, _)) => continue,
+                    Err(e) => {
+                        return Err(ESignError::Pkcs11(format!(
+                            "Failed to read vendor attribute 0x{:08X}: {}",
+                            id, e
+                        )))
+                    }
+                }
+            }
+        }
+
+        Ok(VendorInfo::from_raw_attributes(&manufacturer, &raw))
+    }
+
+    /// Login to the selected slot with PIN (SlotSelected -> LoggedIn)
+    /// Opens a session and authenticates with user PIN
+    /// PIN is securely zeroized after authentication attempt
+    pub fn login(&self, pin: &str) -> Result<(), ESignError> {
+        self.login_with_certificate(pin, None)
+    }
+
+    /// Login, preferring the key and certificate with CKA_ID `cert_id`
+    /// Falls back to the first signing key if no key has that id (e.g. another token)
+    pub fn login_with_certificate(
+        &self,
+        pin: &str,
+        cert_id: Option<&[u8]>,
+    ) -> Result<(), ESignError> {
+        let mut state = self.write_state()?;
+        let slot_id = match *state {
+            TokenState::SlotSelected { slot_id } => slot_id,
+            ref other => return Err(TokenOperation::Login.invalid_in(other.kind())),
+        };
+        let slot = self.find_slot(slot_id)?;
+
         // Open a read-write session
         let session = self
             .ctx
@@ -187,10 +448,14 @@ impl TokenManager {
         })?;
 
         // Find signing private key
-        let key_handle = self.find_signing_key(&session)?;
+        let (key, key_id) = self.find_signing_key(&session, cert_id)?;
 
-        // Find certificate chain (end-entity + issuers)
-        let (cert_der, cert_chain) = self.find_certificate_chain(&session)?;
+        // Find certificate chain (end-entity + issuers) for that key
+        let (cert_der, cert_chain) = self.find_certificate_chain(&session, &key_id)?;
+        validate_signing_certificate(&cert_der)?;
+        if self.config.lock_after_login {
+            lock_certificate_handles(&session, &cert_chain)?;
+        }
 
         // Log chain info
         if cert_chain.len() > 1 {
@@ -202,69 +467,290 @@ impl TokenManager {
             eprintln!("Found single certificate (no issuer chain on token)");
         }
 
-        // Store session, key handle, certificate, and chain
-        {
-            let mut session_guard = self
-                .session
-                .lock()
-                .map_err(|_| ESignError::Pkcs11("Session mutex poisoned".to_string()))?;
-            *session_guard = Some(session);
-        }
-        {
-            let mut key_guard = self
-                .signing_key
-                .lock()
-                .map_err(|_| ESignError::Pkcs11("Signing key mutex poisoned".to_string()))?;
-            *key_guard = Some(key_handle);
-        }
-        {
-            let mut cert_guard = self
-                .certificate_der
-                .lock()
-                .map_err(|_| ESignError::Pkcs11("Certificate mutex poisoned".to_string()))?;
-            *cert_guard = Some(cert_der);
+        *state = TokenState::LoggedIn {
+            slot_id,
+            cert_der,
+            cert_chain,
+            key,
+            key_id,
+            session,
+        };
+
+        Ok(())
+    }
+
+    /// Switch the logged-in session to the key and certificate with CKA_ID `cert_id`
+    pub fn select_certificate(&self, cert_id: &[u8]) -> Result<(), ESignError> {
+        let mut state = self.write_state()?;
+        let kind = state.kind();
+        let TokenState::LoggedIn {
+            session,
+            key,
+            key_id,
+            cert_der,
+            cert_chain,
+            ..
+        } = &mut *state
+        else {
+            return Err(TokenOperation::ReadCertificate.invalid_in(kind));
+        };
+
+        let (new_key, new_key_id) = self.find_signing_key(session, Some(cert_id))?;
+        if new_key_id != cert_id {
+            return Err(ESignError::Signing {
+                code: SigningErrorCode::PrivateKeyNotFound,
+                message: "No signing private key matches the selected certificate".to_string(),
+            });
         }
-        {
-            let mut chain_guard = self
-                .certificate_chain
-                .lock()
-                .map_err(|_| ESignError::Pkcs11("Certificate chain mutex poisoned".to_string()))?;
-            *chain_guard = cert_chain;
+        let (new_cert_der, new_chain) = self.find_certificate_chain(session, &new_key_id)?;
+        validate_signing_certificate(&new_cert_der)?;
+        if self.config.lock_after_login {
+            lock_certificate_handles(session, &new_chain)?;
         }
 
+        *key = new_key;
+        *key_id = new_key_id;
+        *cert_der = new_cert_der;
+        *cert_chain = new_chain;
         Ok(())
     }
 
-    /// Find private key with signing capability
-    fn find_signing_key(&self, session: &Session) -> Result<ObjectHandle, ESignError> {
+    /// All X.509 certificates on the logged-in token (signing, encryption and CA)
+    /// `cert_id` identifies each one for `select_certificate`
+    pub fn list_certificates(&self) -> Result<Vec<CertificateInfo>, ESignError> {
+        let state = self.read_state()?;
+        let session = state
+            .session()
+            .ok_or_else(|| TokenOperation::ReadCertificate.invalid_in(state.kind()))?;
+
+        Ok(self
+            .read_certificates(session)?
+            .into_iter()
+            .filter_map(|(id, der)| match certificate_info_from_der(&der) {
+                Ok(mut info) => {
+                    info.cert_id = (!id.is_empty()).then(|| hex::encode(&id));
+                    Some(info)
+                }
+                Err(e) => {
+                    eprintln!("Skipping unparsable certificate on token: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// All private keys on the logged-in token with their type and size
+    /// Tokens may hold several key pairs (e.g. RSA and EC, or signing and encryption)
+    pub fn list_signing_keys(&self) -> Result<Vec<KeyInfo>, ESignError> {
+        let state = self.read_state()?;
+        let session = state
+            .session()
+            .ok_or_else(|| TokenOperation::ListKeys.invalid_in(state.kind()))?;
+
+        // Held for the whole search: FindObjects state lives on the session
+        let _session_call = self.lock_session_calls()?;
+        let objects = session
+            .find_objects(&[Attribute::Class(ObjectClass::PRIVATE_KEY)])
+            .map_err(|e| ESignError::Signing {
+                code: SigningErrorCode::PrivateKeyNotFound,
+                message: format!("Failed to search for private keys: {}", e),
+            })?;
+
+        let mut keys = Vec::new();
+        for handle in objects {
+            // Attributes a key does not have (e.g. modulus bits on EC) are left out
+            let attributes = session
+                .get_attributes(
+                    handle,
+                    &[
+                        AttributeType::Id,
+                        AttributeType::Label,
+                        AttributeType::KeyType,
+                        AttributeType::Sign,
+                        AttributeType::ModulusBits,
+                        AttributeType::EcParams,
+                    ],
+                )
+                .map_err(|e| ESignError::Pkcs11(format!("Failed to read private key: {}", e)))?;
+
+            let (mut id, mut label, mut key_type, mut sign, mut key_size) =
+                (Vec::new(), String::new(), None, false, 0);
+            for attr in attributes {
+                match attr {
+                    Attribute::Id(value) => id = value,
+                    Attribute::Label(value) => label = String::from_utf8_lossy(&value).to_string(),
+                    Attribute::KeyType(t) => key_type = KeyType::from_pkcs11(t),
+                    Attribute::Sign(value) => sign = value,
+                    Attribute::ModulusBits(bits) => key_size = *bits as u32,
+                    Attribute::EcParams(params) => key_size = ec_key_size(&params),
+                    _ => {}
+                }
+            }
+            keys.push(KeyInfo {
+                id,
+                label,
+                key_type: match key_type {
+                    Some(KeyType::Rsa) => "RSA",
+                    Some(KeyType::Ec) => "EC",
+                    None => "Unsupported",
+                }
+                .to_string(),
+                key_size,
+                can_sign: sign && key_type.is_some(),
+            });
+        }
+        Ok(keys)
+    }
+
+    /// Sign with the private key whose CKA_ID is `key_id` (and the certificate sharing it)
+    pub fn select_signing_key(&self, key_id: &[u8]) -> Result<(), ESignError> {
+        self.select_certificate(key_id)
+    }
+
+    /// Change the user PIN on the selected slot (C_SetPIN)
+    /// Reuses the logged-in session so signing continues without a new login;
+    /// otherwise a read-write session is opened just for the change
+    pub fn change_pin(&self, old_pin: &str, new_pin: &str) -> Result<(), ESignError> {
+        let state = self.read_state()?;
+        let rw_session;
+        let session = match &*state {
+            TokenState::LoggedIn { session, .. } => session,
+            TokenState::SlotSelected { slot_id } => {
+                let slot = self.find_slot(*slot_id)?;
+                rw_session = self
+                    .ctx
+                    .open_rw_session(slot)
+                    .map_err(|e| ESignError::Pkcs11(format!("Failed to open session: {}", e)))?;
+                &rw_session
+            }
+            other => return Err(TokenOperation::ChangePin.invalid_in(other.kind())),
+        };
+
+        // Mutable copies for zeroization, as in login
+        let mut old_copy = old_pin.to_string();
+        let mut new_copy = new_pin.to_string();
+        let old_auth_pin = AuthPin::new(old_copy.clone());
+        let new_auth_pin = AuthPin::new(new_copy.clone());
+        old_copy.zeroize();
+        new_copy.zeroize();
+
+        session
+            .set_pin(&old_auth_pin, &new_auth_pin)
+            .map_err(|e| match e {
+                CryptokiError::Pkcs11(RvError::PinIncorrect, _) => ESignError::Signing {
+                    code: SigningErrorCode::SigningFailed,
+                    message: "Sai PIN cũ. Vui lòng kiểm tra lại mã PIN hiện tại.".to_string(),
+                },
+                CryptokiError::Pkcs11(RvError::PinInvalid | RvError::PinLenRange, _) => {
+                    ESignError::Signing {
+                        code: SigningErrorCode::InvalidInput,
+                        message: format!("New PIN rejected by token: {}", e),
+                    }
+                }
+                _ => ESignError::Pkcs11(format!("Failed to change PIN: {}", e)),
+            })
+    }
+
+    /// Slot chosen via select_slot (or logged in to), if any
+    pub fn selected_slot(&self) -> Option<u64> {
+        self.state.read().ok().and_then(|state| state.slot_id())
+    }
+
+    /// Find private key with signing capability and a supported key type (RSA or EC)
+    /// Returns the key and its CKA_ID; keys with CKA_ID `preferred_id` are tried first
+    fn find_signing_key(
+        &self,
+        session: &Session,
+        preferred_id: Option<&[u8]>,
+    ) -> Result<(SigningKey, Vec<u8>), ESignError> {
         let template = vec![
             Attribute::Class(ObjectClass::PRIVATE_KEY),
             Attribute::Sign(true),
         ];
+        let search = |template: &[Attribute]| {
+            session
+                .find_objects(template)
+                .map_err(|e| ESignError::Signing {
+                    code: SigningErrorCode::PrivateKeyNotFound,
+                    message: format!("Failed to search for private key: {}", e),
+                })
+        };
 
-        let objects = session
-            .find_objects(&template)
-            .map_err(|e| ESignError::Signing {
+        let mut objects = match preferred_id {
+            Some(id) => search(&[template.clone(), vec![Attribute::Id(id.to_vec())]].concat())?,
+            None => Vec::new(),
+        };
+        if objects.is_empty() {
+            objects = search(&template)?;
+        }
+
+        if objects.is_empty() {
+            return Err(ESignError::Signing {
                 code: SigningErrorCode::PrivateKeyNotFound,
-                message: format!("Failed to search for private key: {}", e),
-            })?;
+                message: "No signing private key found on token".to_string(),
+            });
+        }
 
         objects
             .into_iter()
-            .next()
+            .find_map(|handle| {
+                let attributes = session
+                    .get_attributes(handle, &[AttributeType::KeyType, AttributeType::Id])
+                    .ok()?;
+                let mut key_type = None;
+                let mut id = Vec::new();
+                for attr in attributes {
+                    match attr {
+                        Attribute::KeyType(t) => key_type = KeyType::from_pkcs11(t),
+                        Attribute::Id(value) => id = value,
+                        _ => {}
+                    }
+                }
+                Some((
+                    SigningKey {
+                        handle,
+                        key_type: key_type?,
+                    },
+                    id,
+                ))
+            })
             .ok_or_else(|| ESignError::Signing {
                 code: SigningErrorCode::PrivateKeyNotFound,
-                message: "No signing private key found on token".to_string(),
+                message: "Signing key type not supported (RSA or EC required)".to_string(),
             })
     }
 
-    /// Find all certificates on token and build certificate chain
+    /// Find the certificate for the signing key `key_id` and build its chain
     /// Returns (end_entity_cert, full_chain) where chain is ordered [end_entity, issuer1, issuer2, ...]
     fn find_certificate_chain(
         &self,
         session: &Session,
+        key_id: &[u8],
     ) -> Result<(Vec<u8>, Vec<Vec<u8>>), ESignError> {
-        let template = vec![Attribute::Class(ObjectClass::CERTIFICATE)];
+        let certs = self.read_certificates(session)?;
+
+        // Tokens often hold a signing and an encryption certificate; only the one
+        // sharing the key's CKA_ID can verify our signatures
+        let candidates = certificates_for_key(&certs, key_id);
+
+        // Select the best end-entity certificate:
+        // 1. Prefer currently valid certificate (not expired)
+        // 2. If all expired, pick the one with latest not_after date
+        let end_entity = self.select_best_certificate(&candidates);
+
+        // Build chain by matching subject/issuer
+        let all_certs: Vec<Vec<u8>> = certs.into_iter().map(|(_, der)| der).collect();
+        let chain = self.build_certificate_chain(&end_entity, &all_certs);
+
+        Ok((end_entity, chain))
+    }
+
+    /// Read (CKA_ID, DER value) of every X.509 certificate on the token
+    fn read_certificates(&self, session: &Session) -> Result<Vec<(Vec<u8>, Vec<u8>)>, ESignError> {
+        let template = vec![
+            Attribute::Class(ObjectClass::CERTIFICATE),
+            Attribute::CertificateType(CertificateType::X_509),
+        ];
 
         let objects = session
             .find_objects(&template)
@@ -280,22 +766,28 @@ impl TokenManager {
             });
         }
 
-        // Extract all certificate DER values
-        let mut all_certs: Vec<Vec<u8>> = Vec::new();
+        // Extract all certificate DER values with their ids
+        let mut all_certs: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
         for cert_handle in objects {
             let attrs = session
-                .get_attributes(cert_handle, &[AttributeType::Value])
+                .get_attributes(cert_handle, &[AttributeType::Id, AttributeType::Value])
                 .map_err(|e| ESignError::Signing {
                     code: SigningErrorCode::CertificateNotFound,
                     message: format!("Failed to read certificate: {}", e),
                 })?;
 
+            let mut id = Vec::new();
+            let mut value = None;
             for attr in attrs {
-                if let Attribute::Value(der) = attr {
-                    all_certs.push(der);
-                    break;
+                match attr {
+                    Attribute::Id(bytes) => id = bytes,
+                    Attribute::Value(der) => value = Some(der),
+                    _ => {}
                 }
             }
+            if let Some(der) = value {
+                all_certs.push((id, der));
+            }
         }
 
         if all_certs.is_empty() {
@@ -305,15 +797,7 @@ impl TokenManager {
             });
         }
 
-        // Select the best end-entity certificate:
-        // 1. Prefer currently valid certificate (not expired)
-        // 2. If all expired, pick the one with latest not_after date
-        let end_entity = self.select_best_certificate(&all_certs);
-
-        // Build chain by matching subject/issuer
-        let chain = self.build_certificate_chain(&end_entity, &all_certs);
-
-        Ok((end_entity, chain))
+        Ok(all_certs)
     }
 
     /// Select the best certificate from a list
@@ -409,174 +893,462 @@ impl TokenManager {
 
     /// Get certificate information from logged-in token
     pub fn get_certificate_info(&self) -> Result<CertificateInfo, ESignError> {
-        let cert_der = {
-            let guard = self
-                .certificate_der
-                .lock()
-                .map_err(|_| ESignError::Pkcs11("Certificate mutex poisoned".to_string()))?;
-            guard.clone().ok_or_else(|| ESignError::Signing {
-                code: SigningErrorCode::CertificateNotFound,
-                message: "Not logged in or no certificate available".to_string(),
-            })?
+        let (cert_der, key_id) = match &*self.read_state()? {
+            TokenState::LoggedIn {
+                cert_der, key_id, ..
+            } => (cert_der.clone(), key_id.clone()),
+            other => return Err(TokenOperation::ReadCertificate.invalid_in(other.kind())),
         };
 
-        // Parse certificate with x509-parser
-        let (_, cert) = X509Certificate::from_der(&cert_der).map_err(|e| ESignError::Signing {
-            code: SigningErrorCode::CertificateNotFound,
-            message: format!("Failed to parse certificate: {}", e),
-        })?;
-
-        // Extract certificate fields
-        let serial = cert.serial.to_string();
-        let subject = format_dn_utf8(cert.subject());
-        let issuer = format_dn_utf8(cert.issuer());
-
-        // Format dates as Vietnamese standard
-        let valid_from = format_datetime(cert.validity().not_before.timestamp());
-        let valid_to = format_datetime(cert.validity().not_after.timestamp());
-
-        // Calculate SHA-256 thumbprint
-        let mut hasher = Sha256::new();
-        hasher.update(&cert_der);
-        let thumbprint = hex::encode(hasher.finalize());
+        let mut info = certificate_info_from_der(&cert_der)?;
+        info.cert_id = (!key_id.is_empty()).then(|| hex::encode(&key_id));
+        Ok(info)
+    }
 
-        // Base64 encode the DER certificate
-        use base64::{engine::general_purpose::STANDARD, Engine as _};
-        let der_base64 = STANDARD.encode(&cert_der);
+    /// Get certificate information with key algorithm, SANs and key usages
+    pub fn get_certificate_info_extended(&self) -> Result<CertificateInfoExtended, ESignError> {
+        let info = self.get_certificate_info()?;
+        let cert_der = self.get_certificate_der()?;
+        parse_certificate_extended(info, &cert_der)
+    }
 
-        Ok(CertificateInfo {
-            serial,
-            subject,
-            issuer,
-            valid_from,
-            valid_to,
-            thumbprint,
-            der_base64,
-        })
+    /// Get certificate policies (OID, known Vietnamese CA name, CPS URI)
+    pub fn get_certificate_policies(&self) -> Result<Vec<CertPolicyInfo>, ESignError> {
+        let cert_der = self.get_certificate_der()?;
+        parse_certificate_policies(&cert_der)
     }
 
     /// Get raw DER-encoded certificate bytes
     pub fn get_certificate_der(&self) -> Result<Vec<u8>, ESignError> {
-        let guard = self
-            .certificate_der
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Certificate mutex poisoned".to_string()))?;
-        guard.clone().ok_or_else(|| ESignError::Signing {
-            code: SigningErrorCode::CertificateNotFound,
-            message: "Not logged in or no certificate available".to_string(),
-        })
+        match &*self.read_state()? {
+            TokenState::LoggedIn { cert_der, .. } => Ok(cert_der.clone()),
+            other => Err(TokenOperation::ReadCertificate.invalid_in(other.kind())),
+        }
     }
 
     /// Get full certificate chain (end-entity + issuers)
     /// Returns Vec of DER-encoded certificates ordered [end_entity, issuer1, issuer2, ...]
     /// May return single certificate if no issuer chain found on token
-    #[allow(dead_code)] // Ready for PAdES-LT/LTA integration
     pub fn get_certificate_chain(&self) -> Result<Vec<Vec<u8>>, ESignError> {
-        let guard = self
-            .certificate_chain
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Certificate chain mutex poisoned".to_string()))?;
-        if guard.is_empty() {
-            return Err(ESignError::Signing {
-                code: SigningErrorCode::CertificateNotFound,
-                message: "Not logged in or no certificate chain available".to_string(),
-            });
+        match &*self.read_state()? {
+            TokenState::LoggedIn { cert_chain, .. } => Ok(cert_chain.clone()),
+            other => Err(TokenOperation::ReadCertificate.invalid_in(other.kind())),
         }
-        Ok(guard.clone())
     }
 
-    /// Sign data using RSA-PKCS#1 v1.5 with SHA-256
+    /// Sign data with SHA-256: RSA-PKCS#1 v1.5, or DER-encoded ECDSA for EC keys
     pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, ESignError> {
-        let session_guard = self
-            .session
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Session mutex poisoned".to_string()))?;
-        let session = session_guard.as_ref().ok_or_else(|| ESignError::Signing {
-            code: SigningErrorCode::TokenNotFound,
-            message: "Not logged in".to_string(),
-        })?;
+        match self.signing_key()?.key_type {
+            KeyType::Rsa => self.sign_with_algorithm(data, SigningAlgorithm::Sha256WithRsa),
+            KeyType::Ec => self.sign_ecdsa(data),
+        }
+    }
 
-        let key_guard = self
-            .signing_key
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Signing key mutex poisoned".to_string()))?;
-        let key = key_guard.ok_or_else(|| ESignError::Signing {
-            code: SigningErrorCode::PrivateKeyNotFound,
-            message: "No signing key available".to_string(),
-        })?;
+    /// Random bytes from the token's hardware generator (C_GenerateRandom)
+    /// `len` must be 1 to MAX_RANDOM_BYTES
+    pub fn get_random_bytes(&self, len: usize) -> Result<Vec<u8>, ESignError> {
+        validate_random_length(len)?;
+
+        let state = self.read_state()?;
+        let TokenState::LoggedIn { session, .. } = &*state else {
+            return Err(TokenOperation::GenerateRandom.invalid_in(state.kind()));
+        };
 
-        // Use Sha256RsaPkcs - mechanism handles hashing internally
-        let mechanism = Mechanism::Sha256RsaPkcs;
+        let _session_call = self.lock_session_calls()?;
+        session
+            .generate_random_vec(len as u32)
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to generate random bytes: {}", e)))
+    }
 
-        let signature = session
-            .sign(&mechanism, key, data)
-            .map_err(|e| ESignError::Signing {
-                code: SigningErrorCode::SigningFailed,
-                message: format!("Signing operation failed: {}", e),
-            })?;
+    /// Mark the logged-in certificate chain non-modifiable (CKA_MODIFIABLE=false)
+    /// so it cannot be replaced while the session is open; already locked is fine
+    pub fn lock_certificate_objects(&self) -> Result<(), ESignError> {
+        let state = self.read_state()?;
+        let TokenState::LoggedIn {
+            session,
+            cert_chain,
+            ..
+        } = &*state
+        else {
+            return Err(TokenOperation::LockObjects.invalid_in(state.kind()));
+        };
 
-        Ok(signature)
+        let _session_call = self.lock_session_calls()?;
+        lock_certificate_handles(session, cert_chain)
     }
 
-    /// Sign pre-hashed data (digest) using RSA-PKCS#1 v1.5
-    #[allow(dead_code)]
-    pub fn sign_digest(&self, digest: &[u8]) -> Result<Vec<u8>, ESignError> {
-        let session_guard = self
-            .session
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Session mutex poisoned".to_string()))?;
-        let session = session_guard.as_ref().ok_or_else(|| ESignError::Signing {
-            code: SigningErrorCode::TokenNotFound,
-            message: "Not logged in".to_string(),
-        })?;
+    /// Private key located at login
+    fn signing_key(&self) -> Result<SigningKey, ESignError> {
+        match &*self.read_state()? {
+            TokenState::LoggedIn { key, .. } => Ok(*key),
+            other => Err(TokenOperation::Sign.invalid_in(other.kind())),
+        }
+    }
 
-        let key_guard = self
-            .signing_key
-            .lock()
-            .map_err(|_| ESignError::Pkcs11("Signing key mutex poisoned".to_string()))?;
-        let key = key_guard.ok_or_else(|| ESignError::Signing {
-            code: SigningErrorCode::PrivateKeyNotFound,
-            message: "No signing key available".to_string(),
-        })?;
+    /// Sign data with ECDSA/SHA-256 (see `ecdsa_mechanism`)
+    fn sign_ecdsa(&self, data: &[u8]) -> Result<Vec<u8>, ESignError> {
+        let state = self.read_state()?;
+        let TokenState::LoggedIn {
+            slot_id,
+            session,
+            key,
+            ..
+        } = &*state
+        else {
+            return Err(TokenOperation::Sign.invalid_in(state.kind()));
+        };
+
+        let supported = self
+            .ctx
+            .get_mechanism_list(self.find_slot(*slot_id)?)
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to read mechanism list: {}", e)))?;
+        let (mechanism, input) = ecdsa_mechanism(&supported, data);
+
+        let _session_call = self.lock_session_calls()?;
+        let raw_signature =
+            session
+                .sign(&mechanism, key.handle, &input)
+                .map_err(|e| ESignError::Signing {
+                    code: SigningErrorCode::SigningFailed,
+                    message: format!("Signing operation failed: {}", e),
+                })?;
+
+        ecdsa_signature_to_der(&raw_signature)
+    }
+
+    /// Sign data with the given RSA algorithm - mechanism handles hashing internally
+    pub fn sign_with_algorithm(
+        &self,
+        data: &[u8],
+        algorithm: SigningAlgorithm,
+    ) -> Result<Vec<u8>, ESignError> {
+        let state = self.read_state()?;
+        let TokenState::LoggedIn { session, key, .. } = &*state else {
+            return Err(TokenOperation::Sign.invalid_in(state.kind()));
+        };
+        if key.key_type != KeyType::Rsa {
+            return Err(ESignError::Signing {
+                code: SigningErrorCode::InvalidInput,
+                message: format!("{:?} requires an RSA key; the token key is EC", algorithm),
+            });
+        }
+
+        if algorithm.is_deprecated() {
+            eprintln!(
+                "Warning: {:?} is deprecated; use SHA256withRSA or stronger",
+                algorithm
+            );
+        }
+        let mechanism = algorithm.mechanism();
 
-        // Use RSA-PKCS for signing pre-computed digest
-        let mechanism = Mechanism::RsaPkcs;
+        let _session_call = self.lock_session_calls()?;
+        let signature =
+            session
+                .sign(&mechanism, key.handle, data)
+                .map_err(|e| ESignError::Signing {
+                    code: SigningErrorCode::SigningFailed,
+                    message: format!("Signing operation failed: {}", e),
+                })?;
 
+        Ok(signature)
+    }
+
+    /// Sign data with a mechanism chosen by the caller (external integrations)
+    /// `RsaPkcs` signs `data` unchanged, so it must already be a DER DigestInfo;
+    /// ECDSA signatures are returned DER-encoded like `sign`
+    pub fn sign_with_mechanism(
+        &self,
+        data: &[u8],
+        mechanism: SignMechanism,
+    ) -> Result<Vec<u8>, ESignError> {
+        let state = self.read_state()?;
+        let TokenState::LoggedIn { session, key, .. } = &*state else {
+            return Err(TokenOperation::Sign.invalid_in(state.kind()));
+        };
+        if key.key_type != mechanism.key_type() {
+            return Err(ESignError::Signing {
+                code: SigningErrorCode::InvalidInput,
+                message: format!(
+                    "{:?} requires an {:?} key; the token key is {:?}",
+                    mechanism,
+                    mechanism.key_type(),
+                    key.key_type
+                ),
+            });
+        }
+
+        let _session_call = self.lock_session_calls()?;
         let signature = session
-            .sign(&mechanism, key, digest)
+            .sign(&mechanism.mechanism(), key.handle, data)
             .map_err(|e| ESignError::Signing {
                 code: SigningErrorCode::SigningFailed,
-                message: format!("Signing digest failed: {}", e),
+                message: format!("Signing operation failed: {}", e),
             })?;
 
-        Ok(signature)
+        match key.key_type {
+            KeyType::Rsa => Ok(signature),
+            KeyType::Ec => ecdsa_signature_to_der(&signature),
+        }
     }
 
-    /// Logout and close session
+    /// Logout and close session (LoggedIn -> SlotSelected)
     pub fn logout(&self) {
-        // Clear stored handles - ignore poison errors during cleanup
-        if let Ok(mut key_guard) = self.signing_key.lock() {
-            *key_guard = None;
+        // Ignore poison errors during cleanup
+        let Ok(mut state) = self.state.write() else {
+            return;
+        };
+        let TokenState::LoggedIn { slot_id, .. } = *state else {
+            return;
+        };
+        if let TokenState::LoggedIn { session, .. } =
+            std::mem::replace(&mut *state, TokenState::SlotSelected { slot_id })
+        {
+            let _ = session.logout();
         }
-        if let Ok(mut cert_guard) = self.certificate_der.lock() {
-            *cert_guard = None;
+    }
+
+    /// Slot of the logged-in session, if any
+    pub fn logged_in_slot(&self) -> Option<u64> {
+        match &*self.state.read().ok()? {
+            TokenState::LoggedIn { slot_id, .. } => Some(*slot_id),
+            _ => None,
         }
-        if let Ok(mut chain_guard) = self.certificate_chain.lock() {
-            chain_guard.clear();
+    }
+
+    /// Check if currently logged in
+    pub fn is_logged_in(&self) -> bool {
+        self.state
+            .read()
+            .map(|state| state.session().is_some())
+            .unwrap_or(false)
+    }
+
+    /// Check the session still responds (C_GetSessionInfo)
+    /// False if not logged in or the token was removed/re-inserted since login
+    pub fn is_session_alive(&self) -> bool {
+        self.state
+            .read()
+            .map(|state| state.session().is_some_and(|s| s.probe().is_ok()))
+            .unwrap_or(false)
+    }
+
+    /// Fail with a re-login hint when the session is no longer alive
+    pub fn ensure_session_alive(&self) -> Result<(), ESignError> {
+        ensure_session_alive(self.read_state()?.session())
+    }
+}
+
+/// Set CKA_MODIFIABLE=false on the certificate objects whose value is in `chain`
+/// CKR_ATTRIBUTE_READ_ONLY means the object is already locked
+fn lock_certificate_handles(session: &Session, chain: &[Vec<u8>]) -> Result<(), ESignError> {
+    let objects = session
+        .find_objects(&[Attribute::Class(ObjectClass::CERTIFICATE)])
+        .map_err(|e| ESignError::Pkcs11(format!("Failed to find certificates: {}", e)))?;
+
+    for handle in objects {
+        let in_chain = session
+            .get_attributes(handle, &[AttributeType::Value])
+            .map_err(|e| ESignError::Pkcs11(format!("Failed to read certificate: {}", e)))?
+            .into_iter()
+            .any(|attr| matches!(attr, Attribute::Value(der) if chain.contains(&der)));
+        if !in_chain {
+            continue;
         }
-        if let Ok(mut session_guard) = self.session.lock() {
-            if let Some(session) = session_guard.take() {
-                let _ = session.logout();
+        match session.update_attributes(handle, &[Attribute::Modifiable(false)]) {
+            Ok(()) | Err(CryptokiError::Pkcs11(RvError::AttributeReadOnly, _)) => {}
+            Err(e) => {
+                return Err(ESignError::Pkcs11(format!(
+                    "Failed to lock certificate object: {}",
+                    e
+                )))
             }
         }
     }
+    Ok(())
+}
 
-    /// Check if currently logged in
-    pub fn is_logged_in(&self) -> bool {
-        self.session.lock().map(|g| g.is_some()).unwrap_or(false)
+/// DER values of the (CKA_ID, DER) certificates sharing the key's CKA_ID
+/// All certificates when the key has no id or none matches (tokens without CKA_ID links)
+pub(crate) fn certificates_for_key(certs: &[(Vec<u8>, Vec<u8>)], key_id: &[u8]) -> Vec<Vec<u8>> {
+    let matching: Vec<Vec<u8>> = certs
+        .iter()
+        .filter(|(id, _)| !key_id.is_empty() && id.as_slice() == key_id)
+        .map(|(_, der)| der.clone())
+        .collect();
+    if matching.is_empty() {
+        certs.iter().map(|(_, der)| der.clone()).collect()
+    } else {
+        matching
     }
 }
 
+/// ECDSA mechanism and its input for the library's mechanism list
+/// CKM_ECDSA_SHA256 hashes on the token; plain CKM_ECDSA needs the SHA-256 digest
+pub(crate) fn ecdsa_mechanism(
+    supported: &[MechanismType],
+    data: &[u8],
+) -> (Mechanism<'static>, Vec<u8>) {
+    if supported.contains(&MechanismType::ECDSA_SHA256) {
+        (Mechanism::EcdsaSha256, data.to_vec())
+    } else {
+        (Mechanism::Ecdsa, Sha256::digest(data).to_vec())
+    }
+}
+
+/// Convert PKCS#11 ECDSA output (r || s, fixed width) to DER ECDSA-Sig-Value for CMS
+pub(crate) fn ecdsa_signature_to_der(raw: &[u8]) -> Result<Vec<u8>, ESignError> {
+    if raw.is_empty() || raw.len() % 2 != 0 {
+        return Err(ESignError::Signing {
+            code: SigningErrorCode::SigningFailed,
+            message: format!("Invalid ECDSA signature length: {} bytes", raw.len()),
+        });
+    }
+
+    // Minimal positive INTEGER: strip leading zeros, re-add one if the high bit is set
+    let integer = |bytes: &[u8]| {
+        let start = bytes
+            .iter()
+            .position(|&b| b != 0)
+            .unwrap_or(bytes.len() - 1);
+        let mut value = Vec::with_capacity(bytes.len() - start + 1);
+        if bytes[start] & 0x80 != 0 {
+            value.push(0x00);
+        }
+        value.extend_from_slice(&bytes[start..]);
+        (0x02, value)
+    };
+
+    let (r, s) = raw.split_at(raw.len() / 2);
+    build_sequence(&encode_children(&[integer(r), integer(s)])?)
+}
+
+/// Session liveness probe, abstracted so expiry can be tested without a token
+pub(crate) trait SessionProbe {
+    fn probe(&self) -> Result<(), ESignError>;
+}
+
+impl SessionProbe for Session {
+    fn probe(&self) -> Result<(), ESignError> {
+        self.get_session_info()
+            .map(|_| ())
+            .map_err(|e| ESignError::Pkcs11(format!("Session check failed: {}", e)))
+    }
+}
+
+/// Map a missing or unresponsive session to a TokenNotFound signing error
+pub(crate) fn ensure_session_alive<S: SessionProbe>(session: Option<&S>) -> Result<(), ESignError> {
+    match session {
+        Some(session) if session.probe().is_ok() => Ok(()),
+        _ => Err(ESignError::Signing {
+            code: SigningErrorCode::TokenNotFound,
+            message: "Session expired. Please log in again.".to_string(),
+        }),
+    }
+}
+
+/// Timeout for the load probe during async library detection
+const LOAD_PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Check each CA's candidate paths on the blocking pool, CAs concurrently
+/// Keeps CA order; `exists` is injectable so tests can simulate slow filesystems
+pub(crate) async fn detect_paths_concurrently<F>(
+    candidates: Vec<(&'static str, Vec<String>)>,
+    exists: F,
+) -> Vec<DetectedLibrary>
+where
+    F: Fn(&str) -> bool + Send + Sync + 'static,
+{
+    let exists = Arc::new(exists);
+    let checks: Vec<_> = candidates
+        .into_iter()
+        .map(|(name, paths)| {
+            let exists = Arc::clone(&exists);
+            tokio::task::spawn_blocking(move || {
+                detect_first_candidate(name, &paths, |path| exists(path))
+            })
+        })
+        .collect();
+
+    let mut detected = Vec::new();
+    for check in checks {
+        if let Ok(Some(lib)) = check.await {
+            detected.push(lib);
+        }
+    }
+    detected
+}
+
+/// Library at the first of a CA's candidate paths that exists, recording its index
+pub(crate) fn detect_first_candidate(
+    ca_name: &str,
+    paths: &[String],
+    exists: impl Fn(&str) -> bool,
+) -> Option<DetectedLibrary> {
+    let (index, path) = paths.iter().enumerate().find(|(_, path)| exists(path))?;
+    let mut lib = DetectedLibrary::new(ca_name, path);
+    lib.candidate_index = index;
+    Some(lib)
+}
+
+/// Try loading a library within LOAD_PROBE_TIMEOUT
+/// Returns None if the probe timed out (load keeps running in the background)
+async fn probe_library_load(path: String) -> Option<bool> {
+    let probe = tokio::task::spawn_blocking(move || {
+        validate_library_path(&path).is_ok() && load_pkcs11_library(&path).is_ok()
+    });
+
+    match tokio::time::timeout(LOAD_PROBE_TIMEOUT, probe).await {
+        Ok(Ok(loadable)) => Some(loadable),
+        _ => None,
+    }
+}
+
+/// Read C_GetInfo from a library that is not loaded in-process yet
+/// C_GetInfo is tried right after loading; libraries that require C_Initialize
+/// first are initialized (and finalized again when the handle drops)
+pub(crate) fn read_library_info(library_path: &str) -> Result<LibraryVersionInfo, ESignError> {
+    validate_library_path(library_path)?;
+    let ctx = load_pkcs11_library(library_path)?;
+    let info = match ctx.get_library_info() {
+        Err(CryptokiError::Pkcs11(RvError::CryptokiNotInitialized, _)) => ctx
+            .initialize(CInitializeArgs::OsThreads)
+            .and_then(|_| ctx.get_library_info()),
+        result => result,
+    }
+    .map_err(|e| ESignError::Pkcs11(format!("Failed to get library info: {}", e)))?;
+    Ok(version_info(&info))
+}
+
+/// Convert C_GetInfo output, trimming the space-padded fields
+fn version_info(info: &cryptoki::context::Info) -> LibraryVersionInfo {
+    let cryptoki_version = info.cryptoki_version();
+    let library_version = info.library_version();
+    LibraryVersionInfo {
+        manufacturer: info.manufacturer_id().trim().to_string(),
+        description: info.library_description().trim().to_string(),
+        cryptoki_version: format_version(cryptoki_version.major(), cryptoki_version.minor()),
+        library_version: format_version(library_version.major(), library_version.minor()),
+    }
+}
+
+/// Load a PKCS#11 shared library without initializing it
+/// Maps architecture mismatch errors to actionable guidance
+pub(crate) fn load_pkcs11_library(library_path: &str) -> Result<Pkcs11, ESignError> {
+    Pkcs11::new(library_path).map_err(|e| {
+        let error_str = e.to_string();
+
+        // Detect architecture mismatch on macOS
+        if error_str.contains("incompatible architecture") {
+            return create_arch_mismatch_error(&error_str, library_path);
+        }
+
+        ESignError::Pkcs11(format!(
+            "Failed to load PKCS#11 library '{}': {}",
+            library_path, e
+        ))
+    })
+}
+
 impl Drop for TokenManager {
     fn drop(&mut self) {
         self.logout();
@@ -4,13 +4,21 @@
 //! using the PKCS#11 standard via the cryptoki crate.
 
 pub mod helpers;
+mod library_manager;
 pub mod library_paths;
 mod manager;
+mod registry;
+mod state;
 mod types;
 
 #[cfg(test)]
 mod tests;
 
 // Re-export public types
+pub use library_manager::{detect_duplicate_library_path, LibraryManager};
 pub use manager::TokenManager;
-pub use types::{CertificateInfo, DetectedLibrary, TokenInfo};
+pub use types::{
+    CertExportFormat, CertPolicyInfo, CertValidityReport, CertificateInfo, CertificateInfoExtended,
+    DetectedLibrary, KeyInfo, LibraryVersionInfo, MechanismInfo, SignMechanism, SigningAlgorithm,
+    TokenInfo, TokenManagerConfig, VendorInfo,
+};
@@ -0,0 +1,89 @@
+//! Windows registry discovery of PKCS#11 libraries
+//!
+//! Older VNPT-CA installers skip the standard folder and only register the
+//! library under HKLM\SOFTWARE\Microsoft\Cryptography\Callers.
+
+use super::types::DetectedLibrary;
+
+/// Registry keys (under HKLM) whose subkeys name CA libraries; WOW6432Node holds 32-bit ones
+pub const CALLERS_KEYS: &[&str] = &[
+    "SOFTWARE\\Microsoft\\Cryptography\\Callers",
+    "SOFTWARE\\WOW6432Node\\Microsoft\\Cryptography\\Callers",
+];
+
+/// HKLM reads used by discovery, so tests can supply a fake registry
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub trait RegistryReader {
+    /// Names of the subkeys of `key`; empty if the key does not exist
+    fn subkeys(&self, key: &str) -> Vec<String>;
+    /// String value `name` of `key`
+    fn string_value(&self, key: &str, name: &str) -> Option<String>;
+}
+
+/// Libraries registered under `CALLERS_KEYS`: one per subkey with an existing `.dll` `Path`
+/// The subkey name is reported as the CA name; duplicate paths are skipped
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub fn discover_libraries(
+    reader: &impl RegistryReader,
+    exists: impl Fn(&str) -> bool,
+) -> Vec<DetectedLibrary> {
+    let mut detected = Vec::new();
+    for root in CALLERS_KEYS {
+        for name in reader.subkeys(root) {
+            let Some(path) = reader.string_value(&format!("{}\\{}", root, name), "Path") else {
+                continue;
+            };
+            let path = path.trim().trim_matches('"');
+            if !path.to_ascii_lowercase().ends_with(".dll") || !exists(path) {
+                continue;
+            }
+            merge_detected(&mut detected, vec![DetectedLibrary::new(&name, path)]);
+        }
+    }
+    detected
+}
+
+/// Append libraries whose path is not already listed (case-insensitive, as on Windows)
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+pub fn merge_detected(detected: &mut Vec<DetectedLibrary>, more: Vec<DetectedLibrary>) {
+    for lib in more {
+        if !detected
+            .iter()
+            .any(|existing| existing.path.eq_ignore_ascii_case(&lib.path))
+        {
+            detected.push(lib);
+        }
+    }
+}
+
+/// HKEY_LOCAL_MACHINE via winreg
+#[cfg(target_os = "windows")]
+struct WinRegistry;
+
+#[cfg(target_os = "windows")]
+impl RegistryReader for WinRegistry {
+    fn subkeys(&self, key: &str) -> Vec<String> {
+        use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+        RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey(key)
+            .map(|key| key.enum_keys().filter_map(Result::ok).collect())
+            .unwrap_or_default()
+    }
+
+    fn string_value(&self, key: &str, name: &str) -> Option<String> {
+        use winreg::{enums::HKEY_LOCAL_MACHINE, RegKey};
+
+        RegKey::predef(HKEY_LOCAL_MACHINE)
+            .open_subkey(key)
+            .ok()?
+            .get_value(name)
+            .ok()
+    }
+}
+
+/// Libraries registered in the Windows registry (64-bit and 32-bit views)
+#[cfg(target_os = "windows")]
+pub fn detect_via_registry() -> Vec<DetectedLibrary> {
+    discover_libraries(&WinRegistry, |path| std::path::Path::new(path).exists())
+}
@@ -0,0 +1,178 @@
+//! Token session state machine
+//!
+//! Makes the select slot -> login -> sign sequence explicit, so an operation
+//! called out of order fails with an error naming the current and required state.
+
+use crate::error::{ESignError, SigningErrorCode};
+use cryptoki::{
+    object::{KeyType as Pkcs11KeyType, ObjectHandle},
+    session::Session,
+};
+use std::fmt;
+
+/// Session state held by `TokenManager`
+pub enum TokenState {
+    /// Library loaded, no slot chosen yet
+    Uninitialized,
+    /// Slot chosen, waiting for PIN
+    SlotSelected { slot_id: u64 },
+    /// Authenticated session with signing key and certificate located
+    LoggedIn {
+        slot_id: u64,
+        cert_der: Vec<u8>,
+        /// Full certificate chain (end-entity + issuers)
+        cert_chain: Vec<Vec<u8>>,
+        key: SigningKey,
+        /// CKA_ID of the signing key, shared with its certificate (may be empty)
+        key_id: Vec<u8>,
+        session: Session,
+    },
+}
+
+impl TokenState {
+    /// State without its payload
+    pub fn kind(&self) -> TokenStateKind {
+        match self {
+            Self::Uninitialized => TokenStateKind::Uninitialized,
+            Self::SlotSelected { .. } => TokenStateKind::SlotSelected,
+            Self::LoggedIn { .. } => TokenStateKind::LoggedIn,
+        }
+    }
+
+    /// Selected slot, if any
+    pub fn slot_id(&self) -> Option<u64> {
+        match self {
+            Self::Uninitialized => None,
+            Self::SlotSelected { slot_id } | Self::LoggedIn { slot_id, .. } => Some(*slot_id),
+        }
+    }
+
+    /// Open session, if logged in
+    pub fn session(&self) -> Option<&Session> {
+        match self {
+            Self::LoggedIn { session, .. } => Some(session),
+            _ => None,
+        }
+    }
+}
+
+/// Private key located at login
+#[derive(Debug, Clone, Copy)]
+pub struct SigningKey {
+    pub handle: ObjectHandle,
+    pub key_type: KeyType,
+}
+
+/// Private key algorithms supported for signing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Rsa,
+    /// NIST P-256 keys on newer Viettel-CA tokens
+    Ec,
+}
+
+impl KeyType {
+    /// Map CKA_KEY_TYPE, None for unsupported algorithms
+    pub fn from_pkcs11(key_type: Pkcs11KeyType) -> Option<Self> {
+        if key_type == Pkcs11KeyType::RSA {
+            Some(Self::Rsa)
+        } else if key_type == Pkcs11KeyType::EC {
+            Some(Self::Ec)
+        } else {
+            None
+        }
+    }
+}
+
+/// Token state without its payload, for transition checks and error messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenStateKind {
+    Uninitialized,
+    SlotSelected,
+    LoggedIn,
+}
+
+impl fmt::Display for TokenStateKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Uninitialized => "Uninitialized",
+            Self::SlotSelected => "SlotSelected",
+            Self::LoggedIn => "LoggedIn",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Operations whose validity depends on the token state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenOperation {
+    #[allow(dead_code)] // Valid in every state, so never checked at runtime
+    ListSlots,
+    SelectSlot,
+    Login,
+    Sign,
+    ReadCertificate,
+    ListKeys,
+    ChangePin,
+    GenerateRandom,
+    LockObjects,
+    #[allow(dead_code)] // Logout is infallible; kept to document the transition
+    Logout,
+}
+
+impl TokenOperation {
+    /// State reached by performing this operation from `from`
+    pub fn transition(self, from: TokenStateKind) -> Result<TokenStateKind, ESignError> {
+        use TokenStateKind::*;
+
+        match (self, from) {
+            (Self::ListSlots, state) => Ok(state),
+            (Self::SelectSlot, Uninitialized | SlotSelected) => Ok(SlotSelected),
+            (Self::Login, SlotSelected) => Ok(LoggedIn),
+            (
+                Self::Sign
+                | Self::ReadCertificate
+                | Self::ListKeys
+                | Self::GenerateRandom
+                | Self::LockObjects,
+                LoggedIn,
+            ) => Ok(LoggedIn),
+            (Self::ChangePin, state @ (SlotSelected | LoggedIn)) => Ok(state),
+            (Self::Logout, LoggedIn) => Ok(SlotSelected),
+            (Self::Logout, state) => Ok(state),
+            (op, state) => Err(op.invalid_in(state)),
+        }
+    }
+
+    /// Error for attempting this operation in the given state
+    pub fn invalid_in(self, state: TokenStateKind) -> ESignError {
+        let (action, required) = match self {
+            Self::ListSlots => ("list slots", "any state"),
+            Self::SelectSlot => ("select a slot", "Uninitialized or SlotSelected"),
+            Self::Login => ("log in", "SlotSelected"),
+            Self::Sign => ("sign", "LoggedIn"),
+            Self::ReadCertificate => ("read the certificate", "LoggedIn"),
+            Self::ListKeys => ("list private keys", "LoggedIn"),
+            Self::ChangePin => ("change the PIN", "SlotSelected or LoggedIn"),
+            Self::GenerateRandom => ("generate random bytes", "LoggedIn"),
+            Self::LockObjects => ("lock token objects", "LoggedIn"),
+            Self::Logout => ("log out", "any state"),
+        };
+        let code = match (self, state) {
+            (Self::ReadCertificate, _) => SigningErrorCode::CertificateNotFound,
+            (Self::Sign | Self::ListKeys | Self::GenerateRandom | Self::LockObjects, _)
+            | (Self::Login | Self::ChangePin, TokenStateKind::Uninitialized) => {
+                SigningErrorCode::TokenNotFound
+            }
+            _ => SigningErrorCode::InvalidInput,
+        };
+
+        ESignError::Signing {
+            code,
+            message: format!(
+                "Cannot {} while token is {} (requires {})",
+                action, state, required
+            ),
+        }
+    }
+}
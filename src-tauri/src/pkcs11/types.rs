@@ -2,13 +2,71 @@
 //!
 //! Defines structs for library detection, token info, and certificates.
 
+use super::library_paths;
+use super::state::KeyType;
+use crate::error::{CertValidationCode, ESignError};
+use cryptoki::mechanism::Mechanism;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Detected PKCS#11 library information
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DetectedLibrary {
     pub ca_name: String,
+    /// First existing candidate path for the CA
     pub path: String,
+    /// Position of `path` in the CA's candidate list (0 = preferred location)
+    #[serde(default)]
+    pub candidate_index: usize,
+    /// Result of the async load probe (None if not probed or timed out)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loadable: Option<bool>,
+    /// Library version from C_GetInfo ("major.minor"), None if it could not be read
+    #[serde(default)]
+    pub library_version: Option<String>,
+    /// PKCS#11 API version implemented by the library, empty if unknown
+    #[serde(default)]
+    pub cryptoki_version: String,
+    #[serde(default)]
+    pub manufacturer_id: String,
+    /// False when the library version is listed in `library_paths::KNOWN_ISSUES`
+    #[serde(default = "default_compatible")]
+    pub is_compatible: bool,
+    #[serde(default)]
+    pub compatibility_warning: Option<String>,
+}
+
+fn default_compatible() -> bool {
+    true
+}
+
+impl DetectedLibrary {
+    /// Library found at a path, version not read yet
+    pub fn new(ca_name: &str, path: &str) -> Self {
+        Self {
+            ca_name: ca_name.to_string(),
+            path: path.to_string(),
+            candidate_index: 0,
+            loadable: None,
+            library_version: None,
+            cryptoki_version: String::new(),
+            manufacturer_id: String::new(),
+            is_compatible: true,
+            compatibility_warning: None,
+        }
+    }
+
+    /// Fill in C_GetInfo versions and check them against known issues
+    pub fn with_version_info(mut self, info: &LibraryVersionInfo) -> Self {
+        self.library_version = Some(info.library_version.clone());
+        self.cryptoki_version = info.cryptoki_version.clone();
+        self.manufacturer_id = info.manufacturer.clone();
+        if let Some(issue) = library_paths::known_issue(&self.ca_name, &info.library_version) {
+            self.is_compatible = false;
+            self.compatibility_warning = Some(issue.warning.to_string());
+        }
+        self
+    }
 }
 
 /// Token information returned from slot enumeration
@@ -20,6 +78,50 @@ pub struct TokenInfo {
     pub model: String,
     pub serial: String,
     pub has_token: bool,
+    /// PIN attempts left before the token locks; None if the token does not report it
+    pub retries_remaining: Option<u64>,
+}
+
+/// PIN attempts left, derived from CKF_USER_PIN_LOCKED / CKF_USER_PIN_FINAL_TRY
+/// PKCS#11 exposes no exact counter, so only the last attempt and lockout are known
+pub fn retries_from_pin_flags(locked: bool, final_try: bool) -> Option<u64> {
+    if locked {
+        Some(0)
+    } else if final_try {
+        Some(1)
+    } else {
+        None
+    }
+}
+
+/// Private key on the token, for choosing between several key pairs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyInfo {
+    /// Raw CKA_ID bytes, sent to the frontend as hex like other object ids;
+    /// the hex string is what select_signing_key takes
+    #[serde(with = "hex_id")]
+    pub id: Vec<u8>,
+    pub label: String,
+    /// "RSA", "EC" or "Unsupported"
+    pub key_type: String,
+    /// RSA modulus bits or EC curve size; 0 if unknown
+    pub key_size: u32,
+    /// CKA_SIGN is set and the key type is supported
+    pub can_sign: bool,
+}
+
+/// Serde for CKA_ID bytes as a hex string
+mod hex_id {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(id: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(id))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let id = String::deserialize(deserializer)?;
+        hex::decode(&id).map_err(serde::de::Error::custom)
+    }
 }
 
 /// Certificate information from token
@@ -33,6 +135,357 @@ pub struct CertificateInfo {
     pub thumbprint: String,
     /// DER-encoded certificate bytes (base64)
     pub der_base64: String,
+    /// Validity start as Unix timestamp
+    #[serde(default)]
+    pub valid_from_timestamp: i64,
+    /// Validity end as Unix timestamp
+    #[serde(default)]
+    pub valid_to_timestamp: i64,
+    /// Fraction of validity period elapsed (0.0-1.0), computed when info is read
+    #[serde(default)]
+    pub validity_fraction: f64,
+    /// Certificate health class: "ok", "warning", "critical" or "expired"
+    #[serde(default)]
+    pub validity_class: String,
+    /// Still valid but expires within EXPIRY_WARNING_DAYS days, computed when info is read
+    #[serde(default)]
+    pub expiring_soon: bool,
+    /// OCSP responder URL from the AuthorityInfoAccess extension
+    #[serde(default)]
+    pub ocsp_url: Option<String>,
+    /// Issuer certificate URL (caIssuers) from the AuthorityInfoAccess extension
+    #[serde(default)]
+    pub ca_issuers_url: Option<String>,
+    /// CKA_ID on the token (hex), linking the certificate to its private key
+    #[serde(default)]
+    pub cert_id: Option<String>,
+}
+
+impl CertificateInfo {
+    /// Fraction of the validity period elapsed as of now, clamped to [0.0, 1.0]
+    pub fn validity_fraction(&self) -> f64 {
+        self.validity_fraction_at(chrono::Utc::now().timestamp())
+    }
+
+    /// Fraction of the validity period elapsed at the given Unix timestamp
+    pub fn validity_fraction_at(&self, now: i64) -> f64 {
+        let total = self.valid_to_timestamp - self.valid_from_timestamp;
+        if total <= 0 {
+            return 1.0;
+        }
+        ((now - self.valid_from_timestamp) as f64 / total as f64).clamp(0.0, 1.0)
+    }
+
+    /// Certificate health class for the frontend progress bar
+    pub fn validity_class(&self) -> &'static str {
+        validity_class_for(self.validity_fraction())
+    }
+
+    /// Recompute the serialized validity_fraction/validity_class/expiring_soon fields
+    pub fn refresh_validity(&mut self) {
+        self.validity_fraction = self.validity_fraction();
+        self.validity_class = self.validity_class().to_string();
+        self.expiring_soon = self.is_expiring_soon();
+    }
+
+    /// Still valid, but expires within EXPIRY_WARNING_DAYS days
+    pub fn is_expiring_soon(&self) -> bool {
+        self.is_expiring_soon_at(chrono::Utc::now().timestamp())
+    }
+
+    /// Same as `is_expiring_soon` at the given Unix timestamp
+    pub fn is_expiring_soon_at(&self, now: i64) -> bool {
+        let report =
+            CertValidityReport::at(self.valid_from_timestamp, self.valid_to_timestamp, now);
+        report.is_valid && report.days_until_expiry <= EXPIRY_WARNING_DAYS
+    }
+}
+
+/// Certificates expiring within this many days get a warning
+pub const EXPIRY_WARNING_DAYS: i64 = 30;
+
+/// Validity of the signing certificate, checked before signing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertValidityReport {
+    /// Inside the notBefore..notAfter period
+    pub is_valid: bool,
+    pub is_expired: bool,
+    /// Whole days until notAfter, negative once expired
+    pub days_until_expiry: i64,
+    /// Vietnamese message for expired, not yet valid or soon expiring certificates
+    pub warning: Option<String>,
+    pub validation_code: CertValidationCode,
+}
+
+impl CertValidityReport {
+    /// Check a validity period (Unix timestamps) at `now`
+    pub fn at(not_before: i64, not_after: i64, now: i64) -> Self {
+        // Truncates toward zero like chrono's num_days
+        let days_until_expiry = (not_after - now) / 86_400;
+        let is_expired = now > not_after;
+        let not_yet_valid = now < not_before;
+
+        let (validation_code, warning) = if is_expired {
+            (
+                CertValidationCode::Expired,
+                Some("Chứng thư đã hết hạn".to_string()),
+            )
+        } else if not_yet_valid {
+            (
+                CertValidationCode::NotYetValid,
+                Some("Chứng thư chưa có hiệu lực".to_string()),
+            )
+        } else if days_until_expiry <= EXPIRY_WARNING_DAYS {
+            (
+                CertValidationCode::Valid,
+                Some(format!(
+                    "Chứng thư sẽ hết hạn trong {} ngày",
+                    days_until_expiry
+                )),
+            )
+        } else {
+            (CertValidationCode::Valid, None)
+        };
+
+        Self {
+            is_valid: !is_expired && !not_yet_valid,
+            is_expired,
+            days_until_expiry,
+            warning,
+            validation_code,
+        }
+    }
+}
+
+/// Certificate information plus key and extension details
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertificateInfoExtended {
+    #[serde(flatten)]
+    pub info: CertificateInfo,
+    /// "RSA", "EC", or the algorithm name (OID if unknown) for other keys
+    pub key_algorithm: String,
+    /// RSA modulus length or EC named curve size
+    pub key_size_bits: Option<u32>,
+    /// Prefixed names: "email:", "DNS:", "URI:", "IP:" or "DirName:"
+    pub subject_alt_names: Vec<String>,
+    /// RFC 5280 KeyUsage names, e.g. "digitalSignature", "nonRepudiation"
+    pub key_usages: Vec<String>,
+    /// ExtendedKeyUsage names, dotted OIDs for unnamed purposes
+    pub extended_key_usages: Vec<String>,
+    /// Subject equals issuer (and the key identifiers match when both are present)
+    pub is_self_signed: bool,
+}
+
+/// KeyUsage bits relevant to signing and encryption (RFC 5280 §4.2.1.3)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyUsageFlags {
+    pub digital_signature: bool,
+    /// Also called contentCommitment; required for legally binding signatures
+    pub non_repudiation: bool,
+    pub key_encipherment: bool,
+    pub data_encipherment: bool,
+}
+
+/// Map elapsed validity fraction to a health class
+/// ok < 0.8 <= warning <= 0.95 < critical < 1.0 <= expired
+pub fn validity_class_for(fraction: f64) -> &'static str {
+    if fraction >= 1.0 {
+        "expired"
+    } else if fraction > 0.95 {
+        "critical"
+    } else if fraction >= 0.8 {
+        "warning"
+    } else {
+        "ok"
+    }
+}
+
+/// Certificate policy from the CertificatePolicies extension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CertPolicyInfo {
+    pub policy_oid: String,
+    /// Human-readable name for known Vietnamese CA policies
+    pub policy_name: Option<String>,
+    /// Certification Practice Statement URI (id-qt-cps qualifier)
+    pub cps_uri: Option<String>,
+}
+
+/// Mechanism supported by a token slot (C_GetMechanismInfo)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MechanismInfo {
+    /// Human-readable name, e.g. "SHA-256 with RSA PKCS#1 v1.5"
+    pub name: String,
+    /// Key size bounds (bits for RSA/EC, bytes for some symmetric mechanisms)
+    pub key_size_min: u64,
+    pub key_size_max: u64,
+    /// Subset of "Sign", "SignRecover", "Verify", "Decrypt", "Encrypt"
+    pub flags: Vec<String>,
+}
+
+/// Vendor-specific attribute IDs probed on token objects (CKA_VENDOR_DEFINED + 1..=5)
+pub const VENDOR_ATTRIBUTE_IDS: [u32; 5] = [
+    0x8000_0001,
+    0x8000_0002,
+    0x8000_0003,
+    0x8000_0004,
+    0x8000_0005,
+];
+
+/// VNPT-CA firmware version attribute
+pub const VNPT_FIRMWARE_ATTRIBUTE: u32 = 0x8000_0001;
+/// Viettel-CA firmware version attribute
+pub const VIETTEL_FIRMWARE_ATTRIBUTE: u32 = 0x8000_0003;
+/// Vendor serial number attribute (used when CK_TOKEN_INFO serial is blank)
+pub const SERIAL_OVERRIDE_ATTRIBUTE: u32 = 0x8000_0002;
+
+/// Vendor-specific token information read from CKA_VENDOR_DEFINED attributes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VendorInfo {
+    pub firmware_version: Option<String>,
+    pub serial_override: Option<String>,
+    /// All readable vendor attributes, decoded as text (or hex for binary values)
+    pub vendor_attributes: HashMap<u32, String>,
+}
+
+impl VendorInfo {
+    /// Build from raw attribute values, using the token manufacturer to pick
+    /// the vendor's firmware attribute (VNPT: 0x80000001, Viettel: 0x80000003)
+    pub fn from_raw_attributes(manufacturer: &str, raw: &HashMap<u32, Vec<u8>>) -> Self {
+        let vendor_attributes: HashMap<u32, String> = raw
+            .iter()
+            .filter_map(|(id, bytes)| decode_vendor_value(bytes).map(|value| (*id, value)))
+            .collect();
+
+        let firmware_attribute = if manufacturer.to_lowercase().contains("viettel") {
+            VIETTEL_FIRMWARE_ATTRIBUTE
+        } else {
+            VNPT_FIRMWARE_ATTRIBUTE
+        };
+
+        Self {
+            firmware_version: vendor_attributes.get(&firmware_attribute).cloned(),
+            serial_override: vendor_attributes.get(&SERIAL_OVERRIDE_ATTRIBUTE).cloned(),
+            vendor_attributes,
+        }
+    }
+}
+
+/// Decode a vendor attribute value
+/// Printable text is trimmed of NUL/space padding; binary values become uppercase hex
+pub fn decode_vendor_value(bytes: &[u8]) -> Option<String> {
+    let trimmed = bytes
+        .iter()
+        .rposition(|b| *b != 0 && !b.is_ascii_whitespace())
+        .map(|end| &bytes[..=end])?;
+
+    match std::str::from_utf8(trimmed) {
+        Ok(text) if !text.chars().any(|c| c.is_control()) => Some(text.trim().to_string()),
+        _ => Some(trimmed.iter().map(|b| format!("{:02X}", b)).collect()),
+    }
+}
+
+/// RSA PKCS#1 v1.5 signing algorithm (token hashes internally)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigningAlgorithm {
+    /// Legacy only - SHA-1 is deprecated for new signatures
+    Sha1WithRsa,
+    Sha256WithRsa,
+    Sha384WithRsa,
+    Sha512WithRsa,
+}
+
+impl SigningAlgorithm {
+    /// Parse Java-style algorithm name (e.g. "SHA256withRSA")
+    pub fn from_name(name: &str) -> Result<Self, ESignError> {
+        match name {
+            "SHA1withRSA" => Ok(Self::Sha1WithRsa),
+            "SHA256withRSA" => Ok(Self::Sha256WithRsa),
+            "SHA384withRSA" => Ok(Self::Sha384WithRsa),
+            "SHA512withRSA" => Ok(Self::Sha512WithRsa),
+            _ => Err(ESignError::Pkcs11(format!(
+                "Unsupported signing algorithm: {}",
+                name
+            ))),
+        }
+    }
+
+    /// Corresponding PKCS#11 mechanism
+    pub fn mechanism(&self) -> Mechanism<'static> {
+        match self {
+            Self::Sha1WithRsa => Mechanism::Sha1RsaPkcs,
+            Self::Sha256WithRsa => Mechanism::Sha256RsaPkcs,
+            Self::Sha384WithRsa => Mechanism::Sha384RsaPkcs,
+            Self::Sha512WithRsa => Mechanism::Sha512RsaPkcs,
+        }
+    }
+
+    /// Whether the algorithm is deprecated (SHA-1 collisions are practical)
+    pub fn is_deprecated(&self) -> bool {
+        matches!(self, Self::Sha1WithRsa)
+    }
+}
+
+/// Raw signing mechanism requested by external integrations (`sign_data_with_slot`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum SignMechanism {
+    /// CKM_SHA256_RSA_PKCS - token hashes the data
+    Sha256RsaPkcs,
+    /// CKM_RSA_PKCS - no hashing; the caller must pass a DER DigestInfo
+    /// (hash algorithm OID + digest), as PKCS#1 v1.5 signs it unchanged
+    RsaPkcs,
+    /// CKM_ECDSA_SHA256 - token hashes the data; signature returned DER-encoded
+    EcdsaSha256,
+}
+
+impl SignMechanism {
+    /// Corresponding PKCS#11 mechanism
+    pub fn mechanism(&self) -> Mechanism<'static> {
+        match self {
+            Self::Sha256RsaPkcs => Mechanism::Sha256RsaPkcs,
+            Self::RsaPkcs => Mechanism::RsaPkcs,
+            Self::EcdsaSha256 => Mechanism::EcdsaSha256,
+        }
+    }
+
+    /// Private key algorithm the mechanism works with
+    pub fn key_type(&self) -> KeyType {
+        match self {
+            Self::Sha256RsaPkcs | Self::RsaPkcs => KeyType::Rsa,
+            Self::EcdsaSha256 => KeyType::Ec,
+        }
+    }
+}
+
+/// File encoding for an exported certificate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum CertExportFormat {
+    /// Base64 between BEGIN/END CERTIFICATE lines
+    Pem,
+    /// Raw DER bytes
+    Der,
+}
+
+/// TokenManager behaviour options
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenManagerConfig {
+    /// Mark the certificate chain CKA_MODIFIABLE=false right after login, as some
+    /// CA policies require so the certificate cannot be replaced mid-session
+    pub lock_after_login: bool,
+}
+
+/// PKCS#11 library information from C_GetInfo (for bug reports)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryVersionInfo {
+    pub manufacturer: String,
+    pub description: String,
+    /// PKCS#11 API version implemented by the library, e.g. "2.40"
+    pub cryptoki_version: String,
+    pub library_version: String,
+}
+
+/// Format a CK_VERSION as "major.minor"
+pub fn format_version(major: u8, minor: u8) -> String {
+    format!("{}.{}", major, minor)
 }
 
 /// Format Unix timestamp as ISO 8601 datetime for JavaScript compatibility
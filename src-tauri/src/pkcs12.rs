@@ -0,0 +1,373 @@
+//! PKCS#12 Export Module
+//!
+//! Wraps the token certificate chain in a password-protected `.p12` file.
+//! The private key never leaves the token, so the archive holds certificate
+//! bags only: PBES2 (PBKDF2-HMAC-SHA256, AES-256-CBC) encryption with a
+//! SHA-256 MAC, readable by OpenSSL 1.1.1+, Windows and Java keystores.
+
+use crate::error::{CertValidationCode, ESignError};
+use crate::pdf::{
+    build_octet_string, build_oid, build_sequence, build_set, encode_children, OID_DATA, OID_SHA256,
+};
+use aes::Aes256;
+use cbc::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+use rand::distributions::{Alphanumeric, DistString};
+use rand::RngCore;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::num::NonZeroU32;
+use zeroize::Zeroize;
+
+/// encryptedData (1.2.840.113549.1.7.6)
+const OID_ENCRYPTED_DATA: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x06];
+/// PBES2 (1.2.840.113549.1.5.13)
+const OID_PBES2: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x05, 0x0D];
+/// PBKDF2 (1.2.840.113549.1.5.12)
+const OID_PBKDF2: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x05, 0x0C];
+/// hmacWithSHA256 (1.2.840.113549.2.9)
+const OID_HMAC_SHA256: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x02, 0x09];
+/// aes256-CBC (2.16.840.1.101.3.4.1.42)
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2A];
+/// certBag (1.2.840.113549.1.12.10.1.3)
+const OID_CERT_BAG: &[u8] = &[
+    0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x0C, 0x0A, 0x01, 0x03,
+];
+/// x509Certificate cert type (1.2.840.113549.1.9.22.1)
+const OID_X509_CERTIFICATE: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x16, 0x01];
+/// friendlyName bag attribute (1.2.840.113549.1.9.20)
+const OID_FRIENDLY_NAME: &[u8] = &[0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x14];
+
+/// PBKDF2 and MAC iteration count (OpenSSL default)
+const ITERATIONS: u32 = 2048;
+/// Length of the generated protection password
+const PASSWORD_LEN: usize = 24;
+/// PKCS#12 KDF purpose byte for MAC keys (RFC 7292 Appendix B.3)
+const KDF_ID_MAC: u8 = 3;
+
+/// Result of export_certificate_p12; the password is shown to the user once
+#[derive(Debug, Clone, Serialize)]
+pub struct P12ExportResult {
+    pub output_path: String,
+    pub password: String,
+}
+
+/// Build a certificate-only PKCS#12 archive protected by a random password
+/// The first chain entry gets `friendly_name`; returns (archive DER, password)
+pub fn export_certificate_chain_p12(
+    chain: &[Vec<u8>],
+    friendly_name: &str,
+) -> Result<(Vec<u8>, String), ESignError> {
+    let mut rng = rand::thread_rng();
+    let password = Alphanumeric.sample_string(&mut rng, PASSWORD_LEN);
+    let mut salts = [[0u8; 16]; 3];
+    for salt in &mut salts {
+        rng.fill_bytes(salt);
+    }
+    let [salt, iv, mac_salt] = salts;
+    let der = build_p12(chain, friendly_name, &password, &salt, &iv, &mac_salt)?;
+    Ok((der, password))
+}
+
+/// Encode the PFX with the given password and random inputs
+fn build_p12(
+    chain: &[Vec<u8>],
+    friendly_name: &str,
+    password: &str,
+    salt: &[u8; 16],
+    iv: &[u8; 16],
+    mac_salt: &[u8; 16],
+) -> Result<Vec<u8>, ESignError> {
+    if chain.is_empty() {
+        return Err(ESignError::CertValidation {
+            code: CertValidationCode::CertInfoUnavailable,
+            message: "No certificate to export".to_string(),
+        });
+    }
+
+    // SafeContents: one CertBag per certificate, friendlyName on the end-entity
+    let mut bags = Vec::new();
+    for (index, cert_der) in chain.iter().enumerate() {
+        let mut cert_bag = build_oid(OID_X509_CERTIFICATE)?;
+        cert_bag.extend(encode_children(&[(0xA0, build_octet_string(cert_der)?)])?);
+
+        let mut safe_bag = build_oid(OID_CERT_BAG)?;
+        safe_bag.extend(encode_children(&[(0xA0, build_sequence(&cert_bag)?)])?);
+        if index == 0 {
+            safe_bag.extend(build_set(&build_friendly_name(friendly_name)?)?);
+        }
+        bags.extend(build_sequence(&safe_bag)?);
+    }
+    let safe_contents = build_sequence(&bags)?;
+
+    // EncryptedData with PBES2 / AES-256-CBC; PBES2 takes the UTF-8 password as is
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(
+        ring::pbkdf2::PBKDF2_HMAC_SHA256,
+        NonZeroU32::new(ITERATIONS).expect("non-zero iterations"),
+        salt,
+        password.as_bytes(),
+        &mut key,
+    );
+    let ciphertext = cbc::Encryptor::<Aes256>::new(&key.into(), &(*iv).into())
+        .encrypt_padded_vec_mut::<Pkcs7>(&safe_contents);
+    key.zeroize();
+
+    let mut encrypted_content_info = build_oid(OID_DATA)?;
+    encrypted_content_info.extend(build_pbes2_algorithm(salt, iv)?);
+    encrypted_content_info.extend(encode_children(&[(0x80, ciphertext)])?);
+    let mut encrypted_data = build_integer(0)?;
+    encrypted_data.extend(build_sequence(&encrypted_content_info)?);
+
+    let mut content_info = build_oid(OID_ENCRYPTED_DATA)?;
+    content_info.extend(encode_children(&[(
+        0xA0,
+        build_sequence(&encrypted_data)?,
+    )])?);
+    let authenticated_safe = build_sequence(&build_sequence(&content_info)?)?;
+
+    // MacData over the AuthenticatedSafe with a PKCS#12 KDF derived key
+    let mut mac_key = pkcs12_kdf(password, mac_salt, KDF_ID_MAC, ITERATIONS, 32);
+    let mac = ring::hmac::sign(
+        &ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &mac_key),
+        &authenticated_safe,
+    );
+    mac_key.zeroize();
+
+    let mut digest_algorithm = build_oid(OID_SHA256)?;
+    digest_algorithm.extend([0x05, 0x00]); // NULL
+    let mut digest_info = build_sequence(&digest_algorithm)?;
+    digest_info.extend(build_octet_string(mac.as_ref())?);
+    let mut mac_data = build_sequence(&digest_info)?;
+    mac_data.extend(build_octet_string(mac_salt)?);
+    mac_data.extend(build_integer(ITERATIONS)?);
+
+    let mut auth_safe = build_oid(OID_DATA)?;
+    auth_safe.extend(encode_children(&[(
+        0xA0,
+        build_octet_string(&authenticated_safe)?,
+    )])?);
+    let mut pfx = build_integer(3)?;
+    pfx.extend(build_sequence(&auth_safe)?);
+    pfx.extend(build_sequence(&mac_data)?);
+    build_sequence(&pfx)
+}
+
+/// Build friendlyName Attribute (BMPString value)
+fn build_friendly_name(name: &str) -> Result<Vec<u8>, ESignError> {
+    let bmp: Vec<u8> = name.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    let mut attribute = build_oid(OID_FRIENDLY_NAME)?;
+    attribute.extend(build_set(&encode_children(&[(0x1E, bmp)])?)?);
+    build_sequence(&attribute)
+}
+
+/// Build PBES2 AlgorithmIdentifier (PBKDF2-HMAC-SHA256 + AES-256-CBC)
+fn build_pbes2_algorithm(salt: &[u8], iv: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let mut prf = build_oid(OID_HMAC_SHA256)?;
+    prf.extend([0x05, 0x00]); // NULL
+    let mut pbkdf2_params = build_octet_string(salt)?;
+    pbkdf2_params.extend(build_integer(ITERATIONS)?);
+    pbkdf2_params.extend(build_sequence(&prf)?);
+
+    let mut kdf = build_oid(OID_PBKDF2)?;
+    kdf.extend(build_sequence(&pbkdf2_params)?);
+    let mut cipher = build_oid(OID_AES256_CBC)?;
+    cipher.extend(build_octet_string(iv)?);
+
+    let mut params = build_sequence(&kdf)?;
+    params.extend(build_sequence(&cipher)?);
+    let mut algorithm = build_oid(OID_PBES2)?;
+    algorithm.extend(build_sequence(&params)?);
+    build_sequence(&algorithm)
+}
+
+/// Build ASN.1 INTEGER from an unsigned value (minimal encoding)
+fn build_integer(value: u32) -> Result<Vec<u8>, ESignError> {
+    let bytes = value.to_be_bytes();
+    let start = bytes.iter().position(|&b| b != 0).unwrap_or(3);
+    let mut content = bytes[start..].to_vec();
+    if content[0] & 0x80 != 0 {
+        content.insert(0, 0x00);
+    }
+    encode_children(&[(0x02, content)])
+}
+
+/// PKCS#12 key derivation (RFC 7292 Appendix B.2) with SHA-256
+/// The password is used as a NUL-terminated BMPString
+fn pkcs12_kdf(password: &str, salt: &[u8], id: u8, iterations: u32, len: usize) -> Vec<u8> {
+    const BLOCK: usize = 64; // SHA-256 input block size (v)
+
+    let fill = |data: &[u8]| -> Vec<u8> {
+        let size = data.len().div_ceil(BLOCK) * BLOCK;
+        data.iter().cycle().take(size).copied().collect()
+    };
+    let mut bmp: Vec<u8> = password.encode_utf16().flat_map(u16::to_be_bytes).collect();
+    bmp.extend([0x00, 0x00]);
+    let mut input = [fill(salt), fill(&bmp)].concat();
+    bmp.zeroize();
+
+    let mut output = Vec::with_capacity(len);
+    while output.len() < len {
+        let mut block: [u8; 32] = Sha256::new()
+            .chain_update([id; BLOCK])
+            .chain_update(&input)
+            .finalize()
+            .into();
+        for _ in 1..iterations {
+            block = Sha256::digest(block).into();
+        }
+        output.extend_from_slice(&block);
+
+        // I_j = (I_j + B + 1) mod 2^(8v), with B the digest repeated to v bytes
+        let b: Vec<u8> = block.iter().cycle().take(BLOCK).copied().collect();
+        for chunk in input.chunks_mut(BLOCK) {
+            let mut carry = 1u16;
+            for (byte, add) in chunk.iter_mut().zip(&b).rev() {
+                let sum = *byte as u16 + *add as u16 + carry;
+                *byte = sum as u8;
+                carry = sum >> 8;
+            }
+        }
+    }
+    input.zeroize();
+    output.truncate(len);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::der_children;
+    use crate::test_utils::test_identity;
+    use cbc::cipher::BlockDecryptMut;
+
+    const PASSWORD: &str = "Secret123";
+
+    fn sample_p12(chain: &[Vec<u8>]) -> Vec<u8> {
+        build_p12(
+            chain,
+            "Nguyen Van A",
+            PASSWORD,
+            &[1; 16],
+            &[2; 16],
+            &[3; 16],
+        )
+        .unwrap()
+    }
+
+    /// Children of the single top-level SEQUENCE
+    fn children(der: &[u8]) -> Vec<(u8, Vec<u8>)> {
+        let top = der_children(der).unwrap();
+        assert_eq!(top.len(), 1);
+        der_children(&top[0].1).unwrap()
+    }
+
+    // ============ PKCS#12 KDF Tests ============
+
+    #[test]
+    fn test_pkcs12_kdf_known_answer() {
+        // Cross-checked against an OpenSSL-verified MAC key derivation
+        let salt: Vec<u8> = (0..8).collect();
+        let key = pkcs12_kdf(PASSWORD, &salt, KDF_ID_MAC, ITERATIONS, 32);
+        assert_eq!(
+            hex::encode(key),
+            "d4ed26bbab3e743606a09b107df7c7b1503ff34cda7b29cd5c993dbc8dbbe9f4"
+        );
+    }
+
+    #[test]
+    fn test_build_integer_minimal() {
+        assert_eq!(build_integer(0).unwrap(), vec![0x02, 0x01, 0x00]);
+        assert_eq!(build_integer(3).unwrap(), vec![0x02, 0x01, 0x03]);
+        assert_eq!(build_integer(0x80).unwrap(), vec![0x02, 0x02, 0x00, 0x80]);
+        assert_eq!(
+            build_integer(ITERATIONS).unwrap(),
+            vec![0x02, 0x02, 0x08, 0x00]
+        );
+    }
+
+    // ============ PKCS#12 Export Tests ============
+
+    #[test]
+    fn test_p12_mac_verifies() {
+        let identity = test_identity();
+        let pfx = children(&sample_p12(&[identity.cert_der.clone()]));
+        assert_eq!(pfx[0], (0x02, vec![3]));
+
+        let auth_safe = der_children(&pfx[1].1).unwrap();
+        assert_eq!(auth_safe[0], (0x06, OID_DATA.to_vec()));
+        let octets = der_children(&auth_safe[1].1).unwrap();
+        let authenticated_safe = &octets[0].1;
+
+        let mac_data = der_children(&pfx[2].1).unwrap();
+        let digest_info = der_children(&mac_data[0].1).unwrap();
+        assert_eq!(mac_data[1].1, vec![3; 16]);
+
+        let mac_key = pkcs12_kdf(PASSWORD, &[3; 16], KDF_ID_MAC, ITERATIONS, 32);
+        let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, &mac_key);
+        assert!(ring::hmac::verify(&key, authenticated_safe, &digest_info[1].1).is_ok());
+    }
+
+    #[test]
+    fn test_p12_decrypts_to_cert_bags() {
+        let identity = test_identity();
+        let issuer = vec![0x30, 0x03, 0x02, 0x01, 0x01];
+        let pfx = children(&sample_p12(&[identity.cert_der.clone(), issuer.clone()]));
+
+        let auth_safe = der_children(&pfx[1].1).unwrap();
+        let authenticated_safe = &der_children(&auth_safe[1].1).unwrap()[0].1;
+        let content_info = der_children(&children(authenticated_safe)[0].1).unwrap();
+        // Single encryptedData ContentInfo, no key bags
+        assert_eq!(content_info[0], (0x06, OID_ENCRYPTED_DATA.to_vec()));
+
+        let encrypted_data = children(&content_info[1].1);
+        let encrypted_content_info = der_children(&encrypted_data[1].1).unwrap();
+        assert_eq!(encrypted_content_info[2].0, 0x80);
+
+        let mut key = [0u8; 32];
+        ring::pbkdf2::derive(
+            ring::pbkdf2::PBKDF2_HMAC_SHA256,
+            NonZeroU32::new(ITERATIONS).unwrap(),
+            &[1; 16],
+            PASSWORD.as_bytes(),
+            &mut key,
+        );
+        let plain = cbc::Decryptor::<Aes256>::new(&key.into(), &[2u8; 16].into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&encrypted_content_info[2].1)
+            .unwrap();
+
+        let bags = children(&plain);
+        assert_eq!(bags.len(), 2);
+        for (bag, cert) in bags.iter().zip([&identity.cert_der, &issuer]) {
+            let safe_bag = der_children(&bag.1).unwrap();
+            assert_eq!(safe_bag[0], (0x06, OID_CERT_BAG.to_vec()));
+            let cert_bag = children(&safe_bag[1].1);
+            assert_eq!(cert_bag[0], (0x06, OID_X509_CERTIFICATE.to_vec()));
+            assert_eq!(der_children(&cert_bag[1].1).unwrap()[0].1, *cert);
+        }
+
+        // friendlyName only on the end-entity bag
+        let first = der_children(&bags[0].1).unwrap();
+        assert_eq!(
+            first[2],
+            (0x31, build_friendly_name("Nguyen Van A").unwrap())
+        );
+        assert_eq!(der_children(&bags[1].1).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_p12_rejects_empty_chain() {
+        assert!(export_certificate_chain_p12(&[], "Token").is_err());
+    }
+
+    #[test]
+    fn test_p12_random_password() {
+        let identity = test_identity();
+        let chain = [identity.cert_der.clone()];
+        let (first, password) = export_certificate_chain_p12(&chain, "Token").unwrap();
+        let (second, other) = export_certificate_chain_p12(&chain, "Token").unwrap();
+        assert_eq!(password.len(), PASSWORD_LEN);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(password, other);
+        assert_ne!(first, second);
+    }
+}
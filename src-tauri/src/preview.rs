@@ -0,0 +1,396 @@
+//! Signature Preview Module
+//!
+//! Rasterizes the signature appearance box to PNG so the UI can show it
+//! before signing. Layout mirrors the PDF appearance stream in pdf.rs;
+//! glyphs are drawn from the embedded Be Vietnam Pro outlines.
+
+use crate::error::ESignError;
+use crate::font::{
+    parse_color_rgb, text_width_bold, BE_VIETNAM_PRO_REGULAR, BE_VIETNAM_PRO_SEMIBOLD,
+};
+use crate::pdf::{
+    common_name, get_current_signing_time, signature_box_lines, signature_text_left, PdfSigner,
+    StampMode,
+};
+use crate::pkcs11::CertificateInfo;
+use tiny_skia::{
+    Color, FillRule, FilterQuality, LineCap, LineJoin, Paint, PathBuilder, Pixmap, PixmapPaint,
+    Rect, Stroke, Transform,
+};
+use ttf_parser::Face;
+
+/// Preview resolution
+pub const PREVIEW_DPI: f32 = 300.0;
+
+/// Prefix of the signer line; the name after it is drawn in SemiBold
+const SIGNER_PREFIX: &str = "Được ký bởi: ";
+
+/// Render the signature appearance as a PNG at 300 DPI
+/// Signer defaults to the certificate CN and signing time to now, as when signing
+pub fn render_signature_preview(
+    params: &PdfSigner,
+    cert_info: &CertificateInfo,
+) -> Result<Vec<u8>, ESignError> {
+    let width = (params.urx - params.llx) as f32;
+    let height = (params.ury - params.lly) as f32;
+    if width <= 0.0 || height <= 0.0 {
+        return Err(ESignError::Pdf(format!(
+            "Invalid signature box size: {}x{}",
+            width, height
+        )));
+    }
+
+    let scale = PREVIEW_DPI / 72.0;
+    let mut pixmap = Pixmap::new(
+        (width * scale).ceil() as u32,
+        (height * scale).ceil() as u32,
+    )
+    .ok_or_else(|| ESignError::Pdf("Signature preview is too large".to_string()))?;
+
+    // PDF user space (origin bottom-left, points) to pixels (origin top-left)
+    let base = Transform::from_row(scale, 0.0, 0.0, -scale, 0.0, height * scale);
+
+    let mut params = params.with_rendered_template(cert_info);
+    if params.signer.is_none() {
+        params.signer = common_name(&cert_info.subject).map(str::to_string);
+    }
+    if params.signing_time.is_none() {
+        params.signing_time = Some(get_current_signing_time());
+    }
+
+    // Stamps keep a transparent background, like the PDF appearance
+    if params.stamp_mode.is_none() {
+        pixmap.fill(Color::WHITE);
+    }
+    if let Some(ref image_base64) = params.image_base64 {
+        let stretch = params.set_image_background.unwrap_or(false);
+        // The signature box shows a non-background image as a left-third thumbnail
+        let image_width = match params.stamp_mode {
+            None if !stretch => width / 3.0,
+            _ => width,
+        };
+        draw_image(
+            &mut pixmap,
+            image_base64,
+            image_width,
+            height,
+            scale,
+            stretch,
+        )?;
+    }
+    match params.stamp_mode {
+        Some(ref stamp) => draw_stamp(&mut pixmap, base, stamp, width, height),
+        None => draw_signature_box(&mut pixmap, base, &params, width, height),
+    }
+
+    pixmap
+        .encode_png()
+        .map_err(|e| ESignError::Pdf(format!("Failed to encode preview: {}", e)))
+}
+
+/// Background image (PNG only; JPEG is embedded when signing but not previewed)
+fn draw_image(
+    pixmap: &mut Pixmap,
+    image_base64: &str,
+    width: f32,
+    height: f32,
+    scale: f32,
+    stretch: bool,
+) -> Result<(), ESignError> {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    let data = STANDARD
+        .decode(image_base64)
+        .map_err(|e| ESignError::Pdf(format!("Invalid image base64: {}", e)))?;
+    if !data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Ok(());
+    }
+    let image = Pixmap::decode_png(&data)
+        .map_err(|e| ESignError::Pdf(format!("Invalid PNG image: {}", e)))?;
+
+    // Same fit as build_image_content: stretch, or scale to fit and center
+    let (iw, ih) = (image.width() as f32, image.height() as f32);
+    let (w, h) = if stretch {
+        (width, height)
+    } else {
+        let fit = (width / iw).min(height / ih);
+        (iw * fit, ih * fit)
+    };
+    let x = (width - w) / 2.0;
+    let y = (height - h) / 2.0;
+
+    let paint = PixmapPaint {
+        quality: FilterQuality::Bicubic,
+        ..PixmapPaint::default()
+    };
+    let transform = Transform::from_row(
+        w * scale / iw,
+        0.0,
+        0.0,
+        h * scale / ih,
+        x * scale,
+        (height - y - h) * scale,
+    );
+    pixmap.draw_pixmap(0, 0, image.as_ref(), &paint, transform, None);
+    Ok(())
+}
+
+/// Border, green checkmark and text lines (see build_signature_box_content)
+fn draw_signature_box(
+    pixmap: &mut Pixmap,
+    base: Transform,
+    params: &PdfSigner,
+    width: f32,
+    height: f32,
+) {
+    let font_size = params.sig_text_size.unwrap_or(10) as f32;
+    let line_height = font_size * 1.3;
+    let color = rgb_color(params.sig_color_rgb.as_deref().unwrap_or("#dc2626"));
+    let padding = 4.0;
+    let checkmark_size = font_size * 0.9;
+    let checkmark_gap = 3.0;
+    let y_start = height - padding - font_size;
+    let has_image = params.image_base64.is_some() || params.seal_image_url.is_some();
+    let text_left = signature_text_left(params, has_image, width as f64, padding as f64) as f32;
+
+    // Colored border (1pt width)
+    if let Some(rect) = Rect::from_xywh(0.5, 0.5, width - 1.0, height - 1.0) {
+        let path = PathBuilder::from_rect(rect);
+        let stroke = Stroke {
+            width: 1.0,
+            ..Stroke::default()
+        };
+        pixmap.stroke_path(&path, &solid(color), &stroke, base, None);
+    }
+
+    // Green checkmark circle after "Signature Valid"
+    let checkmark_x = text_left + font_size * 7.0 + checkmark_gap;
+    let checkmark_y = y_start + font_size * 0.3;
+    let cr = checkmark_size / 2.0;
+    if let Some(circle) = PathBuilder::from_circle(checkmark_x + cr, checkmark_y + cr, cr) {
+        let green = Color::from_rgba8(56, 204, 92, 255);
+        pixmap.fill_path(&circle, &solid(green), FillRule::Winding, base, None);
+    }
+
+    let s = checkmark_size / 10.0;
+    let mut check = PathBuilder::new();
+    check.move_to(checkmark_x + 2.5 * s, checkmark_y + 5.0 * s);
+    check.line_to(checkmark_x + 4.5 * s, checkmark_y + 3.0 * s);
+    check.line_to(checkmark_x + 7.5 * s, checkmark_y + 7.0 * s);
+    if let Some(check) = check.finish() {
+        let stroke = Stroke {
+            width: checkmark_size * 0.15,
+            line_cap: LineCap::Round,
+            line_join: LineJoin::Round,
+            ..Stroke::default()
+        };
+        pixmap.stroke_path(&check, &solid(Color::WHITE), &stroke, base, None);
+    }
+
+    let (Ok(regular), Ok(bold)) = (
+        Face::parse(BE_VIETNAM_PRO_REGULAR, 0),
+        Face::parse(BE_VIETNAM_PRO_SEMIBOLD, 0),
+    ) else {
+        return;
+    };
+
+    for (i, line) in signature_box_lines(params).iter().enumerate() {
+        let y = y_start - line_height * i as f32;
+        let origin = base.pre_translate(text_left, y);
+        // Signer line: regular prefix + SemiBold name
+        match line.strip_prefix(SIGNER_PREFIX) {
+            Some(name) if i == 1 && params.signature_template.is_none() => {
+                let advance = draw_text(pixmap, &regular, SIGNER_PREFIX, origin, font_size, color);
+                let origin = origin.pre_translate(advance, 0.0);
+                draw_text(pixmap, &bold, name, origin, font_size, color);
+            }
+            _ => {
+                draw_text(pixmap, &regular, line, origin, font_size, color);
+            }
+        }
+    }
+}
+
+/// Filled circle with centered, rotated white text (see build_stamp_content)
+fn draw_stamp(pixmap: &mut Pixmap, base: Transform, stamp: &StampMode, width: f32, height: f32) {
+    let font_size = stamp.font_size as f32;
+    let cx = width / 2.0;
+    let cy = height / 2.0;
+    let cr = (width.min(height) / 2.0 - 1.0).max(0.0);
+
+    if let Some(circle) = PathBuilder::from_circle(cx, cy, cr) {
+        let color = rgb_color(&stamp.color_rgb);
+        pixmap.fill_path(&circle, &solid(color), FillRule::Winding, base, None);
+    }
+
+    let Ok(bold) = Face::parse(BE_VIETNAM_PRO_SEMIBOLD, 0) else {
+        return;
+    };
+    let text_width = text_width_bold(&stamp.text, font_size as f64) as f32;
+    let origin = base
+        .pre_translate(cx, cy)
+        .pre_concat(Transform::from_rotate(stamp.rotation_degrees as f32))
+        .pre_translate(-text_width / 2.0, -font_size * 0.35);
+    draw_text(pixmap, &bold, &stamp.text, origin, font_size, Color::WHITE);
+}
+
+/// Fill glyph outlines along the baseline at `origin`, returning the advance in points
+fn draw_text(
+    pixmap: &mut Pixmap,
+    face: &Face,
+    text: &str,
+    origin: Transform,
+    font_size: f32,
+    color: Color,
+) -> f32 {
+    let units = font_size / face.units_per_em() as f32;
+    let paint = solid(color);
+    let mut pen = 0.0;
+
+    for ch in text.chars() {
+        let Some(glyph) = face.glyph_index(ch) else {
+            continue;
+        };
+        let mut outline = GlyphOutline(PathBuilder::new());
+        if face.outline_glyph(glyph, &mut outline).is_some() {
+            if let Some(path) = outline.0.finish() {
+                let transform = origin.pre_translate(pen, 0.0).pre_scale(units, units);
+                pixmap.fill_path(&path, &paint, FillRule::Winding, transform, None);
+            }
+        }
+        pen += face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * units;
+    }
+
+    pen
+}
+
+/// Collects a glyph outline (font units, y up) into a tiny-skia path
+struct GlyphOutline(PathBuilder);
+
+impl ttf_parser::OutlineBuilder for GlyphOutline {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.0.move_to(x, y);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.0.line_to(x, y);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        self.0.quad_to(x1, y1, x, y);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        self.0.cubic_to(x1, y1, x2, y2, x, y);
+    }
+
+    fn close(&mut self) {
+        self.0.close();
+    }
+}
+
+fn rgb_color(hex: &str) -> Color {
+    let (r, g, b) = parse_color_rgb(hex);
+    Color::from_rgba(r as f32, g as f32, b as f32, 1.0).unwrap_or(Color::BLACK)
+}
+
+fn solid(color: Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(color);
+    paint.anti_alias = true;
+    paint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pkcs11::helpers::certificate_info_from_der;
+    use crate::test_utils::test_identity;
+
+    fn cert_info() -> CertificateInfo {
+        certificate_info_from_der(&test_identity().cert_der).unwrap()
+    }
+
+    fn signer_box(width: f64, height: f64) -> PdfSigner {
+        PdfSigner {
+            llx: 50.0,
+            lly: 50.0,
+            urx: 50.0 + width,
+            ury: 50.0 + height,
+            ..PdfSigner::default()
+        }
+    }
+
+    fn decode(png: &[u8]) -> Pixmap {
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+        Pixmap::decode_png(png).unwrap()
+    }
+
+    // ============ Preview Rendering Tests ============
+
+    #[test]
+    fn test_preview_dimensions_at_300_dpi() {
+        let png = render_signature_preview(&signer_box(144.0, 72.0), &cert_info()).unwrap();
+        let pixmap = decode(&png);
+        assert_eq!(pixmap.width(), 600);
+        assert_eq!(pixmap.height(), 300);
+    }
+
+    #[test]
+    fn test_preview_draws_colored_border_and_white_background() {
+        let png = render_signature_preview(&signer_box(144.0, 72.0), &cert_info()).unwrap();
+        let pixmap = decode(&png);
+
+        // Left border at mid height is the default red (#dc2626)
+        let border = pixmap.pixel(1, 150).unwrap();
+        assert_eq!(
+            (border.red(), border.green(), border.blue()),
+            (0xdc, 0x26, 0x26)
+        );
+
+        // Bottom-right interior stays white
+        let inside = pixmap.pixel(590, 290).unwrap();
+        assert_eq!(
+            (inside.red(), inside.green(), inside.blue()),
+            (255, 255, 255)
+        );
+    }
+
+    #[test]
+    fn test_preview_renders_signer_text() {
+        let png = render_signature_preview(&signer_box(200.0, 60.0), &cert_info()).unwrap();
+        let pixmap = decode(&png);
+
+        // Some non-white, non-border pixels in the text area
+        let colored = (20..pixmap.height() - 20)
+            .flat_map(|y| (20..pixmap.width() / 4).map(move |x| (x, y)))
+            .filter(|&(x, y)| pixmap.pixel(x, y).unwrap().green() < 128)
+            .count();
+        assert!(colored > 100);
+    }
+
+    #[test]
+    fn test_preview_rejects_empty_box() {
+        let result = render_signature_preview(&signer_box(0.0, 50.0), &cert_info());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_preview_stamp_mode_fills_circle() {
+        let params = PdfSigner {
+            stamp_mode: Some(StampMode {
+                text: "ĐÃ KÝ".to_string(),
+                color_rgb: "#0000ff".to_string(),
+                rotation_degrees: 15.0,
+                font_size: 12,
+            }),
+            ..signer_box(72.0, 72.0)
+        };
+        let pixmap = decode(&render_signature_preview(&params, &cert_info()).unwrap());
+
+        // Inside the circle near its edge is blue, corners are transparent
+        let edge = pixmap.pixel(150, 20).unwrap();
+        assert_eq!((edge.red(), edge.blue()), (0, 255));
+        assert_eq!(pixmap.pixel(0, 0).unwrap().alpha(), 0);
+    }
+}
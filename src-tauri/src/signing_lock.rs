@@ -0,0 +1,105 @@
+//! Signing Lock Module
+//!
+//! Serializes PDF signing so two frontend windows cannot drive the same
+//! PKCS#11 session at once.
+
+use crate::error::{ESignError, SigningErrorCode};
+use std::sync::{Mutex, TryLockError};
+
+/// RAII guard marking a signing operation as in progress
+/// The flag is cleared on drop, including when the signing code panics
+pub struct SigningLockGuard<'a> {
+    flag: &'a Mutex<bool>,
+}
+
+impl<'a> SigningLockGuard<'a> {
+    /// Mark signing as in progress, or fail if another operation holds it
+    pub fn acquire(flag: &'a Mutex<bool>) -> Result<Self, ESignError> {
+        let mut in_progress = match flag.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(poisoned)) => poisoned.into_inner(),
+            Err(TryLockError::WouldBlock) => return Err(busy_error()),
+        };
+        if *in_progress {
+            return Err(busy_error());
+        }
+        *in_progress = true;
+        Ok(Self { flag })
+    }
+}
+
+impl Drop for SigningLockGuard<'_> {
+    fn drop(&mut self) {
+        *self.flag.lock().unwrap_or_else(|e| e.into_inner()) = false;
+    }
+}
+
+fn busy_error() -> ESignError {
+    ESignError::Signing {
+        code: SigningErrorCode::UserCancelled,
+        message: "Another signing operation is in progress. Please wait.".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    // ============ SigningLockGuard Tests ============
+
+    #[test]
+    fn test_concurrent_signing_only_one_succeeds() {
+        let flag = Mutex::new(false);
+        let start = Barrier::new(2);
+        let attempted = Barrier::new(2);
+
+        let results: Vec<Result<(), ESignError>> = std::thread::scope(|s| {
+            let handles: Vec<_> = (0..2)
+                .map(|_| {
+                    s.spawn(|| {
+                        start.wait();
+                        let guard = SigningLockGuard::acquire(&flag);
+                        // Hold the lock until both calls have tried to take it
+                        attempted.wait();
+                        guard.map(|_| ())
+                    })
+                })
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        });
+
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        let busy = results.into_iter().find_map(|r| r.err()).unwrap();
+        match busy {
+            ESignError::Signing { code, message } => {
+                assert_eq!(code, SigningErrorCode::UserCancelled);
+                assert!(message.contains("Another signing operation"));
+            }
+            other => panic!("Expected busy signing error, got {:?}", other),
+        }
+        assert!(!*flag.lock().unwrap());
+    }
+
+    #[test]
+    fn test_signing_lock_released_on_drop() {
+        let flag = Mutex::new(false);
+        {
+            let _guard = SigningLockGuard::acquire(&flag).unwrap();
+            assert!(*flag.lock().unwrap());
+            assert!(SigningLockGuard::acquire(&flag).is_err());
+        }
+        assert!(SigningLockGuard::acquire(&flag).is_ok());
+    }
+
+    #[test]
+    fn test_signing_lock_released_on_panic() {
+        let flag = Mutex::new(false);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _guard = SigningLockGuard::acquire(&flag).unwrap();
+            panic!("signing failed");
+        }));
+        assert!(result.is_err());
+        assert!(SigningLockGuard::acquire(&flag).is_ok());
+    }
+}
@@ -0,0 +1,175 @@
+//! Slot Registry Module
+//!
+//! Per-slot storage for token managers (and their signing flags) so several
+//! inserted tokens can be used at once. Each entry has its own lock and the
+//! map lock is only held for lookups, so a long signature on one slot never
+//! blocks another.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Map of slot id to an individually locked entry
+pub struct SlotRegistry<T> {
+    slots: Mutex<HashMap<u64, Arc<Mutex<T>>>>,
+}
+
+impl<T> Default for SlotRegistry<T> {
+    fn default() -> Self {
+        Self {
+            slots: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T> SlotRegistry<T> {
+    /// Create empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Entries are only inserted/removed whole, so a poisoned map is still consistent
+    fn slots(&self) -> MutexGuard<'_, HashMap<u64, Arc<Mutex<T>>>> {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Entry for `slot_id`; the map lock is released on return
+    pub fn get(&self, slot_id: u64) -> Option<Arc<Mutex<T>>> {
+        self.slots().get(&slot_id).cloned()
+    }
+
+    /// Register `value` for `slot_id`, returning the entry it replaces
+    pub fn insert(&self, slot_id: u64, value: T) -> Option<Arc<Mutex<T>>> {
+        self.slots().insert(slot_id, Arc::new(Mutex::new(value)))
+    }
+
+    /// Unregister `slot_id`
+    pub fn remove(&self, slot_id: u64) -> Option<Arc<Mutex<T>>> {
+        self.slots().remove(&slot_id)
+    }
+
+    /// Snapshot of all entries, ordered by slot id
+    pub fn entries(&self) -> Vec<(u64, Arc<Mutex<T>>)> {
+        let mut entries: Vec<_> = self
+            .slots()
+            .iter()
+            .map(|(slot_id, entry)| (*slot_id, Arc::clone(entry)))
+            .collect();
+        entries.sort_by_key(|(slot_id, _)| *slot_id);
+        entries
+    }
+
+    /// Run `f` with the entry for `slot_id` locked
+    /// Only this slot is locked while `f` runs
+    pub fn with_slot<R>(&self, slot_id: u64, f: impl FnOnce(&T) -> R) -> Result<R, String> {
+        let entry = self
+            .get(slot_id)
+            .ok_or_else(|| format!("Token manager for slot {} not initialized", slot_id))?;
+        let guard = entry.lock().map_err(|_| "Token manager mutex poisoned")?;
+        Ok(f(&guard))
+    }
+}
+
+impl<T: Default> SlotRegistry<T> {
+    /// Entry for `slot_id`, created with the default value if missing
+    pub fn get_or_default(&self, slot_id: u64) -> Arc<Mutex<T>> {
+        Arc::clone(self.slots().entry(slot_id).or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{mpsc, Barrier};
+    use std::time::Duration;
+
+    // ============ Slot Registry Tests ============
+
+    #[test]
+    fn test_insert_replaces_and_remove() {
+        let registry = SlotRegistry::new();
+        assert!(registry.insert(1, "first").is_none());
+        let replaced = registry.insert(1, "second").unwrap();
+        assert_eq!(*replaced.lock().unwrap(), "first");
+        assert_eq!(registry.with_slot(1, |v| *v).unwrap(), "second");
+
+        assert!(registry.remove(1).is_some());
+        assert!(registry.get(1).is_none());
+    }
+
+    #[test]
+    fn test_with_slot_missing_slot() {
+        let registry: SlotRegistry<u32> = SlotRegistry::new();
+        let err = registry.with_slot(7, |_| ()).unwrap_err();
+        assert!(err.contains("slot 7"));
+    }
+
+    #[test]
+    fn test_entries_sorted_by_slot() {
+        let registry = SlotRegistry::new();
+        for slot_id in [5, 1, 3] {
+            registry.insert(slot_id, slot_id * 10);
+        }
+        let slots: Vec<u64> = registry.entries().iter().map(|(id, _)| *id).collect();
+        assert_eq!(slots, vec![1, 3, 5]);
+    }
+
+    #[test]
+    fn test_get_or_default_shares_entry() {
+        let registry: SlotRegistry<bool> = SlotRegistry::new();
+        *registry.get_or_default(2).lock().unwrap() = true;
+        assert!(*registry.get_or_default(2).lock().unwrap());
+        assert!(!*registry.get_or_default(3).lock().unwrap());
+    }
+
+    // ============ Concurrent Slot Tests ============
+
+    #[test]
+    fn test_concurrent_slots_do_not_deadlock() {
+        // Stand-in for sign_pdf_with_slot: both slot operations must be inside
+        // their critical section at the same time for the barrier to release
+        let registry = Arc::new(SlotRegistry::new());
+        registry.insert(1, ());
+        registry.insert(2, ());
+        let barrier = Arc::new(Barrier::new(2));
+        let (tx, rx) = mpsc::channel();
+
+        for slot_id in [1, 2] {
+            let (registry, barrier, tx) = (registry.clone(), barrier.clone(), tx.clone());
+            std::thread::spawn(move || {
+                let result = registry.with_slot(slot_id, |_| {
+                    barrier.wait();
+                    // Other registry calls still work while a slot is held
+                    registry.entries().len()
+                });
+                let _ = tx.send(result);
+            });
+        }
+
+        for _ in 0..2 {
+            let result = rx
+                .recv_timeout(Duration::from_secs(5))
+                .expect("slot operations dead-locked");
+            assert_eq!(result, Ok(2));
+        }
+    }
+
+    #[test]
+    fn test_same_slot_is_serialized() {
+        let registry = Arc::new(SlotRegistry::new());
+        registry.insert(1, ());
+        let entry = registry.get(1).unwrap();
+        let held = entry.lock().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let worker = {
+            let registry = registry.clone();
+            std::thread::spawn(move || {
+                let _ = tx.send(registry.with_slot(1, |_| ()));
+            })
+        };
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(held);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), Ok(()));
+        worker.join().unwrap();
+    }
+}
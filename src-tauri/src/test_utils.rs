@@ -0,0 +1,476 @@
+//! Shared test fixtures
+//!
+//! Builds synthetic PDFs and a self-signed RSA test certificate so signing
+//! flows can be exercised without a USB token.
+
+use crate::error::ESignError;
+use lopdf::{dictionary, Document, Object, Stream};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::EncodePublicKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::RsaPrivateKey;
+use sha2::Sha256;
+use std::sync::OnceLock;
+
+/// Test signing identity (private key + self-signed certificate)
+pub struct TestIdentity {
+    pub key: RsaPrivateKey,
+    pub cert_der: Vec<u8>,
+}
+
+/// Shared test identity, generated once per test run (key generation is slow)
+pub fn test_identity() -> &'static TestIdentity {
+    static IDENTITY: OnceLock<TestIdentity> = OnceLock::new();
+    IDENTITY.get_or_init(|| {
+        let key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024)
+            .expect("Failed to generate test RSA key");
+        let cert_der = build_certificate(&key, "Test Signer", "250101000000Z", "491231235959Z");
+        TestIdentity { key, cert_der }
+    })
+}
+
+/// Sign data like the token's CKM_SHA256_RSA_PKCS mechanism (hashes internally)
+pub fn sign_with_test_key(data: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let signing_key = SigningKey::<Sha256>::new(test_identity().key.clone());
+    Ok(signing_key.sign(data).to_vec())
+}
+
+/// Build a self-signed X.509 v3 certificate (sha256WithRSAEncryption)
+/// Validity times use UTCTime format: YYMMDDHHMMSSZ
+pub fn build_certificate(
+    key: &RsaPrivateKey,
+    common_name: &str,
+    not_before: &str,
+    not_after: &str,
+) -> Vec<u8> {
+    build_certificate_with_extensions(key, common_name, not_before, not_after, &[])
+}
+
+/// Build a self-signed certificate with DER-encoded Extension entries
+pub fn build_certificate_with_extensions(
+    key: &RsaPrivateKey,
+    common_name: &str,
+    not_before: &str,
+    not_after: &str,
+    extensions: &[Vec<u8>],
+) -> Vec<u8> {
+    let spki = key
+        .to_public_key()
+        .to_public_key_der()
+        .expect("Failed to encode public key")
+        .as_bytes()
+        .to_vec();
+    build_certificate_with_spki(key, spki, common_name, not_before, not_after, extensions)
+}
+
+/// Build a certificate for a P-256 EC public point (uncompressed), signed by `key`
+pub fn build_ec_certificate(
+    key: &RsaPrivateKey,
+    common_name: &str,
+    public_point: &[u8],
+) -> Vec<u8> {
+    let ec_public_key_oid = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x02, 0x01];
+    let prime256v1_oid = [0x2A, 0x86, 0x48, 0xCE, 0x3D, 0x03, 0x01, 0x07];
+    let mut point = vec![0x00]; // no unused bits
+    point.extend(public_point);
+    let spki = tlv(
+        0x30,
+        &[
+            tlv(
+                0x30,
+                &[tlv(0x06, &ec_public_key_oid), tlv(0x06, &prime256v1_oid)].concat(),
+            ),
+            tlv(0x03, &point),
+        ]
+        .concat(),
+    );
+    build_certificate_with_spki(
+        key,
+        spki,
+        common_name,
+        "250101000000Z",
+        "491231235959Z",
+        &[],
+    )
+}
+
+fn build_certificate_with_spki(
+    key: &RsaPrivateKey,
+    spki: Vec<u8>,
+    common_name: &str,
+    not_before: &str,
+    not_after: &str,
+    extensions: &[Vec<u8>],
+) -> Vec<u8> {
+    let rsa_sha256_oid = [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B];
+    let sig_alg = tlv(
+        0x30,
+        &[tlv(0x06, &rsa_sha256_oid), vec![0x05, 0x00]].concat(),
+    );
+
+    let validity = tlv(
+        0x30,
+        &[
+            tlv(0x17, not_before.as_bytes()),
+            tlv(0x17, not_after.as_bytes()),
+        ]
+        .concat(),
+    );
+
+    let mut tbs_fields = vec![
+        tlv(0xA0, &tlv(0x02, &[0x02])), // version v3
+        tlv(0x02, &[0x01, 0x23, 0x45]), // serial
+        sig_alg.clone(),
+        build_name(common_name),
+        validity,
+        build_name(common_name),
+        spki,
+    ];
+    if !extensions.is_empty() {
+        tbs_fields.push(tlv(0xA3, &tlv(0x30, &extensions.concat())));
+    }
+    let tbs = tlv(0x30, &tbs_fields.concat());
+
+    let signature = SigningKey::<Sha256>::new(key.clone()).sign(&tbs).to_vec();
+    let mut bit_string = vec![0x00]; // no unused bits
+    bit_string.extend(signature);
+
+    tlv(0x30, &[tbs, sig_alg, tlv(0x03, &bit_string)].concat())
+}
+
+/// Build a CertificatePolicies extension (2.5.29.32)
+/// Each policy is (encoded OID bytes, optional CPS URI)
+pub fn certificate_policies_extension(policies: &[(&[u8], Option<&str>)]) -> Vec<u8> {
+    let cps_oid = [0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x02, 0x01];
+    let policy_infos: Vec<u8> = policies
+        .iter()
+        .flat_map(|(oid, cps_uri)| {
+            let mut info = tlv(0x06, oid);
+            if let Some(uri) = cps_uri {
+                let qualifier = tlv(
+                    0x30,
+                    &[tlv(0x06, &cps_oid), tlv(0x16, uri.as_bytes())].concat(),
+                );
+                info.extend(tlv(0x30, &qualifier));
+            }
+            tlv(0x30, &info)
+        })
+        .collect();
+
+    tlv(
+        0x30,
+        &[
+            tlv(0x06, &[0x55, 0x1D, 0x20]),
+            tlv(0x04, &tlv(0x30, &policy_infos)),
+        ]
+        .concat(),
+    )
+}
+
+/// Build an AuthorityInfoAccess extension (1.3.6.1.5.5.7.1.1) with one OCSP URI
+pub fn authority_info_access_extension(ocsp_url: &str) -> Vec<u8> {
+    authority_info_access_extension_with_issuers(ocsp_url, None)
+}
+
+/// Build an AuthorityInfoAccess extension with an OCSP URI and optional caIssuers URI
+pub fn authority_info_access_extension_with_issuers(
+    ocsp_url: &str,
+    ca_issuers_url: Option<&str>,
+) -> Vec<u8> {
+    let ocsp_oid = [0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+    let ca_issuers_oid = [0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x02];
+    let access_description =
+        |oid: &[u8], url: &str| tlv(0x30, &[tlv(0x06, oid), tlv(0x86, url.as_bytes())].concat());
+    let mut access_descriptions = access_description(&ocsp_oid, ocsp_url);
+    if let Some(url) = ca_issuers_url {
+        access_descriptions.extend(access_description(&ca_issuers_oid, url));
+    }
+
+    tlv(
+        0x30,
+        &[
+            tlv(0x06, &[0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01]),
+            tlv(0x04, &tlv(0x30, &access_descriptions)),
+        ]
+        .concat(),
+    )
+}
+
+/// Build a successful OCSPResponse (id-pkix-ocsp-basic) for one serial
+/// `cert_status` is the certStatus TLV; the responder signature is a dummy
+pub fn ocsp_response(serial: &[u8], cert_status: Vec<u8>, next_update: bool) -> Vec<u8> {
+    let cert_id = tlv(
+        0x30,
+        &[
+            tlv(
+                0x30,
+                &[tlv(0x06, &[0x2B, 0x0E, 0x03, 0x02, 0x1A]), vec![0x05, 0x00]].concat(),
+            ),
+            tlv(0x04, &[0x11; 20]),
+            tlv(0x04, &[0x22; 20]),
+            tlv(0x02, serial),
+        ]
+        .concat(),
+    );
+    let mut single = [cert_id, cert_status, tlv(0x18, b"20250301000000Z")].concat();
+    if next_update {
+        single.extend(tlv(0xA0, &tlv(0x18, b"20250308000000Z")));
+    }
+    let response_data = tlv(
+        0x30,
+        &[
+            tlv(0xA2, &tlv(0x04, &[0x33; 20])), // responderID byKey
+            tlv(0x18, b"20250301120000Z"),
+            tlv(0x30, &tlv(0x30, &single)),
+        ]
+        .concat(),
+    );
+    let sig_alg = tlv(0x30, &[tlv(0x06, &[0x2A, 0x03]), vec![0x05, 0x00]].concat());
+    let basic = tlv(
+        0x30,
+        &[response_data, sig_alg, tlv(0x03, &[0x00, 0xAB])].concat(),
+    );
+    let response_bytes = tlv(
+        0x30,
+        &[
+            tlv(
+                0x06,
+                &[0x2B, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01, 0x01],
+            ),
+            tlv(0x04, &basic),
+        ]
+        .concat(),
+    );
+    tlv(
+        0x30,
+        &[tlv(0x0A, &[0x00]), tlv(0xA0, &response_bytes)].concat(),
+    )
+}
+
+/// Build a TimeStampToken whose TSTInfo has a SHA-256 `hashed_message` imprint
+/// and the given nonce INTEGER content (None omits it)
+/// `with_accuracy` adds accuracy and ordering fields before the nonce
+pub fn timestamp_token(
+    hashed_message: &[u8],
+    nonce: Option<&[u8]>,
+    with_accuracy: bool,
+) -> Vec<u8> {
+    let mut tst_info = [
+        tlv(0x02, &[0x01]),                                               // version
+        tlv(0x06, &[0x2A, 0x03, 0x04]),                                   // policy
+        tlv(0x30, &[tlv(0x30, &[]), tlv(0x04, hashed_message)].concat()), // messageImprint
+        tlv(0x02, &[0x10, 0x20]),                                         // serialNumber
+        tlv(0x18, b"20250301120000Z"),                                    // genTime
+    ]
+    .concat();
+    if with_accuracy {
+        tst_info.extend(tlv(0x30, &tlv(0x02, &[0x01])));
+        tst_info.extend(tlv(0x01, &[0x00]));
+    }
+    if let Some(nonce) = nonce {
+        tst_info.extend(tlv(0x02, nonce));
+    }
+    let tst_info_oid = [
+        0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x09, 0x10, 0x01, 0x04,
+    ];
+    let encap_content_info = tlv(
+        0x30,
+        &[
+            tlv(0x06, &tst_info_oid),
+            tlv(0xA0, &tlv(0x04, &tlv(0x30, &tst_info))),
+        ]
+        .concat(),
+    );
+    let signed_data = tlv(
+        0x30,
+        &[
+            tlv(0x02, &[0x03]),
+            tlv(0x31, &[]),
+            encap_content_info,
+            tlv(0x31, &[]),
+        ]
+        .concat(),
+    );
+    let signed_data_oid = [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02];
+    tlv(
+        0x30,
+        &[tlv(0x06, &signed_data_oid), tlv(0xA0, &signed_data)].concat(),
+    )
+}
+
+/// Build a CRLDistributionPoints extension (2.5.29.31) with one fullName URI
+pub fn crl_distribution_points_extension(crl_url: &str) -> Vec<u8> {
+    let full_name = tlv(0xA0, &tlv(0x86, crl_url.as_bytes()));
+    let distribution_point = tlv(0x30, &tlv(0xA0, &full_name));
+    tlv(
+        0x30,
+        &[
+            tlv(0x06, &[0x55, 0x1D, 0x1F]),
+            tlv(0x04, &tlv(0x30, &distribution_point)),
+        ]
+        .concat(),
+    )
+}
+
+/// Build a v2 CRL issued by `issuer_cn`; `revoked` is (serial, optional reasonCode)
+/// The signature is a dummy; times use UTCTime format: YYMMDDHHMMSSZ
+pub fn certificate_list(
+    issuer_cn: &str,
+    revoked: &[(&[u8], Option<u8>)],
+    next_update: Option<&str>,
+) -> Vec<u8> {
+    let rsa_sha256_oid = [0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x01, 0x0B];
+    let sig_alg = tlv(
+        0x30,
+        &[tlv(0x06, &rsa_sha256_oid), vec![0x05, 0x00]].concat(),
+    );
+
+    let mut tbs_fields = vec![
+        tlv(0x02, &[0x01]), // version v2
+        sig_alg.clone(),
+        build_name(issuer_cn),
+        tlv(0x17, b"250101000000Z"),
+    ];
+    if let Some(next_update) = next_update {
+        tbs_fields.push(tlv(0x17, next_update.as_bytes()));
+    }
+    if !revoked.is_empty() {
+        let entries: Vec<u8> = revoked
+            .iter()
+            .flat_map(|(serial, reason)| {
+                let mut entry = [tlv(0x02, serial), tlv(0x17, b"250301000000Z")].concat();
+                if let Some(reason) = reason {
+                    let reason_ext = tlv(
+                        0x30,
+                        &[
+                            tlv(0x06, &[0x55, 0x1D, 0x15]),
+                            tlv(0x04, &tlv(0x0A, &[*reason])),
+                        ]
+                        .concat(),
+                    );
+                    entry.extend(tlv(0x30, &reason_ext));
+                }
+                tlv(0x30, &entry)
+            })
+            .collect();
+        tbs_fields.push(tlv(0x30, &entries));
+    }
+    let tbs = tlv(0x30, &tbs_fields.concat());
+
+    tlv(0x30, &[tbs, sig_alg, tlv(0x03, &[0x00, 0x00])].concat())
+}
+
+/// Build a single-RDN Name containing only CN (UTF8String)
+fn build_name(common_name: &str) -> Vec<u8> {
+    let cn_oid = [0x55, 0x04, 0x03];
+    let atv = tlv(
+        0x30,
+        &[tlv(0x06, &cn_oid), tlv(0x0C, common_name.as_bytes())].concat(),
+    );
+    tlv(0x30, &tlv(0x31, &atv))
+}
+
+/// Encode a DER TLV (definite length, up to 65535 bytes)
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let len = content.len();
+    let mut out = vec![tag];
+    if len < 128 {
+        out.push(len as u8);
+    } else if len < 256 {
+        out.extend([0x81, len as u8]);
+    } else {
+        out.extend([0x82, (len >> 8) as u8, (len & 0xFF) as u8]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Build an A4 PDF with the given number of pages, each showing "Page N"
+pub fn sample_pdf(page_count: usize) -> Vec<u8> {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let resources_id = doc.add_object(dictionary! {
+        "Font" => dictionary! {
+            "F1" => font_id,
+        },
+    });
+
+    let mut kids: Vec<Object> = Vec::new();
+    for i in 0..page_count {
+        let content = format!("BT /F1 24 Tf 100 700 Td (Page {}) Tj ET", i + 1);
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.into_bytes()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => resources_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+        });
+        kids.push(page_id.into());
+    }
+
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count as i64,
+        }),
+    );
+
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output).expect("Failed to save sample PDF");
+    output
+}
+
+/// Build an 8-bit RGB (or RGBA) PNG filled with a solid red color
+pub fn sample_png(width: u32, height: u32, with_alpha: bool) -> Vec<u8> {
+    use flate2::write::ZlibEncoder;
+    use flate2::{Compression, Crc};
+    use std::io::Write;
+
+    let pixel: &[u8] = if with_alpha {
+        &[0xDC, 0x26, 0x26, 0x80]
+    } else {
+        &[0xDC, 0x26, 0x26]
+    };
+    let mut raw = Vec::new();
+    for _ in 0..height {
+        raw.push(0); // filter: None
+        for _ in 0..width {
+            raw.extend_from_slice(pixel);
+        }
+    }
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&raw).unwrap();
+    let idat = encoder.finish().unwrap();
+
+    let mut ihdr = Vec::new();
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(height.to_be_bytes());
+    ihdr.extend([8, if with_alpha { 6 } else { 2 }, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    for (chunk_type, body) in [(b"IHDR", ihdr), (b"IDAT", idat), (b"IEND", Vec::new())] {
+        let mut crc = Crc::new();
+        crc.update(chunk_type);
+        crc.update(&body);
+        png.extend((body.len() as u32).to_be_bytes());
+        png.extend_from_slice(chunk_type);
+        png.extend(&body);
+        png.extend(crc.sum().to_be_bytes());
+    }
+    png
+}
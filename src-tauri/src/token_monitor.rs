@@ -0,0 +1,195 @@
+//! Token Monitor Module
+//!
+//! Polls PKCS#11 slots on a background thread and reports token
+//! insertion/removal so the UI can react without a manual refresh.
+
+use crate::pkcs11::TokenInfo;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Delay between two slot polls
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Slot change detected between two polls
+#[derive(Debug, Clone)]
+pub enum TokenChange {
+    Inserted(TokenInfo),
+    Removed(TokenInfo),
+}
+
+/// Handle to the polling thread; the thread exits after `stop`
+pub struct TokenMonitor {
+    stop: Arc<AtomicBool>,
+}
+
+impl TokenMonitor {
+    /// Spawn the polling thread
+    /// `poll` returns the tokens currently present, or None to skip the round
+    /// The first successful poll only records a baseline and reports nothing
+    pub fn start<P, C>(interval: Duration, mut poll: P, mut on_change: C) -> Self
+    where
+        P: FnMut() -> Option<Vec<TokenInfo>> + Send + 'static,
+        C: FnMut(TokenChange) + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&stop);
+        std::thread::spawn(move || {
+            let mut snapshot: Option<BTreeMap<u64, TokenInfo>> = None;
+            while !flag.load(Ordering::Relaxed) {
+                if let Some(tokens) = poll() {
+                    let current: BTreeMap<u64, TokenInfo> =
+                        tokens.into_iter().map(|t| (t.slot_id, t)).collect();
+                    if let Some(previous) = &snapshot {
+                        for change in diff_snapshots(previous, &current) {
+                            on_change(change);
+                        }
+                    }
+                    snapshot = Some(current);
+                }
+                std::thread::sleep(interval);
+            }
+        });
+        Self { stop }
+    }
+
+    /// Ask the polling thread to exit after its current round
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Drop for TokenMonitor {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Changes between two snapshots keyed by slot id, removals first
+/// A different serial in the same slot counts as removal plus insertion
+pub fn diff_snapshots(
+    previous: &BTreeMap<u64, TokenInfo>,
+    current: &BTreeMap<u64, TokenInfo>,
+) -> Vec<TokenChange> {
+    let same_token = |a: &TokenInfo, b: Option<&TokenInfo>| b.is_some_and(|b| a.serial == b.serial);
+    let removed = previous
+        .values()
+        .filter(|old| !same_token(old, current.get(&old.slot_id)))
+        .cloned()
+        .map(TokenChange::Removed);
+    let inserted = current
+        .values()
+        .filter(|new| !same_token(new, previous.get(&new.slot_id)))
+        .cloned()
+        .map(TokenChange::Inserted);
+    removed.chain(inserted).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::Mutex;
+
+    fn token(slot_id: u64, serial: &str) -> TokenInfo {
+        TokenInfo {
+            slot_id,
+            label: format!("Token {}", slot_id),
+            manufacturer: "VNPT".to_string(),
+            model: "SafeNet".to_string(),
+            serial: serial.to_string(),
+            has_token: true,
+            retries_remaining: None,
+        }
+    }
+
+    fn snapshot(tokens: &[TokenInfo]) -> BTreeMap<u64, TokenInfo> {
+        tokens.iter().map(|t| (t.slot_id, t.clone())).collect()
+    }
+
+    fn describe(changes: &[TokenChange]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|c| match c {
+                TokenChange::Inserted(t) => format!("+{}:{}", t.slot_id, t.serial),
+                TokenChange::Removed(t) => format!("-{}:{}", t.slot_id, t.serial),
+            })
+            .collect()
+    }
+
+    // ============ Snapshot Diff Tests ============
+
+    #[test]
+    fn test_diff_unchanged_is_empty() {
+        let tokens = snapshot(&[token(1, "A"), token(2, "B")]);
+        assert!(diff_snapshots(&tokens, &tokens).is_empty());
+    }
+
+    #[test]
+    fn test_diff_reports_insertion_and_removal() {
+        let previous = snapshot(&[token(1, "A")]);
+        let current = snapshot(&[token(2, "B")]);
+        let changes = diff_snapshots(&previous, &current);
+        assert_eq!(describe(&changes), vec!["-1:A", "+2:B"]);
+    }
+
+    #[test]
+    fn test_diff_swapped_token_in_same_slot() {
+        let previous = snapshot(&[token(1, "A")]);
+        let current = snapshot(&[token(1, "B")]);
+        let changes = diff_snapshots(&previous, &current);
+        assert_eq!(describe(&changes), vec!["-1:A", "+1:B"]);
+    }
+
+    // ============ Polling Thread Tests ============
+
+    #[test]
+    fn test_monitor_reports_changes_after_baseline() {
+        let rounds = Mutex::new(vec![
+            vec![token(2, "B")],
+            vec![token(1, "A"), token(2, "B")],
+            vec![token(1, "A")],
+        ]);
+        let (tx, rx) = mpsc::channel();
+        let monitor = TokenMonitor::start(
+            Duration::from_millis(1),
+            move || {
+                let mut rounds = rounds.lock().unwrap();
+                (!rounds.is_empty()).then(|| rounds.remove(0))
+            },
+            move |change| {
+                let _ = tx.send(change);
+            },
+        );
+
+        let changes: Vec<TokenChange> = (0..2)
+            .map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap())
+            .collect();
+        monitor.stop();
+        assert_eq!(describe(&changes), vec!["+1:A", "-2:B"]);
+    }
+
+    #[test]
+    fn test_monitor_stop_ends_thread() {
+        let (tx, rx) = mpsc::channel::<()>();
+        let monitor = TokenMonitor::start(
+            Duration::from_millis(1),
+            move || {
+                let _ = tx.send(());
+                None
+            },
+            |_| {},
+        );
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        monitor.stop();
+        // The poll closure (and its sender) is dropped once the thread exits
+        loop {
+            match rx.recv_timeout(Duration::from_secs(5)) {
+                Ok(()) => continue,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break,
+                Err(mpsc::RecvTimeoutError::Timeout) => panic!("monitor thread stalled"),
+            }
+        }
+    }
+}
@@ -4,11 +4,14 @@
 //! Supports Vietnamese TSA servers with fallback logic.
 
 use crate::error::ESignError;
+use crate::pdf::{der_children, read_tlv};
 use rand::Rng;
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
-use std::time::Duration;
+use std::collections::HashMap;
+use std::sync::{Barrier, Mutex};
+use std::time::{Duration, Instant};
 
 /// Vietnamese TSA server URLs
 /// HTTPS endpoints are preferred for security; HTTP is fallback only
@@ -32,6 +35,87 @@ pub mod servers {
     }
 }
 
+/// Vietnamese CA roots that TSA connections are pinned to
+/// DER files live in `certs/`; each is listed in `ROOTS` with its expected fingerprint
+pub mod ca_pins {
+    use crate::error::ESignError;
+    use sha2::{Digest, Sha256};
+
+    /// Embedded root certificate with its expected SHA-256 fingerprint
+    pub struct PinnedRoot {
+        pub name: &'static str,
+        pub der: &'static [u8],
+        /// Lowercase hex SHA-256 of `der`
+        pub sha256: &'static str,
+    }
+
+    /// VNPT-CA, Viettel-CA, FPT-CA and NEAC (Vietnam National Root CA) roots, added as
+    /// `PinnedRoot { name, der: include_bytes!("../certs/<file>.der"), sha256 }`
+    /// While empty, TSA connections keep using the built-in roots
+    pub const ROOTS: &[PinnedRoot] = &[];
+
+    /// Lowercase hex SHA-256 of a DER certificate
+    pub fn fingerprint(der: &[u8]) -> String {
+        hex::encode(Sha256::digest(der))
+    }
+
+    /// Parse the roots, refusing any whose DER does not match its pinned fingerprint
+    pub fn load_roots(roots: &[PinnedRoot]) -> Result<Vec<reqwest::Certificate>, ESignError> {
+        roots
+            .iter()
+            .map(|root| {
+                if fingerprint(root.der) != root.sha256 {
+                    return Err(ESignError::Tsa(format!(
+                        "Pinned root {} does not match its fingerprint",
+                        root.name
+                    )));
+                }
+                reqwest::Certificate::from_der(root.der).map_err(|e| {
+                    ESignError::Tsa(format!("Invalid pinned root {}: {}", root.name, e))
+                })
+            })
+            .collect()
+    }
+}
+
+/// Retry guidance shown when a TSA server cannot be reached
+const TSA_RETRY_SUGGESTION: &str = "The signature will be created without a trusted timestamp";
+
+/// Servers are skipped once less than this much of the time budget remains
+const MIN_TSA_REQUEST_TIME: Duration = Duration::from_millis(250);
+
+/// Request nonce length in bytes
+const NONCE_LEN: usize = 16;
+
+/// Message imprint sent by health checks; the token is discarded
+const HEALTH_CHECK_HASH: [u8; 32] = [0x5A; 32];
+
+/// Message imprint sent by latency pings
+const PING_HASH: [u8; 32] = [0; 32];
+
+fn default_max_retries() -> u32 {
+    3
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_ms() -> u64 {
+    10_000
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+fn default_max_total_time() -> Duration {
+    Duration::from_secs(60)
+}
+
+/// Default lifetime of cached timestamp tokens
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
 /// Result of a timestamp request
 #[derive(Debug, Clone)]
 pub struct TimestampResult {
@@ -43,15 +127,124 @@ pub struct TimestampResult {
     pub used_insecure_transport: bool,
 }
 
+/// Availability and round-trip time of one TSA server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TsaHealthResult {
+    pub url: String,
+    /// Server answered with PKIStatus granted or grantedWithMods
+    pub available: bool,
+    /// Request round-trip time in milliseconds
+    pub latency_ms: u64,
+    /// Set for servers reached over plain HTTP
+    pub warning: Option<String>,
+}
+
+/// Ping outcome for one TSA server, see `ping_all_servers`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TsaServerStatus {
+    pub url: String,
+    /// Round-trip time of a granted ping; None if the server failed or timed out
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub is_https: bool,
+}
+
+/// Ping the default TSA servers concurrently, fastest first
+/// HTTPS wins ties; servers that did not answer come last
+pub fn ping_all_servers(timeout_ms: u64) -> Vec<TsaServerStatus> {
+    match TsaClient::new() {
+        Ok(client) => client.ping_servers(Duration::from_millis(timeout_ms)),
+        Err(e) => {
+            eprintln!("TSA ping skipped: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// One TSA server with its own request timeout
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "TsaServerEntry")]
+pub struct TsaServerConfig {
+    pub url: String,
+    /// Request timeout in seconds
+    pub timeout_secs: u64,
+    /// False for plain HTTP endpoints (insecure transport)
+    pub is_https: bool,
+}
+
+impl TsaServerConfig {
+    pub fn new(url: &str, timeout_secs: u64) -> Self {
+        Self {
+            url: url.to_string(),
+            timeout_secs,
+            is_https: !servers::is_insecure(url),
+        }
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+/// Serialized server entry; bare URLs are the pre-`fallback_servers` format
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TsaServerEntry {
+    Url(String),
+    Server {
+        url: String,
+        #[serde(default = "default_timeout_secs")]
+        timeout_secs: u64,
+        #[serde(default)]
+        is_https: Option<bool>,
+    },
+}
+
+impl From<TsaServerEntry> for TsaServerConfig {
+    fn from(entry: TsaServerEntry) -> Self {
+        match entry {
+            TsaServerEntry::Url(url) => Self::new(&url, default_timeout_secs()),
+            TsaServerEntry::Server {
+                url,
+                timeout_secs,
+                is_https,
+            } => {
+                let mut server = Self::new(&url, timeout_secs);
+                if let Some(is_https) = is_https {
+                    server.is_https = is_https;
+                }
+                server
+            }
+        }
+    }
+}
+
 /// TSA server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TsaConfig {
     /// Primary TSA URL
     pub primary_url: String,
-    /// Fallback TSA URLs
-    pub fallback_urls: Vec<String>,
-    /// Request timeout in seconds
+    /// Fallback TSA servers; `fallback_urls` (plain URLs) is still accepted
+    #[serde(alias = "fallback_urls")]
+    pub fallback_servers: Vec<TsaServerConfig>,
+    /// Request timeout for the primary server in seconds
     pub timeout_secs: u64,
+    /// Total time allowed across all servers (default 60 seconds)
+    #[serde(default = "default_max_total_time")]
+    pub max_total_time: Duration,
+    /// Retries per server on transport errors and HTTP 5xx (default 3)
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further retry (default 500 ms)
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    /// Upper bound for a single retry delay, jitter included (default 10 s)
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// Trust the built-in roots instead of the pinned Vietnamese CA roots
+    /// (for corporate proxies that re-sign TLS traffic)
+    #[serde(default)]
+    pub skip_certificate_pinning: bool,
 }
 
 impl Default for TsaConfig {
@@ -59,15 +252,77 @@ impl Default for TsaConfig {
         Self {
             // HTTPS endpoints first, HTTP fallbacks last (security preference)
             primary_url: servers::VNPT_HTTPS.to_string(),
-            fallback_urls: vec![
-                servers::VIETTEL_HTTPS.to_string(),
-                servers::FPT_HTTPS.to_string(),
+            fallback_servers: [
+                servers::VIETTEL_HTTPS,
+                servers::FPT_HTTPS,
                 // HTTP fallbacks as last resort (will trigger warning)
-                servers::VNPT_HTTP.to_string(),
-                servers::VIETTEL_HTTP.to_string(),
-                servers::FPT_HTTP.to_string(),
-            ],
-            timeout_secs: 30,
+                servers::VNPT_HTTP,
+                servers::VIETTEL_HTTP,
+                servers::FPT_HTTP,
+            ]
+            .into_iter()
+            .map(|url| TsaServerConfig::new(url, default_timeout_secs()))
+            .collect(),
+            timeout_secs: default_timeout_secs(),
+            max_total_time: default_max_total_time(),
+            max_retries: default_max_retries(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            skip_certificate_pinning: false,
+        }
+    }
+}
+
+impl TsaConfig {
+    /// Default servers with a timeout per server, fallbacks in default order
+    /// Fallbacks beyond `fallback_timeouts` keep the default timeout
+    pub fn with_server_timeouts(primary_timeout: u64, fallback_timeouts: Vec<u64>) -> Self {
+        let mut config = Self {
+            timeout_secs: primary_timeout,
+            ..Self::default()
+        };
+        for (server, timeout_secs) in config.fallback_servers.iter_mut().zip(fallback_timeouts) {
+            server.timeout_secs = timeout_secs;
+        }
+        config
+    }
+
+    /// Primary server followed by the fallbacks, in the order they are tried
+    pub fn servers(&self) -> Vec<TsaServerConfig> {
+        std::iter::once(TsaServerConfig::new(&self.primary_url, self.timeout_secs))
+            .chain(self.fallback_servers.iter().cloned())
+            .collect()
+    }
+
+    /// Request timeout for `url`, the primary timeout for unknown servers
+    fn timeout_for(&self, url: &str) -> Duration {
+        self.servers()
+            .iter()
+            .find(|server| server.url == url)
+            .map_or(
+                Duration::from_secs(self.timeout_secs),
+                TsaServerConfig::timeout,
+            )
+    }
+
+    /// Retry each server up to `max` times before moving to the next one
+    pub fn with_retry(mut self, max: u32) -> Self {
+        self.max_retries = max;
+        self
+    }
+}
+
+/// Why one HTTP exchange with a TSA failed
+enum SendFailure {
+    /// Transport error or HTTP 5xx; worth retrying the same server
+    Retryable(ESignError),
+    Fatal(ESignError),
+}
+
+impl SendFailure {
+    fn into_error(self) -> ESignError {
+        match self {
+            SendFailure::Retryable(e) | SendFailure::Fatal(e) => e,
         }
     }
 }
@@ -85,11 +340,9 @@ impl TsaClient {
     }
 
     /// Create TSA client with custom configuration
+    /// Timeouts are set per request from the server being contacted
     pub fn with_config(config: TsaConfig) -> Result<Self, ESignError> {
-        let http_client = Client::builder()
-            .timeout(Duration::from_secs(config.timeout_secs))
-            .build()
-            .map_err(|e| ESignError::Tsa(format!("Failed to create HTTP client: {}", e)))?;
+        let http_client = tsa_http_client(config.skip_certificate_pinning)?;
 
         Ok(Self {
             config,
@@ -97,6 +350,41 @@ impl TsaClient {
         })
     }
 
+    /// Client for the default servers with the fastest responding one as primary
+    pub fn from_fastest_server(timeout_ms: u64) -> Result<Self, ESignError> {
+        Self::with_fastest_server(TsaConfig::default(), &ping_all_servers(timeout_ms))
+    }
+
+    /// Client for `config` with the first responding server of `statuses` as primary
+    /// The others stay as fallbacks in their configured order
+    fn with_fastest_server(
+        config: TsaConfig,
+        statuses: &[TsaServerStatus],
+    ) -> Result<Self, ESignError> {
+        let fastest = statuses
+            .iter()
+            .find(|status| status.latency_ms.is_some())
+            .ok_or_else(|| ESignError::Tsa("No TSA server responded to ping".to_string()))?;
+
+        let mut servers = config.servers();
+        let position = servers
+            .iter()
+            .position(|server| server.url == fastest.url)
+            .unwrap_or(0);
+        let primary = servers.remove(position);
+        Self::with_config(TsaConfig {
+            primary_url: primary.url,
+            timeout_secs: primary.timeout_secs,
+            fallback_servers: servers,
+            ..config
+        })
+    }
+
+    /// URL of the server tried first
+    pub fn primary_url(&self) -> &str {
+        &self.config.primary_url
+    }
+
     /// Get timestamp token for signature data
     /// Tries HTTPS servers first, falls back to HTTP with warning
     /// Returns TimestampResult containing DER-encoded TimeStampToken and security info
@@ -106,47 +394,165 @@ impl TsaClient {
         hasher.update(signature);
         let hash = hasher.finalize();
 
-        // Build timestamp request
-        let ts_request = self.build_timestamp_request(&hash)?;
-
         // Try primary server first, then fallbacks
-        let mut urls = vec![self.config.primary_url.clone()];
-        urls.extend(self.config.fallback_urls.clone());
-
-        let mut last_error = None;
-        for url in &urls {
-            match self.send_timestamp_request(url, &ts_request) {
-                Ok(response) => {
-                    let token = self.parse_timestamp_response(&response)?;
-                    let used_insecure = servers::is_insecure(url);
-
-                    // Log warning if using insecure HTTP
-                    if used_insecure {
-                        eprintln!(
-                            "WARNING: Timestamp obtained via insecure HTTP from {}. \
-                             HTTPS servers were unavailable.",
-                            url
-                        );
-                    }
-
-                    return Ok(TimestampResult {
-                        token,
-                        server_url: url.clone(),
-                        used_insecure_transport: used_insecure,
-                    });
-                }
-                Err(e) => {
-                    last_error = Some(e);
-                }
-            }
+        let tsa_servers = self.config.servers();
+
+        // Retries on one server share the overall budget with the fallbacks
+        let deadline = Instant::now() + self.config.max_total_time;
+        let (server, token) =
+            try_servers_within_budget(&tsa_servers, self.config.max_total_time, |url, timeout| {
+                self.send_timestamp_request_verified(url, &hash, timeout, deadline)
+            })?;
+
+        let url = server.url.as_str();
+        let used_insecure = !server.is_https;
+
+        // Log warning if using insecure HTTP
+        if used_insecure {
+            eprintln!(
+                "WARNING: Timestamp obtained via insecure HTTP from {}. \
+                 HTTPS servers were unavailable.",
+                url
+            );
+        }
+
+        Ok(TimestampResult {
+            token,
+            server_url: url.to_string(),
+            used_insecure_transport: used_insecure,
+        })
+    }
+
+    /// Send a timestamp request for a dummy hash and report whether it was granted
+    /// Only the PKIStatus of the response is parsed
+    pub fn health_check(&self, url: &str) -> TsaHealthResult {
+        let started = Instant::now();
+        let status = self
+            .build_timestamp_request(&HEALTH_CHECK_HASH)
+            .and_then(|request| {
+                self.send_timestamp_request(url, &request, self.config.timeout_for(url))
+            })
+            .and_then(|response| parse_pki_status(&response));
+        let latency_ms = started.elapsed().as_millis() as u64;
+
+        if let Err(ref e) = status {
+            eprintln!("TSA health check failed for {}: {}", url, e);
         }
 
-        Err(last_error.unwrap_or_else(|| ESignError::Tsa("No TSA servers available".to_string())))
+        TsaHealthResult {
+            url: url.to_string(),
+            available: matches!(status, Ok(0 | 1)),
+            latency_ms,
+            warning: servers::is_insecure(url).then(|| "Insecure transport".to_string()),
+        }
     }
 
-    /// Build RFC 3161 TimeStampReq
-    /// ASN.1 structure for timestamp request
+    /// Check the primary and fallback servers in parallel
+    /// Available servers come first, each group ordered by latency
+    pub fn check_all_tsa_servers(&self) -> Vec<TsaHealthResult> {
+        let tsa_servers = self.config.servers();
+        let mut results: Vec<TsaHealthResult> = std::thread::scope(|scope| {
+            let handles: Vec<_> = tsa_servers
+                .iter()
+                .map(|server| scope.spawn(move || self.health_check(&server.url)))
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+        results.sort_by_key(|result| (!result.available, result.latency_ms));
+        results
+    }
+
+    /// Time a minimal timestamp request to every server, all started together
+    /// Sorted by latency (HTTPS first on ties); failed servers come last
+    pub fn ping_servers(&self, timeout: Duration) -> Vec<TsaServerStatus> {
+        let tsa_servers = self.config.servers();
+        let start = Barrier::new(tsa_servers.len());
+        let mut results: Vec<TsaServerStatus> = std::thread::scope(|scope| {
+            let handles: Vec<_> = tsa_servers
+                .iter()
+                .map(|server| {
+                    let start = &start;
+                    scope.spawn(move || {
+                        start.wait();
+                        self.ping(server, timeout)
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .filter_map(|handle| handle.join().ok())
+                .collect()
+        });
+        results.sort_by_key(|status| {
+            (
+                status.latency_ms.is_none(),
+                status.latency_ms,
+                !status.is_https,
+            )
+        });
+        results
+    }
+
+    fn ping(&self, server: &TsaServerConfig, timeout: Duration) -> TsaServerStatus {
+        let started = Instant::now();
+        let result = self
+            .build_timestamp_request(&PING_HASH)
+            .and_then(|request| self.send_timestamp_request(&server.url, &request, timeout))
+            .and_then(|response| parse_pki_status(&response))
+            .and_then(|status| match status {
+                0 | 1 => Ok(started.elapsed().as_millis() as u64),
+                status => Err(ESignError::Tsa(format!(
+                    "TSA rejected request (status {})",
+                    status
+                ))),
+            });
+
+        TsaServerStatus {
+            url: server.url.clone(),
+            latency_ms: result.as_ref().ok().copied(),
+            error: result.err().map(|e| e.to_string()),
+            is_https: server.is_https,
+        }
+    }
+
+    /// Request a timestamp with a fresh random nonce and return the TimeStampToken
+    /// Fails if the token does not echo the nonce and imprint (replayed or mismatched response)
+    /// Transient failures are retried on `url` with backoff until `deadline`
+    pub fn send_timestamp_request_verified(
+        &self,
+        url: &str,
+        hash: &[u8],
+        timeout: Duration,
+        deadline: Instant,
+    ) -> Result<Vec<u8>, ESignError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        getrandom::getrandom(&mut nonce)
+            .map_err(|e| ESignError::Tsa(format!("Failed to generate nonce: {}", e)))?;
+
+        let request = self.build_timestamp_request_with_nonce(hash, &nonce)?;
+        let response = self.send_timestamp_request_with_retry(url, &request, timeout, deadline)?;
+        let token = self.parse_timestamp_response(&response)?;
+        verify_token_nonce(&token, &nonce)?;
+        verify_token_imprint(&token, hash)?;
+        Ok(token)
+    }
+
+    /// Build RFC 3161 TimeStampReq with a random 64-bit nonce
     fn build_timestamp_request(&self, hash: &[u8]) -> Result<Vec<u8>, ESignError> {
+        let nonce: u64 = rand::thread_rng().gen();
+        self.build_timestamp_request_with_nonce(hash, &nonce.to_be_bytes())
+    }
+
+    /// Build RFC 3161 TimeStampReq
+    /// ASN.1 structure for timestamp request; `nonce` is an unsigned big-endian integer
+    pub fn build_timestamp_request_with_nonce(
+        &self,
+        hash: &[u8],
+        nonce: &[u8],
+    ) -> Result<Vec<u8>, ESignError> {
         // TimeStampReq ::= SEQUENCE {
         //   version INTEGER { v1(1) },
         //   messageImprint MessageImprint,
@@ -183,25 +589,16 @@ impl TsaClient {
         let version: &[u8] = &[0x02, 0x01, 0x01]; // INTEGER 1
         let cert_req: &[u8] = &[0x01, 0x01, 0xFF]; // BOOLEAN TRUE
 
-        // Generate cryptographically secure random nonce
-        let nonce_value: u64 = rand::thread_rng().gen();
-        let nonce_bytes = nonce_value.to_be_bytes();
-        let mut nonce = vec![0x02]; // INTEGER
-                                    // Remove leading zeros
-        let nonce_trimmed: Vec<u8> = nonce_bytes
-            .iter()
-            .skip_while(|&&b| b == 0)
-            .cloned()
-            .collect();
-        let nonce_data = if nonce_trimmed.is_empty() {
-            vec![0]
-        } else {
-            nonce_trimmed
-        };
-        nonce.push(nonce_data.len() as u8);
-        nonce.extend_from_slice(&nonce_data);
+        // Nonce INTEGER: minimal unsigned encoding (leading 0x00 if the high bit is set)
+        let mut nonce_data: Vec<u8> = nonce.iter().copied().skip_while(|&b| b == 0).collect();
+        if nonce_data.first().is_none_or(|&b| b & 0x80 != 0) {
+            nonce_data.insert(0, 0x00);
+        }
+        let mut nonce_tlv = vec![0x02]; // INTEGER
+        nonce_tlv.push(nonce_data.len() as u8);
+        nonce_tlv.extend_from_slice(&nonce_data);
 
-        let req_content = [version, &msg_imprint[..], &nonce[..], cert_req].concat();
+        let req_content = [version, &msg_imprint[..], &nonce_tlv[..], cert_req].concat();
 
         let mut ts_req = vec![0x30]; // SEQUENCE
         if req_content.len() < 128 {
@@ -218,27 +615,66 @@ impl TsaClient {
         Ok(ts_req)
     }
 
+    /// Send timestamp request, retrying the same server with exponential backoff
+    /// Each attempt's timeout is shortened so it ends by `deadline`
+    fn send_timestamp_request_with_retry(
+        &self,
+        url: &str,
+        request: &[u8],
+        timeout: Duration,
+        deadline: Instant,
+    ) -> Result<Vec<u8>, ESignError> {
+        retry_with_backoff(&self.config, deadline, std::thread::sleep, || {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            self.post_timestamp_request(url, request, timeout.min(remaining))
+        })
+    }
+
     /// Send timestamp request to TSA server
-    fn send_timestamp_request(&self, url: &str, request: &[u8]) -> Result<Vec<u8>, ESignError> {
+    fn send_timestamp_request(
+        &self,
+        url: &str,
+        request: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, ESignError> {
+        self.post_timestamp_request(url, request, timeout)
+            .map_err(SendFailure::into_error)
+    }
+
+    fn post_timestamp_request(
+        &self,
+        url: &str,
+        request: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SendFailure> {
         let response = self
             .http_client
             .post(url)
+            .timeout(timeout)
             .header("Content-Type", "application/timestamp-query")
             .body(request.to_vec())
             .send()
-            .map_err(|e| ESignError::Tsa(format!("HTTP request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            return Err(ESignError::Tsa(format!(
-                "TSA returned error status: {}",
-                response.status()
-            )));
+            .map_err(|e| {
+                SendFailure::Retryable(ESignError::network_error(
+                    url,
+                    &e.to_string(),
+                    TSA_RETRY_SUGGESTION,
+                ))
+            })?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error = ESignError::Tsa(format!("TSA returned error status: {}", status));
+            return Err(if status.is_server_error() {
+                SendFailure::Retryable(error)
+            } else {
+                SendFailure::Fatal(error)
+            });
         }
 
-        response
-            .bytes()
-            .map(|b| b.to_vec())
-            .map_err(|e| ESignError::Tsa(format!("Failed to read response: {}", e)))
+        response.bytes().map(|b| b.to_vec()).map_err(|e| {
+            SendFailure::Retryable(ESignError::Tsa(format!("Failed to read response: {}", e)))
+        })
     }
 
     /// Parse RFC 3161 TimeStampResp and extract TimeStampToken
@@ -318,6 +754,318 @@ impl Default for TsaClient {
     }
 }
 
+/// Builder for TSA clients with optional response caching
+#[derive(Debug, Clone)]
+pub struct TsaClientBuilder {
+    config: TsaConfig,
+    caching: bool,
+    cache_ttl: Duration,
+}
+
+impl Default for TsaClientBuilder {
+    fn default() -> Self {
+        Self {
+            config: TsaConfig::default(),
+            caching: false,
+            cache_ttl: DEFAULT_CACHE_TTL,
+        }
+    }
+}
+
+impl TsaClientBuilder {
+    /// Default Vietnamese servers, caching disabled
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use custom server configuration
+    pub fn config(mut self, config: TsaConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Enable or disable response caching
+    pub fn caching(mut self, enabled: bool) -> Self {
+        self.caching = enabled;
+        self
+    }
+
+    /// How long cached tokens are reused (default 5 minutes)
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn build(self) -> Result<CachingTsaClient, ESignError> {
+        let inner = TsaClient::with_config(self.config)?;
+        Ok(CachingTsaClient {
+            inner,
+            cache_ttl: self.caching.then_some(self.cache_ttl),
+            cache: Mutex::new(HashMap::new()),
+        })
+    }
+}
+
+/// TSA client that reuses tokens for repeated requests of the same batch job
+/// Cache keys include the job id: each signed document keeps its own timestamp
+pub struct CachingTsaClient {
+    inner: TsaClient,
+    /// None when caching is disabled
+    cache_ttl: Option<Duration>,
+    /// SHA-256 of job id and signedAttrs digest -> token and time it was stored
+    cache: Mutex<HashMap<[u8; 32], (TimestampResult, Instant)>>,
+}
+
+impl CachingTsaClient {
+    /// Timestamp without caching (no job to scope the entry to)
+    pub fn get_timestamp(&self, signature: &[u8]) -> Result<TimestampResult, ESignError> {
+        self.inner.get_timestamp(signature)
+    }
+
+    /// Timestamp for `signature`, reused if the same job requested it for the same
+    /// `signed_attrs` within the TTL
+    /// A cached token is only reused while its messageImprint still matches `signature`
+    pub fn get_timestamp_for_job(
+        &self,
+        job_id: &str,
+        signed_attrs: &[u8],
+        signature: &[u8],
+    ) -> Result<TimestampResult, ESignError> {
+        let imprint = Sha256::digest(signature);
+        self.cached(
+            cache_key(job_id, signed_attrs),
+            |cached| {
+                extract_tst_message_imprint(&cached.token)
+                    .is_ok_and(|hashed| hashed == imprint.as_slice())
+            },
+            || self.inner.get_timestamp(signature),
+        )
+    }
+
+    /// Cached entry for `key` if `reusable` accepts it, otherwise the fetched result
+    fn cached(
+        &self,
+        key: [u8; 32],
+        reusable: impl FnOnce(&TimestampResult) -> bool,
+        fetch: impl FnOnce() -> Result<TimestampResult, ESignError>,
+    ) -> Result<TimestampResult, ESignError> {
+        let Some(ttl) = self.cache_ttl else {
+            return fetch();
+        };
+
+        // Entries hold no invariants across calls, so a poisoned cache is still usable
+        {
+            let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            cache.retain(|_, (_, stored_at)| stored_at.elapsed() < ttl);
+            if let Some((result, _)) = cache.get(&key).filter(|(result, _)| reusable(result)) {
+                return Ok(result.clone());
+            }
+        }
+
+        // The lock is not held during the network request
+        let result = fetch()?;
+        self.cache
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(key, (result.clone(), Instant::now()));
+        Ok(result)
+    }
+}
+
+/// Cache key for a job's timestamp request: job id and SHA-256 of the signedAttrs
+/// The length prefix keeps ids unambiguous
+fn cache_key(job_id: &str, signed_attrs: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update((job_id.len() as u64).to_be_bytes());
+    hasher.update(job_id.as_bytes());
+    hasher.update(Sha256::digest(signed_attrs));
+    hasher.finalize().into()
+}
+
+/// HTTP client whose TLS connections must chain to a pinned CA root
+/// Falls back to the built-in roots when pinning is skipped or no roots are embedded
+fn tsa_http_client(skip_pinning: bool) -> Result<Client, ESignError> {
+    let mut builder = Client::builder();
+    let roots = match skip_pinning {
+        true => Vec::new(),
+        false => ca_pins::load_roots(ca_pins::ROOTS)?,
+    };
+    if !roots.is_empty() {
+        builder = builder.tls_built_in_root_certs(false);
+        for root in roots {
+            builder = builder.add_root_certificate(root);
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| ESignError::Tsa(format!("Failed to create HTTP client: {}", e)))
+}
+
+/// Run `attempt` until it succeeds, fails permanently or `max_retries` retries are used
+/// Gives up early rather than sleeping past `deadline`
+fn retry_with_backoff<T>(
+    config: &TsaConfig,
+    deadline: Instant,
+    mut sleep: impl FnMut(Duration),
+    mut attempt: impl FnMut() -> Result<T, SendFailure>,
+) -> Result<T, ESignError> {
+    let mut retry = 0;
+    loop {
+        match attempt() {
+            Ok(value) => return Ok(value),
+            Err(SendFailure::Retryable(e)) if retry < config.max_retries => {
+                let jitter = rand::thread_rng().gen_range(0.0..=0.5);
+                let delay = backoff_delay(config, retry, jitter);
+                if Instant::now() + delay >= deadline {
+                    return Err(e);
+                }
+                eprintln!(
+                    "TSA request failed ({}), retrying in {} ms",
+                    e,
+                    delay.as_millis()
+                );
+                sleep(delay);
+                retry += 1;
+            }
+            Err(failure) => return Err(failure.into_error()),
+        }
+    }
+}
+
+/// Delay before retry number `retry` (0-based): `initial * 2^retry` plus `jitter`
+/// (fraction of it, up to 0.5) against thundering herds, capped at `max_backoff_ms`
+fn backoff_delay(config: &TsaConfig, retry: u32, jitter: f64) -> Duration {
+    let base = config
+        .initial_backoff_ms
+        .saturating_mul(2u64.saturating_pow(retry))
+        .min(config.max_backoff_ms);
+    let delay = base.saturating_add((base as f64 * jitter) as u64);
+    Duration::from_millis(delay.min(config.max_backoff_ms))
+}
+
+/// Try servers in order until one succeeds, within `max_total_time` overall
+/// Each request gets its server's timeout, shortened to the remaining budget;
+/// servers are skipped once less than MIN_TSA_REQUEST_TIME remains
+fn try_servers_within_budget<'a, T>(
+    tsa_servers: &'a [TsaServerConfig],
+    max_total_time: Duration,
+    mut attempt: impl FnMut(&str, Duration) -> Result<T, ESignError>,
+) -> Result<(&'a TsaServerConfig, T), ESignError> {
+    let started = Instant::now();
+    let mut last_error = None;
+
+    for server in tsa_servers {
+        let budget_remaining = max_total_time.saturating_sub(started.elapsed());
+        if budget_remaining < MIN_TSA_REQUEST_TIME {
+            return Err(ESignError::Tsa(format!(
+                "All TSA servers failed within the time budget of {} seconds",
+                max_total_time.as_secs()
+            )));
+        }
+
+        match attempt(&server.url, server.timeout().min(budget_remaining)) {
+            Ok(value) => return Ok((server, value)),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| ESignError::Tsa("No TSA servers available".to_string())))
+}
+
+/// Check that the TSTInfo inside `token` carries the nonce sent in the request
+fn verify_token_nonce(token: &[u8], sent_nonce: &[u8]) -> Result<(), ESignError> {
+    let strip =
+        |bytes: &[u8]| -> Vec<u8> { bytes.iter().copied().skip_while(|&b| b == 0).collect() };
+
+    match extract_tst_nonce(token)? {
+        Some(nonce) if strip(&nonce) == strip(sent_nonce) => Ok(()),
+        _ => Err(ESignError::Tsa(
+            "Nonce mismatch: replay attack detected".to_string(),
+        )),
+    }
+}
+
+/// Check that the TSTInfo inside `token` timestamps `hash`
+fn verify_token_imprint(token: &[u8], hash: &[u8]) -> Result<(), ESignError> {
+    if extract_tst_message_imprint(token)? != hash {
+        return Err(ESignError::Tsa(
+            "Timestamp token does not match the requested message imprint".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Fields of the TSTInfo inside a TimeStampToken
+/// ContentInfo -> [0] SignedData -> encapContentInfo -> [0] OCTET STRING -> TSTInfo
+fn tst_info_fields(token: &[u8]) -> Result<Vec<(u8, Vec<u8>)>, ESignError> {
+    let invalid = || ESignError::Tsa("Invalid TimeStampToken structure".to_string());
+    let child =
+        |children: &[(u8, Vec<u8>)], index: usize, tag: u8| -> Result<Vec<u8>, ESignError> {
+            match children.get(index) {
+                Some((t, content)) if *t == tag => Ok(content.clone()),
+                _ => Err(invalid()),
+            }
+        };
+
+    let (_, content_info, _) = read_tlv(token).ok_or_else(invalid)?;
+    let content_info = der_children(content_info).ok_or_else(invalid)?;
+    let signed_data_wrapper = child(&content_info, 1, 0xA0)?;
+    let (_, signed_data, _) = read_tlv(&signed_data_wrapper).ok_or_else(invalid)?;
+    let signed_data = der_children(signed_data).ok_or_else(invalid)?;
+    let encap_content_info = der_children(&child(&signed_data, 2, 0x30)?).ok_or_else(invalid)?;
+    let econtent = child(&encap_content_info, 1, 0xA0)?;
+    let (_, tst_info_der, _) = read_tlv(&econtent).ok_or_else(invalid)?;
+    let (_, tst_info, _) = read_tlv(tst_info_der).ok_or_else(invalid)?;
+    der_children(tst_info).ok_or_else(invalid)
+}
+
+/// hashedMessage of the TSTInfo messageImprint { hashAlgorithm, hashedMessage }
+pub(crate) fn extract_tst_message_imprint(token: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let invalid = || ESignError::Tsa("Invalid TimeStampToken messageImprint".to_string());
+    let tst_info = tst_info_fields(token)?;
+    let imprint = match tst_info.get(2) {
+        Some((0x30, content)) => der_children(content).ok_or_else(invalid)?,
+        _ => return Err(invalid()),
+    };
+    match imprint.get(1) {
+        Some((0x04, hashed_message)) => Ok(hashed_message.clone()),
+        _ => Err(invalid()),
+    }
+}
+
+/// Nonce INTEGER content from the TSTInfo of a TimeStampToken, if present
+fn extract_tst_nonce(token: &[u8]) -> Result<Option<Vec<u8>>, ESignError> {
+    let tst_info = tst_info_fields(token)?;
+
+    // version, policy, messageImprint, serialNumber, genTime, then
+    // accuracy (SEQUENCE) and ordering (BOOLEAN) may precede the nonce
+    Ok(tst_info
+        .iter()
+        .skip(5)
+        .find(|(tag, _)| *tag == 0x02)
+        .map(|(_, content)| content.clone()))
+}
+
+/// PKIStatus of a TimeStampResp: 0 granted, 1 grantedWithMods, 2+ failure
+fn parse_pki_status(response: &[u8]) -> Result<u8, ESignError> {
+    let invalid = || ESignError::Tsa("Invalid TimeStampResp".to_string());
+
+    if response.first() != Some(&0x30) {
+        return Err(invalid());
+    }
+    let (header_len, _) = parse_asn1_length(&response[1..])?;
+    let status_info = response.get(1 + header_len..).ok_or_else(invalid)?;
+
+    if status_info.first() != Some(&0x30) {
+        return Err(invalid());
+    }
+    let (header_len, _) = parse_asn1_length(&status_info[1..])?;
+    match status_info.get(1 + header_len..) {
+        Some([0x02, 0x01, status, ..]) => Ok(*status),
+        _ => Err(invalid()),
+    }
+}
+
 /// Parse ASN.1 length encoding
 /// Returns (bytes consumed, length value)
 fn parse_asn1_length(data: &[u8]) -> Result<(usize, usize), ESignError> {
@@ -386,21 +1134,25 @@ mod tests {
     fn test_tsa_config_default() {
         let config = TsaConfig::default();
         assert_eq!(config.primary_url, servers::VNPT_HTTPS);
-        assert!(!config.fallback_urls.is_empty());
+        assert!(!config.fallback_servers.is_empty());
         assert_eq!(config.timeout_secs, 30);
         // Verify HTTPS endpoints come before HTTP fallbacks
-        assert!(config.fallback_urls[0].starts_with("https"));
+        assert!(config.fallback_servers[0].is_https);
+        assert!(!config.fallback_servers.last().unwrap().is_https);
+        assert!(config.fallback_servers.iter().all(|s| s.timeout_secs == 30));
     }
 
     #[test]
     fn test_tsa_config_custom() {
         let config = TsaConfig {
             primary_url: "http://custom.tsa.vn".to_string(),
-            fallback_urls: vec!["http://fallback1.vn".to_string()],
+            fallback_servers: vec![TsaServerConfig::new("http://fallback1.vn", 10)],
             timeout_secs: 60,
+            max_total_time: Duration::from_secs(60),
+            ..Default::default()
         };
         assert_eq!(config.primary_url, "http://custom.tsa.vn");
-        assert_eq!(config.fallback_urls.len(), 1);
+        assert_eq!(config.fallback_servers.len(), 1);
         assert_eq!(config.timeout_secs, 60);
     }
 
@@ -409,19 +1161,76 @@ mod tests {
         let config = TsaConfig::default();
         let json = serde_json::to_string(&config).unwrap();
         assert!(json.contains("primary_url"));
-        assert!(json.contains("fallback_urls"));
+        assert!(json.contains("fallback_servers"));
         assert!(json.contains("timeout_secs"));
+        assert!(json.contains("is_https"));
     }
 
     #[test]
     fn test_tsa_config_deserialize() {
-        let json = r#"{"primary_url":"http://test.vn","fallback_urls":[],"timeout_secs":10}"#;
+        let json = r#"{"primary_url":"http://test.vn","fallback_servers":[],"timeout_secs":10}"#;
         let config: TsaConfig = serde_json::from_str(json).unwrap();
         assert_eq!(config.primary_url, "http://test.vn");
-        assert!(config.fallback_urls.is_empty());
+        assert!(config.fallback_servers.is_empty());
         assert_eq!(config.timeout_secs, 10);
     }
 
+    #[test]
+    fn test_tsa_config_deserialize_legacy_fallback_urls() {
+        let json = r#"{"primary_url":"http://test.vn","fallback_urls":["https://fb.vn","http://fb.vn"],"timeout_secs":10}"#;
+        let config: TsaConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config.fallback_servers,
+            vec![
+                TsaServerConfig::new("https://fb.vn", 30),
+                TsaServerConfig::new("http://fb.vn", 30),
+            ]
+        );
+        assert!(config.fallback_servers[0].is_https);
+        assert!(!config.fallback_servers[1].is_https);
+    }
+
+    #[test]
+    fn test_tsa_server_config_deserialize_defaults() {
+        let json = r#"[{"url":"https://a.vn","timeout_secs":5},{"url":"http://b.vn"}]"#;
+        let parsed: Vec<TsaServerConfig> = serde_json::from_str(json).unwrap();
+        assert_eq!(parsed[0], TsaServerConfig::new("https://a.vn", 5));
+        assert_eq!(parsed[1].timeout_secs, 30);
+        assert!(!parsed[1].is_https);
+    }
+
+    #[test]
+    fn test_tsa_config_with_server_timeouts() {
+        let config = TsaConfig::with_server_timeouts(60, vec![20, 5]);
+        assert_eq!(config.primary_url, servers::VNPT_HTTPS);
+        assert_eq!(config.timeout_secs, 60);
+        assert_eq!(config.fallback_servers[0].url, servers::VIETTEL_HTTPS);
+        assert_eq!(config.fallback_servers[0].timeout_secs, 20);
+        assert_eq!(config.fallback_servers[1].url, servers::FPT_HTTPS);
+        assert_eq!(config.fallback_servers[1].timeout_secs, 5);
+        assert_eq!(config.fallback_servers[2].timeout_secs, 30);
+
+        let timeouts: Vec<u64> = config.servers().iter().map(|s| s.timeout_secs).collect();
+        assert_eq!(timeouts, vec![60, 20, 5, 30, 30, 30]);
+    }
+
+    #[test]
+    fn test_tsa_config_timeout_for() {
+        let config = TsaConfig::with_server_timeouts(60, vec![20, 5]);
+        assert_eq!(
+            config.timeout_for(servers::VNPT_HTTPS),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            config.timeout_for(servers::FPT_HTTPS),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            config.timeout_for("https://unknown.vn"),
+            Duration::from_secs(60)
+        );
+    }
+
     // ============ TsaClient Tests ============
 
     #[test]
@@ -434,8 +1243,10 @@ mod tests {
     fn test_tsa_client_with_config() {
         let config = TsaConfig {
             primary_url: servers::VIETTEL_HTTPS.to_string(),
-            fallback_urls: vec![],
+            fallback_servers: vec![],
             timeout_secs: 15,
+            max_total_time: Duration::from_secs(60),
+            ..Default::default()
         };
         let client = TsaClient::with_config(config);
         assert!(client.is_ok());
@@ -447,6 +1258,230 @@ mod tests {
         let _client = TsaClient::default();
     }
 
+    // ============ Timestamp Cache Tests ============
+
+    fn caching_client(ttl: Duration) -> CachingTsaClient {
+        TsaClientBuilder::new()
+            .caching(true)
+            .cache_ttl(ttl)
+            .build()
+            .unwrap()
+    }
+
+    fn fake_timestamp(fetches: &std::cell::Cell<u32>) -> Result<TimestampResult, ESignError> {
+        fetches.set(fetches.get() + 1);
+        Ok(TimestampResult {
+            token: vec![fetches.get() as u8],
+            server_url: servers::VNPT_HTTPS.to_string(),
+            used_insecure_transport: false,
+        })
+    }
+
+    #[test]
+    fn test_cache_hit_reuses_token() {
+        let client = caching_client(Duration::from_secs(60));
+        let fetches = std::cell::Cell::new(0);
+        let key = cache_key("job-1", b"signed attrs");
+
+        let first = client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        let second = client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        assert_eq!(fetches.get(), 1);
+        assert_eq!(first.token, second.token);
+    }
+
+    #[test]
+    fn test_cache_miss_for_other_job_or_data() {
+        let client = caching_client(Duration::from_secs(60));
+        let fetches = std::cell::Cell::new(0);
+
+        for key in [
+            cache_key("job-1", b"signed attrs"),
+            cache_key("job-2", b"signed attrs"),
+            cache_key("job-1", b"other signed attrs"),
+        ] {
+            client
+                .cached(key, |_| true, || fake_timestamp(&fetches))
+                .unwrap();
+        }
+        assert_eq!(fetches.get(), 3);
+        assert_ne!(cache_key("ab", b"c"), cache_key("a", b"bc"));
+    }
+
+    #[test]
+    fn test_cache_entries_expire_after_ttl() {
+        let client = caching_client(Duration::ZERO);
+        let fetches = std::cell::Cell::new(0);
+        let key = cache_key("job-1", b"signed attrs");
+
+        client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+        assert_eq!(client.cache.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_cache_disabled_by_default() {
+        let client = TsaClientBuilder::new().build().unwrap();
+        let fetches = std::cell::Cell::new(0);
+        let key = cache_key("job-1", b"signed attrs");
+
+        client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+        assert!(client.cache.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cache_refetches_entry_that_is_not_reusable() {
+        let client = caching_client(Duration::from_secs(60));
+        let fetches = std::cell::Cell::new(0);
+        let key = cache_key("job-1", b"signed attrs");
+
+        client
+            .cached(key, |_| true, || fake_timestamp(&fetches))
+            .unwrap();
+        let refetched = client
+            .cached(
+                key,
+                |cached| cached.token != [1],
+                || fake_timestamp(&fetches),
+            )
+            .unwrap();
+        assert_eq!(fetches.get(), 2);
+        assert_eq!(refetched.token, vec![2]);
+    }
+
+    #[test]
+    fn test_cache_skips_failed_requests() {
+        let client = caching_client(Duration::from_secs(60));
+        let key = cache_key("job-1", b"signed attrs");
+
+        let failed = client.cached(
+            key,
+            |_| true,
+            || Err(ESignError::Tsa("unavailable".to_string())),
+        );
+        assert!(failed.is_err());
+        assert!(client.cache.lock().unwrap().is_empty());
+    }
+
+    // ============ Time Budget Tests ============
+
+    fn six_servers() -> Vec<TsaServerConfig> {
+        (1..=6)
+            .map(|i| TsaServerConfig::new(&format!("https://tsa{}.test.vn", i), 30))
+            .collect()
+    }
+
+    #[test]
+    fn test_tsa_config_default_max_total_time() {
+        assert_eq!(TsaConfig::default().max_total_time, Duration::from_secs(60));
+    }
+
+    #[test]
+    fn test_time_budget_limits_attempts() {
+        // 1-second budget, every server takes 500ms to fail
+        let mut attempted = Vec::new();
+        let result: Result<(&TsaServerConfig, ()), ESignError> =
+            try_servers_within_budget(&six_servers(), Duration::from_secs(1), |url, timeout| {
+                attempted.push(url.to_string());
+                std::thread::sleep(timeout.min(Duration::from_millis(500)));
+                Err(ESignError::Tsa("timed out".to_string()))
+            });
+
+        assert_eq!(attempted.len(), 2);
+        match result {
+            Err(ESignError::Tsa(message)) => {
+                assert_eq!(
+                    message,
+                    "All TSA servers failed within the time budget of 1 seconds"
+                );
+            }
+            other => panic!("Expected budget error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_time_budget_shortens_request_timeout() {
+        let mut timeouts = Vec::new();
+        let _ = try_servers_within_budget(
+            &six_servers()[..1],
+            Duration::from_secs(2),
+            |_, timeout| -> Result<(), ESignError> {
+                timeouts.push(timeout);
+                Err(ESignError::Tsa("failed".to_string()))
+            },
+        );
+        assert_eq!(timeouts.len(), 1);
+        assert!(timeouts[0] <= Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_time_budget_returns_first_success() {
+        let tsa_servers = six_servers();
+        let (server, value) =
+            try_servers_within_budget(&tsa_servers, Duration::from_secs(60), |url, _| {
+                if url.contains("tsa3") {
+                    Ok(42)
+                } else {
+                    Err(ESignError::Tsa("failed".to_string()))
+                }
+            })
+            .unwrap();
+        assert_eq!(server.url, "https://tsa3.test.vn");
+        assert_eq!(value, 42);
+    }
+
+    #[test]
+    fn test_time_budget_uses_per_server_timeouts() {
+        let tsa_servers = vec![
+            TsaServerConfig::new("https://slow.test.vn", 45),
+            TsaServerConfig::new("https://fast.test.vn", 5),
+            TsaServerConfig::new("https://other.test.vn", 20),
+        ];
+        let mut timeouts = Vec::new();
+        let _ = try_servers_within_budget(
+            &tsa_servers,
+            Duration::from_secs(600),
+            |url, timeout| -> Result<(), ESignError> {
+                timeouts.push((url.to_string(), timeout));
+                Err(ESignError::Tsa("failed".to_string()))
+            },
+        );
+
+        assert_eq!(timeouts.len(), 3);
+        assert_eq!(timeouts[0].0, "https://slow.test.vn");
+        // Budget barely elapsed, so each server keeps (almost) its own timeout
+        assert!(timeouts[0].1 > Duration::from_secs(44));
+        assert_eq!(timeouts[1].1, Duration::from_secs(5));
+        assert_eq!(timeouts[2].1, Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_time_budget_keeps_last_error_when_not_exhausted() {
+        let result = try_servers_within_budget(
+            &six_servers(),
+            Duration::from_secs(60),
+            |url, _| -> Result<(), ESignError> { Err(ESignError::Tsa(format!("{} down", url))) },
+        );
+        match result {
+            Err(ESignError::Tsa(message)) => assert_eq!(message, "https://tsa6.test.vn down"),
+            _ => panic!("Expected last server error"),
+        }
+    }
+
     // ============ ASN.1 Length Parsing Tests ============
 
     #[test]
@@ -512,6 +1547,93 @@ mod tests {
         assert!(result.is_err());
     }
 
+    // ============ Nonce Verification Tests ============
+
+    /// TimeStampToken whose TSTInfo has the given nonce INTEGER content (None omits it)
+    /// `with_accuracy` adds accuracy and ordering fields before the nonce
+    fn token_with_nonce(nonce: Option<&[u8]>, with_accuracy: bool) -> Vec<u8> {
+        crate::test_utils::timestamp_token(&[0xAB; 32], nonce, with_accuracy)
+    }
+
+    #[test]
+    fn test_verify_token_nonce_matches() {
+        let nonce = [0x12, 0x34, 0x56, 0x78];
+        assert!(verify_token_nonce(&token_with_nonce(Some(&nonce), false), &nonce).is_ok());
+        assert!(verify_token_nonce(&token_with_nonce(Some(&nonce), true), &nonce).is_ok());
+    }
+
+    #[test]
+    fn test_verify_token_nonce_ignores_integer_sign_padding() {
+        // High bit set: the INTEGER carries a leading 0x00
+        let nonce = [0x80, 0x01];
+        let token = token_with_nonce(Some(&[0x00, 0x80, 0x01]), false);
+        assert!(verify_token_nonce(&token, &nonce).is_ok());
+    }
+
+    #[test]
+    fn test_verify_token_nonce_mismatch() {
+        let token = token_with_nonce(Some(&[0x12, 0x34, 0x56, 0x78]), true);
+        let err = verify_token_nonce(&token, &[0x12, 0x34, 0x56, 0x79]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            ESignError::Tsa("Nonce mismatch: replay attack detected".to_string()).to_string()
+        );
+    }
+
+    #[test]
+    fn test_verify_token_nonce_missing() {
+        let token = token_with_nonce(None, true);
+        assert!(verify_token_nonce(&token, &[0x01]).is_err());
+    }
+
+    #[test]
+    fn test_verify_token_nonce_malformed_token() {
+        assert!(verify_token_nonce(&[0x30, 0x00], &[0x01]).is_err());
+        assert!(verify_token_nonce(b"garbage", &[0x01]).is_err());
+    }
+
+    #[test]
+    fn test_verify_token_imprint() {
+        let token = token_with_nonce(Some(&[0x01]), true);
+        assert_eq!(extract_tst_message_imprint(&token).unwrap(), vec![0xAB; 32]);
+        assert!(verify_token_imprint(&token, &[0xAB; 32]).is_ok());
+        assert!(verify_token_imprint(&token, &[0xCD; 32]).is_err());
+        assert!(verify_token_imprint(b"garbage", &[0xAB; 32]).is_err());
+    }
+
+    #[test]
+    fn test_parsed_response_nonce_round_trip() {
+        use crate::test_utils::tlv;
+
+        let client = TsaClient::new().unwrap();
+        let nonce = [0xFE; NONCE_LEN];
+        let token = token_with_nonce(Some(&[[0x00].as_slice(), &nonce].concat()), false);
+        let response = tlv(
+            0x30,
+            &[tlv(0x30, &tlv(0x02, &[0x00])), token.clone()].concat(),
+        );
+
+        let parsed = client.parse_timestamp_response(&response).unwrap();
+        assert_eq!(parsed, token);
+        assert!(verify_token_nonce(&parsed, &nonce).is_ok());
+    }
+
+    #[test]
+    fn test_build_timestamp_request_with_nonce_encodes_unsigned() {
+        let client = TsaClient::new().unwrap();
+        let request = client
+            .build_timestamp_request_with_nonce(&[0u8; 32], &[0x00, 0x80, 0x01])
+            .unwrap();
+        let fields = der_children(read_tlv(&request).unwrap().1).unwrap();
+        assert_eq!(fields[2], (0x02, vec![0x00, 0x80, 0x01]));
+
+        let request = client
+            .build_timestamp_request_with_nonce(&[0u8; 32], &[0x00, 0x00, 0x7F])
+            .unwrap();
+        let fields = der_children(read_tlv(&request).unwrap().1).unwrap();
+        assert_eq!(fields[2], (0x02, vec![0x7F]));
+    }
+
     // ============ Timestamp Request Tests ============
 
     #[test]
@@ -550,21 +1672,403 @@ mod tests {
         assert!(request.len() > 32 + 10);
     }
 
+    // ============ Health Check Tests ============
+
+    /// TimeStampResp carrying only PKIStatusInfo { status }
+    fn status_response(status: u8) -> Vec<u8> {
+        vec![0x30, 0x05, 0x30, 0x03, 0x02, 0x01, status]
+    }
+
+    fn health_client(primary_url: String, fallback_urls: Vec<String>) -> TsaClient {
+        TsaClient::with_config(TsaConfig {
+            primary_url,
+            fallback_servers: fallback_urls
+                .iter()
+                .map(|url| TsaServerConfig::new(url, 5))
+                .collect(),
+            timeout_secs: 5,
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_pki_status() {
+        assert_eq!(parse_pki_status(&status_response(0)).unwrap(), 0);
+        assert_eq!(parse_pki_status(&status_response(2)).unwrap(), 2);
+        assert!(parse_pki_status(&[0x30, 0x02, 0x04, 0x00]).is_err());
+        assert!(parse_pki_status(b"<html>").is_err());
+        assert!(parse_pki_status(&[]).is_err());
+    }
+
+    #[test]
+    fn test_health_check_granted() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/tsa"))
+                .respond_with(status_code(200).body(status_response(0))),
+        );
+        let url = server.url("/tsa").to_string();
+
+        let result = health_client(url.clone(), vec![]).health_check(&url);
+        assert!(result.available);
+        assert_eq!(result.url, url);
+        assert_eq!(result.warning.as_deref(), Some("Insecure transport"));
+    }
+
+    #[test]
+    fn test_health_check_rejected() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/tsa"))
+                .respond_with(status_code(200).body(status_response(2))),
+        );
+        let url = server.url("/tsa").to_string();
+
+        assert!(
+            !health_client(url.clone(), vec![])
+                .health_check(&url)
+                .available
+        );
+    }
+
+    #[test]
+    fn test_check_all_tsa_servers_available_first() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ok"))
+                .respond_with(status_code(200).body(status_response(0))),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/down"))
+                .respond_with(status_code(503)),
+        );
+
+        let down = server.url("/down").to_string();
+        let ok = server.url("/ok").to_string();
+        let results = health_client(down.clone(), vec![ok.clone()]).check_all_tsa_servers();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].url, ok);
+        assert!(results[0].available);
+        assert_eq!(results[1].url, down);
+        assert!(!results[1].available);
+    }
+
+    #[test]
+    fn test_ping_servers_orders_responding_first() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ok"))
+                .respond_with(status_code(200).body(status_response(0))),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/rejected"))
+                .respond_with(status_code(200).body(status_response(2))),
+        );
+
+        let ok = server.url("/ok").to_string();
+        let rejected = server.url("/rejected").to_string();
+        let statuses =
+            health_client(rejected.clone(), vec![ok.clone()]).ping_servers(Duration::from_secs(5));
+
+        assert_eq!(statuses.len(), 2);
+        assert_eq!(statuses[0].url, ok);
+        assert!(statuses[0].latency_ms.is_some() && statuses[0].error.is_none());
+        assert!(!statuses[0].is_https);
+        assert_eq!(statuses[1].url, rejected);
+        assert!(statuses[1].latency_ms.is_none());
+        assert!(statuses[1].error.as_deref().unwrap().contains("status 2"));
+    }
+
+    #[test]
+    fn test_with_fastest_server_promotes_responding_server() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/ok"))
+                .respond_with(status_code(200).body(status_response(0))),
+        );
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/down"))
+                .respond_with(status_code(503)),
+        );
+        let ok = server.url("/ok").to_string();
+        let down = server.url("/down").to_string();
+        let pinged = health_client(down.clone(), vec![ok.clone()]);
+        let statuses = pinged.ping_servers(Duration::from_secs(5));
+
+        let client = TsaClient::with_fastest_server(pinged.config, &statuses).unwrap();
+        assert_eq!(client.primary_url(), ok);
+        let urls: Vec<String> = client.config.servers().into_iter().map(|s| s.url).collect();
+        assert_eq!(urls, vec![ok, down]);
+    }
+
+    #[test]
+    fn test_with_fastest_server_fails_when_none_respond() {
+        let client = health_client("https://127.0.0.1:1/tsa".to_string(), vec![]);
+        let statuses = client.ping_servers(Duration::from_millis(200));
+        assert!(statuses[0].latency_ms.is_none() && statuses[0].is_https);
+        assert!(TsaClient::with_fastest_server(client.config, &statuses).is_err());
+    }
+
+    #[test]
+    fn test_health_result_https_has_no_warning() {
+        let client = health_client("https://127.0.0.1:1/tsa".to_string(), vec![]);
+        let result = client.health_check("https://127.0.0.1:1/tsa");
+        assert!(!result.available);
+        assert_eq!(result.warning, None);
+    }
+
+    // ============ Retry Backoff Tests ============
+
+    fn retry_config(max_retries: u32) -> TsaConfig {
+        TsaConfig {
+            initial_backoff_ms: 1,
+            max_backoff_ms: 5,
+            ..TsaConfig::default().with_retry(max_retries)
+        }
+    }
+
+    fn far_deadline() -> Instant {
+        Instant::now() + Duration::from_secs(60)
+    }
+
+    #[test]
+    fn test_tsa_config_retry_defaults() {
+        let config = TsaConfig::default();
+        assert_eq!(config.max_retries, 3);
+        assert_eq!(config.initial_backoff_ms, 500);
+        assert_eq!(config.max_backoff_ms, 10_000);
+        assert_eq!(config.with_retry(7).max_retries, 7);
+
+        let json = r#"{"primary_url":"http://test.vn","fallback_urls":[],"timeout_secs":10}"#;
+        let config: TsaConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(config.max_retries, 3);
+    }
+
+    #[test]
+    fn test_backoff_delay_doubles_and_caps() {
+        let config = TsaConfig::default();
+        assert_eq!(backoff_delay(&config, 0, 0.0), Duration::from_millis(500));
+        assert_eq!(backoff_delay(&config, 1, 0.0), Duration::from_millis(1_000));
+        assert_eq!(backoff_delay(&config, 2, 0.0), Duration::from_millis(2_000));
+        assert_eq!(backoff_delay(&config, 2, 0.5), Duration::from_millis(3_000));
+        assert_eq!(
+            backoff_delay(&config, 5, 0.0),
+            Duration::from_millis(10_000)
+        );
+        assert_eq!(
+            backoff_delay(&config, 4, 0.5),
+            Duration::from_millis(10_000)
+        );
+        assert_eq!(
+            backoff_delay(&config, 63, 0.5),
+            Duration::from_millis(10_000)
+        );
+    }
+
+    #[test]
+    fn test_retry_succeeds_after_transient_failures() {
+        let mut attempts = 0;
+        let mut sleeps = Vec::new();
+        let result = retry_with_backoff(
+            &TsaConfig::default(),
+            far_deadline(),
+            |delay| sleeps.push(delay),
+            || {
+                attempts += 1;
+                if attempts <= 2 {
+                    Err(SendFailure::Retryable(ESignError::Tsa("503".to_string())))
+                } else {
+                    Ok(attempts)
+                }
+            },
+        );
+
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(sleeps.len(), 2);
+        // Jitter adds at most 50%
+        assert!((500..=750).contains(&sleeps[0].as_millis()));
+        assert!((1_000..=1_500).contains(&sleeps[1].as_millis()));
+    }
+
+    #[test]
+    fn test_retry_gives_up_after_max_retries() {
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_with_backoff(
+            &retry_config(2),
+            far_deadline(),
+            |_| {},
+            || {
+                attempts += 1;
+                Err(SendFailure::Retryable(ESignError::Tsa(
+                    "timeout".to_string(),
+                )))
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_retry_stops_on_fatal_error() {
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_with_backoff(
+            &retry_config(3),
+            far_deadline(),
+            |_| {},
+            || {
+                attempts += 1;
+                Err(SendFailure::Fatal(ESignError::Tsa("400".to_string())))
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_does_not_sleep_past_deadline() {
+        let mut attempts = 0;
+        let result: Result<(), _> = retry_with_backoff(
+            &TsaConfig::default(),
+            Instant::now() + Duration::from_millis(100),
+            |_| panic!("slept past the deadline"),
+            || {
+                attempts += 1;
+                Err(SendFailure::Retryable(ESignError::Tsa("503".to_string())))
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_send_retries_server_errors_then_succeeds() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/tsa"))
+                .times(3)
+                .respond_with(httptest::cycle![
+                    status_code(503),
+                    status_code(502),
+                    status_code(200).body(status_response(0)),
+                ]),
+        );
+        let url = server.url("/tsa").to_string();
+        let client = TsaClient::with_config(retry_config(3)).unwrap();
+
+        let response = client
+            .send_timestamp_request_with_retry(&url, b"req", Duration::from_secs(5), far_deadline())
+            .unwrap();
+        assert_eq!(response, status_response(0));
+    }
+
+    #[test]
+    fn test_send_does_not_retry_client_errors() {
+        use httptest::{matchers::*, responders::*, Expectation, Server};
+
+        let server = Server::run();
+        server.expect(
+            Expectation::matching(request::method_path("POST", "/tsa"))
+                .times(1)
+                .respond_with(status_code(400)),
+        );
+        let url = server.url("/tsa").to_string();
+        let client = TsaClient::with_config(retry_config(3)).unwrap();
+
+        let result = client.send_timestamp_request_with_retry(
+            &url,
+            b"req",
+            Duration::from_secs(5),
+            far_deadline(),
+        );
+        assert!(result.is_err());
+    }
+
+    // ============ Certificate Pinning Tests ============
+
+    #[test]
+    fn test_pinned_root_fingerprints_match() {
+        for root in ca_pins::ROOTS {
+            assert_eq!(ca_pins::fingerprint(root.der), root.sha256, "{}", root.name);
+        }
+        assert_eq!(
+            ca_pins::load_roots(ca_pins::ROOTS).unwrap().len(),
+            ca_pins::ROOTS.len()
+        );
+    }
+
+    #[test]
+    fn test_load_roots_rejects_fingerprint_mismatch() {
+        let der = crate::test_utils::test_identity().cert_der;
+        let der: &'static [u8] = Box::leak(der.into_boxed_slice());
+        let sha256: &'static str = Box::leak(ca_pins::fingerprint(der).into_boxed_str());
+
+        let pinned = ca_pins::PinnedRoot {
+            name: "Test Root",
+            der,
+            sha256,
+        };
+        assert_eq!(ca_pins::load_roots(&[pinned]).unwrap().len(), 1);
+
+        let tampered = ca_pins::PinnedRoot {
+            name: "Test Root",
+            der,
+            sha256: "00",
+        };
+        let error = ca_pins::load_roots(&[tampered]).unwrap_err().to_string();
+        assert!(error.contains("Test Root"));
+    }
+
+    #[test]
+    fn test_tsa_client_skip_certificate_pinning() {
+        let config = TsaConfig {
+            skip_certificate_pinning: true,
+            ..Default::default()
+        };
+        assert!(TsaClient::with_config(config).is_ok());
+        assert!(!TsaConfig::default().skip_certificate_pinning);
+    }
+
     // ============ Config Roundtrip Tests ============
 
     #[test]
     fn test_tsa_config_roundtrip() {
         let original = TsaConfig {
             primary_url: "http://test.vn".to_string(),
-            fallback_urls: vec!["http://fb1.vn".to_string(), "http://fb2.vn".to_string()],
+            fallback_servers: vec![
+                TsaServerConfig::new("http://fb1.vn", 10),
+                TsaServerConfig::new("https://fb2.vn", 90),
+            ],
             timeout_secs: 45,
+            max_total_time: Duration::from_secs(60),
+            max_retries: 5,
+            initial_backoff_ms: 250,
+            max_backoff_ms: 4_000,
+            skip_certificate_pinning: true,
         };
         let json = serde_json::to_string(&original).unwrap();
         let restored: TsaConfig = serde_json::from_str(&json).unwrap();
 
         assert_eq!(original.primary_url, restored.primary_url);
-        assert_eq!(original.fallback_urls, restored.fallback_urls);
+        assert_eq!(original.fallback_servers, restored.fallback_servers);
         assert_eq!(original.timeout_secs, restored.timeout_secs);
+        assert_eq!(restored.max_retries, 5);
+        assert_eq!(restored.initial_backoff_ms, 250);
+        assert_eq!(restored.max_backoff_ms, 4_000);
+        assert!(restored.skip_certificate_pinning);
     }
 
     // ============ Edge Cases ============
@@ -573,10 +2077,13 @@ mod tests {
     fn test_tsa_config_empty_fallbacks() {
         let config = TsaConfig {
             primary_url: servers::VNPT_HTTPS.to_string(),
-            fallback_urls: vec![],
+            fallback_servers: vec![],
             timeout_secs: 30,
+            max_total_time: Duration::from_secs(60),
+            ..Default::default()
         };
-        assert!(config.fallback_urls.is_empty());
+        assert!(config.fallback_servers.is_empty());
+        assert_eq!(config.servers().len(), 1);
     }
 
     #[test]
@@ -0,0 +1,700 @@
+//! Signature Verification Module
+//!
+//! Checks the CMS signatures embedded in a PDF without a token: recomputes the
+//! ByteRange digest and verifies the signature with the signer certificate's key.
+//! Also compares a signed copy against the original file to spot edits outside
+//! the signed byte ranges (a heuristic audit, not a cryptographic check).
+
+use crate::error::ESignError;
+use crate::pdf::{
+    der_children, encode_children, parse_sig_sub_filter, read_tlv, signature_fields,
+    validate_pdf_input_path, verify_rsa_pkcs1_v15_sha256, OID_MESSAGE_DIGEST, OID_SHA256,
+    OID_SIGNING_TIME,
+};
+use lopdf::{Dictionary, Document};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::ops::Range;
+use x509_parser::prelude::*;
+
+/// rsaEncryption (1.2.840.113549.1.1.1)
+const RSA_ENCRYPTION_OID: &str = "1.2.840.113549.1.1.1";
+/// id-ecPublicKey (1.2.840.10045.2.1)
+const EC_PUBLIC_KEY_OID: &str = "1.2.840.10045.2.1";
+/// prime256v1 / NIST P-256 (1.2.840.10045.3.1.7)
+const P256_CURVE_OID: &str = "1.2.840.10045.3.1.7";
+
+/// Changed-byte runs listed individually in a diff report; the rest are summarized
+const MAX_MODIFICATION_RECORDS: usize = 100;
+
+/// Verification outcome for one signature field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignatureVerificationResult {
+    /// Signature field name (/T)
+    pub field_name: String,
+    /// Signer certificate CN, or /Name if the certificate is unreadable
+    pub signer_name: String,
+    /// signingTime attribute (RFC 3339), or the raw /M date
+    pub signing_time: Option<String>,
+    /// Digest matches and the signature verifies against the signer certificate
+    pub is_valid: bool,
+    /// Signer certificate is past its notAfter date now
+    pub cert_expired: bool,
+    /// ByteRange does not reach the end of the file (bytes appended after signing)
+    pub modification_detected: bool,
+    /// Reason the signature is not valid
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// One difference between the original and the signed copy
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModificationRecord {
+    /// Byte offset in the signed copy
+    pub offset: usize,
+    pub description: String,
+}
+
+/// Bytes of a signed copy that differ from the original outside the signatures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PdfDiffReport {
+    pub original_pages: u32,
+    pub signed_pages: u32,
+    /// Changed, removed or unsigned appended bytes; 0 for the original plus a signed append
+    pub bytes_outside_signature: u64,
+    pub modifications: Vec<ModificationRecord>,
+}
+
+/// Signer data extracted from a CMS SignedData
+struct SignerData {
+    /// DER signer certificate
+    certificate: Vec<u8>,
+    /// digestAlgorithm OID content bytes
+    digest_algorithm: Vec<u8>,
+    /// Signed attributes re-tagged as SET OF, the bytes the signature covers
+    signed_attrs: Option<Vec<u8>>,
+    message_digest: Option<Vec<u8>>,
+    signing_time: Option<String>,
+    signature: Vec<u8>,
+}
+
+/// Verify every signed signature field in a PDF file
+pub fn verify_pdf_signatures(
+    pdf_path: &str,
+) -> Result<Vec<SignatureVerificationResult>, ESignError> {
+    let input_path = validate_pdf_input_path(pdf_path)?;
+    let bytes = std::fs::read(&input_path)
+        .map_err(|e| ESignError::Pdf(format!("Failed to read PDF file: {}", e)))?;
+    verify_pdf_bytes(&bytes)
+}
+
+/// Verify every signed signature field in PDF bytes
+pub fn verify_pdf_bytes(bytes: &[u8]) -> Result<Vec<SignatureVerificationResult>, ESignError> {
+    let doc = Document::load_mem(bytes)
+        .map_err(|e| ESignError::Pdf(format!("Failed to load PDF: {}", e)))?;
+
+    Ok(signature_fields(&doc)
+        .into_iter()
+        .map(|(field_name, sig_dict)| verify_signature(bytes, field_name, &sig_dict))
+        .collect())
+}
+
+/// Compare an original PDF file with a signed copy (see `diff_pdf_bytes`)
+pub fn diff_pdf_signatures(
+    original_path: &str,
+    signed_path: &str,
+) -> Result<PdfDiffReport, ESignError> {
+    let read = |path: &str| {
+        let path = validate_pdf_input_path(path)?;
+        std::fs::read(&path).map_err(|e| ESignError::Pdf(format!("Failed to read PDF file: {}", e)))
+    };
+    diff_pdf_bytes(&read(original_path)?, &read(signed_path)?)
+}
+
+/// Compare the original with a signed copy that should only append incremental updates
+/// Differences inside signature /Contents are ignored; bytes after the last
+/// signed ByteRange count as unsigned additions
+pub fn diff_pdf_bytes(original: &[u8], signed: &[u8]) -> Result<PdfDiffReport, ESignError> {
+    let load = |bytes: &[u8]| {
+        Document::load_mem(bytes).map_err(|e| ESignError::Pdf(format!("Failed to load PDF: {}", e)))
+    };
+    let original_doc = load(original)?;
+    let signed_doc = load(signed)?;
+
+    let byte_ranges: Vec<[usize; 4]> = signature_fields(&signed_doc)
+        .iter()
+        .filter_map(|(_, sig_dict)| read_byte_range(sig_dict, signed.len()).ok())
+        .collect();
+    // The /Contents hex string between the two parts of each ByteRange
+    let gaps: Vec<Range<usize>> = byte_ranges
+        .iter()
+        .map(|range| range[0] + range[1]..range[2])
+        .collect();
+    let in_gap = |offset: usize| gaps.iter().any(|gap| gap.contains(&offset));
+    let signed_end = byte_ranges
+        .iter()
+        .map(|range| range[2] + range[3])
+        .max()
+        .unwrap_or(0);
+
+    let mut report = PdfDiffReport {
+        original_pages: original_doc.get_pages().len() as u32,
+        signed_pages: signed_doc.get_pages().len() as u32,
+        bytes_outside_signature: 0,
+        modifications: Vec::new(),
+    };
+    let mut record = |offset: usize, len: usize, description: String| {
+        report.bytes_outside_signature += len as u64;
+        if report.modifications.len() < MAX_MODIFICATION_RECORDS {
+            report.modifications.push(ModificationRecord {
+                offset,
+                description,
+            });
+        }
+    };
+
+    // Runs of changed bytes within the original part of the file
+    let common = original.len().min(signed.len());
+    let mut offset = 0;
+    while offset < common {
+        if original[offset] == signed[offset] || in_gap(offset) {
+            offset += 1;
+            continue;
+        }
+        let start = offset;
+        while offset < common && original[offset] != signed[offset] && !in_gap(offset) {
+            offset += 1;
+        }
+        let len = offset - start;
+        record(
+            start,
+            len,
+            format!("{} byte(s) of the original changed", len),
+        );
+    }
+
+    if signed.len() < original.len() {
+        let missing = original.len() - signed.len();
+        record(
+            signed.len(),
+            missing,
+            format!(
+                "Signed copy is {} byte(s) shorter than the original",
+                missing
+            ),
+        );
+    }
+
+    // Appended data not covered by any signature
+    let unsigned_start = signed_end.max(original.len());
+    if signed.len() > unsigned_start {
+        let len = signed.len() - unsigned_start;
+        record(
+            unsigned_start,
+            len,
+            format!("{} byte(s) appended after the last signature", len),
+        );
+    }
+
+    let listed = report.modifications.len();
+    if listed == MAX_MODIFICATION_RECORDS {
+        report.modifications.push(ModificationRecord {
+            offset: 0,
+            description: format!(
+                "Only the first {} differences are listed",
+                MAX_MODIFICATION_RECORDS
+            ),
+        });
+    }
+    Ok(report)
+}
+
+/// Verify one signature dictionary; failures are reported in the result
+fn verify_signature(
+    file: &[u8],
+    field_name: String,
+    sig_dict: &Dictionary,
+) -> SignatureVerificationResult {
+    let text = |key: &[u8]| {
+        sig_dict
+            .get(key)
+            .and_then(|v| v.as_str())
+            .ok()
+            .map(|s| String::from_utf8_lossy(s).to_string())
+    };
+
+    let mut result = SignatureVerificationResult {
+        field_name,
+        signer_name: text(b"Name").unwrap_or_default(),
+        signing_time: text(b"M"),
+        is_valid: false,
+        cert_expired: false,
+        modification_detected: false,
+        error: None,
+    };
+    if let Err(e) = check_signature(file, sig_dict, &mut result) {
+        result.error = Some(e);
+    }
+    result
+}
+
+/// Fill in `result` while checking the signature, failing on the first problem
+fn check_signature(
+    file: &[u8],
+    sig_dict: &Dictionary,
+    result: &mut SignatureVerificationResult,
+) -> Result<(), String> {
+    parse_sig_sub_filter(sig_dict)
+        .ensure_verifiable()
+        .map_err(|e| match e {
+            ESignError::Signing { message, .. } => message,
+            other => other.to_string(),
+        })?;
+
+    let byte_range = read_byte_range(sig_dict, file.len())?;
+    result.modification_detected = byte_range[2] + byte_range[3] != file.len();
+
+    // lopdf has already hex-decoded /Contents; strip the zero padding after the CMS
+    let contents = sig_dict
+        .get(b"Contents")
+        .and_then(|c| c.as_str())
+        .map_err(|_| "Missing /Contents".to_string())?;
+    let (_, _, padding) = read_tlv(contents).ok_or("Invalid CMS in /Contents")?;
+    let cms = &contents[..contents.len() - padding.len()];
+    let signer = parse_signer_data(cms).ok_or("Invalid CMS SignedData")?;
+
+    let (_, cert) = X509Certificate::from_der(&signer.certificate)
+        .map_err(|e| format!("Invalid signer certificate: {}", e))?;
+    if let Some(cn) = common_name(&cert) {
+        result.signer_name = cn;
+    }
+    if signer.signing_time.is_some() {
+        result.signing_time = signer.signing_time.clone();
+    }
+    result.cert_expired = cert.validity().not_after.timestamp() < chrono::Utc::now().timestamp();
+
+    if signer.digest_algorithm != OID_SHA256 {
+        return Err("Unsupported digest algorithm (SHA-256 required)".to_string());
+    }
+
+    let first = &file[byte_range[0]..byte_range[0] + byte_range[1]];
+    let second = &file[byte_range[2]..byte_range[2] + byte_range[3]];
+
+    // With signed attributes the signature covers them, and they carry the content digest
+    let signed_content = match signer.signed_attrs {
+        Some(attrs) => {
+            let mut hasher = Sha256::new();
+            hasher.update(first);
+            hasher.update(second);
+            if signer.message_digest.as_deref() != Some(hasher.finalize().as_slice()) {
+                return Err("Document digest does not match (content was modified)".to_string());
+            }
+            attrs
+        }
+        None => [first, second].concat(),
+    };
+
+    verify_with_certificate(&cert, &signed_content, &signer.signature)?;
+    result.is_valid = true;
+    Ok(())
+}
+
+/// /ByteRange as [offset1, length1, offset2, length2], checked against the file size
+fn read_byte_range(sig_dict: &Dictionary, file_len: usize) -> Result<[usize; 4], String> {
+    let values = sig_dict
+        .get(b"ByteRange")
+        .and_then(|v| v.as_array())
+        .map_err(|_| "Missing /ByteRange".to_string())?
+        .iter()
+        .map(|v| v.as_i64().ok().and_then(|n| usize::try_from(n).ok()))
+        .collect::<Option<Vec<usize>>>()
+        .ok_or("Invalid /ByteRange")?;
+    let byte_range: [usize; 4] = values
+        .try_into()
+        .map_err(|_| "Invalid /ByteRange".to_string())?;
+
+    let ordered = byte_range[0]
+        .checked_add(byte_range[1])
+        .is_some_and(|end| end <= byte_range[2]);
+    let in_file = byte_range[2]
+        .checked_add(byte_range[3])
+        .is_some_and(|end| end <= file_len);
+    if !ordered || !in_file {
+        return Err("/ByteRange outside the file".to_string());
+    }
+    Ok(byte_range)
+}
+
+/// Walk ContentInfo -> SignedData -> first SignerInfo and pick the signer certificate
+fn parse_signer_data(cms: &[u8]) -> Option<SignerData> {
+    let (_, content_info, _) = read_tlv(cms)?;
+    let content_info = der_children(content_info)?;
+    let (_, signed_data, _) = read_tlv(&content_info.get(1)?.1)?;
+    let signed_data = der_children(signed_data)?;
+
+    let certificates = signed_data
+        .iter()
+        .find(|(tag, _)| *tag == 0xA0)
+        .and_then(|(_, certs)| der_children(certs))?;
+    let (_, signer_infos) = signed_data.last().filter(|(tag, _)| *tag == 0x31)?;
+    let signer_info = der_children(&der_children(signer_infos)?.first()?.1)?;
+
+    // version, sid, digestAlgorithm, [0] signedAttrs OPTIONAL, signatureAlgorithm, signature
+    let digest_algorithm = der_children(&signer_info.get(2)?.1)?.first()?.1.clone();
+    let (signed_attrs, rest) = match signer_info.get(3)? {
+        (0xA0, attrs) => (Some(attrs), &signer_info[4..]),
+        _ => (None, &signer_info[3..]),
+    };
+    let (_, signature) = rest.get(1).filter(|(tag, _)| *tag == 0x04)?;
+
+    let mut message_digest = None;
+    let mut signing_time = None;
+    let attributes = match signed_attrs {
+        Some(attrs) => der_children(attrs)?,
+        None => Vec::new(),
+    };
+    for (_, attribute) in attributes {
+        let attribute = der_children(&attribute)?;
+        let (Some((_, oid)), Some((_, values))) = (attribute.first(), attribute.get(1)) else {
+            continue;
+        };
+        let value = der_children(values)?.into_iter().next();
+        if oid == OID_MESSAGE_DIGEST {
+            message_digest = value.map(|(_, digest)| digest);
+        } else if oid == OID_SIGNING_TIME {
+            signing_time = value.and_then(|(tag, time)| format_asn1_time(tag, &time));
+        }
+    }
+
+    Some(SignerData {
+        certificate: signer_certificate(&signer_info.get(1)?.1, &certificates)?,
+        digest_algorithm,
+        signed_attrs: signed_attrs
+            .map(|attrs| encode_children(&[(0x31, attrs.clone())]))
+            .transpose()
+            .ok()?,
+        message_digest,
+        signing_time,
+        signature: signature.clone(),
+    })
+}
+
+/// Certificate matching the IssuerAndSerialNumber sid, else the first certificate
+fn signer_certificate(sid: &[u8], certificates: &[(u8, Vec<u8>)]) -> Option<Vec<u8>> {
+    let encoded: Vec<Vec<u8>> = certificates
+        .iter()
+        .filter(|(tag, _)| *tag == 0x30)
+        .map(|cert| encode_children(std::slice::from_ref(cert)))
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let issuer_and_serial = der_children(sid).filter(|fields| fields.len() == 2);
+    let matching = issuer_and_serial.and_then(|fields| {
+        let issuer = encode_children(&fields[..1]).ok()?;
+        encoded.iter().find(|der| {
+            X509Certificate::from_der(der).is_ok_and(|(_, cert)| {
+                cert.issuer().as_raw() == issuer.as_slice()
+                    && cert.tbs_certificate.raw_serial() == fields[1].1.as_slice()
+            })
+        })
+    });
+
+    matching.or_else(|| encoded.first()).cloned()
+}
+
+/// Verify a SHA-256 RSA (PKCS#1 v1.5) or ECDSA P-256 signature with the certificate key
+fn verify_with_certificate(
+    cert: &X509Certificate,
+    data: &[u8],
+    signature: &[u8],
+) -> Result<(), String> {
+    const MISMATCH: &str = "Signature does not match the signer certificate";
+    let spki = cert.public_key();
+
+    match spki.algorithm.algorithm.to_id_string().as_str() {
+        RSA_ENCRYPTION_OID => match verify_rsa_pkcs1_v15_sha256(spki.raw, data, signature) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(MISMATCH.to_string()),
+            Err(e) => Err(e.to_string()),
+        },
+        EC_PUBLIC_KEY_OID => {
+            let curve = spki
+                .algorithm
+                .parameters
+                .as_ref()
+                .and_then(|params| params.as_oid().ok())
+                .map(|oid| oid.to_id_string());
+            if curve.as_deref() != Some(P256_CURVE_OID) {
+                return Err("Unsupported EC curve (P-256 required)".to_string());
+            }
+            let point: &[u8] = spki.subject_public_key.data.as_ref();
+            ring::signature::UnparsedPublicKey::new(&ring::signature::ECDSA_P256_SHA256_ASN1, point)
+                .verify(data, signature)
+                .map_err(|_| MISMATCH.to_string())
+        }
+        other => Err(format!("Unsupported signer key algorithm {}", other)),
+    }
+}
+
+/// First CN of the certificate subject
+fn common_name(cert: &X509Certificate) -> Option<String> {
+    cert.subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(str::to_string)
+}
+
+/// UTCTime / GeneralizedTime as RFC 3339
+fn format_asn1_time(tag: u8, bytes: &[u8]) -> Option<String> {
+    let format = match tag {
+        0x17 => "%y%m%d%H%M%SZ",
+        0x18 => "%Y%m%d%H%M%SZ",
+        _ => return None,
+    };
+    let time = chrono::NaiveDateTime::parse_from_str(std::str::from_utf8(bytes).ok()?, format);
+    time.ok().map(|t| t.and_utc().to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf::{PdfSigner, PdfSigningEngine};
+    use crate::test_utils::{sample_pdf, sign_with_test_key, test_identity};
+
+    /// Sign a sample PDF through the file API and return the signed bytes
+    fn sign_sample(
+        name: &str,
+        sign_fn: impl Fn(&[u8]) -> Result<Vec<u8>, ESignError>,
+        cert_der: &[u8],
+    ) -> Vec<u8> {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("esign_verify_{}_input.pdf", name));
+        let output = dir.join(format!("esign_verify_{}_output.pdf", name));
+        std::fs::write(&input, sample_pdf(1)).unwrap();
+
+        PdfSigningEngine::new()
+            .sign_pdf(
+                input.to_str().unwrap(),
+                output.to_str().unwrap(),
+                &PdfSigner::default(),
+                sign_fn,
+                cert_der,
+            )
+            .unwrap();
+        let signed = std::fs::read(&output).unwrap();
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        signed
+    }
+
+    fn sign_sample_rsa(name: &str) -> Vec<u8> {
+        sign_sample(name, sign_with_test_key, &test_identity().cert_der)
+    }
+
+    // ============ Verification Tests ============
+
+    #[test]
+    fn test_verify_valid_rsa_signature() {
+        let results = verify_pdf_bytes(&sign_sample_rsa("rsa")).unwrap();
+
+        assert_eq!(results.len(), 1);
+        let result = &results[0];
+        assert!(result.is_valid, "{:?}", result.error);
+        assert_eq!(result.field_name, "Signature1");
+        assert_eq!(result.signer_name, "Test Signer");
+        assert!(result.signing_time.as_deref().unwrap().ends_with("+00:00"));
+        assert!(!result.cert_expired);
+        assert!(!result.modification_detected);
+        assert_eq!(result.error, None);
+    }
+
+    #[test]
+    fn test_verify_valid_ecdsa_signature() {
+        use crate::test_utils::build_ec_certificate;
+        use ring::rand::SystemRandom;
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+
+        let rng = SystemRandom::new();
+        let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).unwrap();
+        let key_pair =
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+                .unwrap();
+        let cert = build_ec_certificate(
+            &test_identity().key,
+            "EC Signer",
+            key_pair.public_key().as_ref(),
+        );
+        let sign_fn = |data: &[u8]| Ok(key_pair.sign(&rng, data).unwrap().as_ref().to_vec());
+
+        let results = verify_pdf_bytes(&sign_sample("ecdsa", sign_fn, &cert)).unwrap();
+        assert!(results[0].is_valid, "{:?}", results[0].error);
+        assert_eq!(results[0].signer_name, "EC Signer");
+    }
+
+    #[test]
+    fn test_verify_detects_modified_content() {
+        let mut signed = sign_sample_rsa("modified");
+        // Change the page text inside the first signed range
+        let pos = signed.windows(8).position(|w| w == b"(Page 1)").unwrap();
+        signed[pos + 6] = b'7';
+
+        let results = verify_pdf_bytes(&signed).unwrap();
+        assert!(!results[0].is_valid);
+        assert!(results[0].error.as_deref().unwrap().contains("digest"));
+    }
+
+    #[test]
+    fn test_verify_detects_appended_bytes() {
+        let mut signed = sign_sample_rsa("appended");
+        signed.extend_from_slice(b"\n% appended after signing\n");
+
+        let results = verify_pdf_bytes(&signed).unwrap();
+        assert!(results[0].is_valid);
+        assert!(results[0].modification_detected);
+    }
+
+    #[test]
+    fn test_verify_wrong_certificate() {
+        use crate::test_utils::build_certificate;
+        use rsa::RsaPrivateKey;
+
+        let other_key = RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap();
+        let other_cert = build_certificate(&other_key, "Other", "250101000000Z", "491231235959Z");
+
+        let results =
+            verify_pdf_bytes(&sign_sample("wrong_cert", sign_with_test_key, &other_cert)).unwrap();
+        assert!(!results[0].is_valid);
+        assert_eq!(
+            results[0].error.as_deref(),
+            Some("Signature does not match the signer certificate")
+        );
+    }
+
+    #[test]
+    fn test_verify_expired_certificate() {
+        use crate::test_utils::build_certificate;
+
+        let identity = test_identity();
+        let expired = build_certificate(&identity.key, "Expired", "200101000000Z", "210101000000Z");
+
+        let results =
+            verify_pdf_bytes(&sign_sample("expired", sign_with_test_key, &expired)).unwrap();
+        assert!(results[0].is_valid, "{:?}", results[0].error);
+        assert!(results[0].cert_expired);
+    }
+
+    #[test]
+    fn test_verify_unsigned_pdf() {
+        assert!(verify_pdf_bytes(&sample_pdf(1)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_verify_not_a_pdf() {
+        assert!(verify_pdf_bytes(b"not a pdf").is_err());
+    }
+
+    // ============ Diff Tests ============
+
+    /// Sample PDF and the same file signed as an incremental update
+    fn original_and_incremental_copy(name: &str) -> (Vec<u8>, Vec<u8>) {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("esign_diff_{}_input.pdf", name));
+        let output = dir.join(format!("esign_diff_{}_output.pdf", name));
+        let original = sample_pdf(2);
+        std::fs::write(&input, &original).unwrap();
+
+        PdfSigningEngine::new()
+            .sign_pdf_incremental(
+                input.to_str().unwrap(),
+                output.to_str().unwrap(),
+                &PdfSigner::default(),
+                sign_with_test_key,
+                &test_identity().cert_der,
+            )
+            .unwrap();
+        let signed = std::fs::read(&output).unwrap();
+
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        (original, signed)
+    }
+
+    #[test]
+    fn test_diff_incremental_signature_is_clean() {
+        let (original, signed) = original_and_incremental_copy("clean");
+        let report = diff_pdf_bytes(&original, &signed).unwrap();
+
+        assert_eq!(report.original_pages, 2);
+        assert_eq!(report.signed_pages, 2);
+        assert_eq!(report.bytes_outside_signature, 0);
+        assert!(report.modifications.is_empty());
+    }
+
+    #[test]
+    fn test_diff_detects_changed_original_bytes() {
+        let (original, mut signed) = original_and_incremental_copy("changed");
+        let pos = signed.windows(8).position(|w| w == b"(Page 2)").unwrap();
+        signed[pos + 6] = b'9';
+
+        let report = diff_pdf_bytes(&original, &signed).unwrap();
+        assert_eq!(report.bytes_outside_signature, 1);
+        assert_eq!(report.modifications.len(), 1);
+        assert_eq!(report.modifications[0].offset, pos + 6);
+    }
+
+    #[test]
+    fn test_diff_detects_unsigned_append() {
+        let (original, mut signed) = original_and_incremental_copy("appended");
+        let tail = b"\n% appended after signing\n";
+        signed.extend_from_slice(tail);
+
+        let report = diff_pdf_bytes(&original, &signed).unwrap();
+        assert_eq!(report.bytes_outside_signature, tail.len() as u64);
+        assert_eq!(report.modifications[0].offset, signed.len() - tail.len());
+        assert!(report.modifications[0].description.contains("appended"));
+    }
+
+    #[test]
+    fn test_diff_identical_and_invalid_files() {
+        let original = sample_pdf(1);
+        let report = diff_pdf_bytes(&original, &original).unwrap();
+        assert_eq!(report.bytes_outside_signature, 0);
+
+        assert!(diff_pdf_bytes(&original, b"not a pdf").is_err());
+    }
+
+    // ============ Helper Tests ============
+
+    #[test]
+    fn test_read_byte_range_rejects_out_of_file() {
+        let dict = |values: Vec<i64>| {
+            lopdf::dictionary! {
+                "ByteRange" => values.into_iter().map(lopdf::Object::Integer).collect::<Vec<_>>(),
+            }
+        };
+        assert_eq!(
+            read_byte_range(&dict(vec![0, 10, 20, 30]), 50).unwrap(),
+            [0, 10, 20, 30]
+        );
+        assert!(read_byte_range(&dict(vec![0, 10, 20, 31]), 50).is_err());
+        assert!(read_byte_range(&dict(vec![0, 30, 20, 10]), 50).is_err());
+        assert!(read_byte_range(&dict(vec![0, -1, 20, 10]), 50).is_err());
+        assert!(read_byte_range(&dict(vec![0, 10, 20]), 50).is_err());
+        assert!(read_byte_range(&Dictionary::new(), 50).is_err());
+    }
+
+    #[test]
+    fn test_format_asn1_time() {
+        assert_eq!(
+            format_asn1_time(0x17, b"250301120000Z").as_deref(),
+            Some("2025-03-01T12:00:00+00:00")
+        );
+        assert_eq!(
+            format_asn1_time(0x18, b"20550301120000Z").as_deref(),
+            Some("2055-03-01T12:00:00+00:00")
+        );
+        assert_eq!(format_asn1_time(0x04, b"250301120000Z"), None);
+    }
+}
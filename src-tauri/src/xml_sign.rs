@@ -0,0 +1,538 @@
+//! XML Signature Module
+//!
+//! Enveloped XMLDSig signatures for XML documents such as tax declarations.
+//! The whole document is referenced (`URI=""`) through the enveloped-signature
+//! and inclusive Canonical XML 1.0 (without comments) transforms, digested with
+//! SHA-256 and signed on the token; the `<ds:Signature>` element is appended as
+//! the last child of the root element.
+
+use crate::error::{ESignError, SigningErrorCode};
+use crate::pdf::{reject_system_input_path, validate_output_path, write_output_atomically};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use der::asn1::{SequenceOf, UintRef};
+use der::Decode;
+use roxmltree::{Document, Node, NodeType};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+const XMLDSIG_NS: &str = "http://www.w3.org/2000/09/xmldsig#";
+const XML_NS: &str = "http://www.w3.org/XML/1998/namespace";
+const ALG_C14N: &str = "http://www.w3.org/TR/2001/REC-xml-c14n-20010315";
+const ALG_ENVELOPED: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const ALG_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const ALG_RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+const ALG_ECDSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#ecdsa-sha256";
+
+/// id-ecPublicKey (1.2.840.10045.2.1)
+const EC_PUBLIC_KEY_OID: &str = "1.2.840.10045.2.1";
+
+/// Result of signing an XML file
+#[derive(Debug, Clone, Serialize)]
+pub struct XmlSignResult {
+    pub success: bool,
+    pub output_path: String,
+}
+
+/// Sign `xml_path` and write the enveloped signature to `output_path` (.xml)
+/// `sign_fn` signs with SHA-256 like `TokenManager::sign` (RSA PKCS#1 v1.5 or DER ECDSA)
+pub fn sign_xml_file<F>(
+    xml_path: &str,
+    output_path: &str,
+    sign_fn: F,
+    cert_der: &[u8],
+) -> Result<XmlSignResult, ESignError>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, ESignError>,
+{
+    let input = validate_xml_input_path(xml_path)?;
+    let output = validate_output_path(output_path, &["xml"])?;
+
+    let xml = std::fs::read_to_string(&input)?;
+    let signed = sign_xml_document(&xml, sign_fn, cert_der)?;
+    write_output_atomically(&output, signed.as_bytes())?;
+
+    Ok(XmlSignResult {
+        success: true,
+        output_path: output.to_string_lossy().to_string(),
+    })
+}
+
+/// Return `xml` with an enveloped `<ds:Signature>` appended to the root element
+/// DTDs are rejected by the parser, so entity expansion cannot change what is signed
+pub fn sign_xml_document<F>(xml: &str, sign_fn: F, cert_der: &[u8]) -> Result<String, ESignError>
+where
+    F: FnOnce(&[u8]) -> Result<Vec<u8>, ESignError>,
+{
+    let doc = Document::parse(xml)
+        .map_err(|e| ESignError::invalid_input(format!("Invalid XML: {}", e)))?;
+    let root = doc.root_element();
+
+    // The signature is not in the document yet, so this equals the enveloped transform output
+    let digest = BASE64.encode(Sha256::digest(canonicalize(&doc).as_bytes()));
+
+    let is_ec = is_ec_certificate(cert_der)?;
+    let signature_method = if is_ec {
+        ALG_ECDSA_SHA256
+    } else {
+        ALG_RSA_SHA256
+    };
+
+    // SignedInfo as canonicalized by a verifier: the apex carries every in-scope namespace
+    let mut namespaces: Vec<(&str, &str)> = in_scope_namespaces(root)
+        .into_iter()
+        .filter(|(prefix, _)| *prefix != "ds")
+        .collect();
+    namespaces.push(("ds", XMLDSIG_NS));
+    namespaces.sort();
+    let mut declarations = String::new();
+    for (prefix, uri) in &namespaces {
+        write_namespace(&mut declarations, prefix, uri);
+    }
+    let canonical_signed_info = signed_info(&declarations, signature_method, &digest);
+
+    let signature = sign_fn(canonical_signed_info.as_bytes())?;
+    let signature = if is_ec {
+        ecdsa_der_to_raw(&signature, ec_coordinate_length(cert_der)?)?
+    } else {
+        signature
+    };
+
+    let signature_element = format!(
+        "<ds:Signature xmlns:ds=\"{}\">{}<ds:SignatureValue>{}</ds:SignatureValue>\
+         <ds:KeyInfo><ds:X509Data><ds:X509Certificate>{}</ds:X509Certificate>\
+         </ds:X509Data></ds:KeyInfo></ds:Signature>",
+        XMLDSIG_NS,
+        signed_info("", signature_method, &digest),
+        BASE64.encode(&signature),
+        BASE64.encode(cert_der),
+    );
+
+    Ok(insert_before_root_end(xml, root, &signature_element))
+}
+
+/// Canonical XML 1.0 (inclusive, comments omitted) of the whole document
+fn canonicalize(doc: &Document) -> String {
+    let mut out = String::new();
+    let mut after_root = false;
+    for node in doc.root().children() {
+        match node.node_type() {
+            NodeType::Element => {
+                write_element(&mut out, node);
+                after_root = true;
+            }
+            NodeType::PI => {
+                if after_root {
+                    out.push('\n');
+                }
+                write_pi(&mut out, node);
+                if !after_root {
+                    out.push('\n');
+                }
+            }
+            // Comments are omitted; whitespace outside the root element is not part of the model
+            _ => {}
+        }
+    }
+    out
+}
+
+fn write_element(out: &mut String, node: Node) {
+    let name = element_qname(node);
+    out.push('<');
+    out.push_str(name);
+
+    // Namespace declarations not already in scope on the parent; xmlns="" when the
+    // parent's default namespace is undeclared here
+    let own = in_scope_namespaces(node);
+    let inherited = node
+        .parent_element()
+        .map(in_scope_namespaces)
+        .unwrap_or_default();
+    let has_default = |namespaces: &[(&str, &str)]| namespaces.iter().any(|(p, _)| p.is_empty());
+    if !has_default(&own) && has_default(&inherited) {
+        out.push_str(" xmlns=\"\"");
+    }
+    for (prefix, uri) in &own {
+        if !inherited.contains(&(*prefix, *uri)) {
+            write_namespace(out, prefix, uri);
+        }
+    }
+
+    // Attributes sorted by namespace URI (none first), then local name
+    let mut attributes: Vec<_> = node.attributes().collect();
+    attributes.sort_by(|a, b| {
+        (a.namespace().unwrap_or(""), a.name()).cmp(&(b.namespace().unwrap_or(""), b.name()))
+    });
+    for attr in attributes {
+        out.push(' ');
+        if let Some(uri) = attr.namespace() {
+            out.push_str(attribute_prefix(node, uri));
+            out.push(':');
+        }
+        out.push_str(attr.name());
+        out.push_str("=\"");
+        escape_attribute(out, attr.value());
+        out.push('"');
+    }
+    out.push('>');
+
+    for child in node.children() {
+        match child.node_type() {
+            NodeType::Element => write_element(out, child),
+            NodeType::Text => escape_text(out, child.text().unwrap_or_default()),
+            NodeType::PI => write_pi(out, child),
+            _ => {}
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(name);
+    out.push('>');
+}
+
+fn write_pi(out: &mut String, node: Node) {
+    if let Some(pi) = node.pi() {
+        out.push_str("<?");
+        out.push_str(pi.target);
+        if let Some(value) = pi.value.filter(|value| !value.is_empty()) {
+            out.push(' ');
+            out.push_str(value);
+        }
+        out.push_str("?>");
+    }
+}
+
+fn write_namespace(out: &mut String, prefix: &str, uri: &str) {
+    out.push_str(" xmlns");
+    if !prefix.is_empty() {
+        out.push(':');
+        out.push_str(prefix);
+    }
+    out.push_str("=\"");
+    escape_attribute(out, uri);
+    out.push('"');
+}
+
+/// In-scope namespaces as (prefix, uri), default namespace as "", sorted by prefix
+/// The implicit `xml` prefix and an undeclared default namespace are left out
+fn in_scope_namespaces<'a>(node: Node<'a, '_>) -> Vec<(&'a str, &'a str)> {
+    let mut namespaces: Vec<_> = node
+        .namespaces()
+        .map(|ns| (ns.name().unwrap_or(""), ns.uri()))
+        .filter(|(prefix, uri)| *prefix != "xml" && !uri.is_empty())
+        .collect();
+    namespaces.sort();
+    namespaces
+}
+
+/// Element name as written in the source, prefix included
+fn element_qname<'a>(node: Node<'a, '_>) -> &'a str {
+    let start_tag = &node.document().input_text()[node.range()];
+    let name = &start_tag[1..];
+    let end = name
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(name.len());
+    &name[..end]
+}
+
+/// Prefix bound to `uri` for a namespaced attribute (never the default namespace)
+fn attribute_prefix<'a>(node: Node<'a, '_>, uri: &str) -> &'a str {
+    if uri == XML_NS {
+        return "xml";
+    }
+    node.namespaces()
+        .find(|ns| ns.uri() == uri && ns.name().is_some())
+        .and_then(|ns| ns.name())
+        .unwrap_or_default()
+}
+
+fn escape_text(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+fn escape_attribute(out: &mut String, value: &str) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            _ => out.push(c),
+        }
+    }
+}
+
+/// SignedInfo in canonical form; `declarations` holds the namespace attributes of the apex
+fn signed_info(declarations: &str, signature_method: &str, digest: &str) -> String {
+    format!(
+        "<ds:SignedInfo{declarations}>\
+         <ds:CanonicalizationMethod Algorithm=\"{ALG_C14N}\"></ds:CanonicalizationMethod>\
+         <ds:SignatureMethod Algorithm=\"{signature_method}\"></ds:SignatureMethod>\
+         <ds:Reference URI=\"\"><ds:Transforms>\
+         <ds:Transform Algorithm=\"{ALG_ENVELOPED}\"></ds:Transform>\
+         <ds:Transform Algorithm=\"{ALG_C14N}\"></ds:Transform>\
+         </ds:Transforms>\
+         <ds:DigestMethod Algorithm=\"{ALG_SHA256}\"></ds:DigestMethod>\
+         <ds:DigestValue>{digest}</ds:DigestValue>\
+         </ds:Reference></ds:SignedInfo>"
+    )
+}
+
+/// Insert `element` as the last child of `root`, expanding a self-closing root tag
+fn insert_before_root_end(xml: &str, root: Node, element: &str) -> String {
+    let range = root.range();
+    let root_xml = &xml[range.clone()];
+    let mut signed = String::with_capacity(xml.len() + element.len() + 16);
+    if root_xml.ends_with("/>") {
+        signed.push_str(&xml[..range.end - 2]);
+        signed.push('>');
+        signed.push_str(element);
+        signed.push_str("</");
+        signed.push_str(element_qname(root));
+        signed.push('>');
+    } else {
+        let end_tag = range.start + root_xml.rfind("</").unwrap_or(root_xml.len());
+        signed.push_str(&xml[..end_tag]);
+        signed.push_str(element);
+        signed.push_str(&xml[end_tag..range.end]);
+    }
+    signed.push_str(&xml[range.end..]);
+    signed
+}
+
+fn is_ec_certificate(cert_der: &[u8]) -> Result<bool, ESignError> {
+    use x509_parser::prelude::*;
+
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .map_err(|e| ESignError::invalid_input(format!("Failed to parse certificate: {}", e)))?;
+    Ok(cert.public_key().algorithm.algorithm.to_id_string() == EC_PUBLIC_KEY_OID)
+}
+
+/// Coordinate size of the certificate's uncompressed EC point (0x04 || X || Y)
+fn ec_coordinate_length(cert_der: &[u8]) -> Result<usize, ESignError> {
+    use x509_parser::prelude::*;
+
+    let (_, cert) = X509Certificate::from_der(cert_der)
+        .map_err(|e| ESignError::invalid_input(format!("Failed to parse certificate: {}", e)))?;
+    let point: &[u8] = cert.public_key().subject_public_key.data.as_ref();
+    Ok(point.len().saturating_sub(1) / 2)
+}
+
+/// Convert a DER ECDSA-Sig-Value to the fixed-width r || s form XMLDSig expects
+fn ecdsa_der_to_raw(signature: &[u8], width: usize) -> Result<Vec<u8>, ESignError> {
+    let invalid = |message: String| ESignError::Signing {
+        code: SigningErrorCode::SigningFailed,
+        message,
+    };
+    let values = SequenceOf::<UintRef, 2>::from_der(signature)
+        .map_err(|e| invalid(format!("Invalid ECDSA signature: {}", e)))?;
+
+    let mut raw = Vec::with_capacity(width * 2);
+    for value in values.iter() {
+        let bytes = value.as_bytes();
+        if bytes.len() > width {
+            return Err(invalid(format!(
+                "ECDSA signature value longer than {} bytes",
+                width
+            )));
+        }
+        raw.resize(raw.len() + width - bytes.len(), 0);
+        raw.extend_from_slice(bytes);
+    }
+    if raw.len() != width * 2 {
+        return Err(invalid("ECDSA signature must hold r and s".to_string()));
+    }
+    Ok(raw)
+}
+
+/// Validate XML input path, like `validate_pdf_input_path` for .xml files
+fn validate_xml_input_path(path: &str) -> Result<PathBuf, ESignError> {
+    let path = Path::new(path);
+    let canonical = path.canonicalize().map_err(|e| {
+        ESignError::invalid_input(format!("Invalid input path '{}': {}", path.display(), e))
+    })?;
+
+    let ext = canonical
+        .extension()
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    if ext != "xml" {
+        return Err(ESignError::invalid_input(format!(
+            "Not an XML file: {}",
+            canonical.display()
+        )));
+    }
+
+    reject_system_input_path(&canonical)?;
+    Ok(canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{sign_with_test_key, test_identity};
+    use rsa::pkcs1v15::{Signature, VerifyingKey};
+    use rsa::signature::Verifier;
+
+    fn canonical(xml: &str) -> String {
+        canonicalize(&Document::parse(xml).unwrap())
+    }
+
+    /// Remove the `<ds:Signature>` element, as the enveloped-signature transform does
+    fn strip_signature(signed: &str) -> String {
+        let start = signed.find("<ds:Signature ").unwrap();
+        let end = signed.find("</ds:Signature>").unwrap() + "</ds:Signature>".len();
+        format!("{}{}", &signed[..start], &signed[end..])
+    }
+
+    fn element_text<'a>(doc: &'a Document, name: &str) -> &'a str {
+        doc.descendants()
+            .find(|n| n.has_tag_name((XMLDSIG_NS, name)))
+            .and_then(|n| n.text())
+            .unwrap()
+    }
+
+    // ============ Canonicalization Tests ============
+
+    #[test]
+    fn test_canonicalize_sorts_attributes_and_expands_empty_elements() {
+        let xml = "<?xml version=\"1.0\"?>\n<!-- header -->\n<a b=\"2\" a=\"1\"><c/><!-- note --><d>x &amp; y &gt; z</d></a>";
+        assert_eq!(
+            canonical(xml),
+            "<a a=\"1\" b=\"2\"><c></c><d>x &amp; y &gt; z</d></a>"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_namespaces() {
+        let xml = "<r xmlns=\"urn:d\" xmlns:b=\"urn:b\" xmlns:a=\"urn:a\"><a:x a:k=\"v\" k=\"w\"/><y xmlns=\"\"/></r>";
+        assert_eq!(
+            canonical(xml),
+            "<r xmlns=\"urn:d\" xmlns:a=\"urn:a\" xmlns:b=\"urn:b\"><a:x k=\"w\" a:k=\"v\"></a:x><y xmlns=\"\"></y></r>"
+        );
+    }
+
+    #[test]
+    fn test_canonicalize_attribute_escaping_and_pis() {
+        let xml = "<?pi first?><r v='a\"&lt;&#9;'/><?pi last?>";
+        assert_eq!(
+            canonical(xml),
+            "<?pi first?>\n<r v=\"a&quot;&lt;&#x9;\"></r>\n<?pi last?>"
+        );
+    }
+
+    // ============ XML Signing Tests ============
+
+    #[test]
+    fn test_sign_xml_document_enveloped_signature() {
+        let xml = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<HSoThueDTu xmlns=\"http://kekhaithue.gdt.gov.vn/TKhaiThue\"><HSoKhaiThue id=\"_NODE_TO_SIGN\"><TTinChung>Công ty A</TTinChung></HSoKhaiThue></HSoThueDTu>";
+        let identity = test_identity();
+        let signed = sign_xml_document(xml, sign_with_test_key, &identity.cert_der).unwrap();
+
+        assert!(signed.ends_with("</ds:Signature></HSoThueDTu>"));
+        assert_eq!(strip_signature(&signed), xml);
+
+        let doc = Document::parse(&signed).unwrap();
+        let signature = doc.root_element().last_element_child().unwrap();
+        assert!(signature.has_tag_name((XMLDSIG_NS, "Signature")));
+
+        let digest = BASE64.encode(Sha256::digest(canonical(xml).as_bytes()));
+        assert_eq!(element_text(&doc, "DigestValue"), digest);
+        assert_eq!(
+            BASE64
+                .decode(element_text(&doc, "X509Certificate"))
+                .unwrap(),
+            identity.cert_der
+        );
+
+        // What a verifier canonicalizes: SignedInfo with the inherited default namespace
+        let expected_signed_info = signed_info(
+            " xmlns=\"http://kekhaithue.gdt.gov.vn/TKhaiThue\" xmlns:ds=\"http://www.w3.org/2000/09/xmldsig#\"",
+            ALG_RSA_SHA256,
+            &digest,
+        );
+        let signature_value = BASE64.decode(element_text(&doc, "SignatureValue")).unwrap();
+        VerifyingKey::<Sha256>::new(identity.key.to_public_key())
+            .verify(
+                expected_signed_info.as_bytes(),
+                &Signature::try_from(signature_value.as_slice()).unwrap(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_sign_xml_document_self_closing_root() {
+        let signed = sign_xml_document(
+            "<root a=\"1\"/>",
+            sign_with_test_key,
+            &test_identity().cert_der,
+        )
+        .unwrap();
+        assert!(signed.starts_with("<root a=\"1\"><ds:Signature "));
+        assert!(signed.ends_with("</ds:Signature></root>"));
+        assert!(Document::parse(&signed).is_ok());
+    }
+
+    #[test]
+    fn test_sign_xml_document_rejects_invalid_xml() {
+        let err =
+            sign_xml_document("<root>", sign_with_test_key, &test_identity().cert_der).unwrap_err();
+        assert!(err.to_string().contains("Invalid XML"));
+
+        // DTDs (and their entities) are not accepted
+        let dtd = "<!DOCTYPE r [<!ENTITY e \"x\">]><r>&e;</r>";
+        assert!(sign_xml_document(dtd, sign_with_test_key, &test_identity().cert_der).is_err());
+    }
+
+    #[test]
+    fn test_sign_xml_file_round_trip() {
+        let dir = std::env::temp_dir().join("esign_xml_sign");
+        std::fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("invoice.xml");
+        let output = dir.join("invoice_signed.xml");
+        std::fs::write(&input, "<Invoice><Total>100</Total></Invoice>").unwrap();
+
+        let result = sign_xml_file(
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+            sign_with_test_key,
+            &test_identity().cert_der,
+        )
+        .unwrap();
+        assert!(result.success);
+        let signed = std::fs::read_to_string(&result.output_path).unwrap();
+        assert!(signed.contains("<ds:SignatureValue>"));
+
+        let not_xml = dir.join("invoice_signed.pdf");
+        assert!(sign_xml_file(
+            input.to_str().unwrap(),
+            not_xml.to_str().unwrap(),
+            sign_with_test_key,
+            &test_identity().cert_der,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_ecdsa_der_to_raw_pads_values() {
+        // SEQUENCE { INTEGER 0x01, INTEGER 0x00FF (positive, leading zero) }
+        let der = [0x30, 0x07, 0x02, 0x01, 0x01, 0x02, 0x02, 0x00, 0xFF];
+        assert_eq!(
+            ecdsa_der_to_raw(&der, 4).unwrap(),
+            vec![0, 0, 0, 1, 0, 0, 0, 0xFF]
+        );
+        assert!(ecdsa_der_to_raw(&der, 0).is_err());
+        assert!(ecdsa_der_to_raw(&[0x30, 0x00], 4).is_err());
+    }
+}
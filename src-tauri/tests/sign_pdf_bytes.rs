@@ -0,0 +1,96 @@
+//! Library API integration test
+//!
+//! Signs a synthetic PDF through the public `PdfSigningEngine` API with a
+//! fixture RSA key (no USB token) and verifies the result.
+
+use konek_esign_lib::verify::verify_pdf_signatures;
+use konek_esign_lib::{ESignError, PdfSigner, PdfSigningEngine};
+use lopdf::{dictionary, Document, Object, Stream};
+use rsa::pkcs1v15::SigningKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::signature::{SignatureEncoding, Signer};
+use rsa::RsaPrivateKey;
+use sha2::Sha256;
+
+/// Self-signed RSA-2048 certificate and its PKCS#8 key
+const CERT_DER: &[u8] = include_bytes!("fixtures/test_signer.cer");
+const KEY_DER: &[u8] = include_bytes!("fixtures/test_signer_key.der");
+
+/// One-page PDF with a single line of text
+fn minimal_pdf() -> Vec<u8> {
+    let mut doc = Document::with_version("1.5");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let content = b"BT /F1 24 Tf 100 700 Td (Integration test) Tj ET".to_vec();
+    let content_id = doc.add_object(Stream::new(dictionary! {}, content));
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "Contents" => content_id,
+        "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+
+    let mut output = Vec::new();
+    doc.save_to(&mut output).expect("Failed to save PDF");
+    output
+}
+
+/// SHA-256 with RSA PKCS#1 v1.5, like the token's CKM_SHA256_RSA_PKCS
+fn sign_with_fixture_key(data: &[u8]) -> Result<Vec<u8>, ESignError> {
+    let key = RsaPrivateKey::from_pkcs8_der(KEY_DER).expect("Invalid fixture key");
+    Ok(SigningKey::<Sha256>::new(key).sign(data).to_vec())
+}
+
+#[test]
+fn test_sign_pdf_bytes_produces_verifiable_signature() {
+    let signed = PdfSigningEngine::new()
+        .sign_pdf_bytes(
+            &minimal_pdf(),
+            &PdfSigner::default(),
+            sign_with_fixture_key,
+            CERT_DER,
+        )
+        .unwrap();
+    assert!(signed.starts_with(b"%PDF-"));
+
+    let path = std::env::temp_dir().join("esign_integration_signed.pdf");
+    std::fs::write(&path, &signed).unwrap();
+    let results = verify_pdf_signatures(path.to_str().unwrap()).unwrap();
+    let _ = std::fs::remove_file(&path);
+
+    assert_eq!(results.len(), 1);
+    let result = &results[0];
+    assert!(result.is_valid, "verification failed: {:?}", result.error);
+    assert!(!result.modification_detected);
+    assert!(!result.cert_expired);
+    assert_eq!(result.signer_name, "Integration Test Signer");
+}
+
+#[test]
+fn test_sign_pdf_bytes_propagates_signer_error() {
+    let result = PdfSigningEngine::new().sign_pdf_bytes(
+        &minimal_pdf(),
+        &PdfSigner::default(),
+        |_| Err(ESignError::Pkcs11("token removed".to_string())),
+        CERT_DER,
+    );
+    assert!(matches!(result, Err(ESignError::Pkcs11(_))));
+}
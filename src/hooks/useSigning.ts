@@ -4,7 +4,13 @@
  */
 
 import { useState, useCallback } from "react";
-import { signPdf, selectPdfFile, SignResult, SignatureAppearance } from "../lib/tauri";
+import {
+  signPdf,
+  selectPdfFile,
+  errorMessage,
+  SignResult,
+  SignatureAppearance,
+} from "../lib/tauri";
 import { PdfPosition } from "../lib/pdf-coordinates";
 
 export type SigningState =
@@ -142,7 +148,7 @@ export function useSigning(): UseSigningState & UseSigningActions {
         setState(selectedFile ? "file_selected" : "idle");
       }
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       setState("error");
     } finally {
       setIsProcessing(false);
@@ -189,7 +195,7 @@ export function useSigning(): UseSigningState & UseSigningActions {
         setState("error");
       }
     } catch (err) {
-      const errorMsg = err instanceof Error ? err.message : String(err);
+      const errorMsg = errorMessage(err);
       setError(mapErrorToVietnamese(errorMsg));
       setState("error");
     } finally {
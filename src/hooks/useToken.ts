@@ -17,6 +17,7 @@ import {
   getCertificate,
   loadSettings,
   saveSettings,
+  errorMessage,
 } from "../lib/tauri";
 
 export type ConnectionState =
@@ -99,7 +100,7 @@ export function useToken(): UseTokenState & UseTokenActions {
 
       setConnectionState("ready");
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       setConnectionState("error");
     } finally {
       setIsLoading(false);
@@ -207,7 +208,7 @@ export function useToken(): UseTokenState & UseTokenActions {
       setConnectionState("library_found");
       await selectLibrary(libraries[0]);
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
       setConnectionState("error");
     } finally {
       setIsLoading(false);
@@ -241,7 +242,7 @@ export function useToken(): UseTokenState & UseTokenActions {
         saveSettings({ ...settings, lastUsedSlot: selectedSlot });
       }
     } catch (err) {
-      const errorMsg = err instanceof Error ? err.message : String(err);
+      const errorMsg = errorMessage(err);
       // Map PKCS#11 errors to Vietnamese
       if (errorMsg.includes("PIN_INCORRECT") || errorMsg.includes("PIN incorrect")) {
         setError("Mã PIN không đúng");
@@ -265,7 +266,7 @@ export function useToken(): UseTokenState & UseTokenActions {
       setCertificate(null);
       setConnectionState("ready");
     } catch (err) {
-      setError(err instanceof Error ? err.message : String(err));
+      setError(errorMessage(err));
     } finally {
       setIsLoading(false);
     }
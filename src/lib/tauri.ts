@@ -10,9 +10,23 @@ import { open } from "@tauri-apps/plugin-dialog";
 
 export interface DetectedLibrary {
   ca_name: string;
+  /** First existing candidate path for the CA */
   path: string;
+  /** Position of path in the CA's candidate list (0 = preferred location) */
+  candidate_index: number;
+  /** Load probe result (async detection only; absent if not probed or timed out) */
+  loadable?: boolean;
+  /** Library version from C_GetInfo ("major.minor"); null if unreadable or not read */
+  library_version: string | null;
+  /** PKCS#11 API version; empty if unknown */
+  cryptoki_version: string;
+  manufacturer_id: string;
+  /** False when the library version has a known defect */
+  is_compatible: boolean;
+  compatibility_warning: string | null;
 }
 
+/** Also the payload of the "token-inserted", "token-removed" and "token-session-invalidated" events */
 export interface TokenInfo {
   slot_id: number;
   label: string;
@@ -20,6 +34,7 @@ export interface TokenInfo {
   model: string;
   serial: string;
   has_token: boolean;
+  retries_remaining: number | null;
 }
 
 export interface CertificateInfo {
@@ -30,6 +45,84 @@ export interface CertificateInfo {
   valid_to: string;
   thumbprint: string;
   der_base64: string;
+  valid_from_timestamp: number;
+  valid_to_timestamp: number;
+  /** Fraction of validity period elapsed (0.0-1.0) */
+  validity_fraction: number;
+  validity_class: "ok" | "warning" | "critical" | "expired";
+  /** Still valid but expires within 30 days */
+  expiring_soon: boolean;
+  /** OCSP responder from the AuthorityInfoAccess extension */
+  ocsp_url: string | null;
+  /** Issuer certificate URL (caIssuers) from the AuthorityInfoAccess extension */
+  ca_issuers_url: string | null;
+  /** CKA_ID on the token (hex); pass to selectCertificate */
+  cert_id: string | null;
+}
+
+export interface CertificateInfoExtended extends CertificateInfo {
+  /** "RSA", "EC", or the algorithm name for other keys */
+  key_algorithm: string;
+  key_size_bits: number | null;
+  /** Prefixed entries such as "email:a@b.vn" or "DNS:b.vn" */
+  subject_alt_names: string[];
+  /** e.g. "digitalSignature", "nonRepudiation" */
+  key_usages: string[];
+  extended_key_usages: string[];
+  is_self_signed: boolean;
+}
+
+export interface CertPolicyInfo {
+  policy_oid: string;
+  policy_name: string | null;
+  cps_uri: string | null;
+}
+
+export type CertExportFormat = "Pem" | "Der";
+
+/** OCSP certificate status from the CA's responder */
+export interface OcspResponse {
+  status: "good" | "revoked" | "unknown";
+  /** RFC 3339 timestamps */
+  produced_at: string;
+  this_update: string;
+  next_update: string | null;
+  revocation_time: string | null;
+  responder_url: string;
+}
+
+/** TSA server probe result */
+export interface TsaHealthResult {
+  url: string;
+  available: boolean;
+  latency_ms: number;
+  /** "Insecure transport" for plain HTTP servers */
+  warning: string | null;
+}
+
+export interface VendorInfo {
+  firmware_version: string | null;
+  serial_override: string | null;
+  /** Vendor attribute ID (e.g. 2147483649 = 0x80000001) to decoded value */
+  vendor_attributes: Record<string, string>;
+}
+
+/** Mechanism supported by a token slot */
+export interface MechanismInfo {
+  /** Human-readable name, e.g. "SHA-256 with RSA PKCS#1 v1.5" */
+  name: string;
+  key_size_min: number;
+  key_size_max: number;
+  /** Subset of "Sign", "SignRecover", "Verify", "Decrypt", "Encrypt" */
+  flags: string[];
+}
+
+export interface LibraryVersionInfo {
+  manufacturer: string;
+  description: string;
+  /** PKCS#11 API version, e.g. "2.40" */
+  cryptoki_version: string;
+  library_version: string;
 }
 
 export interface TokenStatus {
@@ -45,6 +138,59 @@ export interface SignResult {
   output_path: string;
   message: string;
   signing_time: string;
+  /** Set when the signing certificate has expired or expires within 30 days */
+  cert_warning?: string;
+  /** Non-fatal placement issues (e.g. signature partly off-page) */
+  warnings?: string[];
+  /** Per-phase durations in milliseconds */
+  timings: SigningTimings;
+  /** Set when the input looks like an image-only scan */
+  pdf_type_warning?: string;
+}
+
+export interface SigningTimings {
+  pdf_load_ms: number;
+  prepare_ms: number;
+  digest_ms: number;
+  pkcs11_sign_ms: number;
+  cms_build_ms: number;
+  tsa_ms: number;
+  write_ms: number;
+  total_ms: number;
+}
+
+export interface SignatureVerificationResult {
+  field_name: string;
+  signer_name: string;
+  /** RFC 3339 from the CMS signingTime, or the raw PDF /M date */
+  signing_time: string | null;
+  is_valid: boolean;
+  cert_expired: boolean;
+  /** Bytes were appended after this signature (later revision or edit) */
+  modification_detected: boolean;
+  /** Reason the signature is not valid */
+  error?: string;
+}
+
+export interface BatchSignJob {
+  input_path: string;
+  output_path: string;
+  /** Scopes cached TSA responses to this job (defaults to output_path) */
+  job_id?: string;
+}
+
+/** SignResult for one batch job; failed jobs have success=false and the error in message */
+export interface BatchSignResult extends SignResult {
+  job_index: number;
+}
+
+/** Payload of the "sign-batch-progress" event, emitted after each file */
+export interface BatchSignProgress {
+  total: number;
+  completed: number;
+  current_file: string;
+  success: boolean;
+  error?: string;
 }
 
 /** Signature position in PDF coordinates */
@@ -56,6 +202,54 @@ export interface PdfPosition {
   ury: number;
 }
 
+/** Raw PdfSigner parameters (VNPT-CA Plugin compatible, PascalCase) */
+export interface PdfSignerParams {
+  Page: number;
+  Llx: number;
+  Lly: number;
+  Urx: number;
+  Ury: number;
+  SigTextSize?: number;
+  Signer?: string;
+  Description?: string;
+  OnlyDescription?: boolean;
+  SigningTime?: string;
+  CertificateSerial?: string;
+  SigColorRgb?: string;
+  ImageBase64?: string;
+  SetImageBackground?: boolean;
+  /** Official seal image URL (PNG/JPEG, max 512 KB) */
+  SealImageUrl?: string;
+  Visible?: boolean;
+  /** Invisible signatures only: add the field without a widget annotation */
+  InvisibleNoWidget?: boolean;
+  /** Signature field naming (default: Signature1, Signature2, ...) */
+  FieldNaming?: SigFieldNamingStrategy;
+  /** Rewrite the whole file even if it is already signed (invalidates existing signatures) */
+  ForceFullRewrite?: boolean;
+  /** Flatten non-signature form fields before signing (not for already-signed files) */
+  FlattenBeforeSign?: boolean;
+  /**
+   * Signature box text below "Signature Valid", one line per "\n".
+   * Placeholders: {cn}, {date}, {time}, {serial}, {issuer}, {reason}
+   */
+  SignatureTemplate?: string;
+  /** Sign into this empty signature field instead of adding a new one (see findExistingSignatureFields) */
+  ExistingFieldName?: string;
+}
+
+/** Named preset for PdfSignerParams.SignatureTemplate */
+export interface SignatureTemplate {
+  id: string;
+  name: string;
+  template: string;
+}
+
+export type SigFieldNamingStrategy =
+  | { Fixed: string }
+  | { Incremental: { Prefix: string } }
+  | "Uuid";
+
 /** Signature appearance customization */
 export interface SignatureAppearance {
   /** Font family (maps to PDF font) */
@@ -78,6 +272,40 @@ export interface AppInfo {
   description: string;
 }
 
+/**
+ * Error rejected by every command (serialized ESignError).
+ * Switch on `type`, and on `code` for "Signing" (VNPT-CA codes 0-11) and "CertValidation" (0-10).
+ */
+export interface ESignError {
+  type:
+    | "Pkcs11"
+    | "LibraryArchitectureMismatch"
+    | "Pdf"
+    | "Tsa"
+    | "Io"
+    | "Signing"
+    | "CertValidation"
+    | "Internal";
+  code: number | null;
+  message: string;
+  guidance: string | null;
+  /** LibraryArchitectureMismatch only */
+  library_arch?: string;
+  host_arch?: string;
+  library_path?: string;
+}
+
+/** Narrow a rejected invoke() value to ESignError */
+export function isESignError(err: unknown): err is ESignError {
+  return typeof err === "object" && err !== null && "type" in err && "message" in err;
+}
+
+/** Display text for any rejected value (ESignError, Error or string) */
+export function errorMessage(err: unknown): string {
+  if (isESignError(err)) return err.message;
+  return err instanceof Error ? err.message : String(err);
+}
+
 // ============ App Commands ============
 
 export async function getAppInfo(): Promise<AppInfo> {
@@ -90,6 +318,24 @@ export async function detectLibraries(): Promise<DetectedLibrary[]> {
   return invoke("detect_libraries");
 }
 
+export async function detectLibrariesAsync(): Promise<DetectedLibrary[]> {
+  return invoke("detect_libraries_async");
+}
+
+/** Folders PKCS#11 libraries may be loaded from, to tell users where to install CA software */
+export async function getAllowedLibraryPaths(): Promise<string[]> {
+  return invoke("get_allowed_library_paths");
+}
+
+/** Newline-delimited JSON log of login and signing attempts (null when disabled) */
+export async function getAuditLogPath(): Promise<string | null> {
+  return invoke("get_audit_log_path");
+}
+
+export async function warmupLibraries(): Promise<string[]> {
+  return invoke("warmup_libraries");
+}
+
 export async function initTokenManager(libraryPath: string): Promise<void> {
   return invoke("init_token_manager", { libraryPath });
 }
@@ -98,34 +344,231 @@ export async function listTokens(): Promise<TokenInfo[]> {
   return invoke("list_tokens");
 }
 
+export async function getPinRetryCount(slotId: number): Promise<number | null> {
+  return invoke("get_pin_retry_count", { slotId });
+}
+
 export async function loginToken(slotId: number, pin: string): Promise<void> {
   return invoke("login_token", { slotId, pin });
 }
 
+/** Initialize a manager bound to one slot, so several tokens can be used at once */
+export async function initTokenForSlot(libraryPath: string, slotId: number): Promise<void> {
+  return invoke("init_token_for_slot", { libraryPath, slotId });
+}
+
+/** Login on a slot initialized with initTokenForSlot; other slots stay logged in */
+export async function loginSlot(slotId: number, pin: string): Promise<void> {
+  return invoke("login_slot", { slotId, pin });
+}
+
+export async function logoutSlot(slotId: number): Promise<void> {
+  return invoke("logout_slot", { slotId });
+}
+
+/** Slot IDs with a logged-in session */
+export async function getActiveSlots(): Promise<number[]> {
+  return invoke("get_active_slots");
+}
+
+export async function changeTokenPin(
+  slotId: number,
+  oldPin: string,
+  newPin: string
+): Promise<void> {
+  return invoke("change_token_pin", { slotId, oldPin, newPin });
+}
+
 export async function getCertificate(): Promise<CertificateInfo> {
   return invoke("get_certificate");
 }
 
+/** All certificates on the logged-in token (signing, encryption and CA certificates) */
+export async function listCertificates(): Promise<CertificateInfo[]> {
+  return invoke("list_certificates");
+}
+
+export interface KeyInfo {
+  /** CKA_ID (hex); pass to selectSigningKey */
+  id: string;
+  label: string;
+  key_type: "RSA" | "EC" | "Unsupported";
+  /** RSA modulus bits or EC curve size; 0 if unknown */
+  key_size: number;
+  can_sign: boolean;
+}
+
+/** All private keys on the logged-in token (tokens may hold several key pairs) */
+export async function listSigningKeys(): Promise<KeyInfo[]> {
+  return invoke("list_signing_keys");
+}
+
+/** Sign with the private key whose id is given (and its certificate); remembered for later logins */
+export async function selectSigningKey(keyIdHex: string): Promise<void> {
+  return invoke("select_signing_key", { keyIdHex });
+}
+
+/** Sign with the certificate whose cert_id is given; remembered for later logins */
+export async function selectCertificate(certId: string): Promise<void> {
+  return invoke("select_certificate", { certId });
+}
+
+export async function getCertificateExtended(): Promise<CertificateInfoExtended> {
+  return invoke("get_certificate_extended");
+}
+
+export async function getCertificatePolicies(): Promise<CertPolicyInfo[]> {
+  return invoke("get_certificate_policies");
+}
+
+/** CertValidationCode variant name (VNPT-CA certificate validation codes) */
+export type CertValidationCode =
+  | "Valid"
+  | "UnknownError"
+  | "Expired"
+  | "NotYetValid"
+  | "Revoked"
+  | "CannotSign"
+  | "RevocationCheckFailed"
+  | "UntrustedCA"
+  | "CertInfoUnavailable"
+  | "CACertInfoUnavailable"
+  | "OCSPUrlNotFound";
+
+export interface CertValidityReport {
+  is_valid: boolean;
+  is_expired: boolean;
+  /** Negative once the certificate has expired */
+  days_until_expiry: number;
+  warning?: string | null;
+  validation_code: CertValidationCode;
+}
+
+/** Validity period check of the logged-in certificate, with a warning within 30 days of expiry */
+export async function checkCertificateValidity(): Promise<CertValidityReport> {
+  return invoke("check_certificate_validity");
+}
+
+/** Save the token certificate to a .pem or .cer file */
+export async function exportCertificate(
+  outputPath: string,
+  format: CertExportFormat
+): Promise<void> {
+  return invoke("export_certificate", { outputPath, format });
+}
+
+/** Save the certificate chain as concatenated PEM blocks */
+export async function exportCertificateChain(outputPath: string): Promise<void> {
+  return invoke("export_certificate_chain", { outputPath });
+}
+
+/** Result of exportCertificateP12; show the password to the user, it is not stored */
+export interface P12ExportResult {
+  output_path: string;
+  password: string;
+}
+
+/** Save the certificate chain as a .p12/.pfx file (no private key) protected by a random password */
+export async function exportCertificateP12(
+  outputPath: string,
+  friendlyName: string
+): Promise<P12ExportResult> {
+  return invoke("export_certificate_p12", { outputPath, friendlyName });
+}
+
+/** Save the current PKCS#11 library and idle timeout for the next launch */
+export async function saveConfig(): Promise<void> {
+  return invoke("save_config");
+}
+
+/** Delete the saved config and return to defaults (for testing) */
+export async function resetConfig(): Promise<void> {
+  return invoke("reset_config");
+}
+
+/** Query the CA's OCSP responder for the token certificate (network call) */
+export async function checkCertificateRevocation(): Promise<OcspResponse> {
+  return invoke("check_certificate_revocation");
+}
+
+/** CRL revocation status; `unknown` when the certificate has no HTTP CRL distribution point */
+export type RevocationStatus =
+  | { status: "good" }
+  | { status: "revoked"; reason: string }
+  | { status: "unknown" };
+
+/** Check the token certificate against its CA's CRL (network call, cached until nextUpdate) */
+export async function checkCrlRevocation(): Promise<RevocationStatus> {
+  return invoke("check_crl_revocation");
+}
+
+/** Probe one TSA server with a dummy timestamp request (network call) */
+export async function checkTsaServer(url: string): Promise<TsaHealthResult> {
+  return invoke("check_tsa_server", { url });
+}
+
+/** Probe all configured TSA servers in parallel; available and fastest first */
+export async function checkAllTsaServers(): Promise<TsaHealthResult[]> {
+  return invoke("check_all_tsa_servers");
+}
+
+/** Ping all TSA servers concurrently and return the fastest one's URL (network call) */
+export async function selectBestTsaServer(timeoutMs: number): Promise<string> {
+  return invoke("select_best_tsa_server", { timeoutMs });
+}
+
+export async function getVendorAttributes(slotId: number): Promise<VendorInfo> {
+  return invoke("get_vendor_attributes", { slotId });
+}
+
+/** Mechanisms the token in a slot supports, for the diagnostics screen */
+export async function getSlotMechanisms(slotId: number): Promise<MechanismInfo[]> {
+  return invoke("get_slot_mechanisms", { slotId });
+}
+
+/** PKCS#11 library manufacturer and versions, for bug reports */
+export async function getLibraryVersionInfo(): Promise<LibraryVersionInfo> {
+  return invoke("get_library_version_info");
+}
+
 export async function logoutToken(): Promise<void> {
   return invoke("logout_token");
 }
 
+/**
+ * Log out after this many idle seconds (default 300, 0 = never).
+ * The "session-expired" event is emitted when an idle session is logged out.
+ */
+export async function setIdleTimeout(timeoutSecs: number): Promise<void> {
+  return invoke("set_idle_timeout", { timeoutSecs });
+}
+
+/** Seconds until the idle session is logged out; null if disabled or not logged in */
+export async function getSessionRemainingSeconds(): Promise<number | null> {
+  return invoke("get_session_remaining_seconds");
+}
+
 export async function checkTokenStatus(): Promise<TokenStatus> {
   return invoke("check_token_status");
 }
 
 // ============ Signing Commands ============
 
-export async function signPdf(
+/** `options` argument shared by sign_pdf, sign_pdf_with_slot and sign_pdf_async */
+function signPdfOptions(
   pdfPath: string,
   outputPath: string,
   visible: boolean = true,
   reason?: string,
   signerName?: string,
   position?: PdfPosition,
-  appearance?: SignatureAppearance
-): Promise<SignResult> {
-  return invoke("sign_pdf", {
+  appearance?: SignatureAppearance,
+  autoOpenAfterSign: boolean = false,
+  compress: boolean = false,
+  sealImageUrl?: string,
+  invisibleNoWidget: boolean = false
+) {
+  return {
     pdfPath,
     outputPath,
     visible,
@@ -141,13 +584,406 @@ export async function signPdf(
     showName: appearance?.showName,
     showTimestamp: appearance?.showTimestamp,
     showReason: appearance?.showReason,
+    autoOpenAfterSign,
+    compress,
+    sealImageUrl,
+    invisibleNoWidget,
+  };
+}
+
+export async function signPdf(
+  pdfPath: string,
+  outputPath: string,
+  visible: boolean = true,
+  reason?: string,
+  signerName?: string,
+  position?: PdfPosition,
+  appearance?: SignatureAppearance,
+  autoOpenAfterSign: boolean = false,
+  compress: boolean = false,
+  sealImageUrl?: string,
+  invisibleNoWidget: boolean = false
+): Promise<SignResult> {
+  return invoke("sign_pdf", {
+    options: signPdfOptions(
+      pdfPath,
+      outputPath,
+      visible,
+      reason,
+      signerName,
+      position,
+      appearance,
+      autoOpenAfterSign,
+      compress,
+      sealImageUrl,
+      invisibleNoWidget
+    ),
+  });
+}
+
+/** Same as signPdf, using the token logged in with loginSlot(slotId) */
+export async function signPdfWithSlot(
+  slotId: number,
+  pdfPath: string,
+  outputPath: string,
+  visible: boolean = true,
+  reason?: string,
+  signerName?: string,
+  position?: PdfPosition,
+  appearance?: SignatureAppearance,
+  autoOpenAfterSign: boolean = false,
+  compress: boolean = false,
+  sealImageUrl?: string,
+  invisibleNoWidget: boolean = false
+): Promise<SignResult> {
+  return invoke("sign_pdf_with_slot", {
+    slotId,
+    options: signPdfOptions(
+      pdfPath,
+      outputPath,
+      visible,
+      reason,
+      signerName,
+      position,
+      appearance,
+      autoOpenAfterSign,
+      compress,
+      sealImageUrl,
+      invisibleNoWidget
+    ),
+  });
+}
+
+/**
+ * Same as signPdf, but signs on a background thread so the window stays responsive.
+ * Emits "signing-started" (input path) and "signing-complete" (SignResult).
+ */
+export async function signPdfAsync(
+  pdfPath: string,
+  outputPath: string,
+  visible: boolean = true,
+  reason?: string,
+  signerName?: string,
+  position?: PdfPosition,
+  appearance?: SignatureAppearance,
+  autoOpenAfterSign: boolean = false,
+  compress: boolean = false,
+  sealImageUrl?: string,
+  invisibleNoWidget: boolean = false
+): Promise<SignResult> {
+  return invoke("sign_pdf_async", {
+    options: signPdfOptions(
+      pdfPath,
+      outputPath,
+      visible,
+      reason,
+      signerName,
+      position,
+      appearance,
+      autoOpenAfterSign,
+      compress,
+      sealImageUrl,
+      invisibleNoWidget
+    ),
+  });
+}
+
+/** Merge PDFs in order (max 20 files, 200 MB) and sign the result */
+export async function mergeAndSignPdf(
+  pdfPaths: string[],
+  outputPath: string,
+  signerParams: PdfSignerParams
+): Promise<SignResult> {
+  return invoke("merge_and_sign_pdf", { pdfPaths, outputPath, signerParams });
+}
+
+/** SignResult of certifyPdf plus the DocMDP permission level applied */
+export interface CertifyResult extends SignResult {
+  doc_mdp_level: number;
+}
+
+/** Certification of a PDF: 1 = no changes, 2 = form filling/signing, 3 = also annotations */
+export interface CertifyStatus {
+  doc_mdp_level: number;
+  signer_name?: string;
+  signing_time?: string;
+}
+
+/** Sign a PDF and get the signed document as base64, without writing a file */
+export async function signPdfReturnBase64(
+  pdfPath: string,
+  signerParams: PdfSignerParams
+): Promise<string> {
+  return invoke("sign_pdf_return_base64", { pdfPath, signerParams });
+}
+
+/** Sign with an uploaded PNG/JPEG image as background or as a thumbnail left of the text */
+export async function signPdfWithImageSignature(
+  pdfPath: string,
+  outputPath: string,
+  signerParams: PdfSignerParams,
+  imageBase64: string,
+  setImageBackground?: boolean
+): Promise<SignResult> {
+  return invoke("sign_pdf_with_image_signature", {
+    pdfPath,
+    outputPath,
+    signerParams,
+    imageBase64,
+    setImageBackground,
   });
 }
 
+export interface ImageInfo {
+  format: "png" | "jpeg";
+  width: number;
+  height: number;
+  size_bytes: number;
+  has_alpha: boolean;
+}
+
+/** Check an uploaded signature image's format, size and dimensions before signing */
+export async function validateSignatureImage(imageBase64: string): Promise<ImageInfo> {
+  return invoke("validate_signature_image", { imageBase64 });
+}
+
+/** Certify (DocMDP) a PDF that has no signature fields yet; level defaults to 1 */
+export async function certifyPdf(
+  pdfPath: string,
+  outputPath: string,
+  signerParams: PdfSignerParams,
+  docMdpLevel?: number
+): Promise<CertifyResult> {
+  return invoke("certify_pdf", { pdfPath, outputPath, signerParams, docMdpLevel });
+}
+
+/** Certification status of a PDF, or null if it is not certified */
+export async function getPdfCertifyStatus(pdfPath: string): Promise<CertifyStatus | null> {
+  return invoke("get_pdf_certify_status", { pdfPath });
+}
+
+/** Pre-signing checks; is_valid is false when signing is expected to fail */
+export interface PdfValidationReport {
+  is_valid: boolean;
+  errors: string[];
+  warnings: string[];
+  existing_signature_count: number;
+  is_certified: boolean;
+  is_encrypted: boolean;
+  pdf_version: string;
+}
+
+export interface SignatureFieldInfo {
+  field_name: string;
+  is_signed: boolean;
+  /** 1-based page; 0 if the field has no widget on a page */
+  page: number;
+  /** [llx, lly, urx, ury] in PDF points */
+  rect: [number, number, number, number];
+  signer_name: string | null;
+  /** Raw PDF date string (D:YYYYMMDDHHmmSS...) */
+  signing_time: string | null;
+}
+
+/** Signature fields already in a PDF, signed or empty */
+export async function getSignatureFieldList(pdfPath: string): Promise<SignatureFieldInfo[]> {
+  return invoke("get_signature_field_list", { pdfPath });
+}
+
+/** Names of the empty signature fields a PDF can be signed into */
+export async function findExistingSignatureFields(pdfPath: string): Promise<string[]> {
+  return invoke("find_existing_signature_fields", { pdfPath });
+}
+
+export interface XmlSignResult {
+  success: boolean;
+  output_path: string;
+}
+
+/** Sign an XML document (e.g. tax declaration) with an enveloped XMLDSig signature */
+export async function signXml(xmlPath: string, outputPath: string): Promise<XmlSignResult> {
+  return invoke("sign_xml", { xmlPath, outputPath });
+}
+
+/** Check a PDF for encryption, DocMDP certification and parse errors before signing */
+export async function validatePdfBeforeSign(pdfPath: string): Promise<PdfValidationReport> {
+  return invoke("validate_pdf_before_sign", { pdfPath });
+}
+
+export type PdfType =
+  | "digitally_created"
+  | "scanned_image"
+  | "portfolio"
+  | "password_protected"
+  | "unknown";
+
+/** Classify a PDF so users can be warned about scans, portfolios and protected files */
+export async function getPdfType(pdfPath: string): Promise<PdfType> {
+  return invoke("get_pdf_type", { pdfPath });
+}
+
+/**
+ * Sign several PDFs with the same parameters using the current login.
+ * Listen for "sign-batch-progress" (BatchSignProgress) to show per-file progress.
+ */
+export async function signPdfsBatch(
+  jobs: BatchSignJob[],
+  commonParams: PdfSignerParams
+): Promise<BatchSignResult[]> {
+  return invoke("sign_pdfs_batch", { jobs, commonParams });
+}
+
+/** Build signer coordinates from page percentages (origin bottom-left) */
+export async function fromPercentage(
+  pdfPath: string,
+  page: number,
+  xPct: number,
+  yPct: number,
+  widthPct: number,
+  heightPct: number
+): Promise<PdfSignerParams> {
+  return invoke("from_percentage", { pdfPath, page, xPct, yPct, widthPct, heightPct });
+}
+
+/** Size of a PDF page in points (1 pt = 1/72 inch) */
+export interface PageInfo {
+  /** Page number (1-indexed) */
+  page: number;
+  width_pt: number;
+  height_pt: number;
+  /** Clockwise rotation applied by viewers; width/height are unrotated */
+  rotation: 0 | 90 | 180 | 270;
+}
+
+/** Size and rotation of every page, for placing the signature rectangle */
+export async function getPageInfo(pdfPath: string): Promise<PageInfo[]> {
+  return invoke("get_page_info", { pdfPath });
+}
+
+/** Number of pages in a PDF */
+export async function getPageCount(pdfPath: string): Promise<number> {
+  return invoke("get_page_count", { pdfPath });
+}
+
+/** Preset signature text templates (VNPT-CA style, minimal, full) */
+export async function getDefaultSignatureTemplates(): Promise<SignatureTemplate[]> {
+  return invoke("get_default_signature_templates");
+}
+
+/** Flatten form fields into page content, without signing (signed PDFs are rejected) */
+export async function flattenPdfForms(inputPath: string, outputPath: string): Promise<void> {
+  return invoke("flatten_pdf_forms", { inputPath, outputPath });
+}
+
+/**
+ * Stamp a diagonal draft watermark on every page (default "BẢN NHÁP – CHƯA KÝ").
+ * Signed PDFs are rejected; signing the watermarked PDF removes the watermark
+ */
+export async function watermarkUnsignedPdf(
+  pdfPath: string,
+  outputPath: string,
+  watermarkText?: string
+): Promise<void> {
+  return invoke("watermark_unsigned_pdf", { pdfPath, outputPath, watermarkText });
+}
+
+/** Merge PDF files in order into one PDF, without signing (max 20 files, 200 MB) */
+export async function mergePdfs(inputPaths: string[], outputPath: string): Promise<void> {
+  return invoke("merge_pdfs", { inputPaths, outputPath });
+}
+
+/** Signature appearance preview as base64 PNG (300 DPI) */
+export async function previewSignatureAppearance(
+  params: PdfSignerParams,
+  certInfo: CertificateInfo
+): Promise<string> {
+  return invoke("preview_signature_appearance", { params, certInfo });
+}
+
+/** Text drawn inside a signature rectangle, to confirm it covers the intended label */
+export async function extractTextNearSignature(
+  pdfPath: string,
+  page: number,
+  rect: [number, number, number, number]
+): Promise<string> {
+  return invoke("extract_text_near_signature", { pdfPath, page, rect });
+}
+
+/** Verify every signature embedded in a PDF (no token needed) */
+export async function verifyPdfSignatures(
+  pdfPath: string
+): Promise<SignatureVerificationResult[]> {
+  return invoke("verify_pdf_signatures", { pdfPath });
+}
+
+export interface ModificationRecord {
+  offset: number;
+  description: string;
+}
+
+export interface PdfDiffReport {
+  original_pages: number;
+  signed_pages: number;
+  /** 0 when the signed copy only appends signed incremental updates */
+  bytes_outside_signature: number;
+  modifications: ModificationRecord[];
+}
+
+/** Compare a signed copy with the original to spot edits outside the signatures (heuristic) */
+export async function diffPdfSignatures(
+  originalPath: string,
+  signedPath: string
+): Promise<PdfDiffReport> {
+  return invoke("diff_pdf_signatures", { originalPath, signedPath });
+}
+
+/** Check the token session is still usable (token not unplugged since login) */
+export async function checkSessionAlive(): Promise<boolean> {
+  return invoke("check_session_alive");
+}
+
 export async function signData(dataBase64: string): Promise<string> {
   return invoke("sign_data", { dataBase64 });
 }
 
+/** Base64 random bytes from the token's hardware generator (length 1-1024) */
+export async function getRandomBytes(length: number): Promise<string> {
+  return invoke("get_random_bytes", { length });
+}
+
+/** Mark the logged-in certificate chain non-modifiable on the token (already locked is fine) */
+export async function lockTokenObjects(): Promise<void> {
+  return invoke("lock_token_objects");
+}
+
+export type SigningAlgorithm =
+  | "SHA256withRSA"
+  | "SHA384withRSA"
+  | "SHA512withRSA"
+  | "SHA1withRSA";
+
+export async function signDataWithAlgorithm(
+  dataBase64: string,
+  algorithm: SigningAlgorithm
+): Promise<string> {
+  return invoke("sign_data_with_algorithm", { dataBase64, algorithm });
+}
+
+/**
+ * Mechanism for signDataWithSlot.
+ * "RsaPkcs" does not hash: pass a DER DigestInfo (hash OID + digest) as data.
+ */
+export type SignMechanism = "Sha256RsaPkcs" | "RsaPkcs" | "EcdsaSha256";
+
+/** Sign base64 data with the token logged in on slotId (for external integrations) */
+export async function signDataWithSlot(
+  slotId: number,
+  dataBase64: string,
+  mechanism: SignMechanism
+): Promise<string> {
+  return invoke("sign_data_with_slot", { slotId, dataBase64, mechanism });
+}
+
 // ============ Dialog Helpers ============
 
 export async function selectPdfFile(): Promise<string | null> {
@@ -164,6 +1000,11 @@ export async function openFile(path: string): Promise<void> {
   return invoke("open_file", { path });
 }
 
+/** Open a signed PDF in the system default PDF viewer */
+export async function openSignedPdf(pdfPath: string): Promise<void> {
+  return invoke("open_signed_pdf", { pdfPath });
+}
+
 // ============ Settings ============
 
 const SETTINGS_KEY = "esign-settings";